@@ -0,0 +1,166 @@
+# Deutsche Meldungen von kitty. Fehlende Meldungen werden aus en-US.ftl übernommen.
+
+## Eingaben
+
+password-prompt = Repository-Passwort eingeben:
+confirm-prompt = Fortfahren? [j/N]
+confirm-yes = j, ja, y, yes
+operation-canceled = Vorgang abgebrochen.
+
+## Fehler
+
+error = Fehler: { $message }
+error-io = E/A-Fehler: { $detail }
+error-repository-exists = Repository existiert bereits
+error-repository-not-found = Repository nicht gefunden
+error-invalid-password = Falsches Passwort
+error-encryption = Verschlüsselungsfehler: { $detail }
+error-decryption = Entschlüsselungsfehler: { $detail }
+error-file-not-tracked = Datei wird nicht verfolgt: { $detail }
+error-privilege-required = Erweiterte Rechte erforderlich für { $detail }
+error-serialization = Serialisierungsfehler: { $detail }
+error-hex-decoding = Fehler beim Hex-Dekodieren: { $detail }
+error-database = Datenbankfehler: { $detail }
+error-storage-type = Fehler beim Speichertyp: { $detail }
+error-package = Paketfehler: { $detail }
+error-password-unavailable = Kein Passwort verfügbar: { $detail }
+error-daemon = Daemon-Fehler: { $detail }
+error-unsupported-format = Nicht unterstütztes Repository-Format: { $detail }
+error-ordering = Fehler in der Reihenfolge: { $detail }
+error-file-too-large = Datei zu groß: { $detail }
+error-collector = Fehler beim Auslesen: { $detail }
+error-filter = Ungültiger Filter: { $detail }
+error-ambiguous-path = Mehrdeutiger Pfad: { $detail }
+error-pattern = Ungültiges Muster: { $detail }
+error-alias = Ungültiger Alias: { $detail }
+error-inventory = Inventarfehler: { $detail }
+error-role = Unbekannte Rolle: { $detail }
+error-condition = Ungültige Bedingung: { $detail }
+error-template = Vorlagenfehler: { $detail }
+error-vault = Vault-Fehler: { $detail }
+error-aws = AWS-Fehler: { $detail }
+error-mirror = Spiegelfehler: { $detail }
+error-locked = Repository ist gesperrt: { $detail }
+error-conflict = Repository wurde während des Befehls geändert: { $detail }
+error-expiry = Ungültiger Ablauf: { $detail }
+error-validation = Prüfung fehlgeschlagen: { $detail }
+error-stale-plan = Plan ist veraltet: { $detail }
+error-dump = Ungültiger Dump: { $detail }
+error-session = Sitzungsfehler: { $detail }
+error-read-only = Nur Lesezugriff: { $detail }
+error-share = Fehler im Freigabepaket: { $detail }
+error-patch = Patch fehlgeschlagen: { $detail }
+error-mail = E-Mail konnte nicht gesendet werden: { $detail }
+error-point-in-time = Rückblick nicht möglich: { $detail }
+error-mount = Einhängen fehlgeschlagen: { $detail }
+error-signature = Signaturprüfung fehlgeschlagen: { $detail }
+error-usage = Ungültige Argumente: { $detail }
+error-unknown-bundle = Unbekanntes Bündel: { $detail }
+error-not-in-trash = { $detail } ist nicht im Papierkorb
+error-tracked-again = { $detail } wird wieder verfolgt; vor dem Wiederherstellen der Kopie aus dem Papierkorb entfernen
+error-output-exists = { $detail } existiert bereits; mit --force überschreiben
+error-not-a-file = { $detail } ist keine Datei, sondern wird von einem Werkzeug erfasst
+error-not-text = { $detail } ist keine Textdatei
+error-content-missing = Gespeicherter Inhalt von { $detail } fehlt
+error-move = Verschieben nicht möglich: { $detail }
+error-incomplete = Nicht alle Dateien wurden gespeichert: { $detail }
+error-empty-tree = Keine Dateien unter { $detail }
+error-no-home = HOME ist nicht gesetzt, daher kann der Ort des Repositorys nicht gespeichert werden
+error-privileged-read = sudo konnte { $detail } nicht lesen
+
+## Allgemein
+
+no-files-tracked = Im Repository werden derzeit keine Dateien verfolgt.
+
+## list
+
+list-no-match = Keine Dateien entsprechen den angegebenen Filtern.
+list-total = Verfolgte Dateien insgesamt: { $count }
+list-total-stored = Insgesamt gespeichert: { $size }
+
+## status
+
+status-clean = Alle { $count } verfolgten Dateien stimmen mit dem Repository überein.
+status-summary = { $tracked } verfolgt, { $drifted } abweichend, { $missing } fehlend, { $pinned } fixiert
+status-missing-hint = Fehlende Dateien mit `kitty restore --missing-only` wiederherstellen.
+status-moved = (möglicherweise verschoben nach { $path })
+status-moved-hint = Verschiebungen mit `kitty mv --accept-detected` übernehmen oder fehlende Dateien mit `kitty restore --missing-only` wiederherstellen.
+status-expired = { $path } ist seit { $date } abgelaufen; mit `kitty prune --expired` nicht mehr verfolgen
+status-expiring = { $path } läuft am { $date } ab
+status-frozen = { $count } eingefrorene Datei(en), schreibgeschützt bis `kitty unfreeze`
+status-thawed = { $path } ist eingefroren, aber wieder beschreibbar; mit `kitty freeze` erneut schützen
+status-tree = { $count } Datei(en) im Verzeichnisbaum { $name } geändert
+status-tree-hint = Das Verzeichnis eines Baums erneut mit `kitty add` hinzufügen, um neue Dateien zu übernehmen und gelöschte festzuhalten.
+
+## diff
+
+diff-summary-title = Zusammenfassung der Änderungen:
+diff-summary-files = Geänderte Dateien: { $count }
+diff-summary-additions = Hinzugefügt: { $count }
+diff-summary-deletions = Entfernt: { $count }
+diff-no-changes = Keine Änderungen an verfolgten Dateien gefunden.
+
+## restore
+
+restore-all-warning = Kein Pfad angegeben. Alle verfolgten Dateien werden wiederhergestellt.
+restore-canceled = Wiederherstellung abgebrochen.
+restore-summary-title = Zusammenfassung der Wiederherstellung
+restore-summary-processed = Verarbeitete Dateien: { $count }
+restore-summary-restored = Wiederhergestellt: { $count ->
+    [one] { $count } Datei
+   *[other] { $count } Dateien
+}
+restore-summary-skipped = Übersprungen: { $count ->
+    [one] { $count } Datei
+   *[other] { $count } Dateien
+}
+restore-summary-errors = Fehler: { $count ->
+    [one] { $count } Datei
+   *[other] { $count } Dateien
+}
+
+## apply
+
+apply-report-title = Abgleichsbericht
+apply-report-unchanged = Aktuell: { $count ->
+    [one] { $count } Datei
+   *[other] { $count } Dateien
+}
+apply-report-created = Angelegt: { $count ->
+    [one] { $count } Datei
+   *[other] { $count } Dateien
+}
+apply-report-updated = Aktualisiert: { $count ->
+    [one] { $count } Datei
+   *[other] { $count } Dateien
+}
+apply-report-permissions = Rechte wiederhergestellt: { $count ->
+    [one] { $count } Datei
+   *[other] { $count } Dateien
+}
+apply-report-backups = Sicherungen: { $count ->
+    [one] { $count } Datei
+   *[other] { $count } Dateien
+}
+apply-report-hooks = Ausgeführte Hooks: { $count }
+apply-report-errors = Fehler: { $count ->
+    [one] { $count } Datei
+   *[other] { $count } Dateien
+}
+apply-converged = Dieser Rechner entspricht dem Repository.
+apply-not-converged = Nicht abgeglichen: { $count ->
+    [one] { $count } Datei fehlgeschlagen
+   *[other] { $count } Dateien fehlgeschlagen
+}
+
+## rm, purge, trash und db restore
+
+remove-confirm = Datei wird nicht mehr verfolgt: { $path }
+remove-canceled = Entfernen abgebrochen.
+purge-confirm = { $path } und die gesamte Versionsgeschichte werden endgültig gelöscht
+purge-canceled = Löschen abgebrochen.
+trash-empty-confirm = { $count ->
+    [one] { $count } Datei wird
+   *[other] { $count } Dateien werden
+} endgültig aus dem Papierkorb gelöscht
+db-restore-confirm = { $database } wird durch { $backup } ersetzt
@@ -0,0 +1,168 @@
+# Messages shown by kitty, in Fluent syntax (https://projectfluent.org).
+# Other languages live next to this file; a message missing there falls back to this one.
+
+## Prompts
+
+password-prompt = Enter repository password:
+confirm-prompt = Continue? [y/N]
+# Answers that count as yes at a confirmation prompt, separated by commas
+confirm-yes = y, yes
+operation-canceled = Operation canceled.
+
+## Errors
+
+error = Error: { $message }
+error-io = IO error: { $detail }
+error-repository-exists = Repository already exists
+error-repository-not-found = Repository not found
+error-invalid-password = Invalid password
+error-encryption = Encryption error: { $detail }
+error-decryption = Decryption error: { $detail }
+error-file-not-tracked = File not tracked: { $detail }
+error-privilege-required = Privilege escalation required for { $detail }
+error-serialization = Serialization error: { $detail }
+error-hex-decoding = Hex decoding error: { $detail }
+error-database = Database error: { $detail }
+error-storage-type = Storage type error: { $detail }
+error-package = Package error: { $detail }
+error-password-unavailable = No password available: { $detail }
+error-daemon = Daemon error: { $detail }
+error-unsupported-format = Unsupported repository format: { $detail }
+error-ordering = Ordering error: { $detail }
+error-file-too-large = File too large: { $detail }
+error-collector = Collector error: { $detail }
+error-filter = Invalid filter: { $detail }
+error-ambiguous-path = Ambiguous path: { $detail }
+error-pattern = Invalid pattern: { $detail }
+error-alias = Invalid alias: { $detail }
+error-inventory = Inventory error: { $detail }
+error-role = Unknown role: { $detail }
+error-condition = Invalid condition: { $detail }
+error-template = Template error: { $detail }
+error-vault = Vault error: { $detail }
+error-aws = AWS error: { $detail }
+error-mirror = Mirror error: { $detail }
+error-locked = Repository is locked: { $detail }
+error-conflict = Repository changed while this command ran: { $detail }
+error-expiry = Invalid expiry: { $detail }
+error-validation = Validation failed: { $detail }
+error-stale-plan = Plan is out of date: { $detail }
+error-dump = Invalid dump: { $detail }
+error-session = Session error: { $detail }
+error-read-only = Read-only access: { $detail }
+error-share = Share bundle error: { $detail }
+error-patch = Patch failed: { $detail }
+error-mail = Could not send mail: { $detail }
+error-point-in-time = Cannot look back: { $detail }
+error-mount = Mount failed: { $detail }
+error-signature = Signature check failed: { $detail }
+error-usage = Invalid arguments: { $detail }
+error-unknown-bundle = Unknown bundle: { $detail }
+error-not-in-trash = { $detail } is not in the trash
+error-tracked-again = { $detail } is tracked again; remove it before restoring the trashed copy
+error-output-exists = { $detail } already exist(s); pass --force to overwrite
+error-not-a-file = { $detail } is not a file but collected from a tool
+error-not-text = { $detail } is not a text file
+error-content-missing = Stored content of { $detail } is missing
+error-move = Cannot move { $detail }
+error-incomplete = Not every file was stored: { $detail }
+error-empty-tree = No files below { $detail }
+error-no-home = HOME is not set, so the repository location cannot be recorded
+error-privileged-read = sudo could not read { $detail }
+
+## Shared
+
+no-files-tracked = No files are currently tracked in the repository.
+
+## list
+
+list-no-match = No files match the specified filters.
+list-total = Total tracked files: { $count }
+list-total-stored = Total stored: { $size }
+
+## status
+
+status-clean = All { $count } tracked files match the repository.
+status-summary = { $tracked } tracked, { $drifted } drifted, { $missing } missing, { $pinned } pinned
+status-missing-hint = Recreate missing files with `kitty restore --missing-only`.
+status-moved = (possibly moved to { $path })
+status-moved-hint = Record the moves with `kitty mv --accept-detected`, or recreate missing files with `kitty restore --missing-only`.
+status-expired = { $path } expired { $date }; untrack it with `kitty prune --expired`
+status-expiring = { $path } expires { $date }
+status-frozen = { $count } frozen file(s), read-only until `kitty unfreeze`
+status-thawed = { $path } is frozen but writable again; run `kitty freeze` on it to make it read-only
+status-tree = { $count } file(s) changed inside { $name } tree
+status-tree-hint = Add a tree's directory again with `kitty add` to take in new files and record deleted ones.
+
+## diff
+
+diff-summary-title = Summary of changes:
+diff-summary-files = Files changed: { $count }
+diff-summary-additions = Additions: { $count }
+diff-summary-deletions = Deletions: { $count }
+diff-no-changes = No changes found in tracked files.
+
+## restore
+
+restore-all-warning = No specific path provided. This will restore all tracked files.
+restore-canceled = Restore operation canceled.
+restore-summary-title = Restore Summary
+restore-summary-processed = Files processed: { $count }
+restore-summary-restored = Restored: { $count ->
+    [one] { $count } file
+   *[other] { $count } files
+}
+restore-summary-skipped = Skipped: { $count ->
+    [one] { $count } file
+   *[other] { $count } files
+}
+restore-summary-errors = Errors: { $count ->
+    [one] { $count } file
+   *[other] { $count } files
+}
+
+## apply
+
+apply-report-title = Convergence Report
+apply-report-unchanged = Up to date: { $count ->
+    [one] { $count } file
+   *[other] { $count } files
+}
+apply-report-created = Created: { $count ->
+    [one] { $count } file
+   *[other] { $count } files
+}
+apply-report-updated = Updated: { $count ->
+    [one] { $count } file
+   *[other] { $count } files
+}
+apply-report-permissions = Permissions put back: { $count ->
+    [one] { $count } file
+   *[other] { $count } files
+}
+apply-report-backups = Backups: { $count ->
+    [one] { $count } file
+   *[other] { $count } files
+}
+apply-report-hooks = Hooks run: { $count }
+apply-report-errors = Errors: { $count ->
+    [one] { $count } file
+   *[other] { $count } files
+}
+apply-converged = This machine matches the repository.
+apply-not-converged = Not converged: { $count ->
+    [one] { $count } file failed
+   *[other] { $count } files failed
+}
+
+## rm, purge, trash and db restore
+
+remove-confirm = About to remove file from tracking: { $path }
+remove-canceled = Remove operation canceled.
+purge-confirm = About to permanently erase { $path } and all of its history
+purge-canceled = Purge canceled.
+trash-empty-confirm = About to permanently delete { $count ->
+    [one] { $count } file
+   *[other] { $count } files
+} from the trash
+db-restore-confirm = About to replace { $database } with { $backup }
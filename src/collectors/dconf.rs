@@ -0,0 +1,28 @@
+use super::{run_capture, run_with_input};
+use crate::commands::init::KittyError;
+
+/// dconf only dumps and loads directories, which are written with a trailing slash
+fn directory(path: &str) -> Result<String, KittyError> {
+    if !path.starts_with('/') {
+        return Err(KittyError::Collector(format!(
+            "dconf paths must be absolute, e.g. dconf:/org/gnome/terminal/ (got {})",
+            path
+        )));
+    }
+
+    if path.ends_with('/') {
+        Ok(path.to_string())
+    } else {
+        Ok(format!("{}/", path))
+    }
+}
+
+/// Dump a dconf directory in keyfile format
+pub fn dump(path: &str) -> Result<Vec<u8>, KittyError> {
+    run_capture("dconf", &["dump", &directory(path)?])
+}
+
+/// Load a keyfile dump back into a dconf directory
+pub fn load(path: &str, content: &[u8]) -> Result<(), KittyError> {
+    run_with_input("dconf", &["load", &directory(path)?], content)
+}
@@ -0,0 +1,12 @@
+use super::{run_capture, run_with_input};
+use crate::commands::init::KittyError;
+
+/// Export a defaults domain as an XML property list
+pub fn export(domain: &str) -> Result<Vec<u8>, KittyError> {
+    run_capture("defaults", &["export", domain, "-"])
+}
+
+/// Replace a defaults domain with an exported property list
+pub fn import(domain: &str, content: &[u8]) -> Result<(), KittyError> {
+    run_with_input("defaults", &["import", domain, "-"], content)
+}
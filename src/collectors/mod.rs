@@ -0,0 +1,149 @@
+pub mod dconf;
+pub mod defaults;
+pub mod packages;
+pub mod sops;
+
+use crate::commands::init::KittyError;
+use std::{
+    fs,
+    io::Write,
+    process::{Command, Stdio},
+};
+
+/// Settings kept in a tool's own database rather than in a file
+///
+/// Tracked entries name them as `dconf:/org/gnome/terminal/`,
+/// `defaults:com.apple.dock`, `packages:apt` or `sops:/srv/app/secrets.yaml`
+/// in place of a file path.
+pub enum Collector {
+    /// A dconf directory, dumped and loaded with `dconf`
+    Dconf(String),
+
+    /// A macOS defaults domain, exported and imported with `defaults`
+    Defaults(String),
+
+    /// The packages installed with `brew`, `apt` or `pacman`
+    Packages(String),
+
+    /// A SOPS-encrypted file, tracked as plaintext and encrypted again with `sops`
+    Sops(String),
+}
+
+impl Collector {
+    /// Recognise a collector entry, returning None for ordinary file paths
+    pub fn parse(spec: &str) -> Option<Self> {
+        if let Some(path) = spec.strip_prefix("dconf:") {
+            Some(Collector::Dconf(path.to_string()))
+        } else if let Some(domain) = spec.strip_prefix("defaults:") {
+            Some(Collector::Defaults(domain.to_string()))
+        } else if let Some(path) = spec.strip_prefix("sops:") {
+            Some(Collector::Sops(path.to_string()))
+        } else {
+            spec.strip_prefix("packages:")
+                .map(|manager| Collector::Packages(manager.to_string()))
+        }
+    }
+
+    /// Read the current settings through the tool
+    pub fn capture(&self) -> Result<Vec<u8>, KittyError> {
+        match self {
+            Collector::Dconf(path) => dconf::dump(path),
+            Collector::Defaults(domain) => defaults::export(domain),
+            Collector::Packages(manager) => packages::dump(manager),
+            Collector::Sops(path) => sops::decrypt(path),
+        }
+    }
+
+    /// Write settings back through the tool
+    ///
+    /// For package lists this installs whatever is missing.
+    pub fn apply(&self, content: &[u8]) -> Result<(), KittyError> {
+        match self {
+            Collector::Dconf(path) => dconf::load(path, content),
+            Collector::Defaults(domain) => defaults::import(domain, content),
+            Collector::Packages(manager) => packages::install(manager, content),
+            Collector::Sops(path) => sops::write(path, content),
+        }
+    }
+}
+
+/// Current content of a tracked entry, whether it is a file or a collector
+pub fn read_live(original_path: &str) -> Result<Vec<u8>, KittyError> {
+    match Collector::parse(original_path) {
+        Some(collector) => collector.capture(),
+        None => Ok(fs::read(original_path)?),
+    }
+}
+
+/// Run a tool and return its stdout, failing if it exits unsuccessfully
+fn run_capture(program: &str, args: &[&str]) -> Result<Vec<u8>, KittyError> {
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .map_err(|e| KittyError::Collector(format!("Could not run {}: {}", program, e)))?;
+
+    if !output.status.success() {
+        return Err(KittyError::Collector(format!(
+            "{} {} failed: {}",
+            program,
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    Ok(output.stdout)
+}
+
+/// Run a tool with `input` on its stdin
+fn run_with_input(program: &str, args: &[&str], input: &[u8]) -> Result<(), KittyError> {
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| KittyError::Collector(format!("Could not run {}: {}", program, e)))?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(input)?;
+    }
+
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(KittyError::Collector(format!(
+            "{} {} failed: {}",
+            program,
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    Ok(())
+}
+
+/// Run a tool with `input` on its stdin and return its stdout
+fn run_filter(program: &str, args: &[&str], input: &[u8]) -> Result<Vec<u8>, KittyError> {
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| KittyError::Collector(format!("Could not run {}: {}", program, e)))?;
+
+    // sops reads all of its input before writing anything, so this cannot stall
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(input)?;
+    }
+
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(KittyError::Collector(format!(
+            "{} {} failed: {}",
+            program,
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    Ok(output.stdout)
+}
@@ -0,0 +1,147 @@
+use super::run_capture;
+use crate::commands::init::KittyError;
+use std::{
+    collections::BTreeSet,
+    fmt,
+    io::Write,
+    process::{Command, Stdio},
+};
+
+/// A command that installs packages from a stored list
+pub struct InstallCommand {
+    pub program: String,
+    pub args: Vec<String>,
+
+    /// Fed to the command's stdin, for tools that read the list themselves
+    pub input: Option<Vec<u8>>,
+}
+
+impl fmt::Display for InstallCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.program, self.args.join(" "))?;
+        if self.input.is_some() {
+            write!(f, " < stored list")?;
+        }
+        Ok(())
+    }
+}
+
+fn unknown(manager: &str) -> KittyError {
+    KittyError::Collector(format!(
+        "Unknown package manager {}; use packages:brew, packages:apt or packages:pacman",
+        manager
+    ))
+}
+
+/// Dump the packages installed on purpose
+pub fn dump(manager: &str) -> Result<Vec<u8>, KittyError> {
+    match manager {
+        "brew" => run_capture("brew", &["bundle", "dump", "--file=-"]),
+        "apt" => run_capture("dpkg", &["--get-selections"]),
+        "pacman" => run_capture("pacman", &["-Qqe"]),
+        _ => Err(unknown(manager)),
+    }
+}
+
+/// Package names in a stored list
+fn package_names(manager: &str, content: &[u8]) -> BTreeSet<String> {
+    let text = String::from_utf8_lossy(content);
+    match manager {
+        // `dpkg --get-selections` lines are a name and a state
+        "apt" => text
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split_whitespace();
+                match (fields.next(), fields.next()) {
+                    (Some(name), Some("install")) => Some(name.to_string()),
+                    _ => None,
+                }
+            })
+            .collect(),
+        _ => text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect(),
+    }
+}
+
+/// Work out the command that installs what a stored list names
+///
+/// Returns None when everything in the list is already installed.
+pub fn install_command(manager: &str, stored: &[u8]) -> Result<Option<InstallCommand>, KittyError> {
+    let (program, mut args) = match manager {
+        // brew bundle skips what is installed already
+        "brew" => {
+            return Ok(Some(InstallCommand {
+                program: "brew".to_string(),
+                args: vec!["bundle".into(), "install".into(), "--file=-".into()],
+                input: Some(stored.to_vec()),
+            }))
+        }
+        "apt" => (
+            "sudo",
+            vec!["apt-get".to_string(), "install".into(), "-y".into()],
+        ),
+        "pacman" => (
+            "sudo",
+            vec!["pacman".to_string(), "-S".into(), "--needed".into()],
+        ),
+        _ => return Err(unknown(manager)),
+    };
+
+    // On a fresh machine the package manager may not even answer, so install everything
+    let installed = dump(manager)
+        .map(|current| package_names(manager, &current))
+        .unwrap_or_default();
+    let missing: Vec<String> = package_names(manager, stored)
+        .difference(&installed)
+        .cloned()
+        .collect();
+
+    if missing.is_empty() {
+        return Ok(None);
+    }
+
+    args.extend(missing);
+    Ok(Some(InstallCommand {
+        program: program.to_string(),
+        args,
+        input: None,
+    }))
+}
+
+/// Install the packages a stored list names that are missing
+///
+/// The package manager keeps the terminal, so sudo can prompt for a password.
+pub fn install(manager: &str, stored: &[u8]) -> Result<(), KittyError> {
+    let Some(command) = install_command(manager, stored)? else {
+        return Ok(());
+    };
+
+    println!("  Running: {}", command);
+    let mut child = Command::new(&command.program)
+        .args(&command.args)
+        .stdin(if command.input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::inherit()
+        })
+        .spawn()
+        .map_err(|e| KittyError::Collector(format!("Could not run {}: {}", command.program, e)))?;
+
+    if let (Some(mut stdin), Some(input)) = (child.stdin.take(), &command.input) {
+        stdin.write_all(input)?;
+    }
+
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(KittyError::Collector(format!(
+            "{} failed: {}",
+            command, status
+        )));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,62 @@
+use super::{run_capture, run_filter};
+use crate::commands::init::KittyError;
+use std::{fs, path::Path};
+
+/// SOPS picks keys from `.sops.yaml` by file name, so paths must be absolute
+fn absolute(path: &str) -> Result<&str, KittyError> {
+    if Path::new(path).is_absolute() {
+        Ok(path)
+    } else {
+        Err(KittyError::Collector(format!(
+            "sops paths must be absolute, e.g. sops:/home/me/secrets.yaml (got {})",
+            path
+        )))
+    }
+}
+
+/// The SOPS store format for a file, from its extension
+fn format(path: &str) -> &'static str {
+    match Path::new(path).extension().and_then(|e| e.to_str()) {
+        Some("yaml" | "yml") => "yaml",
+        Some("json") => "json",
+        Some("env") => "dotenv",
+        Some("ini") => "ini",
+        _ => "binary",
+    }
+}
+
+/// Decrypt a SOPS file with the keys available to `sops`
+pub fn decrypt(path: &str) -> Result<Vec<u8>, KittyError> {
+    run_capture("sops", &["--decrypt", absolute(path)?])
+}
+
+/// Encrypt plaintext as if it were the file at `path`
+///
+/// Keys come from the `.sops.yaml` creation rule matching `path`, or from the
+/// given age recipients.
+pub fn encrypt(path: &str, content: &[u8], age: &[String]) -> Result<Vec<u8>, KittyError> {
+    let format = format(path);
+    let recipients = age.join(",");
+    let mut args = vec![
+        "--encrypt",
+        "--filename-override",
+        path,
+        "--input-type",
+        format,
+        "--output-type",
+        format,
+    ];
+    if !age.is_empty() {
+        args.extend(["--age", recipients.as_str()]);
+    }
+    args.push("/dev/stdin");
+    run_filter("sops", &args, content)
+}
+
+/// Write plaintext back to a SOPS file, encrypted again
+pub fn write(path: &str, content: &[u8]) -> Result<(), KittyError> {
+    let path = absolute(path)?;
+    let encrypted = encrypt(path, content, &[])?;
+    fs::write(path, encrypted)?;
+    Ok(())
+}
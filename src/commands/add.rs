@@ -1,61 +1,500 @@
 use crate::{
-    commands::init::{Crypto, KittyError, TrackedFile},
-    storage::sqlite::SqliteStorage,
-    utils::file::{get_repository_path, get_repository_salt, get_storage_type},
+    collectors::Collector,
+    commands::init::{
+        ContentFilter, Crypto, FileMetadata, FileVersion, KittyError, Scope, TrackedFile,
+    },
+    repository::{config::RepoConfig, keys::unlock_admin, session::RepoSession},
+    utils::{
+        audit::{self, AuditEntry},
+        file::{
+            format_size, get_repository_path, get_repository_salt, get_storage_type,
+            read_with_privileges,
+        },
+        filter::apply_filters,
+        hash::digest_like,
+        hooks, index, inventory,
+        link::original_for_linked,
+        mirror,
+        package::{find_owning_package, is_system_path},
+        password::read_repository_password,
+        platform::Conditions,
+        stats, theme,
+        tree::{trees, walk},
+    },
 };
 
-use blake3;
-use chrono::Utc;
-use rpassword::read_password;
+use chrono::{DateTime, Duration, Local, NaiveDate, Utc};
 use std::{
-    fs,
-    io::{self, Write},
-    path::Path,
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
 };
 use uuid::Uuid;
 
-pub fn add_file(path: &str) -> Result<(), KittyError> {
+pub fn add_file(path: &str, labels: &FileLabels, force: bool) -> Result<(), KittyError> {
     let repo_path = get_repository_path()?;
 
     if !repo_path.exists() {
         return Err(KittyError::RepositoryNotFound);
     }
 
-    // Get the absolute path to the file
-    let file_path = Path::new(path).canonicalize()?;
+    // Get password from user
+    let password = read_repository_password(false)?;
+
+    if Collector::parse(path).is_none() && Path::new(path).is_dir() {
+        return add_tree(path, &password, labels, force);
+    }
+    add_file_with_labels(path, &password, labels, force)
+}
+
+/// Bundle, tags and ordering constraints to record for a file being added
+#[derive(Default, Clone)]
+pub struct FileLabels {
+    /// Named group the file belongs to
+    pub bundle: Option<String>,
+
+    /// Free-form tags
+    pub tags: Vec<String>,
+
+    /// Paths or bundles that must be restored before this file
+    pub after: Vec<String>,
+
+    /// Content filters to add to the file's existing ones
+    pub filters: Vec<ContentFilter>,
+
+    /// Platforms the file applies to, added to its existing conditions
+    pub conditions: Conditions,
+
+    /// When a temporary file is due to be untracked, replacing any earlier expiry
+    pub expires_at: Option<DateTime<Utc>>,
+
+    /// Whose file it is, replacing the scope recorded earlier or implied by the path
+    pub scope: Option<Scope>,
+
+    /// Root of the directory tree the file is added with
+    pub tree: Option<String>,
+}
+
+/// When an entry added with `--expires` runs out: a duration like 12h, 30d or 2w, or a date
+pub fn parse_expiry(spec: &str) -> Result<DateTime<Utc>, KittyError> {
+    if let Ok(date) = NaiveDate::parse_from_str(spec, "%Y-%m-%d") {
+        return date
+            .and_hms_opt(0, 0, 0)
+            .and_then(|t| t.and_local_timezone(Local).single())
+            .map(|t| t.with_timezone(&Utc))
+            .ok_or_else(|| KittyError::Expiry(spec.to_string()));
+    }
+
+    let invalid = || KittyError::Expiry(format!("{} (expected e.g. 30d or 2025-06-30)", spec));
+    let (count, unit) = spec.split_at(spec.find(|c: char| !c.is_ascii_digit()).unwrap_or(0));
+    let count: i64 = count.parse().map_err(|_| invalid())?;
+    let duration = match unit {
+        "h" => Duration::try_hours(count),
+        "d" => Duration::try_days(count),
+        "w" => Duration::try_weeks(count),
+        _ => None,
+    };
+    duration
+        .filter(|d| *d > Duration::zero())
+        .and_then(|d| Utc::now().checked_add_signed(d))
+        .ok_or_else(invalid)
+}
+
+/// Add or update a tracked file using an already known password
+pub fn add_file_with_password(path: &str, password: &str) -> Result<(), KittyError> {
+    add_file_with_labels(path, password, &FileLabels::default(), false)
+}
+
+/// Store the current content of a tracked file, naming the event that caused it in the audit log
+pub fn update_file_as(path: &str, password: &str, operation: &str) -> Result<(), KittyError> {
+    store_file(
+        path,
+        password,
+        &FileLabels::default(),
+        false,
+        operation,
+        None,
+    )
+}
+
+/// Store `content` for a tracked file in place of what is on disk, auditing it as `operation`
+///
+/// `content` was made from the stored content with hash `base_hash`, which
+/// was read without the repository lock. Storing fails if another kitty has
+/// stored something else since, instead of silently undoing that change.
+pub fn store_content_as(
+    path: &str,
+    content: Vec<u8>,
+    base_hash: &str,
+    password: &str,
+    operation: &str,
+) -> Result<(), KittyError> {
+    store_file(
+        path,
+        password,
+        &FileLabels::default(),
+        false,
+        operation,
+        Some((content, base_hash)),
+    )
+}
+
+/// Add or update a tracked file, recording its bundle and tags
+///
+/// Files above the repository's size limit are refused unless `force` is set.
+pub fn add_file_with_labels(
+    path: &str,
+    password: &str,
+    labels: &FileLabels,
+    force: bool,
+) -> Result<(), KittyError> {
+    store_file(path, password, labels, force, "update", None)
+}
+
+/// Add or update a tracked file; updates are audited as `update_operation`
+///
+/// The file's content is read from disk unless `content` is given, along with
+/// the hash of the stored content it was made from.
+fn store_file(
+    path: &str,
+    password: &str,
+    labels: &FileLabels,
+    force: bool,
+    update_operation: &str,
+    content: Option<(Vec<u8>, &str)>,
+) -> Result<(), KittyError> {
+    let repo_path = get_repository_path()?;
+
+    if !repo_path.exists() {
+        return Err(KittyError::RepositoryNotFound);
+    }
 
-    // Check if file exists
-    if !file_path.exists() {
-        return Err(KittyError::Io(io::Error::new(
-            io::ErrorKind::NotFound,
-            format!("File not found: {}", path),
+    let (content, base_hash) = content.unzip();
+    let (file_path, file_content) = read_input(&repo_path, path, force, content, labels.scope)?;
+    let crypto = repository_crypto(&repo_path, password)?;
+
+    // Held until the new manifest is saved, so no other kitty changes it in between
+    let mut session = RepoSession::open(&repo_path, crypto)?;
+    if let Some(base_hash) = base_hash {
+        let file_path_str = file_path.to_string_lossy();
+        let current = session
+            .repository
+            .files
+            .iter()
+            .find(|f| f.original_path == file_path_str)
+            .map(|f| f.hash.as_str());
+        if current != Some(base_hash) {
+            return Err(KittyError::Conflict(format!(
+                "{} was stored again after this command read it; run the command again",
+                file_path_str
+            )));
+        }
+    }
+    let staged = stage_file(
+        &mut session,
+        &file_path,
+        &file_content,
+        labels,
+        update_operation,
+    )?;
+    session.commit()?;
+    let mut diffs = BTreeMap::new();
+    if let Some(diff) = &staged.diff {
+        diffs.insert(staged.audit_entry.path.clone(), diff.clone());
+    }
+    finish(
+        &mut session,
+        std::slice::from_ref(&staged.audit_entry),
+        &diffs,
+    )?;
+
+    if staged.updated {
+        println!("File updated successfully: {}", path);
+    } else {
+        println!("File added successfully: {}", path);
+    }
+    let stored = session
+        .repository
+        .files
+        .iter()
+        .find(|f| f.original_path == staged.audit_entry.path);
+    if let Some(file) = stored.filter(|f| !f.conditions.is_empty()) {
+        println!("Only used on machines matching {}", file.conditions);
+    }
+    if let Some(expires_at) = stored.and_then(|f| f.expires_at) {
+        println!(
+            "Expires {}; `kitty prune --expired` untracks it after that",
+            expires_at.with_timezone(&Local).format("%Y-%m-%d %H:%M")
+        );
+    }
+    Ok(())
+}
+
+/// Audit operation recording a file deleted from a tree, so restores elsewhere delete it too
+pub const TREE_DELETE: &str = "tree-delete";
+
+/// Track a directory as one unit, storing every file below it as a member of the tree
+///
+/// Adding the tree again stores the files that changed, takes in new ones and
+/// untracks those deleted from the directory.
+/// Files that cannot be read are reported and skipped, like with `init --adopt`.
+pub fn add_tree(
+    path: &str,
+    password: &str,
+    labels: &FileLabels,
+    force: bool,
+) -> Result<(), KittyError> {
+    let repo_path = get_repository_path()?;
+
+    if !repo_path.exists() {
+        return Err(KittyError::RepositoryNotFound);
+    }
+
+    let root = Path::new(path).canonicalize()?;
+    let root_str = root.to_string_lossy().to_string();
+    let paths = walk(&root);
+    let labels = FileLabels {
+        tree: Some(root_str.clone()),
+        ..labels.clone()
+    };
+
+    let crypto = repository_crypto(&repo_path, password)?;
+    let mut session = RepoSession::open(&repo_path, crypto)?;
+
+    // An emptied directory still has a tree to update
+    let tracked = session
+        .repository
+        .files
+        .iter()
+        .any(|f| f.tree.as_deref() == Some(root_str.as_str()));
+    if paths.is_empty() && !tracked {
+        return Err(KittyError::EmptyTree {
+            path: path.to_string(),
+        });
+    }
+
+    let mut entries = Vec::new();
+    let mut diffs = BTreeMap::new();
+    let (mut added, mut changed, mut failed) = (0, 0, 0);
+    for file_path in &paths {
+        let staged = read_input(
+            &repo_path,
+            &file_path.to_string_lossy(),
+            force,
+            None,
+            labels.scope,
+        )
+        .and_then(|(file_path, content)| {
+            stage_file(&mut session, &file_path, &content, &labels, "update")
+        });
+        match staged {
+            Ok(staged) => {
+                if !staged.updated {
+                    added += 1;
+                } else if staged.audit_entry.old_hash != staged.audit_entry.new_hash {
+                    changed += 1;
+                }
+                if let Some(diff) = staged.diff {
+                    diffs.insert(staged.audit_entry.path.clone(), diff);
+                }
+                entries.push(staged.audit_entry);
+            }
+            Err(e) => {
+                println!(
+                    "{} Could not add {}: {}",
+                    theme::error("ERROR:"),
+                    file_path.display(),
+                    e
+                );
+                failed += 1;
+            }
+        }
+    }
+
+    // Files deleted from the directory leave the tree, and restoring it elsewhere deletes them too
+    let mut deleted = Vec::new();
+    while let Some(index) = session.repository.files.iter().position(|f| {
+        f.tree.as_deref() == Some(root_str.as_str()) && !Path::new(&f.original_path).exists()
+    }) {
+        let file = session.untrack(index);
+        println!("Deleted from the tree: {}", file.original_path);
+        deleted.push(AuditEntry::new(
+            TREE_DELETE,
+            &file.original_path,
+            Some(file.hash),
+            None,
+        ));
+    }
+
+    if !entries.is_empty() || !deleted.is_empty() {
+        session.commit()?;
+        for entry in &deleted {
+            audit::record(&session.repo_path, &session.crypto, entry)?;
+        }
+        let paths: Vec<String> = deleted.iter().map(|e| e.path.clone()).collect();
+        let _ = index::forget(&session.repo_path, &paths);
+        finish(&mut session, &entries, &diffs)?;
+        if !deleted.is_empty() {
+            hooks::run(&session.repo_path, "post-remove", &deleted);
+        }
+    }
+
+    let members = trees(&session.repository.files)
+        .get(root_str.as_str())
+        .map_or(0, |tree| tree.members.len());
+    println!(
+        "Tree {} tracked with {} file(s): {} new, {} changed, {} deleted",
+        root_str,
+        members,
+        added,
+        changed,
+        deleted.len()
+    );
+    if !deleted.is_empty() {
+        println!("Deleted files are in the trash; get one back with `kitty trash restore <path>`");
+    }
+    if failed > 0 {
+        return Err(KittyError::Incomplete(format!(
+            "{} file(s) below {} could not be added",
+            failed, path
         )));
     }
+    Ok(())
+}
 
-    // Check if we have permission to read the file
-    let metadata = fs::metadata(&file_path)?;
+/// Add or update many files at once, with one manifest load and save for all of them
+///
+/// Files that cannot be read are skipped and returned with the reason, so the
+/// rest are still stored.
+pub fn add_files_with_labels(
+    files: &[(String, FileLabels)],
+    password: &str,
+) -> Result<Vec<(String, KittyError)>, KittyError> {
+    let repo_path = get_repository_path()?;
 
-    // If we can't read the file normally, we might need elevated privileges
-    if !metadata.permissions().readonly() {
-        // TODO: Implement privilege escalation here
-        println!("Note: This file may require elevated privileges to access.");
+    if !repo_path.exists() {
+        return Err(KittyError::RepositoryNotFound);
     }
 
-    // Read the file content
-    // In a real implementation, you would use privilege escalation if needed
-    let file_content = fs::read(&file_path)?;
+    let crypto = repository_crypto(&repo_path, password)?;
+    let mut session = RepoSession::open(&repo_path, crypto)?;
+    let mut entries = Vec::new();
+    let mut diffs = BTreeMap::new();
+    let mut failed = Vec::new();
+    for (path, labels) in files {
+        let staged = read_input(&repo_path, path, false, None, labels.scope).and_then(
+            |(file_path, content)| stage_file(&mut session, &file_path, &content, labels, "update"),
+        );
+        match staged {
+            Ok(staged) => {
+                if let Some(diff) = staged.diff {
+                    diffs.insert(staged.audit_entry.path.clone(), diff);
+                }
+                entries.push(staged.audit_entry);
+            }
+            Err(e) => failed.push((path.clone(), e)),
+        }
+    }
 
-    // Get password from user
-    print!("Enter repository password: ");
-    io::stdout().flush()?;
-    let password = read_password()?;
+    if !entries.is_empty() {
+        session.commit()?;
+        finish(&mut session, &entries, &diffs)?;
+    }
+    Ok(failed)
+}
+
+/// The path a file is tracked under and its current content
+///
+/// Files above the repository's size limit are refused unless `force` is set.
+fn read_input(
+    repo_path: &Path,
+    path: &str,
+    force: bool,
+    content: Option<Vec<u8>>,
+    scope: Option<Scope>,
+) -> Result<(PathBuf, Vec<u8>), KittyError> {
+    // SOPS files are tracked by absolute path, like ordinary files
+    let path = &match path.strip_prefix("sops:") {
+        Some(file) => format!("sops:{}", Path::new(file).canonicalize()?.display()),
+        None => path.to_string(),
+    };
 
+    // Settings read through dconf or defaults are tracked under their name, not a file path
+    Ok(if let Some(collector) = Collector::parse(path) {
+        (PathBuf::from(path), collector.capture()?)
+    } else {
+        // Get the absolute path to the file
+        let file_path = Path::new(path).canonicalize()?;
+
+        // A file restored as a link resolves into the repository; track the link's path instead
+        let file_path = original_for_linked(repo_path, &file_path).unwrap_or(file_path);
+
+        // Check if file exists
+        if !file_path.exists() {
+            return Err(KittyError::Io(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("File not found: {}", path),
+            )));
+        }
+
+        // Check if we have permission to read the file
+        let metadata = fs::metadata(&file_path)?;
+
+        // Kitty is built for configuration files, not whole application profiles
+        let limits = RepoConfig::load(repo_path)?.limits;
+        let size = metadata.len();
+        if limits.max_bytes > 0 && size > limits.max_bytes && !force {
+            return Err(KittyError::FileTooLarge(format!(
+                "{} is {} (limit {}); pass --force to track it anyway",
+                path,
+                format_size(size),
+                format_size(limits.max_bytes)
+            )));
+        }
+        if limits.warn_bytes > 0 && size > limits.warn_bytes {
+            println!(
+                "Warning: {} is {}; large files make every backup and restore slower",
+                path,
+                format_size(size)
+            );
+        }
+
+        let file_content = match content {
+            Some(content) => content,
+            None => read_file(&file_path, scope.unwrap_or_else(|| Scope::of(&file_path)))?,
+        };
+        (file_path, file_content)
+    })
+}
+
+/// Read a file, through sudo when it is a system file the current user cannot read
+fn read_file(file_path: &Path, scope: Scope) -> Result<Vec<u8>, KittyError> {
+    match fs::read(file_path) {
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied && scope == Scope::System => {
+            println!(
+                "Permission denied, reading {} with elevated privileges...",
+                file_path.display()
+            );
+            read_with_privileges(file_path)
+        }
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+            Err(KittyError::PrivilegeRequired(format!(
+                "{} (user files are never read with sudo; pass --scope system for a system file)",
+                file_path.display()
+            )))
+        }
+        result => Ok(result?),
+    }
+}
+
+/// Unlock the repository key with the admin password
+fn repository_crypto(repo_path: &Path, password: &str) -> Result<Crypto, KittyError> {
     // Get the storage type
-    let storage_type = get_storage_type(&repo_path)?;
+    let storage_type = get_storage_type(repo_path)?;
     println!("Using storage type: {}", storage_type);
 
     // Get the salt from the repository
-    let salt_str = get_repository_salt(&repo_path)?;
+    let salt_str = get_repository_salt(repo_path)?;
     println!(
         "Retrieved salt (length={}): {}",
         salt_str.len(),
@@ -63,136 +502,281 @@ pub fn add_file(path: &str) -> Result<(), KittyError> {
     );
 
     // Decode the hex-encoded salt
-    let config_salt = match hex::decode(&salt_str) {
-        Ok(salt) => {
-            println!("Decoded salt successfully, length: {} bytes", salt.len());
-            salt
-        }
+    match hex::decode(&salt_str) {
+        Ok(salt) => println!("Decoded salt successfully, length: {} bytes", salt.len()),
         Err(e) => {
             println!("Error decoding salt: {}", e);
             return Err(KittyError::HexDecoding(e));
         }
-    };
+    }
 
-    // Create crypto instance with password and salt
-    let crypto = Crypto::from_password_and_salt(&password, &config_salt);
+    // Only the admin password may change the repository
+    unlock_admin(repo_path, password)
+}
 
-    // Load repository based on storage type
-    let mut repository = if storage_type == "sqlite" {
-        // Use SQLite storage
-        let storage = SqliteStorage::new(&repo_path)?;
-        storage.load_repository()?
-    } else {
-        // Read and decrypt repository configuration
-        let encrypted_config = fs::read(repo_path.join("config.enc"))?;
+/// What staging a file did, for reporting once the session is committed
+struct Staged {
+    audit_entry: AuditEntry,
 
-        // Decrypt configuration
-        println!("Attempting to decrypt configuration...");
-        let decrypted_config = match crypto.decrypt(&encrypted_config) {
-            Ok(config) => {
-                println!(
-                    "Decryption successful! Config length: {} bytes",
-                    config.len()
-                );
-                config
-            }
-            Err(e) => {
-                println!("Decryption failed: {}", e);
-                return Err(e);
-            }
-        };
+    /// Whether the file was tracked already
+    updated: bool,
 
-        // Parse the JSON configuration
-        serde_json::from_slice(&decrypted_config)?
-    };
+    /// What changed, when the post-add hooks take diffs
+    diff: Option<String>,
+}
+
+/// Record a file's new content and labels in a session, to be written when it commits
+fn stage_file(
+    session: &mut RepoSession,
+    file_path: &Path,
+    file_content: &[u8],
+    labels: &FileLabels,
+    update_operation: &str,
+) -> Result<Staged, KittyError> {
+    let repo_path = session.repo_path.clone();
 
     // Check if this file is already tracked
     let file_path_str = file_path.to_string_lossy().to_string();
-    let existing_file_index = repository
+    let existing_file_index = session
+        .repository
         .files
         .iter()
         .position(|f| f.original_path == file_path_str);
 
+    // Strip or rewrite volatile and sensitive fragments before anything is stored
+    let mut filters = existing_file_index
+        .map(|index| session.repository.files[index].filters.clone())
+        .unwrap_or_default();
+    for filter in &labels.filters {
+        if !filters.contains(filter) {
+            filters.push(filter.clone());
+        }
+    }
+    let file_content = apply_filters(file_content, &filters)?;
+
+    // Read the stored content for the hooks before anything replaces it
+    let diff = if hooks::wants_diffs(&repo_path, "post-add") {
+        let old_content = match existing_file_index {
+            Some(index) => {
+                let repo_file_path = session.repository.files[index].repo_path.clone();
+                let encrypted = session.get_file(&repo_file_path)?;
+                session.crypto.decrypt(&encrypted)?
+            }
+            None => Vec::new(),
+        };
+        hooks::unified_diff(&file_path_str, &old_content, &file_content)
+    } else {
+        None
+    };
+
     // Encrypt file content
-    let encrypted_content = crypto.encrypt(&file_content)?;
+    let encrypted_content = session.crypto.encrypt(&file_content)?;
 
-    let hash = blake3::hash(&file_content).to_hex().to_string();
+    let hash = RepoConfig::load(&repo_path)?
+        .hash_algorithm
+        .digest(&file_content);
+
+    // Record which package ships system files so vendor changes can be told apart from local edits
+    let owner = if is_system_path(file_path) {
+        find_owning_package(file_path)
+    } else {
+        None
+    };
+    if let Some(owner) = &owner {
+        println!(
+            "File is owned by package: {} ({})",
+            owner.name, owner.manager
+        );
+    }
+    let package = owner.as_ref().map(|o| o.name.clone());
+    let package_manager = owner.map(|o| o.manager);
+    let file_metadata = FileMetadata::from_path(file_path);
+
+    let old_hash = existing_file_index.map(|index| session.repository.files[index].hash.clone());
+    let audit_entry = AuditEntry::new(
+        if existing_file_index.is_some() {
+            update_operation
+        } else {
+            "add"
+        },
+        &file_path_str,
+        old_hash,
+        Some(hash.clone()),
+    );
 
     let now = Utc::now();
 
     if let Some(index) = existing_file_index {
         // File is already tracked, update the existing entry
         println!("File is already tracked, updating existing entry.");
-        let tracked_file = &mut repository.files[index];
+        let revision = session.repository.next_generation();
+        let repo_file_path = session.repository.files[index].repo_path.clone();
+        let old_hash = session.repository.files[index].hash.clone();
 
-        // Save the repo_path as we'll reuse it
-        let repo_file_path = tracked_file.repo_path.clone();
+        // Keep the old content as a version when it actually changed
+        // Compare with the entry's own algorithm, so switching algorithms alone adds no version
+        let mut new_repo_path = repo_file_path.clone();
+        if old_hash != digest_like(&old_hash, &file_content) {
+            let version_path = if session.uses_database() {
+                // The content being replaced is copied to a version of its own
+                let version_path = format!("files/{}", Uuid::new_v4());
+                let old_content = session.get_file(&repo_file_path)?;
+                session.put_file(&version_path, old_content);
+                version_path
+            } else {
+                // The old blob becomes the version and the new content gets a path of its own,
+                // so nothing the saved manifest points at changes before it is replaced
+                new_repo_path = format!("files/{}", Uuid::new_v4());
+                repo_file_path.clone()
+            };
+
+            let tracked_file = &mut session.repository.files[index];
+            tracked_file.versions.push(FileVersion {
+                repo_path: version_path,
+                hash: tracked_file.hash.clone(),
+                saved_at: tracked_file.last_updated,
+            });
+        }
+        put_content(session, &new_repo_path, encrypted_content, &hash)?;
 
         // Update the tracked file metadata
+        let tracked_file = &mut session.repository.files[index];
+        tracked_file.repo_path = new_repo_path;
         tracked_file.last_updated = now;
         tracked_file.hash = hash; // Updated hash
+        tracked_file.package = package;
+        tracked_file.package_manager = package_manager;
+        // A frozen file is read-only on disk; keep the mode unfreezing puts back
+        let frozen_mode = tracked_file
+            .metadata
+            .filter(|_| tracked_file.frozen)
+            .map(|m| m.mode);
+        tracked_file.metadata = file_metadata.map(|m| FileMetadata {
+            mode: frozen_mode.unwrap_or(m.mode),
+            ..m
+        });
+        tracked_file.filters = filters;
 
-        // For file-based storage, save file immediately
-        if storage_type != "sqlite" {
-            // Save to filesystem for file-based storage
-            fs::write(repo_path.join(&repo_file_path), &encrypted_content)?;
+        // Labels are only ever added here, never cleared
+        if labels.bundle.is_some() {
+            tracked_file.bundle = labels.bundle.clone();
+        }
+        for tag in &labels.tags {
+            if !tracked_file.tags.contains(tag) {
+                tracked_file.tags.push(tag.clone());
+            }
         }
-        // For SQLite storage, we'll save the file content after updating the repository metadata
+        for dependency in &labels.after {
+            if !tracked_file.after.contains(dependency) {
+                tracked_file.after.push(dependency.clone());
+            }
+        }
+        tracked_file.conditions.merge(&labels.conditions);
+        if labels.expires_at.is_some() {
+            tracked_file.expires_at = labels.expires_at;
+        }
+        if labels.scope.is_some() {
+            tracked_file.scope = labels.scope;
+        }
+        if labels.tree.is_some() {
+            tracked_file.tree = labels.tree.clone();
+        }
+        tracked_file.revision = revision;
     } else {
         // File is not tracked yet, create a new entry
         // Generate a unique filename for the repository
         let file_id = Uuid::new_v4().to_string();
         let repo_file_path = format!("files/{}", file_id);
-
-        // For file-based storage, save file immediately
-        if storage_type != "sqlite" {
-            // Save to filesystem for file-based storage
-            fs::write(repo_path.join(&repo_file_path), &encrypted_content)?;
-        }
+        put_content(session, &repo_file_path, encrypted_content, &hash)?;
 
         // Add new entry to repository config
-        repository.files.push(TrackedFile {
+        let revision = session.repository.next_generation();
+        session.repository.files.push(TrackedFile {
             original_path: file_path_str,
             repo_path: repo_file_path,
             added_at: now,
             last_updated: now,
             // In a real implementation, you would compute a hash here
             hash: hash,
+            package,
+            package_manager,
+            versions: Vec::new(),
+            bundle: labels.bundle.clone(),
+            tags: labels.tags.clone(),
+            after: labels.after.clone(),
+            metadata: file_metadata,
+            ignore_drift: false,
+            filters,
+            conditions: labels.conditions.clone(),
+            revision,
+            expires_at: labels.expires_at,
+            frozen: false,
+            scope: labels.scope,
+            tree: labels.tree.clone(),
         });
     }
 
-    // Save repository based on storage type
-    if storage_type == "sqlite" {
-        // Use SQLite storage
-        let mut storage = SqliteStorage::new(&repo_path)?;
+    Ok(Staged {
+        audit_entry,
+        updated: existing_file_index.is_some(),
+        diff,
+    })
+}
 
-        // First save the repository metadata
-        storage.save_repository(&repository)?;
+/// Stage content for a repo path, sharing the blob of stored content with the same hash
+///
+/// Only the file backend shares blobs; a database stores every copy.
+fn put_content(
+    session: &mut RepoSession,
+    path: &str,
+    encrypted: Vec<u8>,
+    hash: &str,
+) -> Result<(), KittyError> {
+    let existing = session
+        .repository
+        .files
+        .iter()
+        .flat_map(|f| {
+            std::iter::once((&f.repo_path, &f.hash))
+                .chain(f.versions.iter().map(|v| (&v.repo_path, &v.hash)))
+        })
+        .find(|(repo_path, stored_hash)| {
+            *stored_hash == hash && *repo_path != path && session.has_file(repo_path)
+        })
+        .map(|(repo_path, _)| repo_path.clone());
 
-        // Now save the file content after the metadata is saved
-        // This is crucial for SQLite storage to work correctly
-        if let Some(index) = existing_file_index {
-            // Use existing file's repo_path
-            let repo_file_path = &repository.files[index].repo_path;
-            storage.save_file(repo_file_path, &encrypted_content)?;
-        } else {
-            // Use the newly created repo_file_path
-            let repo_file_path = &repository.files[0].repo_path;
-            storage.save_file(&repo_file_path, &encrypted_content)?;
+    match existing {
+        Some(existing) if !session.uses_database() => session.link_file(path, &existing),
+        _ => {
+            session.put_file(path, encrypted);
+            Ok(())
         }
-    } else {
-        // Serialize and encrypt updated configuration
-        let updated_config_json = serde_json::to_string(&repository)?;
-        let encrypted_updated_config = crypto.encrypt(updated_config_json.as_bytes())?;
-
-        // Write updated encrypted configuration
-        fs::write(repo_path.join("config.enc"), encrypted_updated_config)?;
     }
+}
 
-    if existing_file_index.is_some() {
-        println!("File updated successfully: {}", path);
-    } else {
-        println!("File added successfully: {}", path);
+/// Audit and announce files stored by a committed session, then run the post-add hooks
+fn finish(
+    session: &mut RepoSession,
+    entries: &[AuditEntry],
+    diffs: &BTreeMap<String, String>,
+) -> Result<(), KittyError> {
+    for entry in entries {
+        audit::record(&session.repo_path, &session.crypto, entry)?;
     }
+    inventory::update(&session.repo_path, &session.crypto, &session.repository);
+    stats::record(&session.repo_path, &session.crypto, &session.repository);
+    mirror::update(&session.repo_path);
+    // Hooks may run kitty themselves
+    session.unlock();
+    hooks::run_with_diffs(&session.repo_path, "post-add", entries, diffs);
+
+    // The prompt index is only a cache; a failure here must not fail the add
+    let stored: Vec<&TrackedFile> = session
+        .repository
+        .files
+        .iter()
+        .filter(|f| entries.iter().any(|entry| entry.path == f.original_path))
+        .collect();
+    let _ = index::refresh(&session.repo_path, &stored);
     Ok(())
 }
@@ -0,0 +1,125 @@
+use crate::{
+    commands::{
+        add::{add_files_with_labels, FileLabels},
+        init::{ContentFilter, KittyError, Scope},
+    },
+    utils::{file::get_repository_path, platform::Conditions, theme},
+};
+use serde::Deserialize;
+use std::{fs, path::Path};
+use walkdir::WalkDir;
+
+/// Declarative list of files to track, as written by provisioning tools
+#[derive(Deserialize)]
+struct AdoptManifest {
+    files: Vec<AdoptEntry>,
+}
+
+/// A file to track, either a bare path or a path with labels and ordering constraints
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum AdoptEntry {
+    Path(String),
+    Labeled {
+        path: String,
+        #[serde(default)]
+        bundle: Option<String>,
+        #[serde(default)]
+        tags: Vec<String>,
+        #[serde(default)]
+        after: Vec<String>,
+        #[serde(default)]
+        filters: Vec<ContentFilter>,
+        #[serde(default)]
+        only: Conditions,
+        #[serde(default)]
+        scope: Option<Scope>,
+    },
+}
+
+impl AdoptEntry {
+    fn into_parts(self) -> (String, FileLabels) {
+        match self {
+            AdoptEntry::Path(path) => (path, FileLabels::default()),
+            AdoptEntry::Labeled {
+                path,
+                bundle,
+                tags,
+                after,
+                filters,
+                only,
+                scope,
+            } => (
+                path,
+                FileLabels {
+                    bundle,
+                    tags,
+                    after,
+                    filters,
+                    conditions: only,
+                    expires_at: None,
+                    scope,
+                    tree: None,
+                },
+            ),
+        }
+    }
+}
+
+/// Track every file listed in a manifest, or every file below a directory
+pub fn adopt_files(source: &str, password: &str) -> Result<(), KittyError> {
+    let source_path = Path::new(source);
+
+    let entries: Vec<(String, FileLabels)> = if source_path.is_dir() {
+        // The repository itself may be below the directory, as with `init --adopt .`
+        let repo_path = get_repository_path()?;
+        let repo_path = fs::canonicalize(&repo_path).unwrap_or(repo_path);
+        WalkDir::new(source_path)
+            .into_iter()
+            .filter_entry(|e| {
+                !e.file_type().is_dir()
+                    || fs::canonicalize(e.path()).map_or(true, |path| path != repo_path)
+            })
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+            .map(|e| {
+                (
+                    e.path().to_string_lossy().to_string(),
+                    FileLabels::default(),
+                )
+            })
+            .collect()
+    } else {
+        let manifest: AdoptManifest = serde_json::from_str(&fs::read_to_string(source_path)?)?;
+        manifest
+            .files
+            .into_iter()
+            .map(AdoptEntry::into_parts)
+            .collect()
+    };
+
+    println!("Adopting {} file(s) from {}", entries.len(), source);
+
+    // One session for all files, so the manifest is read and saved once
+    let mut failed = Vec::new();
+    for (path, e) in add_files_with_labels(&entries, password)? {
+        println!("{} Could not adopt {}: {}", theme::error("ERROR:"), path, e);
+        failed.push(path);
+    }
+
+    println!(
+        "\nAdopted {} of {} file(s)",
+        entries.len() - failed.len(),
+        entries.len()
+    );
+
+    if !failed.is_empty() {
+        return Err(KittyError::Incomplete(format!(
+            "{} file(s) could not be adopted: {}",
+            failed.len(),
+            failed.join(", ")
+        )));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,136 @@
+use crate::{
+    collectors::Collector,
+    commands::{
+        diff::{diff_files, DiffOptions},
+        init::KittyError,
+    },
+    repository::{
+        history::{parse_point_in_time, revision_at},
+        keys::unlock,
+    },
+    utils::{
+        file::{get_repository_path, load_repository, read_stored_files},
+        password::read_repository_password,
+        select::select_tracked,
+        theme,
+    },
+};
+use chrono::Local;
+use std::io::{self, Write};
+
+/// List the files the repository held at a point in time, with when each was stored
+///
+/// Files changed since are marked, so it shows at a glance what is different now.
+pub fn list_at(when: &str, path: Option<&str>) -> Result<(), KittyError> {
+    let at = parse_point_in_time(when)?;
+    let repo_path = get_repository_path()?;
+
+    if !repo_path.exists() {
+        return Err(KittyError::RepositoryNotFound);
+    }
+
+    // Get password from user
+    let password = read_repository_password(false)?;
+
+    let crypto = unlock(&repo_path, &password)?;
+    let repository = load_repository(&repo_path, &crypto)?;
+
+    let mut files: Vec<_> = repository
+        .files
+        .iter()
+        .filter(|f| path.is_none_or(|path| f.original_path.contains(path)))
+        .filter_map(|f| revision_at(f, at).map(|revision| (f, revision)))
+        .collect();
+    files.sort_by(|(a, _), (b, _)| a.original_path.cmp(&b.original_path));
+
+    let at_label = at.with_timezone(&Local).format("%Y-%m-%d %H:%M");
+    if files.is_empty() {
+        println!("No tracked files as of {}", at_label);
+        return Ok(());
+    }
+
+    println!(
+        "Tracked files as of {}:",
+        theme::emphasis(&at_label.to_string())
+    );
+    for (file, revision) in &files {
+        println!(
+            "  {}  {}{}",
+            revision
+                .saved_at
+                .with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M"),
+            file.original_path,
+            if revision.current {
+                ""
+            } else {
+                "  (changed since)"
+            }
+        );
+    }
+    let changed = files
+        .iter()
+        .filter(|(_, revision)| !revision.current)
+        .count();
+    println!("{} file(s), {} changed since", files.len(), changed);
+    Ok(())
+}
+
+/// Print a tracked file as it was stored at a point in time
+pub fn cat_at(when: &str, path: &str) -> Result<(), KittyError> {
+    let at = parse_point_in_time(when)?;
+    let repo_path = get_repository_path()?;
+
+    if !repo_path.exists() {
+        return Err(KittyError::RepositoryNotFound);
+    }
+
+    // Get password from user
+    let password = read_repository_password(false)?;
+
+    let crypto = unlock(&repo_path, &password)?;
+    let repository = load_repository(&repo_path, &crypto)?;
+
+    let [index] = select_tracked(&repository.files, path, false, false)?[..] else {
+        return Err(KittyError::AmbiguousPath(format!(
+            "'{}' matches several tracked files; print one at a time",
+            path
+        )));
+    };
+    let file = &repository.files[index];
+    if Collector::parse(&file.original_path).is_some() {
+        return Err(KittyError::PointInTime(format!(
+            "{} is not a file",
+            file.original_path
+        )));
+    }
+    let revision = revision_at(file, at).ok_or_else(|| {
+        KittyError::PointInTime(format!(
+            "{} has no stored content from {}",
+            file.original_path,
+            at.with_timezone(&Local).format("%Y-%m-%d %H:%M")
+        ))
+    })?;
+
+    // The batch read prints nothing, which matters when stdout is the content
+    let encrypted = read_stored_files(&repo_path, &[revision.repo_path])?
+        .remove(revision.repo_path)
+        .ok_or_else(|| KittyError::ContentMissing {
+            path: file.original_path.clone(),
+        })?;
+    let content = crypto.decrypt(&encrypted)?;
+
+    let mut stdout = io::stdout().lock();
+    stdout.write_all(&content)?;
+    stdout.flush()?;
+    Ok(())
+}
+
+/// Show how files on disk differ from how they were stored at a point in time
+pub fn diff_at(when: &str, options: Option<DiffOptions>) -> Result<(), KittyError> {
+    let options = DiffOptions {
+        at: Some(parse_point_in_time(when)?),
+        ..options.unwrap_or_default()
+    };
+    diff_files(Some(options))
+}
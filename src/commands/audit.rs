@@ -0,0 +1,114 @@
+use crate::{
+    commands::init::KittyError,
+    repository::keys::unlock,
+    utils::{
+        audit::{read_entries, AuditEntry},
+        file::get_repository_path,
+        hash::hex_digits,
+        password::read_repository_password,
+    },
+};
+use chrono::Local;
+
+/// Options for the audit command
+#[derive(Default)]
+pub struct AuditOptions {
+    /// Filter entries by path (partial match)
+    pub path: Option<String>,
+
+    /// Filter entries by date (format: YYYY-MM-DD)
+    pub date: Option<String>,
+
+    /// Emit entries as JSON
+    pub json: bool,
+}
+
+fn matches(entry: &AuditEntry, options: &AuditOptions) -> bool {
+    if let Some(path) = &options.path {
+        if !entry.path.contains(path) {
+            return false;
+        }
+    }
+
+    if let Some(date) = &options.date {
+        if entry.timestamp.format("%Y-%m-%d").to_string() != *date {
+            return false;
+        }
+    }
+
+    true
+}
+
+fn short_hash(hash: &Option<String>) -> String {
+    match hash {
+        Some(hash) => hex_digits(hash).chars().take(8).collect(),
+        None => "-".to_string(),
+    }
+}
+
+/// Show the audit log of repository operations
+pub fn show_audit_log(options: Option<AuditOptions>) -> Result<(), KittyError> {
+    let options = options.unwrap_or_default();
+    let repo_path = get_repository_path()?;
+
+    if !repo_path.exists() {
+        return Err(KittyError::RepositoryNotFound);
+    }
+
+    // Get password from user
+    let password = read_repository_password(false)?;
+
+    let crypto = unlock(&repo_path, &password)?;
+
+    let entries: Vec<AuditEntry> = read_entries(&repo_path, &crypto)?
+        .into_iter()
+        .filter(|e| matches(e, &options))
+        .collect();
+
+    if options.json {
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+        return Ok(());
+    }
+
+    if entries.is_empty() {
+        println!("No audit entries match.");
+        return Ok(());
+    }
+
+    println!(
+        "{:<20} {:<12} {:<8} {:<20} Path",
+        "Time", "User", "Action", "Hash (old -> new)"
+    );
+    println!(
+        "{:<20} {:<12} {:<8} {:<20} ----",
+        "----", "----", "------", "-----------------"
+    );
+
+    for entry in &entries {
+        let user = match &entry.sudo_user {
+            Some(sudo_user) => format!("{} ({})", entry.user, sudo_user),
+            None => entry.user.clone(),
+        };
+        let hashes = format!(
+            "{} -> {}",
+            short_hash(&entry.old_hash),
+            short_hash(&entry.new_hash)
+        );
+
+        println!(
+            "{:<20} {:<12} {:<8} {:<20} {}",
+            entry
+                .timestamp
+                .with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M:%S"),
+            user,
+            entry.operation,
+            hashes,
+            entry.path
+        );
+    }
+
+    println!("\nTotal entries: {}", entries.len());
+
+    Ok(())
+}
@@ -0,0 +1,132 @@
+use crate::{
+    collectors::Collector,
+    commands::init::KittyError,
+    repository::{history::revisions, keys::unlock},
+    utils::{
+        audit::{self, AuditEntry},
+        file::{get_repository_path, load_repository, read_stored_files},
+        password::read_repository_password,
+        select::select_tracked,
+        theme,
+    },
+};
+use chrono::Local;
+use similar::{ChangeTag, TextDiff};
+
+/// Audit operations that read stored content without storing any
+const READ_OPERATIONS: [&str; 3] = ["restore", "share", "exec"];
+
+/// Show which stored revision of a file introduced each line of its current content
+///
+/// Revisions are numbered from the oldest kept one, which is also credited with
+/// lines that are older still when earlier versions were pruned. Who stored a
+/// revision and how comes from the audit log, when it has an entry for it.
+pub fn blame_file(path: &str) -> Result<(), KittyError> {
+    let repo_path = get_repository_path()?;
+
+    if !repo_path.exists() {
+        return Err(KittyError::RepositoryNotFound);
+    }
+
+    // Get password from user
+    let password = read_repository_password(false)?;
+
+    let crypto = unlock(&repo_path, &password)?;
+    let repository = load_repository(&repo_path, &crypto)?;
+
+    let [index] = select_tracked(&repository.files, path, false, false)?[..] else {
+        return Err(KittyError::AmbiguousPath(format!(
+            "'{}' matches several tracked files; blame one at a time",
+            path
+        )));
+    };
+    let file = &repository.files[index];
+    let not_text = || KittyError::NotText {
+        path: file.original_path.clone(),
+    };
+    if Collector::parse(&file.original_path).is_some() {
+        return Err(not_text());
+    }
+
+    let revisions = revisions(file);
+    let repo_paths: Vec<&str> = revisions.iter().map(|r| r.repo_path).collect();
+    let stored = read_stored_files(&repo_path, &repo_paths)?;
+    let mut contents = Vec::new();
+    for revision in &revisions {
+        let encrypted =
+            stored
+                .get(revision.repo_path)
+                .cloned()
+                .ok_or_else(|| KittyError::ContentMissing {
+                    path: file.original_path.clone(),
+                })?;
+        contents.push(String::from_utf8(crypto.decrypt(&encrypted)?).map_err(|_| not_text())?);
+    }
+
+    // Every line starts out credited to the oldest revision and is taken over by
+    // each later one that inserts it
+    let mut origins: Vec<usize> = vec![0; contents[0].lines().count()];
+    for (number, pair) in contents.windows(2).enumerate() {
+        let diff = TextDiff::from_lines(pair[0].as_str(), pair[1].as_str());
+        origins = diff
+            .iter_all_changes()
+            .filter_map(|change| match change.tag() {
+                ChangeTag::Equal => change.old_index().map(|old| origins[old]),
+                ChangeTag::Insert => Some(number + 1),
+                ChangeTag::Delete => None,
+            })
+            .collect();
+    }
+
+    let entries: Vec<AuditEntry> = audit::read_entries(&repo_path, &crypto)?
+        .into_iter()
+        .filter(|entry| {
+            entry.path == file.original_path && !READ_OPERATIONS.contains(&entry.operation.as_str())
+        })
+        .collect();
+
+    println!("Revisions of {}:", theme::emphasis(&file.original_path));
+    for (number, revision) in revisions.iter().enumerate() {
+        // The entry written when the revision was stored follows it most closely
+        let stored_by = entries
+            .iter()
+            .filter(|entry| entry.new_hash.as_deref() == Some(revision.hash))
+            .min_by_key(|entry| {
+                (entry.timestamp - revision.saved_at)
+                    .num_milliseconds()
+                    .abs()
+            })
+            .map(|entry| {
+                let user = entry.sudo_user.as_ref().unwrap_or(&entry.user);
+                format!("  {:<8} {}", entry.operation, user)
+            })
+            .unwrap_or_default();
+        println!(
+            "  {:>3}  {}{}{}",
+            number + 1,
+            revision
+                .saved_at
+                .with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M"),
+            stored_by,
+            if revision.current { "  (current)" } else { "" }
+        );
+    }
+    println!();
+
+    let current = contents.last().map(String::as_str).unwrap_or_default();
+    let width = current.lines().count().to_string().len();
+    for (line_number, (line, origin)) in current.lines().zip(&origins).enumerate() {
+        println!(
+            "{:>3} {} {:>width$}| {}",
+            origin + 1,
+            revisions[*origin]
+                .saved_at
+                .with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M"),
+            line_number + 1,
+            line
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,55 @@
+use crate::{
+    commands::init::KittyError,
+    repository::keys::unlock,
+    utils::{
+        file::{get_repository_path, load_repository, read_stored_files},
+        password::read_script_password,
+        select::find_by_id,
+    },
+};
+use std::{
+    io::{self, Write},
+    path::Path,
+};
+
+/// Write the repository's copy of a tracked file to stdout, exactly as stored
+///
+/// Meant for editor plugins, so the path must name one file: by its full
+/// path, the path it canonicalizes to, or an `@id`. Partial paths are refused.
+pub fn print_blob(path: &str, password_file: Option<&str>) -> Result<(), KittyError> {
+    let repo_path = get_repository_path()?;
+
+    if !repo_path.exists() {
+        return Err(KittyError::RepositoryNotFound);
+    }
+
+    let password = read_script_password(password_file)?;
+    let crypto = unlock(&repo_path, &password)?;
+    let repository = load_repository(&repo_path, &crypto)?;
+
+    let canonical = Path::new(path).canonicalize().ok();
+    let index = match find_by_id(&repository.files, path)? {
+        Some(index) => index,
+        None => repository
+            .files
+            .iter()
+            .position(|f| {
+                f.original_path == path || canonical.as_deref() == Some(Path::new(&f.original_path))
+            })
+            .ok_or_else(|| KittyError::FileNotTracked(path.to_string()))?,
+    };
+    let file = &repository.files[index];
+
+    // The batch read prints nothing, which matters when stdout is the content
+    let encrypted = read_stored_files(&repo_path, &[file.repo_path.as_str()])?
+        .remove(&file.repo_path)
+        .ok_or_else(|| KittyError::ContentMissing {
+            path: file.original_path.clone(),
+        })?;
+    let content = crypto.decrypt(&encrypted)?;
+
+    let mut stdout = io::stdout().lock();
+    stdout.write_all(&content)?;
+    stdout.flush()?;
+    Ok(())
+}
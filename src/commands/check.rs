@@ -0,0 +1,413 @@
+use crate::{
+    collectors::Collector,
+    commands::init::{Crypto, KittyError, Repository, TrackedFile},
+    repository::{
+        config::{RepoConfig, SmtpSettings},
+        keys::unlock,
+    },
+    storage::{is_database, open_database},
+    utils::{
+        events::{self, Event, Severity},
+        file::{
+            get_repository_path, get_storage_type, load_repository, read_stored, read_stored_files,
+        },
+        filter::read_filtered,
+        hash::{digest_file_like, digest_like},
+        hooks::unified_diff,
+        index,
+        mail::{self, Mail},
+        metrics::{write_check_metrics, CheckMetrics},
+        password::read_noninteractive_password,
+        platform::hostname,
+        policy::evaluate,
+    },
+};
+use serde::Serialize;
+use std::path::Path;
+
+/// Exit code when every checked file matches the repository
+pub const EXIT_OK: i32 = 0;
+/// Exit code when drift exceeds the configured threshold
+pub const EXIT_DRIFT: i32 = 1;
+/// Exit code when the check itself could not be completed
+pub const EXIT_ERROR: i32 = 2;
+/// Exit code when the repository violates its password policy and `policy.fail_check` is set
+pub const EXIT_POLICY: i32 = 3;
+
+/// Options for the check command
+#[derive(Default)]
+pub struct CheckOptions {
+    /// Only check files whose path contains this string
+    pub path: Option<String>,
+
+    /// Emit the report as JSON
+    pub json: bool,
+
+    /// Read the repository password from this file instead of KITTY_PASSWORD
+    pub password_file: Option<String>,
+
+    /// Number of drifted files tolerated before the check fails
+    pub max_drift: usize,
+
+    /// Write Prometheus textfile collector metrics to this path
+    pub metrics_file: Option<String>,
+
+    /// Mail the report to these addresses when drift is found
+    pub email_to: Vec<String>,
+}
+
+/// Drift state of a single tracked file
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DriftState {
+    /// Live file matches the stored hash
+    Clean,
+    /// Live file differs from the stored hash
+    Modified,
+    /// Live file no longer exists
+    Missing,
+    /// Live file exists but could not be read
+    Unreadable,
+    /// Live file differs, but the file is pinned to differ per machine
+    Pinned,
+}
+
+impl DriftState {
+    pub fn is_drifted(&self) -> bool {
+        !matches!(self, DriftState::Clean | DriftState::Pinned)
+    }
+
+    /// Single-letter code used in the text report
+    pub fn code(&self) -> &'static str {
+        match self {
+            DriftState::Clean => " ",
+            DriftState::Modified => "M",
+            DriftState::Missing => "D",
+            DriftState::Unreadable => "?",
+            DriftState::Pinned => "P",
+        }
+    }
+
+    /// Lowercase name, as in the JSON report
+    pub fn name(&self) -> &'static str {
+        match self {
+            DriftState::Clean => "clean",
+            DriftState::Modified => "modified",
+            DriftState::Missing => "missing",
+            DriftState::Unreadable => "unreadable",
+            DriftState::Pinned => "pinned",
+        }
+    }
+}
+
+/// Drift details for a single tracked file
+#[derive(Serialize)]
+pub struct FileDrift {
+    pub path: String,
+    pub state: DriftState,
+    pub stored_hash: String,
+    pub current_hash: Option<String>,
+    pub last_updated: String,
+}
+
+/// Result of a drift check over the repository
+#[derive(Serialize)]
+pub struct CheckReport {
+    pub checked: usize,
+    pub drifted: usize,
+    /// Drifted files whose original no longer exists
+    pub missing: usize,
+    pub max_drift: usize,
+    pub passed: bool,
+    /// Manifest generation the files were checked against
+    pub generation: u64,
+    pub files: Vec<FileDrift>,
+    /// Ways the repository violates its password policy
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub policy_violations: Vec<String>,
+}
+
+/// Compare a tracked file's live content with its stored hash
+pub fn compute_drift(file: &TrackedFile) -> FileDrift {
+    let path = Path::new(&file.original_path);
+    let is_file = Collector::parse(&file.original_path).is_none();
+
+    // Filters need the whole content; plain files are hashed straight from disk
+    let current = if is_file && file.filters.is_empty() {
+        digest_file_like(&file.hash, path).map_err(KittyError::from)
+    } else {
+        read_filtered(file).map(|content| digest_like(&file.hash, &content))
+    };
+
+    let (state, current_hash) = if is_file && !path.exists() {
+        (DriftState::Missing, None)
+    } else {
+        match current {
+            Ok(hash) => {
+                let state = if hash == file.hash {
+                    DriftState::Clean
+                } else if file.ignore_drift {
+                    DriftState::Pinned
+                } else {
+                    DriftState::Modified
+                };
+                (state, Some(hash))
+            }
+            Err(_) => (DriftState::Unreadable, None),
+        }
+    };
+
+    FileDrift {
+        path: file.original_path.clone(),
+        state,
+        stored_hash: file.hash.clone(),
+        current_hash,
+        last_updated: file.last_updated.to_rfc3339(),
+    }
+}
+
+/// The system log event for a drifted file, found by `source`
+pub fn drift_event(file: &FileDrift, source: &str) -> Event {
+    Event::new(
+        "drift",
+        Severity::Warning,
+        format!("Drift detected: {} {}", file.state.code(), file.path),
+    )
+    .field("path", &file.path)
+    .field("state", file.state.name())
+    .field("source", source)
+}
+
+/// Check tracked files for drift without ever prompting
+///
+/// Returns the process exit code: `EXIT_OK`, `EXIT_DRIFT` when more than
+/// `max_drift` files have drifted, or `EXIT_POLICY` when the password policy
+/// is violated and the repository settings make that fail the check.
+pub fn check_files(options: Option<CheckOptions>) -> Result<i32, KittyError> {
+    let options = options.unwrap_or_default();
+    let repo_path = get_repository_path()?;
+
+    if !repo_path.exists() {
+        return Err(KittyError::RepositoryNotFound);
+    }
+
+    let config = RepoConfig::load(&repo_path)?;
+    // A report that cannot be sent should fail before the check, not after it
+    let smtp = match (&config.smtp, options.email_to.is_empty()) {
+        (_, true) => None,
+        (Some(smtp), false) => Some(smtp),
+        (None, false) => {
+            return Err(KittyError::Mail(
+                "add smtp settings to .kitty/config.json to mail reports".to_string(),
+            ))
+        }
+    };
+
+    let password = read_noninteractive_password(options.password_file.as_deref())?;
+
+    // Unlock the repository key with the password
+    let crypto = unlock(&repo_path, &password)?;
+
+    let repository = load_repository(&repo_path, &crypto)?;
+
+    let files: Vec<FileDrift> = repository
+        .files
+        .iter()
+        .filter(|f| match &options.path {
+            Some(path) => f.original_path.contains(path),
+            None => true,
+        })
+        .filter(|f| f.applies_here())
+        .map(compute_drift)
+        .collect();
+
+    let _ = if options.path.is_some() {
+        index::update(&repo_path, &files)
+    } else {
+        index::replace(&repo_path, &files)
+    };
+
+    let policy = &config.policy;
+    let violations = evaluate(policy, &repository).violations;
+    let policy_failed = policy.fail_check && !violations.is_empty();
+
+    let drifted = files.iter().filter(|f| f.state.is_drifted()).count();
+    let missing = files
+        .iter()
+        .filter(|f| f.state == DriftState::Missing)
+        .count();
+    let report = CheckReport {
+        checked: files.len(),
+        drifted,
+        missing,
+        max_drift: options.max_drift,
+        passed: drifted <= options.max_drift && !policy_failed,
+        generation: repository.generation,
+        files,
+        policy_violations: violations,
+    };
+
+    let mut logged: Vec<Event> = report
+        .files
+        .iter()
+        .filter(|f| f.state.is_drifted())
+        .map(|f| drift_event(f, "check"))
+        .collect();
+    logged.push(
+        Event::new(
+            "check",
+            if report.passed {
+                Severity::Info
+            } else {
+                Severity::Warning
+            },
+            format!(
+                "Checked {} file(s), {} drifted",
+                report.checked, report.drifted
+            ),
+        )
+        .field("checked", report.checked)
+        .field("drifted", report.drifted)
+        .field("missing", report.missing)
+        .field("passed", report.passed),
+    );
+    events::emit(&repo_path, &logged);
+
+    if let Some(metrics_file) = &options.metrics_file {
+        let metrics = CheckMetrics {
+            tracked_files: repository.files.len(),
+            drifted_files: report.drifted,
+            missing_files: report.missing,
+            passed: report.passed,
+        };
+        write_check_metrics(Path::new(metrics_file), &repo_path, &metrics)?;
+    }
+
+    if options.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        for file in report.files.iter().filter(|f| f.state.is_drifted()) {
+            println!("{} {}", file.state.code(), file.path);
+        }
+        for violation in &report.policy_violations {
+            println!("POLICY: {}", violation);
+        }
+        let missing = if report.missing > 0 {
+            format!(" ({} missing)", report.missing)
+        } else {
+            String::new()
+        };
+        println!(
+            "{}: {} checked, {} drifted{} (threshold {})",
+            if report.passed {
+                "OK"
+            } else if drifted > options.max_drift {
+                "DRIFT"
+            } else {
+                "POLICY"
+            },
+            report.checked,
+            report.drifted,
+            missing,
+            report.max_drift
+        );
+    }
+
+    if let Some(smtp) = smtp.filter(|_| report.drifted > 0) {
+        email_report(
+            &repo_path,
+            &crypto,
+            &repository,
+            &report,
+            smtp,
+            &options.email_to,
+        )?;
+        if !options.json {
+            println!("Report mailed to {}", options.email_to.join(", "));
+        }
+    }
+
+    Ok(if drifted > options.max_drift {
+        EXIT_DRIFT
+    } else if policy_failed {
+        EXIT_POLICY
+    } else {
+        EXIT_OK
+    })
+}
+
+/// Mail a drift summary, with a diff attached for every modified file
+///
+/// Missing and unreadable files are only listed, as are binary and very large ones.
+fn email_report(
+    repo_path: &Path,
+    crypto: &Crypto,
+    repository: &Repository,
+    report: &CheckReport,
+    smtp: &SmtpSettings,
+    to: &[String],
+) -> Result<(), KittyError> {
+    let host = hostname();
+    let drifted: Vec<&FileDrift> = report
+        .files
+        .iter()
+        .filter(|f| f.state.is_drifted())
+        .collect();
+
+    let mut body = format!(
+        "kitty found {} drifted file(s) on {} in {}:\n\n",
+        report.drifted,
+        host,
+        repo_path.display()
+    );
+    for file in &drifted {
+        body.push_str(&format!("{} {}\n", file.state.code(), file.path));
+    }
+    for violation in &report.policy_violations {
+        body.push_str(&format!("POLICY: {}\n", violation));
+    }
+    body.push_str(&format!(
+        "\n{} checked, {} drifted, {} missing (threshold {})\n",
+        report.checked, report.drifted, report.missing, report.max_drift
+    ));
+
+    let modified: Vec<&TrackedFile> = drifted
+        .iter()
+        .filter(|f| f.state == DriftState::Modified)
+        .filter_map(|f| repository.files.iter().find(|t| t.original_path == f.path))
+        .collect();
+    let repo_paths: Vec<&str> = modified.iter().map(|f| f.repo_path.as_str()).collect();
+    let mut stored = read_stored_files(repo_path, &repo_paths)?;
+    let storage_type = get_storage_type(repo_path)?;
+
+    let mut attachments = Vec::new();
+    for file in &modified {
+        let encrypted = match stored.remove(&file.repo_path) {
+            Some(encrypted) => encrypted,
+            None if is_database(&storage_type) => {
+                open_database(repo_path)?.get_file(&file.repo_path)?
+            }
+            None => read_stored(&repo_path.join(&file.repo_path))?,
+        };
+        let Ok(live) = read_filtered(file) else {
+            continue;
+        };
+        if let Some(diff) = unified_diff(&file.original_path, &crypto.decrypt(&encrypted)?, &live) {
+            let name = file.original_path.trim_start_matches('/').replace('/', "_");
+            attachments.push((format!("{}.diff", name), diff));
+        }
+    }
+    if attachments.len() < modified.len() {
+        body.push_str("\nBinary and large files have no diff attached.\n");
+    }
+
+    mail::send(
+        smtp,
+        to,
+        Mail {
+            subject: format!("kitty: {} drifted file(s) on {}", report.drifted, host),
+            body,
+            attachments,
+        },
+    )
+}
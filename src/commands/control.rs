@@ -0,0 +1,93 @@
+use crate::{
+    commands::init::KittyError,
+    utils::{file::get_repository_path, theme},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    io::{Read, Write},
+    net::Shutdown,
+    os::unix::net::UnixStream,
+};
+
+/// Socket in the repository directory through which the CLI talks to a running daemon
+pub const CONTROL_SOCKET: &str = "daemon.sock";
+
+/// A request on the control socket: one line of JSON, answered by one line of JSON
+///
+/// Answers carry `"ok": true` and the daemon's status, or `"ok": false` and an `error`.
+#[derive(Serialize, Deserialize)]
+pub struct ControlRequest {
+    /// `status`, `pause`, `resume` or `flush`
+    pub command: String,
+}
+
+/// Send a command to the daemon watching this repository and print its answer
+pub fn control_daemon(command: &str, json: bool) -> Result<(), KittyError> {
+    let repo_path = get_repository_path()?;
+    if !repo_path.exists() {
+        return Err(KittyError::RepositoryNotFound);
+    }
+
+    let socket_path = repo_path.join(CONTROL_SOCKET);
+    let mut stream = UnixStream::connect(&socket_path).map_err(|_| {
+        KittyError::Daemon(format!(
+            "No daemon is running for this repository ({} cannot be reached)",
+            socket_path.display()
+        ))
+    })?;
+
+    let request = ControlRequest {
+        command: command.to_string(),
+    };
+    writeln!(stream, "{}", serde_json::to_string(&request)?)?;
+    stream.shutdown(Shutdown::Write)?;
+
+    let mut answer = String::new();
+    stream.read_to_string(&mut answer)?;
+    let answer: Value = serde_json::from_str(answer.trim())?;
+
+    if answer["ok"] != Value::Bool(true) {
+        let error = answer["error"].as_str().unwrap_or("no reason given");
+        return Err(KittyError::Daemon(error.to_string()));
+    }
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&answer)?);
+        return Ok(());
+    }
+
+    match command {
+        "pause" => println!("Daemon paused; run `kitty daemon resume` to continue"),
+        "resume" => println!("Daemon resumed"),
+        _ => {}
+    }
+    print_status(&answer);
+    Ok(())
+}
+
+fn print_status(status: &Value) {
+    let paused = status["paused"].as_bool().unwrap_or(false);
+    println!(
+        "{} last check {}, {} checked, {} drifted",
+        if paused {
+            theme::warning("Paused:")
+        } else {
+            theme::success("Running:")
+        },
+        status["last_check"].as_str().unwrap_or("never"),
+        status["checked"].as_u64().unwrap_or(0),
+        status["drifted"].as_u64().unwrap_or(0)
+    );
+    if let Some(next) = status["next_snapshot"].as_str() {
+        println!("Next scheduled snapshot at {}", next);
+    }
+
+    for file in status["files"].as_array().into_iter().flatten() {
+        let state = file["state"].as_str().unwrap_or_default();
+        if matches!(state, "clean" | "pinned") {
+            continue;
+        }
+        println!("{} {}", state, file["path"].as_str().unwrap_or_default());
+    }
+}
@@ -0,0 +1,668 @@
+use crate::{
+    commands::{
+        add::{add_file_with_password, update_file_as},
+        check::{compute_drift, drift_event, DriftState, FileDrift},
+        control::{ControlRequest, CONTROL_SOCKET},
+        init::{Crypto, KittyError, Repository},
+        prune::prune_with_password,
+        restore::{restore_files_with_password, RestoreOptions},
+    },
+    repository::{config::RepoConfig, keys::unlock_admin},
+    utils::{
+        events,
+        file::{get_repository_path, load_repository, write_private},
+        index,
+        order::refers_to,
+        password::read_noninteractive_password,
+        watch::FileWatcher,
+    },
+};
+use chrono::{DateTime, Local, Utc};
+use croner::Cron;
+use rand::{rngs::OsRng, Rng};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{
+    fs,
+    io::{BufRead, BufReader, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+/// Environment variable holding the API token
+pub const TOKEN_ENV: &str = "KITTY_DAEMON_TOKEN";
+
+/// File inside the repository where a generated API token is stored
+const TOKEN_FILE: &str = "daemon.token";
+
+/// Longest wait for an API request, since file events and control commands cannot wake the server
+const POLL: Duration = Duration::from_millis(200);
+
+/// Options for the daemon command
+pub struct DaemonOptions {
+    /// Address for the HTTP API to listen on
+    pub listen: String,
+
+    /// Serve the API on a unix socket instead of TCP
+    pub socket: Option<String>,
+
+    /// Seconds between drift checks of the tracked files
+    pub interval: u64,
+
+    /// Read the repository password from this file instead of KITTY_PASSWORD
+    pub password_file: Option<String>,
+
+    /// Cron expression for automatic snapshots, overriding the repository setting
+    pub snapshot_schedule: Option<String>,
+
+    /// Check files as soon as they change instead of only on the interval
+    pub watch: bool,
+}
+
+impl Default for DaemonOptions {
+    fn default() -> Self {
+        Self {
+            listen: "127.0.0.1:7373".to_string(),
+            socket: None,
+            interval: 30,
+            password_file: None,
+            snapshot_schedule: None,
+            watch: true,
+        }
+    }
+}
+
+/// State the daemon keeps between drift checks
+struct DaemonState {
+    password: String,
+    crypto: Crypto,
+    repository: Repository,
+    drift: Vec<FileDrift>,
+    last_check: DateTime<Utc>,
+    snapshots: Option<SnapshotTimer>,
+    watcher: Option<FileWatcher>,
+
+    /// Set by `kitty daemon pause`: no checks, auto-adds or snapshots until resumed
+    paused: bool,
+}
+
+/// When the next scheduled snapshot is due
+struct SnapshotTimer {
+    cron: Cron,
+    prune: bool,
+    next: DateTime<Local>,
+}
+
+impl SnapshotTimer {
+    fn new(expression: &str, prune: bool) -> Result<Self, KittyError> {
+        let invalid = |e: croner::errors::CronError| {
+            KittyError::Daemon(format!("Invalid snapshot schedule '{}': {}", expression, e))
+        };
+        let cron: Cron = expression.parse().map_err(invalid)?;
+        let next = cron
+            .find_next_occurrence(&Local::now(), false)
+            .map_err(invalid)?;
+        Ok(Self { cron, prune, next })
+    }
+
+    /// Time left until the snapshot is due
+    fn remaining(&self) -> Duration {
+        (self.next - Local::now()).to_std().unwrap_or_default()
+    }
+
+    fn advance(&mut self) -> Result<(), KittyError> {
+        self.next = self
+            .cron
+            .find_next_occurrence(&Local::now(), false)
+            .map_err(|e| KittyError::Daemon(e.to_string()))?;
+        Ok(())
+    }
+}
+
+impl DaemonState {
+    /// Reload the manifest and recompute drift, logging files whose state changed
+    ///
+    /// Modified files covered by the `auto_add` setting are stored right away.
+    fn refresh(&mut self, repo_path: &Path) -> Result<(), KittyError> {
+        self.repository = load_repository(repo_path, &self.crypto)?;
+        let mut drift: Vec<FileDrift> = self
+            .repository
+            .files
+            .iter()
+            .filter(|f| f.applies_here())
+            .map(compute_drift)
+            .collect();
+
+        if self.auto_add(repo_path, &drift) > 0 {
+            self.repository = load_repository(repo_path, &self.crypto)?;
+            drift = self
+                .repository
+                .files
+                .iter()
+                .filter(|f| f.applies_here())
+                .map(compute_drift)
+                .collect();
+        }
+
+        let mut detected = Vec::new();
+        for file in &drift {
+            let previous = self.drift.iter().find(|d| d.path == file.path);
+            if previous.map(|p| p.state) != Some(file.state) && file.state.is_drifted() {
+                println!(
+                    "[{}] Drift detected: {} {}",
+                    Utc::now().to_rfc3339(),
+                    file.state.code(),
+                    file.path
+                );
+                detected.push(drift_event(file, "daemon"));
+            }
+        }
+        events::emit(repo_path, &detected);
+
+        let _ = index::replace(repo_path, &drift);
+        self.drift = drift;
+        self.last_check = Utc::now();
+
+        // Follow files added or removed since the last check
+        if let Some(watcher) = &mut self.watcher {
+            watcher.track(&self.repository.files);
+        }
+        Ok(())
+    }
+
+    /// Store modified files the `auto_add` setting covers, returning how many were stored
+    fn auto_add(&self, repo_path: &Path, drift: &[FileDrift]) -> usize {
+        let policy = match RepoConfig::load(repo_path) {
+            Ok(config) => config.auto_add,
+            Err(e) => {
+                println!("Could not read the auto-add policy: {}", e);
+                return 0;
+            }
+        };
+        if policy.is_empty() {
+            return 0;
+        }
+
+        let mut stored = 0;
+        // Drift only covers the files that apply here, so it is matched to files by path
+        for drift in drift.iter().filter(|d| d.state == DriftState::Modified) {
+            let files = &self.repository.files;
+            let Some(file) = files.iter().find(|f| f.original_path == drift.path) else {
+                continue;
+            };
+            if !policy.iter().any(|entry| refers_to(entry, file)) {
+                continue;
+            }
+            match update_file_as(&file.original_path, &self.password, "auto-add") {
+                Ok(()) => {
+                    println!(
+                        "[{}] Auto-added: {}",
+                        Utc::now().to_rfc3339(),
+                        file.original_path
+                    );
+                    stored += 1;
+                }
+                Err(e) => println!("Auto-add of {} failed: {}", file.original_path, e),
+            }
+        }
+        stored
+    }
+}
+
+/// Body accepted by the snapshot and restore endpoints
+#[derive(Deserialize, Default)]
+struct PathRequest {
+    path: Option<String>,
+}
+
+#[derive(Serialize)]
+struct TrackedFileView<'a> {
+    path: &'a str,
+    hash: &'a str,
+    added_at: String,
+    last_updated: String,
+}
+
+/// Run the watch daemon with its local control API
+pub fn run_daemon(options: Option<DaemonOptions>) -> Result<(), KittyError> {
+    let options = options.unwrap_or_default();
+    let repo_path = get_repository_path()?;
+
+    if !repo_path.exists() {
+        return Err(KittyError::RepositoryNotFound);
+    }
+
+    // The daemon runs unattended, so the password must come from the environment or a file
+    let password = read_noninteractive_password(options.password_file.as_deref())?;
+    let crypto = unlock_admin(&repo_path, &password)?;
+    let repository = load_repository(&repo_path, &crypto)?;
+
+    let token = load_or_create_token(&repo_path)?;
+    let control = bind_control_socket(&repo_path)?;
+
+    // Stop between requests on Ctrl-C or SIGTERM so the control socket is removed
+    let stopping = Arc::new(AtomicBool::new(false));
+    for signal in [signal_hook::consts::SIGINT, signal_hook::consts::SIGTERM] {
+        signal_hook::flag::register(signal, Arc::clone(&stopping))?;
+    }
+
+    // The flag overrides the schedule from the repository settings
+    let config = RepoConfig::load(&repo_path)?;
+    let settings = config.snapshots;
+    let snapshots = match options
+        .snapshot_schedule
+        .as_ref()
+        .or(settings.schedule.as_ref())
+    {
+        Some(expression) => Some(SnapshotTimer::new(expression, settings.prune)?),
+        None => None,
+    };
+    let watcher = if options.watch {
+        Some(FileWatcher::new(&config.watch)?)
+    } else {
+        None
+    };
+
+    let server = match &options.socket {
+        Some(socket) => {
+            // A stale socket from a previous run would make binding fail
+            let _ = fs::remove_file(socket);
+            Server::http_unix(Path::new(socket))
+        }
+        None => Server::http(&options.listen),
+    }
+    .map_err(|e| KittyError::Daemon(e.to_string()))?;
+
+    match &options.socket {
+        Some(socket) => println!("kitty daemon listening on unix socket {}", socket),
+        None => println!("kitty daemon listening on http://{}", options.listen),
+    }
+    println!(
+        "Checking {} tracked file(s) every {}s{}",
+        repository.files.len(),
+        options.interval,
+        if watcher.is_some() {
+            " and when they change"
+        } else {
+            ""
+        }
+    );
+    if let Some(timer) = &snapshots {
+        println!(
+            "Next scheduled snapshot at {}",
+            timer.next.format("%Y-%m-%d %H:%M:%S")
+        );
+    }
+
+    let mut state = DaemonState {
+        password,
+        crypto,
+        repository,
+        drift: Vec::new(),
+        last_check: Utc::now(),
+        snapshots,
+        watcher,
+        paused: false,
+    };
+    state.refresh(&repo_path)?;
+
+    let interval = Duration::from_secs(options.interval.max(1));
+    let mut next_check = Instant::now() + interval;
+
+    while !stopping.load(Ordering::Relaxed) {
+        let mut timeout = POLL;
+        if !state.paused {
+            timeout = timeout.min(next_check.saturating_duration_since(Instant::now()));
+            if let Some(timer) = &state.snapshots {
+                timeout = timeout.min(timer.remaining());
+            }
+            if let Some(due) = state.watcher.as_mut().and_then(FileWatcher::next_due) {
+                timeout = timeout.min(due);
+            }
+        }
+        match server.recv_timeout(timeout) {
+            Ok(Some(request)) => handle_request(request, &mut state, &repo_path, &token),
+            Ok(None) => {}
+            Err(e) => println!("Failed to receive request: {}", e),
+        }
+
+        while let Ok((stream, _)) = control.accept() {
+            handle_control(stream, &mut state, &repo_path);
+        }
+
+        // Work that falls due during a pause is done on resume
+        if state.paused {
+            continue;
+        }
+
+        // One check per logical save, however many events the editor caused
+        let changed = state
+            .watcher
+            .as_mut()
+            .map(FileWatcher::settled)
+            .unwrap_or_default();
+        if !changed.is_empty() || Instant::now() >= next_check {
+            if let Err(e) = state.refresh(&repo_path) {
+                println!("Drift check failed: {}", e);
+            }
+            next_check = Instant::now() + interval;
+        }
+
+        if state
+            .snapshots
+            .as_ref()
+            .is_some_and(|timer| timer.remaining().is_zero())
+        {
+            scheduled_snapshot(&mut state, &repo_path);
+        }
+    }
+
+    let _ = fs::remove_file(repo_path.join(CONTROL_SOCKET));
+    if let Some(socket) = &options.socket {
+        let _ = fs::remove_file(socket);
+    }
+    println!("kitty daemon stopped");
+    Ok(())
+}
+
+/// Capture every modified file, then apply the retention policy if the schedule asks for it
+fn scheduled_snapshot(state: &mut DaemonState, repo_path: &Path) {
+    if let Err(e) = state.refresh(repo_path) {
+        println!("Drift check failed: {}", e);
+    } else {
+        let (_, result) = snapshot(state, repo_path, None);
+        let captured = result["captured"].as_array().map_or(0, Vec::len);
+        println!(
+            "[{}] Scheduled snapshot captured {} file(s)",
+            Utc::now().to_rfc3339(),
+            captured
+        );
+        for error in result["errors"].as_array().into_iter().flatten() {
+            println!("Scheduled snapshot failed: {}", error);
+        }
+
+        let prune = state.snapshots.as_ref().is_some_and(|timer| timer.prune);
+        if prune {
+            match RepoConfig::load(repo_path) {
+                Ok(config) if !config.retention.is_empty() => {
+                    if let Err(e) = prune_with_password(&config.retention, false, &state.password) {
+                        println!("Pruning after the snapshot failed: {}", e);
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => println!("Pruning after the snapshot failed: {}", e),
+            }
+        }
+    }
+
+    if let Some(timer) = &mut state.snapshots {
+        if let Err(e) = timer.advance() {
+            println!("No further snapshots scheduled: {}", e);
+            state.snapshots = None;
+        }
+    }
+}
+
+/// Listen on the control socket, which only the repository owner can connect to
+fn bind_control_socket(repo_path: &Path) -> Result<UnixListener, KittyError> {
+    let socket_path = repo_path.join(CONTROL_SOCKET);
+    if socket_path.exists() {
+        if UnixStream::connect(&socket_path).is_ok() {
+            return Err(KittyError::Daemon(
+                "A daemon is already running for this repository".to_string(),
+            ));
+        }
+        // Left behind by a daemon that did not shut down cleanly
+        fs::remove_file(&socket_path)?;
+    }
+
+    let listener = UnixListener::bind(&socket_path)?;
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&socket_path, fs::Permissions::from_mode(0o600))?;
+    }
+    listener.set_nonblocking(true)?;
+    Ok(listener)
+}
+
+/// Answer one command on the control socket
+fn handle_control(stream: UnixStream, state: &mut DaemonState, repo_path: &Path) {
+    // A client that never finishes its request must not stall the daemon
+    let _ = stream.set_nonblocking(false);
+    let _ = stream.set_read_timeout(Some(Duration::from_secs(1)));
+
+    let mut line = String::new();
+    let result = BufReader::new(&stream)
+        .read_line(&mut line)
+        .map_err(KittyError::from)
+        .and_then(|_| Ok(serde_json::from_str::<ControlRequest>(line.trim())?))
+        .and_then(|request| control_command(state, repo_path, &request.command));
+
+    let answer = match result {
+        Ok(()) => {
+            let mut status = status_payload(state);
+            status["ok"] = json!(true);
+            status
+        }
+        Err(KittyError::Daemon(message)) => json!({ "ok": false, "error": message }),
+        Err(e) => json!({ "ok": false, "error": e.to_string() }),
+    };
+    let _ = writeln!(&stream, "{}", answer);
+}
+
+fn control_command(
+    state: &mut DaemonState,
+    repo_path: &Path,
+    command: &str,
+) -> Result<(), KittyError> {
+    match command {
+        "status" => Ok(()),
+        "pause" => {
+            state.paused = true;
+            println!("[{}] Paused", Utc::now().to_rfc3339());
+            Ok(())
+        }
+        "resume" => {
+            state.paused = false;
+            println!("[{}] Resumed", Utc::now().to_rfc3339());
+            state.refresh(repo_path)
+        }
+        "flush" => {
+            if let Some(watcher) = &mut state.watcher {
+                watcher.take_all();
+            }
+            state.refresh(repo_path)
+        }
+        other => Err(KittyError::Daemon(format!("Unknown command '{}'", other))),
+    }
+}
+
+/// Use the token from the environment, or the one stored in the repository, generating it if needed
+fn load_or_create_token(repo_path: &Path) -> Result<String, KittyError> {
+    if let Ok(token) = std::env::var(TOKEN_ENV) {
+        if !token.is_empty() {
+            return Ok(token);
+        }
+    }
+
+    let token_path = repo_path.join(TOKEN_FILE);
+    if token_path.exists() {
+        return Ok(fs::read_to_string(&token_path)?.trim().to_string());
+    }
+
+    let mut bytes = [0u8; 32];
+    OsRng.fill(&mut bytes);
+    let token = hex::encode(bytes);
+    write_private(&token_path, &token)?;
+
+    println!("Generated API token in {}", token_path.display());
+    Ok(token)
+}
+
+/// Compare tokens without leaking how many leading bytes matched
+fn token_matches(provided: &str, expected: &str) -> bool {
+    provided.len() == expected.len()
+        && provided
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+fn is_authorized(request: &Request, token: &str) -> bool {
+    request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv("Authorization"))
+        .and_then(|h| h.value.as_str().strip_prefix("Bearer "))
+        .map(|provided| token_matches(provided.trim(), token))
+        .unwrap_or(false)
+}
+
+fn handle_request(mut request: Request, state: &mut DaemonState, repo_path: &Path, token: &str) {
+    if !is_authorized(&request, token) {
+        respond(request, 401, json!({ "error": "unauthorized" }));
+        return;
+    }
+
+    let mut body = String::new();
+    let _ = request.as_reader().read_to_string(&mut body);
+    let path_request: PathRequest = if body.trim().is_empty() {
+        PathRequest::default()
+    } else {
+        match serde_json::from_str(&body) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                respond(request, 400, json!({ "error": e.to_string() }));
+                return;
+            }
+        }
+    };
+
+    let url = request.url().split('?').next().unwrap_or("").to_string();
+    let (status, payload) = match (request.method(), url.as_str()) {
+        (Method::Get, "/files") => {
+            let files: Vec<TrackedFileView> = state
+                .repository
+                .files
+                .iter()
+                .map(|f| TrackedFileView {
+                    path: &f.original_path,
+                    hash: &f.hash,
+                    added_at: f.added_at.to_rfc3339(),
+                    last_updated: f.last_updated.to_rfc3339(),
+                })
+                .collect();
+            (200, json!({ "files": files }))
+        }
+        (Method::Get, "/status") => (200, status_payload(state)),
+        (Method::Post, "/snapshot") => snapshot(state, repo_path, path_request.path),
+        (Method::Post, "/restore") => restore(state, repo_path, path_request.path),
+        _ => (404, json!({ "error": "not found" })),
+    };
+
+    respond(request, status, payload);
+}
+
+fn status_payload(state: &DaemonState) -> serde_json::Value {
+    let drifted = state.drift.iter().filter(|d| d.state.is_drifted()).count();
+    json!({
+        "checked": state.drift.len(),
+        "drifted": drifted,
+        "last_check": state.last_check.to_rfc3339(),
+        "paused": state.paused,
+        "next_snapshot": state.snapshots.as_ref().map(|timer| timer.next.to_rfc3339()),
+        "files": state.drift,
+    })
+}
+
+/// Capture the current content of drifted files (or one given path) into the repository
+fn snapshot(
+    state: &mut DaemonState,
+    repo_path: &Path,
+    path: Option<String>,
+) -> (u16, serde_json::Value) {
+    let targets: Vec<String> = match path {
+        Some(path) => vec![path],
+        None => state
+            .drift
+            .iter()
+            .filter(|d| d.state == DriftState::Modified)
+            .map(|d| d.path.clone())
+            .collect(),
+    };
+
+    let mut captured = Vec::new();
+    let mut errors = Vec::new();
+    for target in targets {
+        match add_file_with_password(&target, &state.password) {
+            Ok(()) => captured.push(target),
+            Err(e) => errors.push(json!({ "path": target, "error": e.to_string() })),
+        }
+    }
+
+    if let Err(e) = state.refresh(repo_path) {
+        errors.push(json!({ "error": e.to_string() }));
+    }
+
+    let status = if errors.is_empty() { 200 } else { 500 };
+    (status, json!({ "captured": captured, "errors": errors }))
+}
+
+/// Restore one tracked file from the repository
+fn restore(
+    state: &mut DaemonState,
+    repo_path: &Path,
+    path: Option<String>,
+) -> (u16, serde_json::Value) {
+    let path = match path {
+        Some(path) => path,
+        None => return (400, json!({ "error": "a path is required" })),
+    };
+
+    let options = RestoreOptions {
+        path: Some(path.clone()),
+        force: true,
+        dry_run: false,
+        backup: Some(true),
+        packages: false,
+        patch: false,
+        preserve_times: false,
+        link: false,
+        json: false,
+        all: false,
+        regex: false,
+        role: None,
+        skip_unchanged: false,
+        render: false,
+        profile: None,
+        missing_only: false,
+        plan: None,
+        permissions: false,
+    };
+    let result = restore_files_with_password(options, &state.password);
+    let _ = state.refresh(repo_path);
+
+    match result {
+        Ok(report) if report.errors.is_empty() => (200, json!({ "restored": path })),
+        Ok(report) => (500, json!({ "path": path, "errors": report.errors })),
+        Err(e) => (500, json!({ "path": path, "error": e.to_string() })),
+    }
+}
+
+fn respond(request: Request, status: u16, payload: serde_json::Value) {
+    let header = Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..])
+        .expect("static header is valid");
+    let response = Response::from_string(payload.to_string())
+        .with_status_code(status)
+        .with_header(header);
+    if let Err(e) = request.respond(response) {
+        println!("Failed to send response: {}", e);
+    }
+}
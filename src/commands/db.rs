@@ -0,0 +1,198 @@
+use crate::{
+    commands::init::KittyError,
+    repository::keys::{has_read_key, sign_manifest, unlock_admin},
+    storage::{sqlite::SqliteStorage, Storage},
+    tr,
+    utils::{
+        file::{get_repository_path, get_repository_salt, get_storage_type},
+        i18n::confirm,
+        lock::RepoLock,
+        password::read_repository_password,
+        theme,
+    },
+};
+use std::{fs, path::Path};
+
+/// Open the repository's SQLite database, failing for file-based repositories
+fn open_storage() -> Result<SqliteStorage, KittyError> {
+    let repo_path = get_repository_path()?;
+
+    if !repo_path.exists() {
+        return Err(KittyError::RepositoryNotFound);
+    }
+
+    if get_storage_type(&repo_path)? != "sqlite" {
+        return Err(KittyError::Database(
+            "This repository does not use SQLite storage".to_string(),
+        ));
+    }
+
+    SqliteStorage::new(&repo_path)
+}
+
+/// Vacuum an open database, returning the number of bytes reclaimed
+pub fn vacuum_storage(storage: &SqliteStorage) -> Result<u64, KittyError> {
+    let before = storage.database_size()?;
+    storage.vacuum()?;
+    let after = storage.database_size()?;
+    Ok(before.saturating_sub(after))
+}
+
+/// Rebuild the SQLite database to reclaim unused space
+pub fn vacuum_database() -> Result<(), KittyError> {
+    let storage = open_storage()?;
+    let before = storage.database_size()?;
+    let reclaimed = vacuum_storage(&storage)?;
+
+    println!(
+        "{} Database vacuumed: {} -> {} bytes ({} bytes reclaimed)",
+        theme::success("SUCCESS:"),
+        before,
+        before - reclaimed,
+        reclaimed
+    );
+
+    Ok(())
+}
+
+/// Verify the integrity of the SQLite database
+pub fn check_database() -> Result<(), KittyError> {
+    let storage = open_storage()?;
+    let problems = storage.integrity_check()?;
+
+    if problems.is_empty() {
+        println!(
+            "{} Database integrity check passed",
+            theme::success("SUCCESS:")
+        );
+        return Ok(());
+    }
+
+    for problem in &problems {
+        println!("{} {}", theme::error("ERROR:"), problem);
+    }
+
+    Err(KittyError::Database(format!(
+        "Integrity check found {} problem(s)",
+        problems.len()
+    )))
+}
+
+/// Write a consistent copy of the SQLite database to `path`
+pub fn backup_database(path: &str) -> Result<(), KittyError> {
+    let storage = open_storage()?;
+    let backup_path = Path::new(path);
+
+    if backup_path.exists() {
+        return Err(KittyError::Database(format!(
+            "Backup target already exists: {}",
+            path
+        )));
+    }
+
+    // VACUUM INTO reads a single snapshot, so the copy is consistent even while kitty is in use
+    storage.backup_to(backup_path)?;
+
+    println!(
+        "{} Database backed up to {} ({} bytes)",
+        theme::success("SUCCESS:"),
+        path,
+        fs::metadata(backup_path)?.len()
+    );
+
+    Ok(())
+}
+
+/// Replace the SQLite database with a backup made by `kitty db backup`
+pub fn restore_database(path: &str, force: bool) -> Result<(), KittyError> {
+    let repo_path = get_repository_path()?;
+    // Make sure this is an SQLite repository before touching anything
+    drop(open_storage()?);
+
+    let backup_path = Path::new(path);
+    if !backup_path.exists() {
+        return Err(KittyError::Database(format!("Backup not found: {}", path)));
+    }
+
+    // Work on a copy so a bad backup never replaces the live database
+    let db_path = repo_path.join("kitty.db");
+    let staged_path = repo_path.join("kitty.db.restore");
+    fs::copy(backup_path, &staged_path)?;
+
+    let verified = verify_backup(&staged_path, &get_repository_salt(&repo_path)?);
+    if let Err(e) = verified {
+        let _ = fs::remove_file(&staged_path);
+        return Err(e);
+    }
+
+    // The restored manifest has to be signed again, which takes the admin password
+    let admin = if has_read_key(&repo_path) {
+        let password = read_repository_password(false)?;
+        match unlock_admin(&repo_path, &password) {
+            Ok(crypto) => Some(crypto),
+            Err(e) => {
+                let _ = fs::remove_file(&staged_path);
+                return Err(e);
+            }
+        }
+    } else {
+        None
+    };
+
+    if !force {
+        println!(
+            "{}",
+            tr!(
+                "db-restore-confirm",
+                database = db_path.display().to_string(),
+                backup = theme::emphasis(path).to_string()
+            )
+        );
+        if !confirm()? {
+            let _ = fs::remove_file(&staged_path);
+            println!("{}", tr!("restore-canceled"));
+            return Ok(());
+        }
+    }
+
+    let _lock = RepoLock::acquire(&repo_path)?;
+
+    // Keep the database being replaced, in case the backup was the wrong one
+    let previous_path = repo_path.join("kitty.db.bak");
+    fs::copy(&db_path, &previous_path)?;
+    fs::rename(&staged_path, &db_path)?;
+    if let Some(crypto) = &admin {
+        sign_manifest(&repo_path, crypto)?;
+    }
+
+    println!(
+        "{} Database restored from {} (previous database kept at {})",
+        theme::success("SUCCESS:"),
+        path,
+        previous_path.display()
+    );
+
+    Ok(())
+}
+
+/// Check that a backup is a healthy database belonging to this repository
+fn verify_backup(db_path: &Path, salt: &str) -> Result<(), KittyError> {
+    let storage = SqliteStorage::open(db_path)?;
+
+    let problems = storage.integrity_check()?;
+    if !problems.is_empty() {
+        return Err(KittyError::Database(format!(
+            "Backup failed the integrity check: {}",
+            problems.join("; ")
+        )));
+    }
+
+    // A backup from another repository was encrypted with a different key
+    if storage.get_salt()?.trim() != salt.trim() {
+        return Err(KittyError::Database(
+            "Backup belongs to a different repository (salt mismatch)".to_string(),
+        ));
+    }
+
+    Ok(())
+}
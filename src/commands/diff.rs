@@ -1,15 +1,20 @@
 use crate::{
-    commands::init::{Crypto, KittyError, Repository, TrackedFile},
-    utils::file::{get_repository_path, get_repository_salt, get_storage_type},
+    commands::init::{Crypto, KittyError, TrackedFile},
+    repository::{history::revision_at, keys::unlock},
+    storage::{is_database, open_database},
+    tr,
+    utils::{
+        file::{get_repository_path, get_storage_type, load_repository, read_stored},
+        filter::{apply_filters, read_filtered},
+        package::fetch_pristine_content,
+        password::read_repository_password,
+        select::{load_candidates, select_tracked},
+        theme,
+    },
 };
-use colored::Colorize;
-use rpassword::read_password;
+use chrono::{DateTime, Utc};
 use similar::{ChangeTag, TextDiff};
-use std::{
-    fs,
-    io::{self, Write},
-    path::Path,
-};
+use std::{fs, io, path::Path};
 
 /// Options for the diff command
 pub struct DiffOptions {
@@ -27,6 +32,21 @@ pub struct DiffOptions {
 
     /// Number of context lines to show (when context is true)
     pub context_lines: usize,
+
+    /// Compare the live file against the owning package's pristine version
+    pub against_package: bool,
+
+    /// Diff every file the path matches when it matches several
+    pub all: bool,
+
+    /// Treat the path as a regular expression
+    pub regex: bool,
+
+    /// Compare the stored copy against this file instead of the tracked path
+    pub with: Option<String>,
+
+    /// Compare with the content stored at this time instead of the latest
+    pub at: Option<DateTime<Utc>>,
 }
 
 impl Default for DiffOptions {
@@ -37,17 +57,22 @@ impl Default for DiffOptions {
             summary: false,
             context: false,
             context_lines: 3,
+            against_package: false,
+            all: false,
+            regex: false,
+            with: None,
+            at: None,
         }
     }
 }
 
 /// Holds the result of a diff operation
-struct DiffResult {
-    path: String,
-    has_changes: bool,
-    additions: usize,
-    deletions: usize,
-    diff_text: String,
+pub struct DiffResult {
+    pub path: String,
+    pub has_changes: bool,
+    pub additions: usize,
+    pub deletions: usize,
+    pub diff_text: String,
 }
 
 /// Perform diff on a single file
@@ -57,12 +82,23 @@ fn diff_single_file(
     file: &TrackedFile,
     options: &DiffOptions,
 ) -> Result<DiffResult, KittyError> {
-    // Get the original file path
-    let file_path = Path::new(&file.original_path);
+    // A file named with --with was asked for, so failing to read it is an error
+    if let Some(other) = &options.with {
+        let content =
+            fs::read(other).map_err(|e| io::Error::new(e.kind(), format!("{}: {}", other, e)))?;
+        let content = apply_filters(&content, &file.filters)?;
+        let stored = read_stored_content(repo_path, crypto, stored_path(file, options)?)?;
+        return Ok(diff_contents(
+            &format!("{} against {}", file.original_path, other),
+            &String::from_utf8_lossy(&stored),
+            &String::from_utf8_lossy(&content),
+            options,
+        ));
+    }
 
     // Try to read the current file content
-    let current_content = match fs::read_to_string(file_path) {
-        Ok(content) => content,
+    let current_content = match read_filtered(file) {
+        Ok(content) => String::from_utf8_lossy(&content).to_string(),
         Err(_) => {
             // File doesn't exist or can't be read
             return Ok(DiffResult {
@@ -78,26 +114,102 @@ fn diff_single_file(
         }
     };
 
+    let decrypted_stored_content =
+        read_stored_content(repo_path, crypto, stored_path(file, options)?)?;
+    let stored_content = String::from_utf8_lossy(&decrypted_stored_content).to_string();
+
+    Ok(diff_contents(
+        &file.original_path,
+        &stored_content,
+        &current_content,
+        options,
+    ))
+}
+
+/// Where the content a file is compared with is stored: the latest, or the one from `options.at`
+fn stored_path<'a>(file: &'a TrackedFile, options: &DiffOptions) -> Result<&'a str, KittyError> {
+    match options.at {
+        Some(at) => revision_at(file, at).map(|r| r.repo_path).ok_or_else(|| {
+            KittyError::PointInTime(format!(
+                "{} has no stored content from then",
+                file.original_path
+            ))
+        }),
+        None => Ok(&file.repo_path),
+    }
+}
+
+/// Read and decrypt stored content of a tracked file
+fn read_stored_content(
+    repo_path: &Path,
+    crypto: &Crypto,
+    stored_path: &str,
+) -> Result<Vec<u8>, KittyError> {
     // Get the storage type
     let storage_type = get_storage_type(repo_path)?;
 
-    // Read and decrypt the stored file content
-    let decrypted_stored_content = if storage_type == "sqlite" {
-        // Use SQLite storage to get the file
-        use crate::storage::sqlite::SqliteStorage;
-        let storage = SqliteStorage::new(repo_path)?;
-        let encrypted_stored_content = storage.get_file(&file.repo_path)?;
-        crypto.decrypt(&encrypted_stored_content)?
+    if is_database(&storage_type) {
+        // Use database storage to get the file
+        let storage = open_database(repo_path)?;
+        let encrypted_stored_content = storage.get_file(stored_path)?;
+        crypto.decrypt(&encrypted_stored_content)
     } else {
         // Use file-based storage
-        let encrypted_stored_content = fs::read(repo_path.join(&file.repo_path))?;
-        crypto.decrypt(&encrypted_stored_content)?
+        let encrypted_stored_content = read_stored(&repo_path.join(stored_path))?;
+        crypto.decrypt(&encrypted_stored_content)
+    }
+}
+
+/// Diff the live file against the pristine copy shipped by its owning package
+fn diff_against_package(
+    file: &TrackedFile,
+    options: &DiffOptions,
+) -> Result<Option<DiffResult>, KittyError> {
+    let (package, manager) = match (&file.package, &file.package_manager) {
+        (Some(package), Some(manager)) => (package, manager),
+        _ => return Ok(None),
     };
 
-    let stored_content = String::from_utf8_lossy(&decrypted_stored_content).to_string();
+    let current_content = match fs::read_to_string(&file.original_path) {
+        Ok(content) => content,
+        Err(_) => {
+            return Ok(Some(DiffResult {
+                path: file.original_path.clone(),
+                has_changes: true,
+                additions: 0,
+                deletions: 0,
+                diff_text: format!(
+                    "File {} no longer exists or cannot be read\n",
+                    file.original_path
+                ),
+            }));
+        }
+    };
+
+    println!(
+        "Fetching pristine {} from package {} ({})...",
+        file.original_path, package, manager
+    );
+    let pristine = fetch_pristine_content(manager, package, Path::new(&file.original_path))?;
+    let pristine_content = String::from_utf8_lossy(&pristine).to_string();
+
+    Ok(Some(diff_contents(
+        &file.original_path,
+        &pristine_content,
+        &current_content,
+        options,
+    )))
+}
 
+/// Calculate a line diff from `old_content` to `new_content`
+pub fn diff_contents(
+    path: &str,
+    old_content: &str,
+    new_content: &str,
+    options: &DiffOptions,
+) -> DiffResult {
     // Calculate diff
-    let diff = TextDiff::from_lines(&stored_content, &current_content);
+    let diff = TextDiff::from_lines(old_content, new_content);
 
     // Count additions and deletions
     let mut additions = 0;
@@ -118,13 +230,13 @@ fn diff_single_file(
 
     // If no changes, just indicate files are identical
     if !has_any_changes {
-        return Ok(DiffResult {
-            path: file.original_path.clone(),
+        return DiffResult {
+            path: path.to_string(),
             has_changes: false,
             additions: 0,
             deletions: 0,
             diff_text: "Files are identical.\n".to_string(),
-        });
+        };
     }
 
     // Second pass: track changes with proper formatting
@@ -132,11 +244,11 @@ fn diff_single_file(
         match change.tag() {
             ChangeTag::Delete => {
                 deletions += 1;
-                diff_text.push_str(&format!("{}{}", "-".red(), change));
+                diff_text.push_str(&format!("{}{}", theme::deletion("-"), change));
             }
             ChangeTag::Insert => {
                 additions += 1;
-                diff_text.push_str(&format!("{}{}", "+".green(), change));
+                diff_text.push_str(&format!("{}{}", theme::addition("+"), change));
             }
             ChangeTag::Equal => {
                 // Only include unchanged lines if context mode is enabled
@@ -149,13 +261,13 @@ fn diff_single_file(
 
     let has_changes = additions > 0 || deletions > 0;
 
-    Ok(DiffResult {
-        path: file.original_path.clone(),
+    DiffResult {
+        path: path.to_string(),
         has_changes,
         additions,
         deletions,
         diff_text,
-    })
+    }
 }
 
 /// List files with differences
@@ -169,60 +281,47 @@ pub fn diff_files(options: Option<DiffOptions>) -> Result<(), KittyError> {
     }
 
     // Get password from user
-    print!("Enter repository password: ");
-    io::stdout().flush()?;
-    let password = read_password()?;
-    println!(); // Add a newline after password input
-
-    // Get storage type
-    let storage_type = get_storage_type(&repo_path)?;
-
-    // Get salt and create crypto instance
-    let config_salt = hex::decode(get_repository_salt(&repo_path)?)?;
-    let crypto = Crypto::from_password_and_salt(&password, &config_salt);
-
-    // Load repository based on storage type
-    let repository: Repository = if storage_type == "sqlite" {
-        // Use SQLite storage to load repository
-        use crate::storage::sqlite::SqliteStorage;
-        let storage = SqliteStorage::new(&repo_path)?;
-        storage.load_repository()?
-    } else {
-        // Use file-based storage
-        let encrypted_config = fs::read(repo_path.join("config.enc"))?;
-        let decrypted_config = crypto.decrypt(&encrypted_config)?;
-        serde_json::from_slice(&decrypted_config)?
+    let password = read_repository_password(false)?;
+
+    // Unlock the repository key with the password
+    let crypto = unlock(&repo_path, &password)?;
+
+    // A path only needs the files it can name, not the whole manifest
+    let files = match &options.path {
+        Some(path) => load_candidates(&repo_path, &crypto, path, options.regex)?,
+        None => load_repository(&repo_path, &crypto)?.files,
     };
 
-    if repository.files.is_empty() {
-        println!("No files are currently tracked in the repository.");
+    if files.is_empty() {
+        println!("{}", tr!("no-files-tracked"));
         return Ok(());
     }
 
     // Filter files based on path option
-    let files_to_diff: Vec<&TrackedFile> = match &options.path {
-        Some(path) => {
-            // If path is provided, find the specific file
-            let file_path = Path::new(path)
-                .canonicalize()
-                .unwrap_or_else(|_| Path::new(path).to_path_buf());
-
-            let matching_file = repository.files.iter().find(|f| {
-                Path::new(&f.original_path) == file_path || f.original_path.contains(path)
-            });
-
-            match matching_file {
-                Some(file) => vec![file],
-                None => {
-                    return Err(KittyError::FileNotTracked(path.to_string()));
-                }
-            }
-        }
+    let mut files_to_diff: Vec<&TrackedFile> = match &options.path {
+        Some(path) => select_tracked(&files, path, options.all, options.regex)?
+            .into_iter()
+            .map(|index| &files[index])
+            .collect(),
         None => {
-            // If no path is provided, diff all files
-            repository.files.iter().collect()
+            // If no path is provided, diff all files meant for this machine
+            files.iter().filter(|f| f.applies_here()).collect()
         }
     };
+    // Files tracked later, or whose versions from then were pruned, have nothing to compare with
+    if let Some(at) = options.at {
+        files_to_diff.retain(|file| revision_at(file, at).is_some());
+    }
+
+    if let Some(other) = &options.with {
+        if files_to_diff.len() > 1 {
+            return Err(KittyError::AmbiguousPath(format!(
+                "--with {} compares one tracked file, but {} match",
+                other,
+                files_to_diff.len()
+            )));
+        }
+    }
 
     // Run diff for each file
     let mut diff_results = Vec::new();
@@ -231,7 +330,19 @@ pub fn diff_files(options: Option<DiffOptions>) -> Result<(), KittyError> {
     let mut files_with_changes = 0;
 
     for file in files_to_diff {
-        let result = diff_single_file(&repo_path, &crypto, file, &options)?;
+        let result = if options.against_package {
+            match diff_against_package(file, &options)? {
+                Some(result) => result,
+                None => {
+                    if options.path.is_some() {
+                        println!("{} is not owned by a known package.", file.original_path);
+                    }
+                    continue;
+                }
+            }
+        } else {
+            diff_single_file(&repo_path, &crypto, file, &options)?
+        };
 
         if result.has_changes {
             files_with_changes += 1;
@@ -246,20 +357,29 @@ pub fn diff_files(options: Option<DiffOptions>) -> Result<(), KittyError> {
 
     // Display results
     if options.summary {
-        println!("Summary of changes:");
-        println!("  Files changed: {}", files_with_changes);
-        println!("  Additions: {}", total_additions);
-        println!("  Deletions: {}", total_deletions);
+        println!("{}", tr!("diff-summary-title"));
+        println!(
+            "  {}",
+            tr!("diff-summary-files", count = files_with_changes)
+        );
+        println!(
+            "  {}",
+            tr!("diff-summary-additions", count = total_additions)
+        );
+        println!(
+            "  {}",
+            tr!("diff-summary-deletions", count = total_deletions)
+        );
         println!();
     }
 
     if diff_results.is_empty() {
-        println!("No changes found in tracked files.");
+        println!("{}", tr!("diff-no-changes"));
         return Ok(());
     }
 
     for result in diff_results {
-        println!("File: {}", result.path.bold());
+        println!("File: {}", theme::emphasis(&result.path));
         if options.summary {
             println!("  +{} -{}", result.additions, result.deletions);
         } else {
@@ -0,0 +1,50 @@
+use crate::commands::init::KittyError;
+use clap::Command;
+use clap_mangen::Man;
+use std::{fs, path::Path};
+
+/// Write documentation for every command, generated from the CLI definition itself
+///
+/// `man` writes one page per command and subcommand (`kitty.1`, `kitty-restore.1`,
+/// `kitty-trash-empty.1`, ...). `markdown` writes the whole reference to `kitty.md`.
+pub fn generate_docs(command: Command, format: &str, out_dir: &Path) -> Result<(), KittyError> {
+    let mut command = command.disable_help_subcommand(true);
+    fs::create_dir_all(out_dir)?;
+
+    match format {
+        "man" => {
+            // Building names each subcommand `kitty-<name>`, which the page file names use
+            command.build();
+            let mut count = 0;
+            write_man_pages(command, out_dir, &mut count)?;
+            println!("Wrote {} man pages to {}", count, out_dir.display());
+        }
+        "markdown" => {
+            let path = out_dir.join(format!("{}.md", command.get_name()));
+            fs::write(&path, clap_markdown::help_markdown_command(&command))?;
+            println!("Wrote {}", path.display());
+        }
+        _ => {
+            return Err(KittyError::Usage(format!(
+                "unknown documentation format '{}': use man or markdown",
+                format
+            )))
+        }
+    }
+
+    Ok(())
+}
+
+/// Write the page for a command and then for each of its visible subcommands
+fn write_man_pages(command: Command, out_dir: &Path, count: &mut usize) -> Result<(), KittyError> {
+    for subcommand in command.get_subcommands().filter(|s| !s.is_hide_set()) {
+        write_man_pages(subcommand.clone(), out_dir, count)?;
+    }
+
+    Man::new(command)
+        .source(format!("kitty {}", env!("CARGO_PKG_VERSION")))
+        .manual("Kitty Manual")
+        .generate_to(out_dir)?;
+    *count += 1;
+    Ok(())
+}
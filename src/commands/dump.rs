@@ -0,0 +1,217 @@
+use crate::{
+    commands::init::{Crypto, KittyError, Repository},
+    repository::{config::RepoConfig, keys::unlock, session::RepoSession},
+    utils::{
+        audit::{self, AuditEntry},
+        file::{
+            get_repository_path, get_repository_salt, get_storage_type, load_repository,
+            read_stored_files, write_private,
+        },
+        inventory,
+        lock::RepoLock,
+        mirror,
+        password::read_repository_password,
+        stats, theme,
+    },
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{DateTime, Local, Utc};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, path::Path};
+
+/// First line of a dump file; the second holds the salt in hex
+const DUMP_HEADER: &str = "kitty-dump 1";
+
+/// Version of the JSON document inside a dump
+const FORMAT_VERSION: u32 = 1;
+
+/// A whole repository as one document, independent of the storage backend
+///
+/// On disk the JSON is encrypted with the repository's password, behind a
+/// short plaintext header carrying the salt to derive the key from.
+#[derive(Serialize, Deserialize)]
+pub struct Dump {
+    pub format: u32,
+    pub dumped_at: DateTime<Utc>,
+
+    /// Backend the repository was dumped from
+    pub storage: String,
+
+    /// The manifest, with every file's versions
+    pub repository: Repository,
+
+    /// The repository settings from `config.json`
+    pub settings: RepoConfig,
+
+    /// Decrypted content of every file and version, in base64, keyed by repo path
+    pub blobs: BTreeMap<String, String>,
+}
+
+/// Options for the dump command
+#[derive(Default)]
+pub struct DumpOptions {
+    /// Overwrite the output file if it exists
+    pub force: bool,
+}
+
+/// Write the repository, content included, to a single encrypted file
+///
+/// The trash, the audit log and statistics are not part of a dump.
+pub fn dump_repository(output: &str, options: Option<DumpOptions>) -> Result<(), KittyError> {
+    let options = options.unwrap_or_default();
+    let repo_path = get_repository_path()?;
+
+    if !repo_path.exists() {
+        return Err(KittyError::RepositoryNotFound);
+    }
+
+    if Path::new(output).exists() && !options.force {
+        return Err(KittyError::OutputExists {
+            paths: vec![output.to_string()],
+        });
+    }
+
+    // Get password from user
+    let password = read_repository_password(false)?;
+
+    let salt = get_repository_salt(&repo_path)?;
+    let crypto = unlock(&repo_path, &password)?;
+    let _lock = RepoLock::acquire(&repo_path)?;
+    let repository = load_repository(&repo_path, &crypto)?;
+
+    let paths: Vec<&str> = repository
+        .files
+        .iter()
+        .flat_map(|f| {
+            std::iter::once(f.repo_path.as_str())
+                .chain(f.versions.iter().map(|v| v.repo_path.as_str()))
+        })
+        .collect();
+    let stored = read_stored_files(&repo_path, &paths)?;
+
+    let mut blobs = BTreeMap::new();
+    for path in paths {
+        // A dump that silently lacks content would only be noticed when it is needed
+        let encrypted = stored.get(path).ok_or_else(|| {
+            KittyError::Dump(format!(
+                "stored content {} is missing; run `kitty recover` first",
+                path
+            ))
+        })?;
+        blobs.insert(
+            path.to_string(),
+            STANDARD.encode(crypto.decrypt(encrypted)?),
+        );
+    }
+
+    let dump = Dump {
+        format: FORMAT_VERSION,
+        dumped_at: Utc::now(),
+        storage: get_storage_type(&repo_path)?,
+        repository,
+        settings: RepoConfig::load(&repo_path)?,
+        blobs,
+    };
+    let mut contents = format!("{}\n{}\n", DUMP_HEADER, salt.trim()).into_bytes();
+    contents.extend(crypto.encrypt(&serde_json::to_vec(&dump)?)?);
+    write_private(Path::new(output), contents)?;
+
+    println!(
+        "{} Dumped {} file(s) and {} stored blob(s) to {}",
+        theme::success("SUCCESS:"),
+        dump.repository.files.len(),
+        dump.blobs.len(),
+        output
+    );
+    Ok(())
+}
+
+/// Decrypt a dump file with the password of the repository it came from
+pub fn read_dump(path: &Path, password: &str) -> Result<Dump, KittyError> {
+    let contents = fs::read(path)?;
+    let mut parts = contents.splitn(3, |&b| b == b'\n');
+    let (Some(header), Some(salt), Some(encrypted)) = (parts.next(), parts.next(), parts.next())
+    else {
+        return Err(KittyError::Dump(format!(
+            "{} is not a kitty dump",
+            path.display()
+        )));
+    };
+    if header != DUMP_HEADER.as_bytes() {
+        return Err(KittyError::Dump(format!(
+            "{} is not a kitty dump",
+            path.display()
+        )));
+    }
+
+    let crypto = Crypto::from_password_and_salt(password, &hex::decode(salt)?);
+    let decrypted = crypto.decrypt(encrypted).map_err(|_| {
+        KittyError::Dump("the password does not match the dumped repository".to_string())
+    })?;
+    let dump: Dump = serde_json::from_slice(&decrypted)?;
+    if dump.format > FORMAT_VERSION {
+        return Err(KittyError::Dump(format!(
+            "format {} is newer than this kitty understands ({}); upgrade kitty",
+            dump.format, FORMAT_VERSION
+        )));
+    }
+    Ok(dump)
+}
+
+/// Fill a newly initialized repository with the files, history and settings of a dump
+///
+/// Content is encrypted again with the new repository's key, so the dump
+/// can come from any backend.
+pub fn load_dump(repo_path: &Path, crypto: Crypto, dump: Dump) -> Result<(), KittyError> {
+    let Dump {
+        dumped_at,
+        storage,
+        repository,
+        settings,
+        blobs,
+        ..
+    } = dump;
+    settings.save(repo_path)?;
+
+    let mut session = RepoSession::open(repo_path, crypto)?;
+    let generation = session.repository.next_generation();
+    for file in &repository.files {
+        let versions = file.versions.iter().map(|v| &v.repo_path);
+        for path in std::iter::once(&file.repo_path).chain(versions) {
+            let encoded = blobs
+                .get(path)
+                .ok_or_else(|| KittyError::Dump(format!("content of {} is missing", path)))?;
+            let content = STANDARD
+                .decode(encoded)
+                .map_err(|e| KittyError::Dump(format!("content of {}: {}", path, e)))?;
+            let encrypted = session.crypto.encrypt(&content)?;
+            session.put_file(path, encrypted);
+        }
+    }
+    session.repository.created_at = repository.created_at;
+    session.repository.files = repository.files;
+    for file in &mut session.repository.files {
+        file.revision = generation;
+    }
+    session.commit()?;
+
+    for file in &session.repository.files {
+        audit::record(
+            repo_path,
+            &session.crypto,
+            &AuditEntry::new("import", &file.original_path, None, Some(file.hash.clone())),
+        )?;
+    }
+    inventory::update(repo_path, &session.crypto, &session.repository);
+    stats::record(repo_path, &session.crypto, &session.repository);
+    mirror::update(repo_path);
+
+    println!(
+        "{} Imported {} file(s) from a {} repository dumped {}",
+        theme::success("SUCCESS:"),
+        session.repository.files.len(),
+        storage,
+        dumped_at.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S")
+    );
+    Ok(())
+}
@@ -0,0 +1,224 @@
+use crate::{
+    commands::init::{KittyError, TrackedFile},
+    repository::keys::unlock,
+    storage::{is_database, open_database},
+    utils::{
+        audit::{self, AuditEntry},
+        file::{
+            get_repository_path, get_storage_type, load_repository, read_stored, read_stored_files,
+        },
+        password::read_repository_password,
+        select::find_by_id,
+        shred::{private_temp_dir, secure_delete_dir},
+    },
+};
+use std::{
+    fs,
+    os::unix::process::ExitStatusExt,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How long the command gets to exit on its own after kitty is interrupted
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(2);
+
+/// Options for the exec command
+#[derive(Default)]
+pub struct ExecOptions {
+    /// Tracked files to expose, as `path` or `NAME=path`
+    pub files: Vec<String>,
+
+    /// Expose every file in this bundle
+    pub bundle: Option<String>,
+
+    /// Command to run, followed by its arguments
+    pub command: Vec<String>,
+}
+
+/// Shreds the decrypted files when dropped, so every way out of `exec` cleans up
+struct PlaintextDir(PathBuf);
+
+impl Drop for PlaintextDir {
+    fn drop(&mut self) {
+        if let Err(e) = secure_delete_dir(&self.0) {
+            eprintln!("Warning: could not clean up {}: {}", self.0.display(), e);
+        }
+    }
+}
+
+/// Environment variable name for a file exposed without an explicit name
+fn default_variable(path: &str) -> String {
+    let name: String = Path::new(path)
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("KITTY_FILE_{}", name.trim_matches('_'))
+}
+
+/// Decrypt tracked files into a private directory, run a command, then shred them
+///
+/// Each file's temporary path is exported as an environment variable and can be
+/// passed as an argument with `{NAME}`. Returns the command's exit code.
+pub fn exec_with_files(options: Option<ExecOptions>) -> Result<i32, KittyError> {
+    let options = options.unwrap_or_default();
+    let repo_path = get_repository_path()?;
+
+    if !repo_path.exists() {
+        return Err(KittyError::RepositoryNotFound);
+    }
+
+    let Some((program, args)) = options.command.split_first() else {
+        return Err(KittyError::Usage("no command given".to_string()));
+    };
+
+    // Get password from user
+    let password = read_repository_password(false)?;
+
+    let storage_type = get_storage_type(&repo_path)?;
+    let crypto = unlock(&repo_path, &password)?;
+    let repository = load_repository(&repo_path, &crypto)?;
+
+    // Resolve every requested file to a variable name and manifest entry
+    let mut selected: Vec<(String, &TrackedFile)> = Vec::new();
+    for spec in &options.files {
+        let (variable, path) = match spec.split_once('=') {
+            Some((variable, path)) => (Some(variable.to_string()), path),
+            None => (None, spec.as_str()),
+        };
+        let file = match find_by_id(&repository.files, path)? {
+            Some(index) => &repository.files[index],
+            None => {
+                let file_path = Path::new(path)
+                    .canonicalize()
+                    .unwrap_or_else(|_| PathBuf::from(path));
+                repository
+                    .files
+                    .iter()
+                    .find(|f| Path::new(&f.original_path) == file_path)
+                    .ok_or_else(|| KittyError::FileNotTracked(path.to_string()))?
+            }
+        };
+        selected.push((
+            variable.unwrap_or_else(|| default_variable(&file.original_path)),
+            file,
+        ));
+    }
+    if let Some(bundle) = &options.bundle {
+        let members: Vec<&TrackedFile> = repository
+            .files
+            .iter()
+            .filter(|f| f.bundle.as_deref() == Some(bundle.as_str()))
+            .collect();
+        if members.is_empty() {
+            return Err(KittyError::UnknownBundle(bundle.clone()));
+        }
+        for file in members {
+            selected.push((default_variable(&file.original_path), file));
+        }
+    }
+
+    if selected.is_empty() {
+        return Err(KittyError::Usage(
+            "select files with --file or --bundle".to_string(),
+        ));
+    }
+
+    let plaintext = PlaintextDir(private_temp_dir("kitty-exec")?);
+    let paths: Vec<&str> = selected.iter().map(|(_, f)| f.repo_path.as_str()).collect();
+    let mut stored = read_stored_files(&repo_path, &paths)?;
+
+    let mut exposed: Vec<(String, PathBuf)> = Vec::new();
+    for (index, (variable, file)) in selected.iter().enumerate() {
+        let encrypted = match stored.remove(&file.repo_path) {
+            Some(encrypted) => encrypted,
+            None if is_database(&storage_type) => {
+                open_database(&repo_path)?.get_file(&file.repo_path)?
+            }
+            None => read_stored(&repo_path.join(&file.repo_path))?,
+        };
+        let content = crypto.decrypt(&encrypted)?;
+
+        // Keep the original file name where possible, since some tools care about it
+        let file_name = Path::new(&file.original_path)
+            .file_name()
+            .map(|n| n.to_os_string())
+            .unwrap_or_else(|| index.to_string().into());
+        let mut target = plaintext.0.join(&file_name);
+        if exposed.iter().any(|(_, path)| *path == target) {
+            let subdir = plaintext.0.join(index.to_string());
+            fs::create_dir(&subdir)?;
+            target = subdir.join(&file_name);
+        }
+        fs::write(&target, content)?;
+
+        audit::record(
+            &repo_path,
+            &crypto,
+            &AuditEntry::new("exec", &file.original_path, None, None),
+        )?;
+        exposed.push((variable.clone(), target));
+    }
+
+    // Substitute {NAME} in the arguments with the decrypted file's path
+    let args: Vec<String> = args
+        .iter()
+        .map(|arg| {
+            exposed.iter().fold(arg.clone(), |arg, (variable, path)| {
+                arg.replace(&format!("{{{}}}", variable), &path.to_string_lossy())
+            })
+        })
+        .collect();
+
+    // Stay alive on interrupts so the files are shredded after the command exits
+    let interrupted = Arc::new(AtomicBool::new(false));
+    for signal in [
+        signal_hook::consts::SIGINT,
+        signal_hook::consts::SIGTERM,
+        signal_hook::consts::SIGHUP,
+    ] {
+        signal_hook::flag::register(signal, Arc::clone(&interrupted))?;
+    }
+
+    let mut child = Command::new(program)
+        .args(&args)
+        .envs(exposed.iter().map(|(variable, path)| (variable, path)))
+        .env("KITTY_EXEC_DIR", &plaintext.0)
+        .spawn()?;
+
+    let mut interrupted_at: Option<Instant> = None;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if interrupted.load(Ordering::Relaxed) {
+            let since = *interrupted_at.get_or_insert_with(Instant::now);
+            if since.elapsed() >= SHUTDOWN_GRACE {
+                let _ = child.kill();
+            }
+        }
+        thread::sleep(Duration::from_millis(50));
+    };
+
+    drop(plaintext);
+
+    // Report a command killed by a signal the way shells do
+    if let Some(signal) = status.signal() {
+        return Ok(128 + signal);
+    }
+    Ok(status.code().unwrap_or(1))
+}
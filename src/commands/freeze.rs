@@ -0,0 +1,164 @@
+use crate::{
+    collectors::Collector,
+    commands::init::{FileMetadata, KittyError, TrackedFile},
+    repository::keys::unlock_admin,
+    utils::{
+        audit::{self, AuditEntry},
+        file::{get_repository_path, load_repository, save_repository},
+        lock::RepoLock,
+        mirror,
+        password::read_repository_password,
+        select::select_tracked,
+        theme,
+    },
+};
+use std::{fs, io, os::unix::fs::PermissionsExt, path::Path};
+
+/// Clear every write bit, so editors and scripts refuse to change the file
+pub fn make_read_only(path: &Path) -> io::Result<()> {
+    let mut permissions = fs::metadata(path)?.permissions();
+    permissions.set_mode(permissions.mode() & !0o222);
+    fs::set_permissions(path, permissions)
+}
+
+/// Give a frozen file back the mode recorded when it was added, or write access for its owner
+pub fn make_writable(path: &Path, metadata: Option<&FileMetadata>) -> io::Result<()> {
+    let mode = match metadata {
+        Some(metadata) => metadata.mode & 0o7777,
+        None => fs::metadata(path)?.permissions().mode() | 0o200,
+    };
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+}
+
+/// Whether a frozen file was made writable again behind kitty's back
+pub fn is_thawed(path: &Path) -> bool {
+    fs::metadata(path).is_ok_and(|m| m.permissions().mode() & 0o222 != 0)
+}
+
+/// Indexes of the files a `freeze` argument names: every member of a bundle, or a path
+fn select(files: &[TrackedFile], target: &str) -> Result<Vec<usize>, KittyError> {
+    let members: Vec<usize> = files
+        .iter()
+        .enumerate()
+        .filter(|(_, f)| f.bundle.as_deref() == Some(target))
+        .map(|(index, _)| index)
+        .collect();
+    if !members.is_empty() {
+        return Ok(members);
+    }
+    select_tracked(files, target, false, false)
+}
+
+/// Freeze or unfreeze tracked files, given by path or bundle
+///
+/// A frozen file's live copy is made read-only, so it is not edited by
+/// accident outside kitty. `kitty restore` still writes it and freezes it
+/// again. Unfreezing puts back the mode recorded when the file was added.
+pub fn freeze_files(target: &str, frozen: bool) -> Result<(), KittyError> {
+    let repo_path = get_repository_path()?;
+
+    if !repo_path.exists() {
+        return Err(KittyError::RepositoryNotFound);
+    }
+
+    // Get password from user
+    let password = read_repository_password(false)?;
+
+    let crypto = unlock_admin(&repo_path, &password)?;
+    let _lock = RepoLock::acquire(&repo_path)?;
+    let mut repository = load_repository(&repo_path, &crypto)?;
+
+    let mut changed = Vec::new();
+    for index in select(&repository.files, target)? {
+        let file = &repository.files[index];
+        let live = Path::new(&file.original_path);
+
+        if Collector::parse(&file.original_path).is_some() {
+            println!("Skipping {}: only files can be frozen", file.original_path);
+            continue;
+        }
+        // A frozen file made writable again by hand only needs its mode fixed
+        if frozen && file.frozen && is_thawed(live) {
+            match make_read_only(live) {
+                Ok(()) => println!(
+                    "{} Froze {} again",
+                    theme::success("SUCCESS:"),
+                    file.original_path
+                ),
+                Err(e) => println!(
+                    "{} Failed to change permissions of {}: {}",
+                    theme::warning("WARNING:"),
+                    file.original_path,
+                    e
+                ),
+            }
+            continue;
+        }
+        if file.frozen == frozen {
+            println!(
+                "{} is already {}",
+                file.original_path,
+                if frozen { "frozen" } else { "unfrozen" }
+            );
+            continue;
+        }
+
+        // An unfrozen entry stays unfrozen even if its live file is gone
+        let applied = match (frozen, live.exists()) {
+            (true, true) => make_read_only(live),
+            (false, true) => make_writable(live, file.metadata.as_ref()),
+            (true, false) => {
+                println!(
+                    "{} {} is missing; restore it first",
+                    theme::warning("WARNING:"),
+                    file.original_path
+                );
+                continue;
+            }
+            (false, false) => Ok(()),
+        };
+        if let Err(e) = applied {
+            println!(
+                "{} Failed to change permissions of {}: {}",
+                theme::warning("WARNING:"),
+                file.original_path,
+                e
+            );
+            continue;
+        }
+
+        repository.files[index].frozen = frozen;
+        repository.touch(index);
+        changed.push(repository.files[index].original_path.clone());
+    }
+
+    if changed.is_empty() {
+        return Ok(());
+    }
+    save_repository(&repo_path, &crypto, &repository)?;
+
+    for original_path in &changed {
+        audit::record(
+            &repo_path,
+            &crypto,
+            &AuditEntry::new(
+                if frozen { "freeze" } else { "unfreeze" },
+                original_path,
+                None,
+                None,
+            ),
+        )?;
+        if frozen {
+            println!(
+                "{} Froze {}; it is read-only until `kitty unfreeze`",
+                theme::success("SUCCESS:"),
+                original_path
+            );
+        } else {
+            println!("{} Unfroze {}", theme::success("SUCCESS:"), original_path);
+        }
+    }
+    mirror::update(&repo_path);
+
+    Ok(())
+}
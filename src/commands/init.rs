@@ -1,15 +1,32 @@
-use crate::utils::file::get_repository_path;
+use crate::commands::add::add_file_with_password;
+use crate::commands::adopt::adopt_files;
+use crate::commands::dump::{load_dump, read_dump};
+use crate::repository::{
+    config::RepoConfig,
+    format::write_format,
+    keys::{write_read_key, KEYS_FILE},
+};
+use crate::storage::open_database;
+use crate::tr;
+use crate::utils::file::{
+    record_repository_location, repository_path_from_env, write_atomic, write_config,
+    REPOSITORY_DIR,
+};
+use crate::utils::platform::{Conditions, Platform};
+use crate::utils::timings::{self, Phase};
 use chacha20poly1305::aead::Aead;
 use chacha20poly1305::{ChaCha20Poly1305, Key, KeyInit, Nonce};
 use chrono::{DateTime, Utc};
 use hex::FromHexError;
 use rand::{rngs::OsRng, Rng};
-use ring::pbkdf2;
+use ring::{pbkdf2, signature::Ed25519KeyPair};
 use rpassword::read_password;
 use serde::{Deserialize, Serialize};
 use std::{
     fs,
     io::{self, Write},
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
 };
 use thiserror::Error;
 
@@ -17,7 +34,10 @@ use thiserror::Error;
 const SALT_LEN: usize = 32;
 const NONCE_LEN: usize = 12;
 const KEY_LEN: usize = 32;
-const PBKDF2_ITERATIONS: u32 = 100_000;
+/// Bytes encryption adds to content: the nonce in front and the authentication tag
+pub const ENCRYPTION_OVERHEAD: usize = NONCE_LEN + 16;
+/// Iterations of PBKDF2-SHA256 deriving the key from the password
+pub const PBKDF2_ITERATIONS: u32 = 100_000;
 
 #[derive(Error, Debug)]
 pub enum KittyError {
@@ -56,6 +76,286 @@ pub enum KittyError {
 
     #[error("Storage type error: {0}")]
     StorageType(String),
+
+    #[error("Package error: {0}")]
+    Package(String),
+
+    #[error("No password available: {0}")]
+    PasswordUnavailable(String),
+
+    #[error("Daemon error: {0}")]
+    Daemon(String),
+
+    #[error("Unsupported repository format: {0}")]
+    UnsupportedFormat(String),
+
+    #[error("Ordering error: {0}")]
+    Ordering(String),
+
+    #[error("File too large: {0}")]
+    FileTooLarge(String),
+
+    #[error("Collector error: {0}")]
+    Collector(String),
+
+    #[error("Invalid filter: {0}")]
+    Filter(String),
+
+    #[error("Ambiguous path: {0}")]
+    AmbiguousPath(String),
+
+    #[error("Invalid pattern: {0}")]
+    Pattern(String),
+
+    #[error("Invalid alias: {0}")]
+    Alias(String),
+
+    #[error("Inventory error: {0}")]
+    Inventory(String),
+
+    #[error("Unknown role: {0}")]
+    Role(String),
+
+    #[error("Invalid condition: {0}")]
+    Condition(String),
+
+    #[error("Template error: {0}")]
+    Template(String),
+
+    #[error("Vault error: {0}")]
+    Vault(String),
+
+    #[error("AWS error: {0}")]
+    Aws(String),
+
+    #[error("Mirror error: {0}")]
+    Mirror(String),
+
+    #[error("Repository is locked: {0}")]
+    Locked(String),
+
+    #[error("Repository changed while this command ran: {0}")]
+    Conflict(String),
+
+    #[error("Invalid expiry: {0}")]
+    Expiry(String),
+
+    #[error("Validation failed: {0}")]
+    Validation(String),
+
+    #[error("Plan is out of date: {0}")]
+    StalePlan(String),
+
+    #[error("Invalid dump: {0}")]
+    Dump(String),
+
+    #[error("Session error: {0}")]
+    Session(String),
+
+    #[error("Read-only access: {0}")]
+    ReadOnly(String),
+
+    #[error("Share bundle error: {0}")]
+    Share(String),
+
+    #[error("Patch failed: {0}")]
+    Patch(String),
+
+    #[error("Could not send mail: {0}")]
+    Mail(String),
+
+    #[error("Cannot look back: {0}")]
+    PointInTime(String),
+
+    #[error("Mount failed: {0}")]
+    Mount(String),
+
+    #[error("Signature check failed: {0}")]
+    Signature(String),
+
+    #[error("Invalid arguments: {0}")]
+    Usage(String),
+
+    #[error("Unknown bundle: {0}")]
+    UnknownBundle(String),
+
+    #[error("{path} is not in the trash")]
+    NotInTrash { path: String },
+
+    #[error("{path} is tracked again; remove it before restoring the trashed copy")]
+    TrackedAgain { path: String },
+
+    #[error("{} already exist(s); pass --force to overwrite", .paths.join(", "))]
+    OutputExists { paths: Vec<String> },
+
+    #[error("{path} is not a file but collected from a tool")]
+    NotAFile { path: String },
+
+    #[error("{path} is not a text file")]
+    NotText { path: String },
+
+    #[error("Stored content of {path} is missing")]
+    ContentMissing { path: String },
+
+    #[error("Cannot move {path}: {reason}")]
+    Move { path: String, reason: String },
+
+    #[error("Not every file was stored: {0}")]
+    Incomplete(String),
+
+    #[error("No files below {path}")]
+    EmptyTree { path: String },
+
+    #[error("HOME is not set, so the repository location cannot be recorded")]
+    NoHome,
+
+    #[error("sudo could not read {path}")]
+    PrivilegedRead { path: String },
+}
+
+impl KittyError {
+    /// The error message in the user's language
+    pub fn localized(&self) -> String {
+        let (code, detail) = self.parts();
+        tr!(&format!("error-{}", code), detail = detail)
+    }
+
+    /// Stable identifier of the kind of error, for programs that wrap kitty
+    ///
+    /// Codes are never renamed or reused; `README.md` lists them.
+    pub fn code(&self) -> &'static str {
+        self.parts().0
+    }
+
+    /// The tracked path the error is about, when there is one
+    pub fn path(&self) -> Option<&str> {
+        match self {
+            KittyError::FileNotTracked(path)
+            | KittyError::NotInTrash { path }
+            | KittyError::TrackedAgain { path }
+            | KittyError::NotAFile { path }
+            | KittyError::NotText { path }
+            | KittyError::ContentMissing { path }
+            | KittyError::Move { path, .. }
+            | KittyError::EmptyTree { path }
+            | KittyError::PrivilegedRead { path } => Some(path),
+            KittyError::OutputExists { paths } => match &paths[..] {
+                [path] => Some(path),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// What to try next, for errors whose message does not already say
+    pub fn hint(&self) -> Option<&'static str> {
+        match self {
+            KittyError::RepositoryExists => {
+                Some("use the existing repository, or pass --path to create another")
+            }
+            KittyError::RepositoryNotFound => {
+                Some("run `kitty init`, or set KITTY_REPO to the repository")
+            }
+            KittyError::InvalidPassword | KittyError::Decryption(_) => Some(
+                "check the password; for a repository made before salt.key, \
+                 run `kitty recover --find-salt`",
+            ),
+            KittyError::FileNotTracked(_) => Some("`kitty ls-files` lists the tracked paths"),
+            KittyError::AmbiguousPath(_) => {
+                Some("pass the full path or an @id, or --all for every match")
+            }
+            KittyError::Conflict(_) => Some("run the command again"),
+            KittyError::StalePlan(_) => Some("make a new plan with `kitty apply --plan`"),
+            KittyError::ReadOnly(_) => Some("use the admin password of the repository"),
+            KittyError::Signature(_) => Some(
+                "check the repository with the admin password; if nobody changed it, \
+                 `kitty recover` signs it again",
+            ),
+            KittyError::Usage(_) => Some("`kitty help <command>` lists its arguments"),
+            KittyError::UnknownBundle(_) => {
+                Some("`kitty list --group-by bundle` lists the bundles")
+            }
+            KittyError::NotInTrash { .. } => Some("`kitty trash list` lists the trashed files"),
+            KittyError::NotAFile { .. } => Some("`kitty restore` puts it in place"),
+            KittyError::ContentMissing { .. } => {
+                Some("`kitty recover` checks what else is missing and repairs the manifest")
+            }
+            KittyError::NoHome => Some("set KITTY_REPO to the repository instead"),
+            _ => None,
+        }
+    }
+
+    /// The error as an object for `--json` output: `code`, `message`, `path` and `hint`
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "code": self.code(),
+            "message": self.localized(),
+            "path": self.path(),
+            "hint": self.hint(),
+        })
+    }
+
+    fn parts(&self) -> (&'static str, String) {
+        match self {
+            KittyError::Io(e) => ("io", e.to_string()),
+            KittyError::RepositoryExists => ("repository-exists", String::new()),
+            KittyError::RepositoryNotFound => ("repository-not-found", String::new()),
+            KittyError::InvalidPassword => ("invalid-password", String::new()),
+            KittyError::Encryption(detail) => ("encryption", detail.clone()),
+            KittyError::Decryption(detail) => ("decryption", detail.clone()),
+            KittyError::FileNotTracked(detail) => ("file-not-tracked", detail.clone()),
+            KittyError::PrivilegeRequired(detail) => ("privilege-required", detail.clone()),
+            KittyError::Serialization(e) => ("serialization", e.to_string()),
+            KittyError::HexDecoding(e) => ("hex-decoding", e.to_string()),
+            KittyError::Database(detail) => ("database", detail.clone()),
+            KittyError::StorageType(detail) => ("storage-type", detail.clone()),
+            KittyError::Package(detail) => ("package", detail.clone()),
+            KittyError::PasswordUnavailable(detail) => ("password-unavailable", detail.clone()),
+            KittyError::Daemon(detail) => ("daemon", detail.clone()),
+            KittyError::UnsupportedFormat(detail) => ("unsupported-format", detail.clone()),
+            KittyError::Ordering(detail) => ("ordering", detail.clone()),
+            KittyError::FileTooLarge(detail) => ("file-too-large", detail.clone()),
+            KittyError::Collector(detail) => ("collector", detail.clone()),
+            KittyError::Filter(detail) => ("filter", detail.clone()),
+            KittyError::AmbiguousPath(detail) => ("ambiguous-path", detail.clone()),
+            KittyError::Pattern(detail) => ("pattern", detail.clone()),
+            KittyError::Alias(detail) => ("alias", detail.clone()),
+            KittyError::Inventory(detail) => ("inventory", detail.clone()),
+            KittyError::Role(detail) => ("role", detail.clone()),
+            KittyError::Condition(detail) => ("condition", detail.clone()),
+            KittyError::Template(detail) => ("template", detail.clone()),
+            KittyError::Vault(detail) => ("vault", detail.clone()),
+            KittyError::Aws(detail) => ("aws", detail.clone()),
+            KittyError::Mirror(detail) => ("mirror", detail.clone()),
+            KittyError::Locked(detail) => ("locked", detail.clone()),
+            KittyError::Conflict(detail) => ("conflict", detail.clone()),
+            KittyError::Expiry(detail) => ("expiry", detail.clone()),
+            KittyError::Validation(detail) => ("validation", detail.clone()),
+            KittyError::StalePlan(detail) => ("stale-plan", detail.clone()),
+            KittyError::Dump(detail) => ("dump", detail.clone()),
+            KittyError::Session(detail) => ("session", detail.clone()),
+            KittyError::ReadOnly(detail) => ("read-only", detail.clone()),
+            KittyError::Share(detail) => ("share", detail.clone()),
+            KittyError::Patch(detail) => ("patch", detail.clone()),
+            KittyError::Mail(detail) => ("mail", detail.clone()),
+            KittyError::PointInTime(detail) => ("point-in-time", detail.clone()),
+            KittyError::Mount(detail) => ("mount", detail.clone()),
+            KittyError::Signature(detail) => ("signature", detail.clone()),
+            KittyError::Usage(detail) => ("usage", detail.clone()),
+            KittyError::UnknownBundle(bundle) => ("unknown-bundle", bundle.clone()),
+            KittyError::NotInTrash { path } => ("not-in-trash", path.clone()),
+            KittyError::TrackedAgain { path } => ("tracked-again", path.clone()),
+            KittyError::OutputExists { paths } => ("output-exists", paths.join(", ")),
+            KittyError::NotAFile { path } => ("not-a-file", path.clone()),
+            KittyError::NotText { path } => ("not-text", path.clone()),
+            KittyError::ContentMissing { path } => ("content-missing", path.clone()),
+            KittyError::Move { path, reason } => ("move", format!("{}: {}", path, reason)),
+            KittyError::Incomplete(detail) => ("incomplete", detail.clone()),
+            KittyError::EmptyTree { path } => ("empty-tree", path.clone()),
+            KittyError::NoHome => ("no-home", String::new()),
+            KittyError::PrivilegedRead { path } => ("privileged-read", path.clone()),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -63,6 +363,10 @@ pub struct Repository {
     pub created_at: DateTime<Utc>,
     pub salt: String, // Hex encoded
     pub files: Vec<TrackedFile>,
+    #[serde(default)]
+    pub generation: u64, // Bumped on every save, so a writer can tell the manifest changed under it
+    #[serde(default)]
+    pub password_set_at: Option<DateTime<Utc>>, // Repositories from before this was recorded have none
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -72,11 +376,172 @@ pub struct TrackedFile {
     pub added_at: DateTime<Utc>,
     pub last_updated: DateTime<Utc>,
     pub hash: String, // Hash of file content for quick comparison
+    #[serde(default)]
+    pub package: Option<String>, // Owning package, if the file came from one
+    #[serde(default)]
+    pub package_manager: Option<String>, // dpkg, rpm or pacman
+    #[serde(default)]
+    pub versions: Vec<FileVersion>, // Previous contents, oldest first
+    #[serde(default)]
+    pub bundle: Option<String>, // Named group of files managed together
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub after: Vec<String>, // Paths or bundles to restore before this file
+    #[serde(default)]
+    pub metadata: Option<FileMetadata>, // Mode and ownership when last added
+    #[serde(default)]
+    pub ignore_drift: bool, // Pinned: differs per machine, so changes are not drift
+    #[serde(default)]
+    pub filters: Vec<ContentFilter>, // Applied to content before it is stored or compared
+    #[serde(default)]
+    pub conditions: Conditions, // Platforms the file applies to; empty means all
+    #[serde(default)]
+    pub revision: u64, // Repository generation in which the entry last changed
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>, // Temporary entries are untracked by `prune --expired` after this
+    #[serde(default)]
+    pub frozen: bool, // Live file kept read-only so it is only changed through kitty
+    #[serde(default)]
+    pub scope: Option<Scope>, // Given with `add --scope`; otherwise decided by the path
+    #[serde(default)]
+    pub tree: Option<String>, // Root of the directory the file was added with as one unit
+}
+
+impl Repository {
+    /// Generation the manifest gets when it is saved next
+    pub fn next_generation(&self) -> u64 {
+        self.generation + 1
+    }
+
+    /// Mark a file as changed by the next save
+    pub fn touch(&mut self, index: usize) {
+        self.files[index].revision = self.next_generation();
+    }
+
+    /// Fail unless the saved manifest is still at the generation this one was read at
+    ///
+    /// `changed` lists the files the saved manifest changed after a given generation.
+    pub fn check_saved(
+        &self,
+        saved: u64,
+        changed: impl FnOnce(u64) -> Vec<String>,
+    ) -> Result<(), KittyError> {
+        if saved == self.generation {
+            return Ok(());
+        }
+        let mut detail = format!(
+            "the manifest was read at generation {} and is now at {}",
+            self.generation, saved
+        );
+        let changed = changed(self.generation);
+        if !changed.is_empty() {
+            detail.push_str(&format!(" ({} changed)", changed.join(", ")));
+        }
+        detail.push_str("; run the command again");
+        Err(KittyError::Conflict(detail))
+    }
+}
+
+impl TrackedFile {
+    /// Whether the file belongs on this machine according to its conditions
+    pub fn applies_here(&self) -> bool {
+        self.conditions.matches(Platform::current())
+    }
+
+    /// The scope given when the file was added, or else the one its path implies
+    pub fn scope(&self) -> Scope {
+        self.scope
+            .unwrap_or_else(|| Scope::of(std::path::Path::new(&self.original_path)))
+    }
+}
+
+/// A rule that rewrites a file's content before it is stored or compared
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ContentFilter {
+    /// Drop every line matching the pattern
+    Strip(String),
+
+    /// Replace every match of the pattern
+    Replace { pattern: String, with: String },
+}
+
+/// A previous version of a tracked file's content
+#[derive(Serialize, Deserialize, Clone)]
+pub struct FileVersion {
+    pub repo_path: String, // Relative path of the stored blob
+    pub hash: String,
+    pub saved_at: DateTime<Utc>, // When this content was captured
+}
+
+/// Whose file an entry is
+///
+/// System files may be read and written with sudo and default to root
+/// ownership. User files never cause a privilege prompt.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    User,
+    System,
+}
+
+/// Directories whose files belong to the system rather than a user
+const SYSTEM_DIRS: [&str; 7] = ["/etc", "/usr", "/opt", "/var", "/srv", "/boot", "/lib"];
+
+impl Scope {
+    /// The scope of a path nobody chose one for: system below the system directories
+    pub fn of(path: &std::path::Path) -> Self {
+        if SYSTEM_DIRS.iter().any(|dir| path.starts_with(dir)) {
+            Scope::System
+        } else {
+            Scope::User
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Scope::User => "user",
+            Scope::System => "system",
+        }
+    }
+}
+
+/// Permissions, ownership and modification time of a tracked file on disk
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct FileMetadata {
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    #[serde(default)]
+    pub modified: Option<DateTime<Utc>>, // Files added before mtimes were recorded have none
+}
+
+impl FileMetadata {
+    /// Read the mode, owner and mtime of a file (None if it cannot be read)
+    pub fn from_path(path: &std::path::Path) -> Option<Self> {
+        let metadata = fs::metadata(path).ok()?;
+        Some(Self {
+            mode: metadata.mode() & 0o7777,
+            uid: metadata.uid(),
+            gid: metadata.gid(),
+            modified: metadata.modified().ok().map(DateTime::<Utc>::from),
+        })
+    }
 }
 
 pub struct Crypto {
     salt: [u8; SALT_LEN],
     key: [u8; KEY_LEN],
+
+    /// Unlocked with the read key, which can decrypt but not change the repository
+    read_only: bool,
+
+    /// Public key every manifest must be signed with, for repositories with a read key
+    manifest_key: Option<Vec<u8>>,
+
+    /// Signs manifests; only the admin password unwraps it
+    signer: Option<Ed25519KeyPair>,
 }
 
 impl Crypto {
@@ -86,15 +551,23 @@ impl Crypto {
         rng.fill(&mut salt);
 
         let mut key = [0u8; KEY_LEN];
-        pbkdf2::derive(
-            pbkdf2::PBKDF2_HMAC_SHA256,
-            std::num::NonZeroU32::new(PBKDF2_ITERATIONS).unwrap(),
-            &salt,
-            password.as_bytes(),
-            &mut key,
-        );
+        timings::time(Phase::KeyDerivation, || {
+            pbkdf2::derive(
+                pbkdf2::PBKDF2_HMAC_SHA256,
+                std::num::NonZeroU32::new(PBKDF2_ITERATIONS).unwrap(),
+                &salt,
+                password.as_bytes(),
+                &mut key,
+            )
+        });
 
-        Self { salt, key }
+        Self {
+            salt,
+            key,
+            read_only: false,
+            manifest_key: None,
+            signer: None,
+        }
     }
 
     pub fn from_password_and_salt(password: &str, salt: &[u8]) -> Self {
@@ -111,20 +584,83 @@ impl Crypto {
         }
 
         let mut key = [0u8; KEY_LEN];
-        pbkdf2::derive(
-            pbkdf2::PBKDF2_HMAC_SHA256,
-            std::num::NonZeroU32::new(PBKDF2_ITERATIONS).unwrap(),
-            &salt_array,
-            password.as_bytes(),
-            &mut key,
-        );
+        timings::time(Phase::KeyDerivation, || {
+            pbkdf2::derive(
+                pbkdf2::PBKDF2_HMAC_SHA256,
+                std::num::NonZeroU32::new(PBKDF2_ITERATIONS).unwrap(),
+                &salt_array,
+                password.as_bytes(),
+                &mut key,
+            )
+        });
 
         Self {
             salt: salt_array,
             key,
+            read_only: false,
+            manifest_key: None,
+            signer: None,
         }
     }
 
+    /// The key encrypted with `wrapping`, so another password can unlock it
+    pub fn wrap_key(&self, wrapping: &Crypto) -> Result<Vec<u8>, KittyError> {
+        wrapping.encrypt(&self.key)
+    }
+
+    /// The read-only key `wrap_key` encrypted with `wrapping`, for the repository with `salt`
+    pub fn unwrap_read_key(
+        wrapped: &[u8],
+        wrapping: &Crypto,
+        salt: &[u8],
+    ) -> Result<Self, KittyError> {
+        let key = wrapping.decrypt(wrapped)?;
+        if key.len() != KEY_LEN || salt.len() != SALT_LEN {
+            return Err(KittyError::Decryption("Invalid read key".to_string()));
+        }
+        let mut crypto = Self {
+            salt: [0u8; SALT_LEN],
+            key: [0u8; KEY_LEN],
+            read_only: true,
+            manifest_key: None,
+            signer: None,
+        };
+        crypto.salt.copy_from_slice(salt);
+        crypto.key.copy_from_slice(&key);
+        Ok(crypto)
+    }
+
+    pub fn salt(&self) -> &[u8] {
+        &self.salt
+    }
+
+    /// Require manifests signed with `public`, signing them with `signer` when it is the admin's
+    pub fn with_manifest_key(mut self, public: Vec<u8>, signer: Option<Ed25519KeyPair>) -> Self {
+        self.manifest_key = Some(public);
+        self.signer = signer;
+        self
+    }
+
+    /// The public key manifests are checked against, if the repository signs them
+    pub fn manifest_key(&self) -> Option<&[u8]> {
+        self.manifest_key.as_deref()
+    }
+
+    /// The admin's signing key, when the admin password unlocked the repository
+    pub fn signer(&self) -> Option<&Ed25519KeyPair> {
+        self.signer.as_ref()
+    }
+
+    /// Fail when unlocked with the read key, before a command changes the repository
+    pub fn require_admin(&self) -> Result<(), KittyError> {
+        if self.read_only {
+            return Err(KittyError::ReadOnly(
+                "the read key can restore files but not change the repository".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
     pub fn encrypt(&self, data: &[u8]) -> Result<Vec<u8>, KittyError> {
         let mut nonce = [0u8; NONCE_LEN];
         let mut rng = OsRng;
@@ -133,8 +669,7 @@ impl Crypto {
         let cipher = ChaCha20Poly1305::new(Key::from_slice(&self.key));
         let nonce = Nonce::from_slice(&nonce);
 
-        let ciphertext = cipher
-            .encrypt(nonce, data)
+        let ciphertext = timings::time(Phase::Encryption, || cipher.encrypt(nonce, data))
             .map_err(|e| KittyError::Encryption(e.to_string()))?;
 
         // Prepend the nonce to the ciphertext
@@ -156,8 +691,7 @@ impl Crypto {
         let cipher = ChaCha20Poly1305::new(Key::from_slice(&self.key));
         let nonce = Nonce::from_slice(nonce);
 
-        let plaintext = cipher
-            .decrypt(nonce, ciphertext)
+        let plaintext = timings::time(Phase::Decryption, || cipher.decrypt(nonce, ciphertext))
             .map_err(|e| KittyError::Decryption(e.to_string()))?;
 
         Ok(plaintext)
@@ -166,81 +700,185 @@ impl Crypto {
 
 /// Options for initializing a repository
 pub struct InitOptions {
-    /// Use SQLite for storage instead of files
-    pub use_sqlite: bool,
+    /// Storage backend: file, sqlite or redb
+    pub backend: String,
+
+    /// Manifest file or directory of files to track right after initializing
+    pub adopt: Option<String>,
+
+    /// Dump written by `kitty dump` to fill the repository from
+    pub import: Option<String>,
+
+    /// Directory to create the repository in, instead of the current directory
+    pub path: Option<String>,
+
+    /// Name of the repository directory, instead of `.kitty`
+    pub dir_name: Option<String>,
+
+    /// Settings to write instead of the defaults
+    pub settings: Option<RepoConfig>,
+
+    /// Files to track right after initializing
+    pub track: Vec<String>,
+
+    /// Ask for a second, read-only password that can restore but not change the repository
+    pub read_key: bool,
 }
 
 impl Default for InitOptions {
     fn default() -> Self {
-        Self { use_sqlite: false }
+        Self {
+            backend: "file".to_string(),
+            adopt: None,
+            import: None,
+            path: None,
+            dir_name: None,
+            settings: None,
+            track: Vec::new(),
+            read_key: false,
+        }
     }
 }
 
 pub fn init_repository_with_options(options: &InitOptions) -> Result<(), KittyError> {
-    let repo_path = get_repository_path()?;
+    let custom_location = options.path.is_some() || options.dir_name.is_some();
+    let repo_path = match repository_path_from_env() {
+        Some(path) if !custom_location => path,
+        _ => {
+            let parent = match &options.path {
+                Some(path) => PathBuf::from(path),
+                None => std::env::current_dir()?,
+            };
+            parent.join(options.dir_name.as_deref().unwrap_or(REPOSITORY_DIR))
+        }
+    };
 
     if repo_path.exists() {
         return Err(KittyError::RepositoryExists);
     }
 
+    if !["file", "sqlite", "redb"].contains(&options.backend.as_str()) {
+        return Err(KittyError::StorageType(format!(
+            "Unknown backend: {} (expected file, sqlite or redb)",
+            options.backend
+        )));
+    }
+
+    // Get password from user
+    if options.import.is_some() {
+        print!("Enter the password of the dumped repository: ");
+    } else {
+        print!("Enter a password for the repository: ");
+    }
+    io::stdout().flush()?;
+    let password = read_password()?;
+
+    let read_password = if options.read_key {
+        print!("Enter a read-only password for machines that only restore: ");
+        io::stdout().flush()?;
+        let read_password = read_password()?;
+        if read_password == password {
+            return Err(KittyError::ReadOnly(
+                "the read-only password must differ from the admin password".to_string(),
+            ));
+        }
+        Some(read_password)
+    } else {
+        None
+    };
+
+    // Read the dump before anything is created, so a wrong password leaves nothing behind
+    let dump = match &options.import {
+        Some(file) => Some(read_dump(Path::new(file), &password)?),
+        None => None,
+    };
+
     // Create repository directory structure
     fs::create_dir_all(&repo_path)?;
 
     // Only create files directory for file-based storage
-    if !options.use_sqlite {
+    if options.backend == "file" {
         fs::create_dir_all(repo_path.join("files"))?;
     }
 
-    // Get password from user
-    print!("Enter a password for the repository: ");
-    io::stdout().flush()?;
-    let password = read_password()?;
-
     // Create crypto instance
     let crypto = Crypto::new_from_password(&password);
+    write_format(&repo_path)?;
 
     // Create initial repository configuration
     let repository = Repository {
         created_at: Utc::now(),
         salt: hex::encode(crypto.salt),
         files: Vec::new(),
+        generation: 0,
+        password_set_at: Some(Utc::now()),
     };
 
-    if options.use_sqlite {
-        // Initialize SQLite storage
-        println!("Using SQLite storage backend");
-
-        use crate::storage::sqlite::SqliteStorage;
+    if options.backend != "file" {
+        if options.backend == "sqlite" {
+            println!("Using SQLite storage backend");
+        } else {
+            println!("Using redb storage backend");
+        }
 
-        // Create and initialize the SQLite database
-        let mut storage = SqliteStorage::new(&repo_path)?;
+        // Create a marker file to indicate which database we're using
+        write_atomic(&repo_path.join("storage.type"), &options.backend)?;
 
-        // Save the repository configuration to SQLite
+        // Create and initialize the database, then save the repository configuration to it
+        let mut storage = open_database(&repo_path)?;
         storage.save_repository(&repository)?;
 
-        // Create a marker file to indicate we're using SQLite
-        fs::write(repo_path.join("storage.type"), "sqlite")?;
-
-        // No need to create the files directory for SQLite as we'll store content in the database
-        println!("Note: When using SQLite, file content is stored in the database");
+        // No need to create the files directory as we'll store content in the database
+        println!("Note: File content is stored in the database");
     } else {
         // Use file-based storage
         println!("Using file-based storage backend");
 
-        // Serialize and encrypt the repository configuration
-        let config_json = serde_json::to_string(&repository)?;
-        let encrypted_config = crypto.encrypt(config_json.as_bytes())?;
-
-        // Write encrypted configuration to file
-        fs::write(repo_path.join("config.enc"), encrypted_config)?;
+        // Write the encrypted repository configuration
+        write_config(&repo_path, &crypto, &repository)?;
 
         // Create a marker file to indicate we're using file-based storage
-        fs::write(repo_path.join("storage.type"), "file")?;
+        write_atomic(&repo_path.join("storage.type"), "file")?;
     }
 
     // Store the salt in a separate file for easier access
-    fs::write(repo_path.join("salt.key"), hex::encode(&crypto.salt))?;
+    write_atomic(&repo_path.join("salt.key"), hex::encode(&crypto.salt))?;
+
+    // Write settings even when they are the defaults, so they are easy to discover and edit
+    match &options.settings {
+        Some(settings) => settings.save(&repo_path)?,
+        None => RepoConfig::default().save(&repo_path)?,
+    }
+
+    if let Some(read_password) = &read_password {
+        write_read_key(&repo_path, &crypto, &password, read_password)?;
+        println!("Read key written to {}", KEYS_FILE);
+    }
 
     println!("Repository initialized successfully.");
+
+    if custom_location {
+        // Commands run elsewhere would otherwise look for ./.kitty
+        let repo_path = fs::canonicalize(&repo_path)?;
+        let location_file = record_repository_location(&repo_path)?;
+        println!(
+            "Repository location {} recorded in {}",
+            repo_path.display(),
+            location_file.display()
+        );
+    }
+
+    if let Some(dump) = dump {
+        load_dump(&repo_path, crypto, dump)?;
+    }
+
+    if let Some(source) = &options.adopt {
+        adopt_files(source, &password)?;
+    }
+
+    for path in &options.track {
+        add_file_with_password(path, &password)?;
+    }
+
     Ok(())
 }
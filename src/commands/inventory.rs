@@ -0,0 +1,91 @@
+use crate::{
+    commands::init::KittyError,
+    repository::keys::unlock_admin,
+    utils::{
+        file::{get_repository_path, load_repository},
+        inventory::{self, INVENTORY_FILE, PUBLIC_KEY_FILE},
+        password::read_repository_password,
+        theme,
+    },
+};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Options for `kitty inventory verify`
+#[derive(Default)]
+pub struct VerifyOptions {
+    /// Directory holding the inventory, instead of the repository
+    pub dir: Option<String>,
+
+    /// Public key to check against, in hex or as a file holding it
+    pub key: Option<String>,
+
+    /// Print the verified inventory as JSON
+    pub json: bool,
+}
+
+/// Write and sign the inventory now, e.g. right after enabling it
+pub fn write_inventory() -> Result<(), KittyError> {
+    let repo_path = get_repository_path()?;
+
+    if !repo_path.exists() {
+        return Err(KittyError::RepositoryNotFound);
+    }
+
+    // Get password from user
+    let password = read_repository_password(false)?;
+
+    let crypto = unlock_admin(&repo_path, &password)?;
+    let repository = load_repository(&repo_path, &crypto)?;
+
+    inventory::write(&repo_path, &crypto, &repository)?;
+
+    println!(
+        "{} Inventory of {} file(s) written to {}",
+        theme::success("SUCCESS:"),
+        repository.files.len(),
+        repo_path.join(INVENTORY_FILE).display()
+    );
+    println!(
+        "Public key: {}",
+        fs::read_to_string(repo_path.join(PUBLIC_KEY_FILE))?.trim()
+    );
+    Ok(())
+}
+
+/// Check the inventory against its signature; needs no password
+pub fn verify_inventory(options: Option<VerifyOptions>) -> Result<(), KittyError> {
+    let options = options.unwrap_or_default();
+    let dir = match &options.dir {
+        Some(dir) => PathBuf::from(dir),
+        None => get_repository_path()?,
+    };
+
+    let key = match &options.key {
+        Some(key) if Path::new(key).is_file() => Some(fs::read_to_string(key)?),
+        Some(key) => Some(key.clone()),
+        None => None,
+    };
+
+    let inventory = inventory::verify(&dir, key.as_deref())?;
+
+    if options.json {
+        println!("{}", serde_json::to_string_pretty(&inventory)?);
+        return Ok(());
+    }
+
+    println!(
+        "{} Inventory signature is valid: {} file(s), written {}",
+        theme::success("SUCCESS:"),
+        inventory.files.len(),
+        inventory.generated_at.format("%Y-%m-%d %H:%M:%S")
+    );
+    if options.key.is_none() {
+        println!(
+            "Checked against the key stored with the inventory; pass --key to pin a known key"
+        );
+    }
+    Ok(())
+}
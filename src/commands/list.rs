@@ -1,12 +1,23 @@
 use crate::{
-    commands::init::{Crypto, KittyError, TrackedFile},
-    storage::sqlite::SqliteStorage,
-    utils::file::{get_repository_path, get_repository_salt, get_storage_type},
+    commands::{
+        check::{compute_drift, DriftState},
+        init::{KittyError, TrackedFile},
+    },
+    repository::keys::unlock,
+    storage::{is_database, open_database},
+    tr,
+    utils::{
+        file::{format_size, get_repository_path, get_storage_type, load_repository},
+        password::read_repository_password,
+        select::{short_ids, PathPattern},
+        table::{fit_path, path_column_width, terminal_width},
+        theme,
+    },
 };
 use chrono::Local;
-use rpassword::read_password;
+use colored::{ColoredString, Colorize};
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     fs,
     io::{self, Write},
     path::Path,
@@ -20,8 +31,23 @@ pub struct ListOptions {
     /// Filter files by date (format: YYYY-MM-DD)
     pub date: Option<String>,
 
-    /// Group files by path components
-    pub group: bool,
+    /// Group files by these properties, nesting each grouping in the one before
+    pub group_by: Vec<GroupBy>,
+
+    /// Show only this many files, largest first
+    pub largest: Option<usize>,
+
+    /// Treat the path filter as a regular expression
+    pub regex: bool,
+
+    /// Print only the paths, one per line, for scripts
+    pub paths_only: bool,
+
+    /// End each path with NUL instead of a newline
+    pub null: bool,
+
+    /// Show whether each file matches the repository, was changed, or is missing
+    pub status: bool,
 }
 
 impl Default for ListOptions {
@@ -29,21 +55,54 @@ impl Default for ListOptions {
         Self {
             path: None,
             date: None,
-            group: false,
+            group_by: Vec::new(),
+            largest: None,
+            regex: false,
+            paths_only: false,
+            null: false,
+            status: false,
         }
     }
 }
 
+/// Write the paths of `files` and nothing else, so they can be piped into other tools
+fn print_paths(files: &[TrackedFile], null: bool) -> Result<(), KittyError> {
+    let terminator = if null { '\0' } else { '\n' };
+    let mut stdout = io::stdout().lock();
+    for file in files {
+        match write!(stdout, "{}{}", file.original_path, terminator) {
+            // The reader has seen enough, as with `| head`
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => return Ok(()),
+            result => result?,
+        }
+    }
+    match stdout.flush() {
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => Ok(result?),
+    }
+}
+
 /// Filter files based on the provided options
-fn filter_files(files: &[TrackedFile], options: &ListOptions) -> Vec<TrackedFile> {
+fn filter_files(
+    files: &[TrackedFile],
+    options: &ListOptions,
+) -> Result<Vec<TrackedFile>, KittyError> {
     let mut result = Vec::new();
+    let pattern = match &options.path {
+        Some(path_filter) => PathPattern::parse(path_filter, options.regex)?,
+        None => None,
+    };
 
     for file in files {
         let mut include = true;
 
         // Apply path filter if specified
         if let Some(path_filter) = &options.path {
-            if !file.original_path.contains(path_filter) {
+            let matched = match &pattern {
+                Some(pattern) => pattern.is_match(&file.original_path),
+                None => file.original_path.contains(path_filter),
+            };
+            if !matched {
                 include = false;
             }
         }
@@ -61,47 +120,267 @@ fn filter_files(files: &[TrackedFile], options: &ListOptions) -> Vec<TrackedFile
         }
     }
 
-    result
+    Ok(result)
 }
 
-/// Display files grouped by common directories
-fn display_grouped_files(files: &[TrackedFile]) {
-    let mut groups: HashMap<String, Vec<TrackedFile>> = HashMap::new();
+/// Width of the last-updated column, `YYYY-MM-DD HH:MM:SS`
+const DATE_WIDTH: usize = 19;
 
-    // Group files by directory
-    for file in files {
-        let path = Path::new(&file.original_path);
-        let parent = path.parent().and_then(|p| p.to_str()).unwrap_or("Other");
+/// Width of the size columns in `--largest`
+const SIZE_WIDTH: usize = 12;
+
+/// Width of the state column in `--status`, fitting `unreadable`
+const STATE_WIDTH: usize = 10;
+
+/// A file's drift state for `--status`, padded to the column and colored like `kitty status`
+fn state_cell(file: &TrackedFile) -> ColoredString {
+    // Files for other platforms would only ever show up as missing
+    if !file.applies_here() {
+        return format!("{:<STATE_WIDTH$}", "-").dimmed();
+    }
+    let state = compute_drift(file).state;
+    let cell = format!("{:<STATE_WIDTH$}", state.name());
+    match state {
+        DriftState::Clean => cell.normal(),
+        DriftState::Pinned => cell.dimmed(),
+        DriftState::Modified => theme::warning(&cell),
+        DriftState::Missing | DriftState::Unreadable => theme::error(&cell),
+    }
+}
 
-        groups
-            .entry(parent.to_string())
-            .or_insert_with(Vec::new)
-            .push(file.clone());
+/// IDs all have the same length, but never make the column narrower than its heading
+fn id_column_width(ids: &HashMap<&str, String>) -> usize {
+    ids.values()
+        .map(|id| id.len())
+        .max()
+        .unwrap_or(0)
+        .max("ID".len())
+}
+
+/// A property files can be grouped by in `list --group-by`
+#[derive(Clone, Copy, PartialEq)]
+pub enum GroupBy {
+    /// Parent directory
+    Directory,
+
+    /// Day the file was last updated
+    Date,
+
+    /// Each of the file's tags; untagged files form their own group
+    Tag,
+
+    /// Bundle the file belongs to
+    Bundle,
+
+    /// Directory tree the file was added with
+    Tree,
+}
+
+impl GroupBy {
+    pub fn parse(name: &str) -> Result<Self, KittyError> {
+        match name {
+            "dir" => Ok(GroupBy::Directory),
+            "date" => Ok(GroupBy::Date),
+            "tag" => Ok(GroupBy::Tag),
+            "bundle" => Ok(GroupBy::Bundle),
+            "tree" => Ok(GroupBy::Tree),
+            _ => Err(KittyError::Usage(format!(
+                "cannot group by '{}': use dir, date, tag, bundle or tree",
+                name
+            ))),
+        }
     }
 
-    // Display each group
-    for (group, group_files) in groups.iter() {
-        println!("\n[{}] - {} file(s)", group, group_files.len());
-        println!("{:<5} {:<50} {:<25}", "ID", "Filename", "Last Updated");
-        println!("{:<5} {:<50} {:<25}", "---", "--------", "------------");
+    /// Group names a file belongs to; only tags can give more than one
+    fn keys(self, file: &TrackedFile) -> Vec<String> {
+        match self {
+            GroupBy::Directory => vec![Path::new(&file.original_path)
+                .parent()
+                .and_then(|p| p.to_str())
+                .unwrap_or("Other")
+                .to_string()],
+            GroupBy::Date => vec![file
+                .last_updated
+                .with_timezone(&Local)
+                .format("%Y-%m-%d")
+                .to_string()],
+            GroupBy::Tag if file.tags.is_empty() => vec!["(untagged)".to_string()],
+            GroupBy::Tag => file.tags.clone(),
+            GroupBy::Bundle => vec![file
+                .bundle
+                .clone()
+                .unwrap_or_else(|| "(no bundle)".to_string())],
+            GroupBy::Tree => vec![file.tree.clone().unwrap_or_else(|| "(no tree)".to_string())],
+        }
+    }
+}
 
-        for (idx, file) in group_files.iter().enumerate() {
-            // Get just the filename instead of the full path
-            let filename = Path::new(&file.original_path)
+/// Display files grouped by each of `group_by` in turn, nesting the later groupings
+///
+/// Groups are sorted by name, except dates, which come newest first. Files are
+/// sorted by path. Once files are grouped by directory, only their names are shown.
+fn display_grouped_files(files: &[TrackedFile], group_by: &[GroupBy], ids: &HashMap<&str, String>) {
+    let by_directory = group_by.contains(&GroupBy::Directory);
+    let shown_name = |file: &TrackedFile| {
+        if by_directory {
+            Path::new(&file.original_path)
                 .file_name()
                 .and_then(|f| f.to_str())
-                .unwrap_or(&file.original_path);
+                .unwrap_or(&file.original_path)
+                .to_string()
+        } else {
+            file.original_path.clone()
+        }
+    };
+
+    // One width for every table, so nested groups line up
+    let indent = 2 * group_by.len().saturating_sub(1);
+    let id_width = id_column_width(ids);
+    let names: Vec<String> = files.iter().map(&shown_name).collect();
+    let name_width = path_column_width(
+        names.iter().map(String::as_str),
+        indent + id_width + DATE_WIDTH + 2,
+    )
+    .max("Filename".len());
+
+    let mut sorted: Vec<&TrackedFile> = files.iter().collect();
+    sorted.sort_by(|a, b| a.original_path.cmp(&b.original_path));
 
+    let table = GroupTable {
+        ids,
+        by_directory,
+        id_width,
+        name_width,
+        shown_name: &shown_name,
+    };
+    display_groups(&sorted, group_by, 0, &table);
+}
+
+/// Column layout shared by every group's table
+struct GroupTable<'a> {
+    ids: &'a HashMap<&'a str, String>,
+    by_directory: bool,
+    id_width: usize,
+    name_width: usize,
+    shown_name: &'a dyn Fn(&TrackedFile) -> String,
+}
+
+fn display_groups(files: &[&TrackedFile], group_by: &[GroupBy], depth: usize, table: &GroupTable) {
+    let Some((&grouping, nested)) = group_by.split_first() else {
+        return;
+    };
+
+    let mut groups: BTreeMap<String, Vec<&TrackedFile>> = BTreeMap::new();
+    for &file in files {
+        for key in grouping.keys(file) {
+            groups.entry(key).or_default().push(file);
+        }
+    }
+    let mut groups: Vec<(String, Vec<&TrackedFile>)> = groups.into_iter().collect();
+    if grouping == GroupBy::Date {
+        groups.reverse();
+    }
+
+    let indent = " ".repeat(2 * depth);
+    for (group, group_files) in groups {
+        let heading = format!("{}[{}] - {} file(s)", indent, group, group_files.len());
+        match terminal_width() {
+            Some(width) => println!("\n{}", fit_path(&heading, width)),
+            None => println!("\n{}", heading),
+        }
+
+        if !nested.is_empty() {
+            display_groups(&group_files, nested, depth + 1, table);
+            continue;
+        }
+
+        let (id_width, name_width) = (table.id_width, table.name_width);
+        let heading = if table.by_directory {
+            "Filename"
+        } else {
+            "Path"
+        };
+        println!(
+            "{}{:<id_width$} {:<name_width$} Last Updated",
+            indent, "ID", heading
+        );
+        println!(
+            "{}{:<id_width$} {:<name_width$} ------------",
+            indent,
+            "--",
+            "-".repeat(heading.len())
+        );
+
+        for file in group_files {
             let last_updated = file
                 .last_updated
                 .with_timezone(&Local)
                 .format("%Y-%m-%d %H:%M:%S");
 
-            println!("{:<5} {:<50} {:<25}", idx + 1, filename, last_updated);
+            println!(
+                "{}{:<id_width$} {:<name_width$} {}",
+                indent,
+                table.ids[file.repo_path.as_str()],
+                fit_path(&(table.shown_name)(file), name_width),
+                last_updated
+            );
         }
     }
 }
 
+/// Display the largest files by stored size, counting current content and history separately
+fn display_largest_files(
+    files: &[TrackedFile],
+    count: usize,
+    ids: &HashMap<&str, String>,
+    stored_size: impl Fn(&str) -> u64,
+) {
+    let mut sized: Vec<(&TrackedFile, u64, u64)> = files
+        .iter()
+        .map(|file| {
+            let history = file
+                .versions
+                .iter()
+                .map(|v| stored_size(&v.repo_path))
+                .sum();
+            (file, stored_size(&file.repo_path), history)
+        })
+        .collect();
+    sized.sort_by_key(|&(_, size, history)| std::cmp::Reverse(size + history));
+
+    let id_width = id_column_width(ids);
+    let path_width = path_column_width(
+        sized
+            .iter()
+            .take(count)
+            .map(|(file, _, _)| file.original_path.as_str()),
+        id_width + 2 * SIZE_WIDTH + 3,
+    )
+    .max("Path".len());
+
+    println!(
+        "\n{:<id_width$} {:<path_width$} {:>SIZE_WIDTH$} {:>SIZE_WIDTH$}",
+        "ID", "Path", "Size", "History"
+    );
+    println!(
+        "{:<id_width$} {:<path_width$} {:>SIZE_WIDTH$} {:>SIZE_WIDTH$}",
+        "--", "----", "----", "-------"
+    );
+
+    for (file, size, history) in sized.iter().take(count) {
+        println!(
+            "{:<id_width$} {:<path_width$} {:>SIZE_WIDTH$} {:>SIZE_WIDTH$}",
+            ids[file.repo_path.as_str()],
+            fit_path(&file.original_path, path_width),
+            format_size(*size),
+            format_size(*history)
+        );
+    }
+
+    let total: u64 = sized.iter().map(|&(_, size, history)| size + history).sum();
+    println!("\n{}", tr!("list-total-stored", size = format_size(total)));
+}
+
 /// Lists all files tracked in the kitty repository
 pub fn list_files(options: Option<ListOptions>) -> Result<(), KittyError> {
     let options = options.unwrap_or_default();
@@ -111,74 +390,110 @@ pub fn list_files(options: Option<ListOptions>) -> Result<(), KittyError> {
         return Err(KittyError::RepositoryNotFound);
     }
 
-    // Get password from user
-    print!("Enter repository password: ");
-    io::stdout().flush()?;
-    let password = read_password()?;
-    println!(); // Add a newline after password input
+    // Get password from user, keeping stdout for the paths with --paths-only
+    let password = read_repository_password(options.paths_only)?;
 
     // Get the storage type
     let storage_type = get_storage_type(&repo_path)?;
 
-    // Get salt and create crypto instance
-    let salt_str = get_repository_salt(&repo_path)?;
-    let config_salt = hex::decode(&salt_str)?;
-    let crypto = Crypto::from_password_and_salt(&password, &config_salt);
+    // Unlock the repository key with the password
+    let crypto = unlock(&repo_path, &password)?;
 
-    // Load repository based on storage type
-    let repository = if storage_type == "sqlite" {
-        // Use SQLite storage
-        let storage = SqliteStorage::new(&repo_path)?;
-        storage.load_repository()?
-    } else {
-        // Use file-based storage
-        let encrypted_config = fs::read(repo_path.join("config.enc"))?;
-        let decrypted_config = crypto.decrypt(&encrypted_config)?;
-        serde_json::from_slice(&decrypted_config)?
-    };
+    let repository = load_repository(&repo_path, &crypto)?;
+
+    // IDs are worked out over the whole repository so they stay the same when filtering
+    let ids: HashMap<&str, String> = repository
+        .files
+        .iter()
+        .map(|file| file.repo_path.as_str())
+        .zip(short_ids(&repository.files))
+        .collect();
 
     // Apply filters to the file list
-    let filtered_files = filter_files(&repository.files, &options);
+    let filtered_files = filter_files(&repository.files, &options)?;
+
+    if options.paths_only {
+        return print_paths(&filtered_files, options.null);
+    }
 
     if filtered_files.is_empty() {
         if options.path.is_some() || options.date.is_some() {
-            println!("No files match the specified filters.");
+            println!("{}", tr!("list-no-match"));
         } else {
-            println!("No files are currently tracked in the repository.");
+            println!("{}", tr!("no-files-tracked"));
         }
         return Ok(());
     }
 
-    // If grouping is enabled, display files by group
-    if options.group {
-        display_grouped_files(&filtered_files);
+    if let Some(count) = options.largest {
+        let storage = if is_database(&storage_type) {
+            Some(open_database(&repo_path)?)
+        } else {
+            None
+        };
+        display_largest_files(&filtered_files, count, &ids, |path| match &storage {
+            Some(storage) => storage.content_size(path).unwrap_or(0),
+            None => fs::metadata(repo_path.join(path))
+                .map(|m| m.len())
+                .unwrap_or(0),
+        });
+    } else if !options.group_by.is_empty() {
+        // If grouping is enabled, display files by group
+        display_grouped_files(&filtered_files, &options.group_by, &ids);
     } else {
-        // Display the tracked files in a formatted table
-        println!("\n{:<5} {:<50} {:<25}", "ID", "Path", "Last Updated");
-        println!("{:<5} {:<50} {:<25}", "---", "----", "------------");
-
-        for (idx, file) in filtered_files.iter().enumerate() {
-            let path_display = if file.original_path.len() > 50 {
-                format!(
-                    "...{}",
-                    &file.original_path[file.original_path.len() - 47..]
-                )
-            } else {
-                file.original_path.clone()
-            };
+        // Display the tracked files in a table sized to the terminal
+        let id_width = id_column_width(&ids);
+        let state_width = if options.status { STATE_WIDTH + 1 } else { 0 };
+        let path_width = path_column_width(
+            filtered_files
+                .iter()
+                .map(|file| file.original_path.as_str()),
+            id_width + state_width + DATE_WIDTH + 2,
+        )
+        .max("Path".len());
 
+        if options.status {
+            println!(
+                "\n{:<id_width$} {:<STATE_WIDTH$} {:<path_width$} Last Updated",
+                "ID", "State", "Path"
+            );
+            println!(
+                "{:<id_width$} {:<STATE_WIDTH$} {:<path_width$} ------------",
+                "--", "-----", "----"
+            );
+        } else {
+            println!("\n{:<id_width$} {:<path_width$} Last Updated", "ID", "Path");
+            println!("{:<id_width$} {:<path_width$} ------------", "--", "----");
+        }
+
+        for file in &filtered_files {
             // Format the last updated date in a human-readable format
             let last_updated = file
                 .last_updated
                 .with_timezone(&Local)
                 .format("%Y-%m-%d %H:%M:%S");
 
-            println!("{:<5} {:<50} {:<25}", idx + 1, path_display, last_updated);
+            if options.status {
+                println!(
+                    "{:<id_width$} {} {:<path_width$} {}",
+                    ids[file.repo_path.as_str()],
+                    state_cell(file),
+                    fit_path(&file.original_path, path_width),
+                    last_updated
+                );
+            } else {
+                println!(
+                    "{:<id_width$} {:<path_width$} {}",
+                    ids[file.repo_path.as_str()],
+                    fit_path(&file.original_path, path_width),
+                    last_updated
+                );
+            }
         }
     }
 
     // Display total count
-    println!("\nTotal tracked files: {}", filtered_files.len());
+    println!("\n{}", tr!("list-total", count = filtered_files.len()));
 
     Ok(())
 }
@@ -0,0 +1,140 @@
+use crate::{
+    commands::{
+        check::compute_drift,
+        init::{KittyError, TrackedFile},
+    },
+    repository::keys::unlock,
+    utils::{
+        file::{get_repository_path, load_repository},
+        password::read_script_password,
+        select::file_id,
+    },
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// Version of the `ls-files --json` output; bumped only when a field changes meaning or goes away
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Options for the ls-files command
+#[derive(Default)]
+pub struct LsFilesOptions {
+    /// Only list files at or below this path
+    pub path: Option<String>,
+
+    /// Print one JSON document instead of a line per file
+    pub json: bool,
+
+    /// Read the repository password from a file instead of KITTY_PASSWORD or a prompt
+    pub password_file: Option<String>,
+}
+
+/// One tracked file, as editor plugins see it
+#[derive(Serialize)]
+struct ListedFile {
+    path: String,
+    id: String,
+
+    /// clean, modified, missing, unreadable or pinned; `other-platform` for files
+    /// meant for other machines, which are not checked
+    state: &'static str,
+    hash: String,
+    bundle: Option<String>,
+    tags: Vec<String>,
+    pinned: bool,
+    frozen: bool,
+    versions: usize,
+    added_at: DateTime<Utc>,
+    last_updated: DateTime<Utc>,
+    expires_at: Option<DateTime<Utc>>,
+}
+
+/// The `ls-files --json` document
+#[derive(Serialize)]
+struct Listing {
+    format: u32,
+    repository: String,
+    files: Vec<ListedFile>,
+}
+
+fn listed(file: &TrackedFile) -> ListedFile {
+    let state = if file.applies_here() {
+        compute_drift(file).state.name()
+    } else {
+        "other-platform"
+    };
+    ListedFile {
+        path: file.original_path.clone(),
+        id: file_id(file).to_string(),
+        state,
+        hash: file.hash.clone(),
+        bundle: file.bundle.clone(),
+        tags: file.tags.clone(),
+        pinned: file.ignore_drift,
+        frozen: file.frozen,
+        versions: file.versions.len(),
+        added_at: file.added_at,
+        last_updated: file.last_updated,
+        expires_at: file.expires_at,
+    }
+}
+
+/// Whether `path` is `dir` or lies below it
+fn is_within(path: &str, dir: &str) -> bool {
+    let dir = dir.trim_end_matches('/');
+    path == dir
+        || path
+            .strip_prefix(dir)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
+/// List tracked files with their state, in a format kept stable for editor plugins
+///
+/// Without `json`, each line is the state, a tab and the path. Paths are
+/// sorted, so the output of two runs can be compared.
+pub fn ls_files(options: Option<LsFilesOptions>) -> Result<(), KittyError> {
+    let options = options.unwrap_or_default();
+    let repo_path = get_repository_path()?;
+
+    if !repo_path.exists() {
+        return Err(KittyError::RepositoryNotFound);
+    }
+
+    let password = read_script_password(options.password_file.as_deref())?;
+    let crypto = unlock(&repo_path, &password)?;
+    let repository = load_repository(&repo_path, &crypto)?;
+
+    // Editors pass the path of the open buffer, which may not be canonical
+    let prefix = options.path.as_deref().map(|path| {
+        std::path::Path::new(path)
+            .canonicalize()
+            .map(|p| p.to_string_lossy().into_owned())
+            .unwrap_or_else(|_| path.to_string())
+    });
+    let mut files: Vec<ListedFile> = repository
+        .files
+        .iter()
+        .filter(|f| {
+            prefix
+                .as_deref()
+                .is_none_or(|dir| is_within(&f.original_path, dir))
+        })
+        .map(listed)
+        .collect();
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+
+    if options.json {
+        let listing = Listing {
+            format: FORMAT_VERSION,
+            repository: repo_path.display().to_string(),
+            files,
+        };
+        println!("{}", serde_json::to_string_pretty(&listing)?);
+    } else {
+        for file in &files {
+            println!("{}\t{}", file.state, file.path);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,95 @@
+use crate::{
+    commands::init::KittyError,
+    repository::config::RepoConfig,
+    utils::{
+        file::{expand_home, get_repository_path},
+        mirror::{self, check_target},
+        theme,
+    },
+};
+use std::{fs, path::Path};
+
+/// Options for the mirror command
+#[derive(Default)]
+pub struct MirrorOptions {
+    /// Directory to mirror to; without one, every configured mirror is updated
+    pub dir: Option<String>,
+
+    /// Stop updating the mirror in `dir`, leaving its files in place
+    pub stop: bool,
+}
+
+/// Copy the encrypted repository to another directory and keep it up to date
+///
+/// Only encrypted content and repository metadata are copied, so the
+/// directory can live on a NAS, in a Syncthing folder or in Dropbox. No
+/// password is needed.
+pub fn mirror_repository(options: Option<MirrorOptions>) -> Result<(), KittyError> {
+    let options = options.unwrap_or_default();
+    let repo_path = get_repository_path()?;
+
+    if !repo_path.exists() {
+        return Err(KittyError::RepositoryNotFound);
+    }
+
+    let mut config = RepoConfig::load(&repo_path)?;
+
+    let Some(dir) = &options.dir else {
+        if config.mirrors.is_empty() {
+            println!("No mirrors are set up; add one with `kitty mirror <dir>`");
+            return Ok(());
+        }
+        for mirror in &config.mirrors {
+            let stats = mirror::sync(&repo_path, Path::new(mirror))?;
+            println!(
+                "{}: {} file(s) copied, {} removed",
+                mirror, stats.copied, stats.removed
+            );
+        }
+        return Ok(());
+    };
+
+    let dir = Path::new(&expand_home(dir)).to_path_buf();
+    if options.stop {
+        let dir = dir.canonicalize().unwrap_or(dir);
+        let before = config.mirrors.len();
+        config.mirrors.retain(|mirror| Path::new(mirror) != dir);
+        if config.mirrors.len() == before {
+            println!("{} is not a mirror of this repository", dir.display());
+            return Ok(());
+        }
+        config.save(&repo_path)?;
+        println!(
+            "{} Stopped mirroring to {}; its files were left in place",
+            theme::success("SUCCESS:"),
+            dir.display()
+        );
+        return Ok(());
+    }
+
+    check_target(&dir)?;
+    fs::create_dir_all(&dir)?;
+    let dir = dir.canonicalize()?;
+    if dir.starts_with(repo_path.canonicalize()?) {
+        let _ = fs::remove_dir(&dir);
+        return Err(KittyError::Mirror(
+            "a mirror cannot be inside the repository".to_string(),
+        ));
+    }
+
+    // Saved first, so the copy carries the setting too
+    let name = dir.to_string_lossy().to_string();
+    if !config.mirrors.contains(&name) {
+        config.mirrors.push(name.clone());
+        config.save(&repo_path)?;
+    }
+    let stats = mirror::sync(&repo_path, &dir)?;
+
+    println!(
+        "{} Mirroring to {} ({} file(s) copied); it is updated after every change",
+        theme::success("SUCCESS:"),
+        name,
+        stats.copied
+    );
+    Ok(())
+}
@@ -1,6 +1,41 @@
-pub mod init;
 pub mod add;
+pub mod adopt;
+pub mod at;
+pub mod audit;
+pub mod blame;
+pub mod blob;
+pub mod check;
+pub mod control;
+pub mod daemon;
+pub mod db;
 pub mod diff;
+pub mod docs;
+pub mod dump;
+pub mod exec;
+pub mod freeze;
+pub mod init;
+pub mod inventory;
 pub mod list;
+pub mod ls_files;
+pub mod mirror;
+pub mod mount;
+pub mod mv;
+pub mod patch;
+pub mod pin;
+pub mod plan;
+pub mod prompt;
+pub mod prune;
+pub mod purge;
+pub mod recover;
 pub mod remove;
-pub mod restore;
\ No newline at end of file
+pub mod resolve;
+pub mod restore;
+pub mod session;
+pub mod share;
+pub mod sops;
+pub mod stats;
+pub mod status;
+pub mod trash;
+pub mod unlink;
+pub mod vars;
+pub mod wizard;
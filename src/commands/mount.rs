@@ -0,0 +1,398 @@
+use crate::{
+    collectors::Collector,
+    commands::init::{Crypto, KittyError},
+    repository::{
+        history::{parse_point_in_time, revision_at},
+        keys::unlock,
+    },
+    storage::{is_database, open_database},
+    utils::{
+        file::{get_repository_path, get_storage_type, load_repository, read_stored},
+        password::read_repository_password,
+        theme,
+    },
+};
+use chrono::{DateTime, Local, Utc};
+use fuser::{
+    Config, Errno, FileAttr, FileHandle, FileType, Filesystem, Generation, INodeNo, LockOwner,
+    MountOption, OpenFlags, ReplyAttr, ReplyData, ReplyDirectory, ReplyEntry, Request,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    ffi::{OsStr, OsString},
+    fs,
+    os::unix::fs::MetadataExt,
+    path::{Component, Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, SystemTime},
+};
+
+/// How long the kernel may cache names and attributes; the content never changes while mounted
+const TTL: Duration = Duration::from_secs(60);
+
+/// Options for the mount command
+#[derive(Default)]
+pub struct MountOptions {
+    /// Show the files as they were at this point in time instead of now
+    pub at: Option<String>,
+}
+
+enum Node {
+    Directory {
+        parent: u64,
+        children: BTreeMap<OsString, u64>,
+    },
+    File {
+        repo_path: String,
+        mode: u32,
+        saved_at: DateTime<Utc>,
+    },
+}
+
+/// Tracked files laid out at their original paths, decrypted when read
+struct RepositoryFs {
+    repo_path: PathBuf,
+    crypto: Crypto,
+    database: bool,
+
+    /// Indexed by inode number less one, so the root directory comes first
+    nodes: Vec<Node>,
+
+    /// Owner of the mount point, who the files are shown as belonging to
+    uid: u32,
+    gid: u32,
+    mounted_at: SystemTime,
+
+    /// Decrypted content, kept in memory only until the filesystem is unmounted
+    content: Mutex<HashMap<u64, Arc<Vec<u8>>>>,
+}
+
+impl RepositoryFs {
+    fn new(
+        repo_path: PathBuf,
+        crypto: Crypto,
+        database: bool,
+        mount_point: &Path,
+    ) -> Result<Self, KittyError> {
+        let metadata = fs::metadata(mount_point)?;
+        Ok(Self {
+            repo_path,
+            crypto,
+            database,
+            nodes: vec![Node::Directory {
+                parent: INodeNo::ROOT.into(),
+                children: BTreeMap::new(),
+            }],
+            uid: metadata.uid(),
+            gid: metadata.gid(),
+            mounted_at: SystemTime::now(),
+            content: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Add a file below the root at its original path, creating the directories it is in
+    ///
+    /// Returns false when a file is tracked where another needs a directory.
+    fn insert(&mut self, path: &Path, file: Node) -> bool {
+        let names: Vec<&OsStr> = path
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(name) => Some(name),
+                _ => None,
+            })
+            .collect();
+        let Some((file_name, directories)) = names.split_last() else {
+            return false;
+        };
+
+        let mut parent = u64::from(INodeNo::ROOT);
+        for name in directories {
+            let existing = match &self.nodes[(parent - 1) as usize] {
+                Node::Directory { children, .. } => children.get(*name).copied(),
+                Node::File { .. } => return false,
+            };
+            parent = match existing {
+                Some(ino) => ino,
+                None => self.push(
+                    parent,
+                    name,
+                    Node::Directory {
+                        parent,
+                        children: BTreeMap::new(),
+                    },
+                ),
+            };
+        }
+        match &self.nodes[(parent - 1) as usize] {
+            Node::Directory { children, .. } if !children.contains_key(*file_name) => {
+                self.push(parent, file_name, file);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn push(&mut self, parent: u64, name: &OsStr, node: Node) -> u64 {
+        self.nodes.push(node);
+        let ino = self.nodes.len() as u64;
+        if let Node::Directory { children, .. } = &mut self.nodes[(parent - 1) as usize] {
+            children.insert(name.to_os_string(), ino);
+        }
+        ino
+    }
+
+    fn node(&self, ino: INodeNo) -> Option<&Node> {
+        u64::from(ino)
+            .checked_sub(1)
+            .and_then(|index| self.nodes.get(index as usize))
+    }
+
+    /// The plaintext of a file, decrypted the first time it is needed
+    fn content(&self, ino: INodeNo, repo_path: &str) -> Result<Arc<Vec<u8>>, Errno> {
+        let mut cache = self.content.lock().map_err(|_| Errno::EIO)?;
+        if let Some(content) = cache.get(&u64::from(ino)) {
+            return Ok(Arc::clone(content));
+        }
+        let encrypted = if self.database {
+            open_database(&self.repo_path).and_then(|db| db.get_file(repo_path))
+        } else {
+            read_stored(&self.repo_path.join(repo_path)).map_err(KittyError::from)
+        };
+        let content = encrypted
+            .and_then(|encrypted| self.crypto.decrypt(&encrypted))
+            .map(Arc::new)
+            .map_err(|_| Errno::EIO)?;
+        cache.insert(u64::from(ino), Arc::clone(&content));
+        Ok(content)
+    }
+
+    fn attr(&self, ino: INodeNo) -> Result<FileAttr, Errno> {
+        let (kind, perm, size, modified) = match self.node(ino).ok_or(Errno::ENOENT)? {
+            Node::Directory { children, .. } => (
+                FileType::Directory,
+                0o555,
+                children.len() as u64,
+                self.mounted_at,
+            ),
+            Node::File {
+                repo_path,
+                mode,
+                saved_at,
+            } => {
+                let size = self.content(ino, repo_path)?.len() as u64;
+                // Read-only whatever the mode was, and never set-id
+                let perm = (mode & 0o777 & !0o222) as u16;
+                (
+                    FileType::RegularFile,
+                    perm,
+                    size,
+                    SystemTime::from(*saved_at),
+                )
+            }
+        };
+        Ok(FileAttr {
+            ino,
+            size,
+            blocks: size.div_ceil(512),
+            atime: modified,
+            mtime: modified,
+            ctime: modified,
+            crtime: modified,
+            kind,
+            perm,
+            nlink: if kind == FileType::Directory { 2 } else { 1 },
+            uid: self.uid,
+            gid: self.gid,
+            rdev: 0,
+            blksize: 4096,
+            flags: 0,
+        })
+    }
+}
+
+impl Filesystem for RepositoryFs {
+    fn lookup(&self, _req: &Request, parent: INodeNo, name: &OsStr, reply: ReplyEntry) {
+        let child = match self.node(parent) {
+            Some(Node::Directory { children, .. }) => children.get(name).copied(),
+            _ => None,
+        };
+        match child.map(|ino| self.attr(INodeNo(ino))) {
+            Some(Ok(attr)) => reply.entry(&TTL, &attr, Generation(0)),
+            Some(Err(e)) => reply.error(e),
+            None => reply.error(Errno::ENOENT),
+        }
+    }
+
+    fn getattr(&self, _req: &Request, ino: INodeNo, _fh: Option<FileHandle>, reply: ReplyAttr) {
+        match self.attr(ino) {
+            Ok(attr) => reply.attr(&TTL, &attr),
+            Err(e) => reply.error(e),
+        }
+    }
+
+    fn read(
+        &self,
+        _req: &Request,
+        ino: INodeNo,
+        _fh: FileHandle,
+        offset: u64,
+        size: u32,
+        _flags: OpenFlags,
+        _lock_owner: Option<LockOwner>,
+        reply: ReplyData,
+    ) {
+        let Some(Node::File { repo_path, .. }) = self.node(ino) else {
+            reply.error(Errno::EISDIR);
+            return;
+        };
+        match self.content(ino, repo_path) {
+            Ok(content) => {
+                let start = (offset as usize).min(content.len());
+                let end = start.saturating_add(size as usize).min(content.len());
+                reply.data(&content[start..end]);
+            }
+            Err(e) => reply.error(e),
+        }
+    }
+
+    fn readdir(
+        &self,
+        _req: &Request,
+        ino: INodeNo,
+        _fh: FileHandle,
+        offset: u64,
+        mut reply: ReplyDirectory,
+    ) {
+        let Some(Node::Directory { parent, children }) = self.node(ino) else {
+            reply.error(Errno::ENOTDIR);
+            return;
+        };
+        let entries = [
+            (u64::from(ino), FileType::Directory, OsStr::new(".")),
+            (*parent, FileType::Directory, OsStr::new("..")),
+        ]
+        .into_iter()
+        .chain(children.iter().map(|(name, &child)| {
+            let kind = match self.nodes[(child - 1) as usize] {
+                Node::Directory { .. } => FileType::Directory,
+                Node::File { .. } => FileType::RegularFile,
+            };
+            (child, kind, name.as_os_str())
+        }));
+        for (index, (child, kind, name)) in entries.enumerate().skip(offset as usize) {
+            // The offset handed back is where the next call continues
+            if reply.add(INodeNo(child), (index + 1) as u64, kind, name) {
+                break;
+            }
+        }
+        reply.ok();
+    }
+}
+
+/// Show the tracked files read-only below `dir`, decrypted as they are read
+///
+/// Files appear at their original paths, so `/etc/ssh/sshd_config` is found at
+/// `<dir>/etc/ssh/sshd_config`. Runs until interrupted or unmounted; the
+/// decrypted content is only ever held in memory.
+pub fn mount_repository(dir: &str, options: Option<MountOptions>) -> Result<(), KittyError> {
+    let options = options.unwrap_or_default();
+    let repo_path = get_repository_path()?;
+
+    if !repo_path.exists() {
+        return Err(KittyError::RepositoryNotFound);
+    }
+
+    let mount_point = Path::new(dir);
+    if !mount_point.is_dir() {
+        return Err(KittyError::Mount(format!("{} is not a directory", dir)));
+    }
+    if fs::read_dir(mount_point)?.next().is_some() {
+        return Err(KittyError::Mount(format!(
+            "{} is not empty; mount on an empty directory",
+            dir
+        )));
+    }
+    let at = options.at.as_deref().map(parse_point_in_time).transpose()?;
+
+    // Get password from user
+    let password = read_repository_password(false)?;
+
+    let crypto = unlock(&repo_path, &password)?;
+    let repository = load_repository(&repo_path, &crypto)?;
+    let database = is_database(&get_storage_type(&repo_path)?);
+
+    let mut filesystem = RepositoryFs::new(repo_path.clone(), crypto, database, mount_point)?;
+    let mut shown = 0;
+    for file in &repository.files {
+        if Collector::parse(&file.original_path).is_some() {
+            continue;
+        }
+        let (repo_file, saved_at) = match at {
+            Some(at) => match revision_at(file, at) {
+                Some(revision) => (revision.repo_path.to_string(), revision.saved_at),
+                None => continue,
+            },
+            None => (file.repo_path.clone(), file.last_updated),
+        };
+        let node = Node::File {
+            repo_path: repo_file,
+            mode: file.metadata.as_ref().map_or(0o644, |m| m.mode),
+            saved_at,
+        };
+        if filesystem.insert(Path::new(&file.original_path), node) {
+            shown += 1;
+        }
+    }
+
+    let mut config = Config::default();
+    config.mount_options = vec![
+        MountOption::RO,
+        MountOption::NoExec,
+        MountOption::NoSuid,
+        MountOption::NoDev,
+        MountOption::DefaultPermissions,
+        MountOption::FSName("kitty".to_string()),
+        MountOption::Subtype("kitty".to_string()),
+    ];
+    let session = fuser::spawn_mount(filesystem, mount_point, &config)
+        .map_err(|e| KittyError::Mount(format!("{}: {}", dir, e)))?;
+
+    println!(
+        "{} Mounted {} file(s){} read-only on {}",
+        theme::success("SUCCESS:"),
+        shown,
+        at.map(|at| format!(
+            " as of {}",
+            at.with_timezone(&Local).format("%Y-%m-%d %H:%M")
+        ))
+        .unwrap_or_default(),
+        dir
+    );
+    println!(
+        "Press Ctrl-C or run `umount {}` to unmount and lock it again",
+        dir
+    );
+
+    // Unmount on Ctrl-C or SIGTERM, or stop once someone else unmounted it
+    let stopping = Arc::new(AtomicBool::new(false));
+    for signal in [signal_hook::consts::SIGINT, signal_hook::consts::SIGTERM] {
+        signal_hook::flag::register(signal, Arc::clone(&stopping))?;
+    }
+    while !stopping.load(Ordering::Relaxed) && !session.guard.is_finished() {
+        thread::sleep(Duration::from_millis(200));
+    }
+
+    if session.guard.is_finished() {
+        session.join()?;
+    } else {
+        session
+            .umount_and_join()
+            .map_err(|e| KittyError::Mount(format!("could not unmount {}: {}", dir, e)))?;
+    }
+    println!("Unmounted {}", dir);
+    Ok(())
+}
@@ -0,0 +1,212 @@
+use crate::{
+    collectors::Collector,
+    commands::init::{KittyError, Repository},
+    repository::keys::unlock_admin,
+    utils::{
+        audit::{self, AuditEntry},
+        file::{get_repository_path, load_repository, save_repository},
+        index, inventory,
+        lock::RepoLock,
+        mirror,
+        moves::detect_moves,
+        password::read_repository_password,
+        select::find_by_id,
+        stats, theme,
+    },
+};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Options for the mv command
+#[derive(Default)]
+pub struct MoveOptions {
+    /// Tracked file to move, by path or ID
+    pub from: Option<String>,
+
+    /// New path, or a directory to move the file into
+    pub to: Option<String>,
+
+    /// Record the moves `kitty status` detects instead of moving one file
+    pub accept_detected: bool,
+}
+
+/// Move a tracked file, or record where missing files went
+///
+/// The stored content stays where it is; only the path it belongs to changes.
+/// When the file is still at its old path it is moved on disk as well, and
+/// when it was already moved by hand only the repository is updated.
+pub fn move_file(options: Option<MoveOptions>) -> Result<(), KittyError> {
+    let options = options.unwrap_or_default();
+    let repo_path = get_repository_path()?;
+
+    if !repo_path.exists() {
+        return Err(KittyError::RepositoryNotFound);
+    }
+
+    // Get password from user
+    let password = read_repository_password(false)?;
+
+    let crypto = unlock_admin(&repo_path, &password)?;
+    let _lock = RepoLock::acquire(&repo_path)?;
+    let mut repository = load_repository(&repo_path, &crypto)?;
+
+    let moves: Vec<(usize, PathBuf)> = if options.accept_detected {
+        let missing: Vec<_> = repository
+            .files
+            .iter()
+            .filter(|f| {
+                f.applies_here()
+                    && Collector::parse(&f.original_path).is_none()
+                    && !Path::new(&f.original_path).exists()
+            })
+            .collect();
+        let detected = detect_moves(&repo_path, &repository.files, &missing)?;
+        if detected.is_empty() {
+            println!("No moved files detected");
+            return Ok(());
+        }
+        detected
+            .into_iter()
+            .filter_map(|(from, to)| {
+                let index = repository
+                    .files
+                    .iter()
+                    .position(|f| f.original_path == from)?;
+                Some((index, to))
+            })
+            .collect()
+    } else {
+        let (Some(from), Some(to)) = (&options.from, &options.to) else {
+            return Err(KittyError::Usage(
+                "give the tracked file and its new path, or --accept-detected".to_string(),
+            ));
+        };
+        let index = find_tracked(&repository, from)?;
+        let target = move_on_disk(&repository, index, to)?;
+        vec![(index, target)]
+    };
+
+    let revision = repository.next_generation();
+    let mut moved = Vec::new();
+    for (index, target) in moves {
+        let target = target.to_string_lossy().to_string();
+        let old = std::mem::replace(&mut repository.files[index].original_path, target.clone());
+        repository.files[index].revision = revision;
+
+        // Keep files that are restored after this one pointing at it
+        for file in repository
+            .files
+            .iter_mut()
+            .filter(|f| f.after.contains(&old))
+        {
+            for after in file.after.iter_mut().filter(|after| **after == old) {
+                *after = target.clone();
+            }
+            file.revision = revision;
+        }
+        moved.push((index, old, target));
+    }
+    save_repository(&repo_path, &crypto, &repository)?;
+
+    let old_paths: Vec<String> = moved.iter().map(|(_, old, _)| old.clone()).collect();
+    let _ = index::forget(&repo_path, &old_paths);
+    let files: Vec<_> = moved
+        .iter()
+        .map(|&(index, _, _)| &repository.files[index])
+        .collect();
+    let _ = index::refresh(&repo_path, &files);
+    inventory::update(&repo_path, &crypto, &repository);
+    stats::record(&repo_path, &crypto, &repository);
+
+    for (index, old, target) in &moved {
+        audit::record(
+            &repo_path,
+            &crypto,
+            &AuditEntry::new(
+                "mv",
+                target,
+                None,
+                Some(repository.files[*index].hash.clone()),
+            ),
+        )?;
+        println!("{} Moved {} to {}", theme::success("SUCCESS:"), old, target);
+    }
+    mirror::update(&repo_path);
+
+    Ok(())
+}
+
+/// Index of a tracked file given by ID or path
+fn find_tracked(repository: &Repository, path: &str) -> Result<usize, KittyError> {
+    if let Some(index) = find_by_id(&repository.files, path)? {
+        return Ok(index);
+    }
+    let file_path = Path::new(path)
+        .canonicalize()
+        .unwrap_or_else(|_| Path::new(path).to_path_buf());
+    repository
+        .files
+        .iter()
+        .position(|f| Path::new(&f.original_path) == file_path || f.original_path == path)
+        .ok_or_else(|| KittyError::FileNotTracked(path.to_string()))
+}
+
+/// Work out the new path of a tracked file and move it there unless that was done already
+fn move_on_disk(repository: &Repository, index: usize, to: &str) -> Result<PathBuf, KittyError> {
+    let file = &repository.files[index];
+    if Collector::parse(&file.original_path).is_some() {
+        return Err(KittyError::NotAFile {
+            path: file.original_path.clone(),
+        });
+    }
+    let from = Path::new(&file.original_path);
+
+    // Like mv, moving into a directory keeps the file name
+    let mut target = PathBuf::from(to);
+    if target.is_dir() {
+        if let Some(name) = from.file_name() {
+            target.push(name);
+        }
+    }
+    let name = target
+        .file_name()
+        .ok_or_else(|| KittyError::Usage(format!("{} is not a file path", to)))?
+        .to_os_string();
+    let parent = match target.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let target = parent.canonicalize()?.join(name);
+
+    if repository
+        .files
+        .iter()
+        .any(|f| Path::new(&f.original_path) == target)
+    {
+        return Err(KittyError::Move {
+            path: file.original_path.clone(),
+            reason: format!("{} is already tracked", target.display()),
+        });
+    }
+
+    match (from.exists(), target.exists()) {
+        (true, true) => Err(KittyError::Move {
+            path: file.original_path.clone(),
+            reason: format!("{} already exists", target.display()),
+        }),
+        (true, false) => {
+            fs::rename(from, &target)?;
+            Ok(target)
+        }
+        (false, true) => Ok(target),
+        (false, false) => Err(KittyError::Move {
+            path: file.original_path.clone(),
+            reason: format!(
+                "neither it nor {} exists; restore the file first",
+                target.display()
+            ),
+        }),
+    }
+}
@@ -0,0 +1,142 @@
+use crate::{
+    collectors::Collector,
+    commands::{
+        add::store_content_as,
+        diff::{diff_contents, DiffOptions},
+        init::KittyError,
+    },
+    repository::keys::unlock,
+    storage::{is_database, open_database},
+    utils::{
+        file::{get_repository_path, get_storage_type, load_repository, read_stored},
+        password::read_repository_password,
+        select::select_tracked,
+        theme,
+    },
+};
+use std::{
+    fs,
+    io::{self, IsTerminal, Read},
+};
+
+/// Options for the patch command
+#[derive(Default)]
+pub struct PatchOptions {
+    /// File holding the patch, instead of stdin
+    pub file: Option<String>,
+
+    /// Show what the patch changes without storing it
+    pub dry_run: bool,
+}
+
+/// Apply a unified diff to the stored copy of a tracked file
+///
+/// The live file is left alone, so a proposed change can be reviewed and
+/// stored first and put in place later with `kitty restore`. The previous
+/// content is kept as a version, like any other update.
+pub fn patch_file(path: &str, options: Option<PatchOptions>) -> Result<(), KittyError> {
+    let options = options.unwrap_or_default();
+    let repo_path = get_repository_path()?;
+
+    if !repo_path.exists() {
+        return Err(KittyError::RepositoryNotFound);
+    }
+
+    let patch_text = match &options.file {
+        Some(file) => fs::read_to_string(file)?,
+        None => {
+            if io::stdin().is_terminal() {
+                return Err(KittyError::Patch(
+                    "pipe the patch in, or pass --file".to_string(),
+                ));
+            }
+            let mut text = String::new();
+            io::stdin().read_to_string(&mut text)?;
+            text
+        }
+    };
+    let patch = diffy::Patch::from_str(&patch_text)
+        .map_err(|e| KittyError::Patch(format!("not a unified diff: {}", e)))?;
+    if patch.hunks().is_empty() {
+        return Err(KittyError::Patch("no hunks in the patch".to_string()));
+    }
+
+    // Get password from user
+    let password = read_repository_password(false)?;
+
+    let crypto = unlock(&repo_path, &password)?;
+    if !options.dry_run {
+        // Found out before the patch is applied rather than after it
+        crypto.require_admin()?;
+    }
+    let repository = load_repository(&repo_path, &crypto)?;
+
+    let [index] = select_tracked(&repository.files, path, false, false)?[..] else {
+        return Err(KittyError::AmbiguousPath(format!(
+            "'{}' matches several tracked files; patch one at a time",
+            path
+        )));
+    };
+    let file = &repository.files[index];
+    if Collector::parse(&file.original_path).is_some() {
+        return Err(KittyError::Patch(format!(
+            "{} is not a file",
+            file.original_path
+        )));
+    }
+
+    let encrypted = if is_database(&get_storage_type(&repo_path)?) {
+        open_database(&repo_path)?.get_file(&file.repo_path)?
+    } else {
+        read_stored(&repo_path.join(&file.repo_path))?
+    };
+    let stored = String::from_utf8(crypto.decrypt(&encrypted)?).map_err(|_| {
+        KittyError::Patch(format!(
+            "{} is stored as binary content",
+            file.original_path
+        ))
+    })?;
+
+    let patched = diffy::apply(&stored, &patch).map_err(|e| {
+        KittyError::Patch(format!(
+            "does not apply to the stored copy of {}: {}",
+            file.original_path, e
+        ))
+    })?;
+    if patched == stored {
+        println!("The patch changes nothing in {}", file.original_path);
+        return Ok(());
+    }
+
+    if options.dry_run {
+        let result = diff_contents(
+            &file.original_path,
+            &stored,
+            &patched,
+            &DiffOptions::default(),
+        );
+        println!("File: {}", theme::emphasis(&result.path));
+        println!("{}", result.diff_text);
+        println!(
+            "Dry run: the stored copy would get {} addition(s) and {} deletion(s)",
+            result.additions, result.deletions
+        );
+        return Ok(());
+    }
+
+    let original_path = file.original_path.clone();
+    store_content_as(
+        &original_path,
+        patched.into_bytes(),
+        &file.hash,
+        &password,
+        "patch",
+    )?;
+    println!(
+        "{} Patched the stored copy of {}; `kitty restore {}` puts it in place",
+        theme::success("SUCCESS:"),
+        original_path,
+        original_path
+    );
+    Ok(())
+}
@@ -0,0 +1,87 @@
+use crate::{
+    commands::init::KittyError,
+    repository::keys::unlock_admin,
+    utils::{
+        audit::{self, AuditEntry},
+        file::{get_repository_path, load_repository, save_repository},
+        lock::RepoLock,
+        mirror,
+        password::read_repository_password,
+        select::find_by_id,
+        theme,
+    },
+};
+use std::path::Path;
+
+/// Pin or unpin a tracked file
+///
+/// A pinned file is expected to differ per machine: status, check and the
+/// daemon no longer report its changes as drift, and restoring everything
+/// leaves it alone. Restoring it by path still works.
+pub fn pin_file(path: &str, pinned: bool) -> Result<(), KittyError> {
+    let repo_path = get_repository_path()?;
+
+    if !repo_path.exists() {
+        return Err(KittyError::RepositoryNotFound);
+    }
+
+    // Get password from user
+    let password = read_repository_password(false)?;
+
+    let crypto = unlock_admin(&repo_path, &password)?;
+    let _lock = RepoLock::acquire(&repo_path)?;
+    let mut repository = load_repository(&repo_path, &crypto)?;
+
+    let index = match find_by_id(&repository.files, path)? {
+        Some(index) => index,
+        None => {
+            let file_path = Path::new(path)
+                .canonicalize()
+                .unwrap_or_else(|_| Path::new(path).to_path_buf());
+            repository
+                .files
+                .iter()
+                .position(|f| Path::new(&f.original_path) == file_path || f.original_path == path)
+                .ok_or_else(|| KittyError::FileNotTracked(path.to_string()))?
+        }
+    };
+    let file = &mut repository.files[index];
+
+    if file.ignore_drift == pinned {
+        println!(
+            "{} is already {}",
+            file.original_path,
+            if pinned { "pinned" } else { "unpinned" }
+        );
+        return Ok(());
+    }
+
+    file.ignore_drift = pinned;
+    let original_path = file.original_path.clone();
+    repository.touch(index);
+    save_repository(&repo_path, &crypto, &repository)?;
+
+    audit::record(
+        &repo_path,
+        &crypto,
+        &AuditEntry::new(
+            if pinned { "pin" } else { "unpin" },
+            &original_path,
+            None,
+            None,
+        ),
+    )?;
+    mirror::update(&repo_path);
+
+    if pinned {
+        println!(
+            "{} Pinned {}; local changes are no longer reported as drift",
+            theme::success("SUCCESS:"),
+            original_path
+        );
+    } else {
+        println!("{} Unpinned {}", theme::success("SUCCESS:"), original_path);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,251 @@
+use crate::{
+    collectors::{read_live, Collector},
+    commands::{
+        init::{KittyError, TrackedFile},
+        restore::{restore_files, RestoreOptions, RestoreReport},
+    },
+    repository::config::RepoConfig,
+    utils::{
+        file::{get_repository_path, write_atomic},
+        hash::digest_like,
+        theme,
+    },
+};
+use chrono::{DateTime, Local, Utc};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
+
+/// What applying a plan does to one tracked entry
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PlanAction {
+    /// Write a file that does not exist yet
+    Create,
+
+    /// Replace a file or collector's settings that differ from the repository
+    Update,
+
+    /// Install the missing packages of a package list
+    Install,
+}
+
+impl PlanAction {
+    fn symbol(self) -> &'static str {
+        match self {
+            PlanAction::Create => "+",
+            PlanAction::Update => "~",
+            PlanAction::Install => "*",
+        }
+    }
+}
+
+/// One change a plan makes
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PlannedChange {
+    pub path: String,
+    pub action: PlanAction,
+
+    /// Hash of the stored content the change comes from
+    pub stored_hash: String,
+
+    /// Hash of what will be written, after rendering
+    pub hash: String,
+
+    /// Hash of the live content when the plan was made; None if there was none
+    pub current_hash: Option<String>,
+
+    pub additions: usize,
+    pub deletions: usize,
+
+    /// Frozen files are written read-only
+    #[serde(default)]
+    pub read_only: bool,
+
+    /// Commands that check the new content before it is written
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub validate: Vec<String>,
+
+    /// Package manager command, for package lists
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+}
+
+/// Everything `kitty apply` will do for a role, computed without changing anything
+///
+/// Saved as JSON, a plan can be reviewed and then applied with `--from-plan`,
+/// which refuses to run if the repository or the live files changed since.
+#[derive(Serialize, Deserialize)]
+pub struct Plan {
+    pub role: String,
+    pub created_at: DateTime<Utc>,
+
+    /// Whether templates are rendered, and with which profile
+    #[serde(default)]
+    pub render: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+
+    /// Whether the plan installs packages rather than restoring files
+    #[serde(default)]
+    pub packages: bool,
+
+    pub changes: Vec<PlannedChange>,
+
+    /// `post-restore` hooks run once the changes are written
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hooks: Vec<String>,
+}
+
+impl Plan {
+    pub fn load(path: &Path) -> Result<Self, KittyError> {
+        Ok(serde_json::from_slice(&fs::read(path)?)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), KittyError> {
+        write_atomic(path, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// The planned change to an entry, if the plan touches it
+    pub fn change(&self, path: &str) -> Option<&PlannedChange> {
+        self.changes.iter().find(|change| change.path == path)
+    }
+
+    /// Print the plan for review, one line per change
+    pub fn print(&self) {
+        // Plans made without a role cover every file meant for the machine
+        let scope = if self.role.is_empty() {
+            "this machine".to_string()
+        } else {
+            format!("role '{}'", self.role)
+        };
+        if self.changes.is_empty() {
+            println!("No changes. The files of {} match the repository.", scope);
+            return;
+        }
+
+        println!(
+            "Plan for {}, made {}:\n",
+            scope,
+            self.created_at
+                .with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M:%S")
+        );
+        for change in &self.changes {
+            let line = format!("  {} {}", change.action.symbol(), change.path);
+            match change.action {
+                PlanAction::Create => {
+                    println!("{} ({} lines)", theme::success(&line), change.additions)
+                }
+                PlanAction::Update => println!(
+                    "{} (+{} -{})",
+                    theme::warning(&line),
+                    change.additions,
+                    change.deletions
+                ),
+                PlanAction::Install => println!("{}", line),
+            }
+            if let Some(command) = &change.command {
+                println!("      runs {}", command);
+            }
+            if change.read_only {
+                println!("      {}", "stays read-only (frozen)".dimmed());
+            }
+            for command in &change.validate {
+                println!("      {}", format!("checked with `{}`", command).dimmed());
+            }
+        }
+        for hook in &self.hooks {
+            println!("  {} post-restore hook `{}`", "!".bold(), hook);
+        }
+
+        let count = |action| self.changes.iter().filter(|c| c.action == action).count();
+        println!(
+            "\n{} to create, {} to update, {} package list(s) to install.",
+            count(PlanAction::Create),
+            count(PlanAction::Update),
+            count(PlanAction::Install)
+        );
+    }
+
+    /// The files a saved plan changes, provided nothing changed since it was made
+    ///
+    /// Every planned entry must still be selected, stored with the same content
+    /// and, unless it is a package list, live with the content it had. Entries
+    /// the plan does not mention are left alone.
+    pub fn select<'a>(
+        &self,
+        files: Vec<&'a TrackedFile>,
+    ) -> Result<Vec<&'a TrackedFile>, KittyError> {
+        let mut stale = Vec::new();
+        for change in &self.changes {
+            let Some(file) = files.iter().find(|f| f.original_path == change.path) else {
+                stale.push(format!("{} is no longer selected", change.path));
+                continue;
+            };
+            if file.hash != change.stored_hash {
+                stale.push(format!("{} changed in the repository", change.path));
+                continue;
+            }
+            if matches!(
+                Collector::parse(&file.original_path),
+                Some(Collector::Packages(_))
+            ) {
+                continue;
+            }
+            let current = read_live(&file.original_path)
+                .ok()
+                .map(|content| digest_like(&file.hash, &content));
+            if current != change.current_hash {
+                stale.push(format!("{} changed on disk", change.path));
+            }
+        }
+        if !stale.is_empty() {
+            return Err(KittyError::StalePlan(stale.join("; ")));
+        }
+
+        Ok(files
+            .into_iter()
+            .filter(|f| self.change(&f.original_path).is_some())
+            .collect())
+    }
+}
+
+/// Work out what applying a role would change, without changing anything
+///
+/// Runs a dry restore with `options`, which must name a role. Its progress
+/// goes to stderr; files that would fail, e.g. validation, are in the report.
+pub fn make_plan(options: RestoreOptions) -> Result<(Plan, RestoreReport), KittyError> {
+    let role = options.role.clone().unwrap_or_default();
+    let render = options.render;
+    let profile = options.profile.clone();
+    let packages = options.packages;
+    let mut report = restore_files(Some(RestoreOptions {
+        dry_run: true,
+        json: true,
+        ..options
+    }))?;
+
+    let changes = std::mem::take(&mut report.planned);
+    let hooks = if changes.is_empty() {
+        Vec::new()
+    } else {
+        let config = RepoConfig::load(&get_repository_path()?)?;
+        config
+            .hooks
+            .get("post-restore")
+            .cloned()
+            .unwrap_or_default()
+    };
+    let plan = Plan {
+        role,
+        created_at: Utc::now(),
+        render,
+        profile,
+        packages,
+        changes,
+        hooks,
+    };
+    Ok((plan, report))
+}
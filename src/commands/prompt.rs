@@ -0,0 +1,28 @@
+use crate::{
+    commands::init::KittyError,
+    utils::{file::get_repository_path, index},
+};
+
+/// Print a compact drift summary for embedding in a shell prompt
+///
+/// Only the index left by `status`, `check` and the daemon is read, so this
+/// never asks for the password and returns quickly. Files are compared by size
+/// and mtime, not content. Outside a repository nothing is printed, and `?`
+/// means no drift check has run yet.
+pub fn show_prompt() -> Result<(), KittyError> {
+    let repo_path = get_repository_path()?;
+    if !repo_path.exists() {
+        return Ok(());
+    }
+
+    let Some(index) = index::load(&repo_path) else {
+        println!("?");
+        return Ok(());
+    };
+
+    match index.files.iter().filter(|f| f.is_drifted()).count() {
+        0 => println!("✓"),
+        drifted => println!("✗{}", drifted),
+    }
+    Ok(())
+}
@@ -0,0 +1,307 @@
+use crate::{
+    commands::{
+        db::vacuum_storage,
+        init::{FileVersion, KittyError, TrackedFile},
+        trash::move_to_trash,
+    },
+    repository::{
+        config::{RepoConfig, RetentionPolicy},
+        keys::{sign_manifest, unlock_admin},
+    },
+    storage::{is_database, open_database, sqlite::SqliteStorage},
+    utils::{
+        audit::{self, AuditEntry},
+        file::{get_repository_path, get_storage_type, load_repository, write_config},
+        hooks, index, inventory,
+        lock::RepoLock,
+        mirror,
+        password::read_repository_password,
+        stats, theme,
+    },
+};
+use chrono::{Duration, Local, Utc};
+use std::fs;
+
+/// Vacuum the SQLite database automatically when a prune frees at least this much
+const AUTO_VACUUM_BYTES: u64 = 1024 * 1024;
+
+/// Options for the prune command
+#[derive(Default)]
+pub struct PruneOptions {
+    /// Keep this many of the most recent versions of each file
+    pub keep_last: Option<usize>,
+
+    /// Keep versions saved within this many days
+    pub keep_days: Option<u64>,
+
+    /// Untrack entries whose expiry has passed
+    pub expired: bool,
+
+    /// Show what would be removed without removing anything
+    pub dry_run: bool,
+}
+
+/// Returns true if the version at `index` (oldest first) survives the policy
+fn is_kept(policy: &RetentionPolicy, versions: &[FileVersion], index: usize) -> bool {
+    let kept_by_count = policy
+        .keep_last
+        .map(|n| index + n >= versions.len())
+        .unwrap_or(false);
+
+    let kept_by_age = policy
+        .keep_days
+        .map(|days| versions[index].saved_at > Utc::now() - Duration::days(days as i64))
+        .unwrap_or(false);
+
+    kept_by_count || kept_by_age
+}
+
+/// Remove old versions of tracked files according to the retention policy
+pub fn prune_versions(options: Option<PruneOptions>) -> Result<(), KittyError> {
+    let options = options.unwrap_or_default();
+    let repo_path = get_repository_path()?;
+
+    if !repo_path.exists() {
+        return Err(KittyError::RepositoryNotFound);
+    }
+
+    // Expired entries alone, unless a retention policy is asked for as well
+    if options.expired {
+        let password = read_repository_password(false)?;
+
+        prune_expired_with_password(options.dry_run, &password)?;
+        if options.keep_last.is_none() && options.keep_days.is_none() {
+            return Ok(());
+        }
+        let policy = RetentionPolicy {
+            keep_last: options.keep_last,
+            keep_days: options.keep_days,
+        };
+        println!();
+        return prune_with_password(&policy, options.dry_run, &password);
+    }
+
+    // Flags override the defaults from the repository settings
+    let config = RepoConfig::load(&repo_path)?;
+    let policy = RetentionPolicy {
+        keep_last: options.keep_last.or(config.retention.keep_last),
+        keep_days: options.keep_days.or(config.retention.keep_days),
+    };
+
+    if policy.is_empty() {
+        println!("No retention policy given.");
+        println!(
+            "Pass --keep-last and/or --keep-days, or set \"retention\" in {}",
+            repo_path.join("config.json").display()
+        );
+        return Ok(());
+    }
+
+    // Get password from user
+    let password = read_repository_password(false)?;
+
+    prune_with_password(&policy, options.dry_run, &password)
+}
+
+/// Remove the versions a retention policy does not keep, using an already known password
+pub fn prune_with_password(
+    policy: &RetentionPolicy,
+    dry_run: bool,
+    password: &str,
+) -> Result<(), KittyError> {
+    let repo_path = get_repository_path()?;
+    let storage_type = get_storage_type(&repo_path)?;
+    let crypto = unlock_admin(&repo_path, password)?;
+
+    let lock = RepoLock::acquire(&repo_path)?;
+    let mut repository = load_repository(&repo_path, &crypto)?;
+    let storage = if is_database(&storage_type) {
+        Some(open_database(&repo_path)?)
+    } else {
+        None
+    };
+
+    let mut removed: Vec<(String, FileVersion)> = Vec::new();
+    let mut reclaimed: u64 = 0;
+    let revision = repository.next_generation();
+
+    for file in &mut repository.files {
+        let mut kept = Vec::new();
+        for (index, version) in file.versions.iter().enumerate() {
+            if is_kept(policy, &file.versions, index) {
+                kept.push(version.clone());
+                continue;
+            }
+
+            reclaimed += match &storage {
+                Some(storage) => storage.content_size(&version.repo_path)?,
+                None => fs::metadata(repo_path.join(&version.repo_path))
+                    .map(|m| m.len())
+                    .unwrap_or(0),
+            };
+            removed.push((file.original_path.clone(), version.clone()));
+        }
+        if kept.len() != file.versions.len() {
+            file.revision = revision;
+        }
+        file.versions = kept;
+    }
+
+    if removed.is_empty() {
+        println!("Nothing to prune.");
+        return Ok(());
+    }
+
+    for (path, version) in &removed {
+        println!(
+            "{} {} (saved {})",
+            if dry_run { "Would remove" } else { "Removing" },
+            path,
+            version.saved_at.format("%Y-%m-%d %H:%M:%S")
+        );
+    }
+
+    if dry_run {
+        println!(
+            "\nDry run: {} version(s) would be removed, reclaiming {} bytes",
+            removed.len(),
+            reclaimed
+        );
+        return Ok(());
+    }
+
+    match storage {
+        // Versions missing from the manifest are dropped along with their content
+        Some(mut storage) => {
+            storage.save_repository(&repository)?;
+            drop(storage);
+            sign_manifest(&repo_path, &crypto)?;
+
+            // Deleted rows only free space inside an SQLite file until it is vacuumed
+            if storage_type == "sqlite" && reclaimed >= AUTO_VACUUM_BYTES {
+                println!("Vacuuming database...");
+                let freed = vacuum_storage(&SqliteStorage::new(&repo_path)?)?;
+                println!("Database shrank by {} bytes", freed);
+            }
+        }
+        None => {
+            write_config(&repo_path, &crypto, &repository)?;
+
+            for (_, version) in &removed {
+                let version_path = repo_path.join(&version.repo_path);
+                if version_path.exists() {
+                    fs::remove_file(version_path)?;
+                }
+            }
+        }
+    }
+
+    inventory::update(&repo_path, &crypto, &repository);
+    stats::record(&repo_path, &crypto, &repository);
+
+    let mut hook_entries = Vec::new();
+    for (path, version) in &removed {
+        let entry = AuditEntry::new("prune", path, Some(version.hash.clone()), None);
+        audit::record(&repo_path, &crypto, &entry)?;
+        hook_entries.push(entry);
+    }
+    mirror::update(&repo_path);
+    // Hooks may run kitty themselves
+    drop(lock);
+    hooks::run(&repo_path, "post-prune", &hook_entries);
+
+    println!(
+        "{} Removed {} version(s), reclaiming {} bytes",
+        theme::success("SUCCESS:"),
+        removed.len(),
+        reclaimed
+    );
+
+    Ok(())
+}
+
+/// Untrack every entry whose expiry has passed, moving its content to the trash
+pub fn prune_expired_with_password(dry_run: bool, password: &str) -> Result<(), KittyError> {
+    let repo_path = get_repository_path()?;
+    let storage_type = get_storage_type(&repo_path)?;
+    let crypto = unlock_admin(&repo_path, password)?;
+
+    let lock = RepoLock::acquire(&repo_path)?;
+    let mut repository = load_repository(&repo_path, &crypto)?;
+    let now = Utc::now();
+    let (expired, kept): (Vec<TrackedFile>, Vec<TrackedFile>) = repository
+        .files
+        .drain(..)
+        .partition(|file| file.expires_at.is_some_and(|t| t <= now));
+    repository.files = kept;
+
+    if expired.is_empty() {
+        println!("No expired entries.");
+        return Ok(());
+    }
+
+    for file in &expired {
+        println!(
+            "{} {} (expired {})",
+            if dry_run {
+                "Would untrack"
+            } else {
+                "Untracking"
+            },
+            file.original_path,
+            file.expires_at
+                .unwrap_or(now)
+                .with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M")
+        );
+    }
+
+    if dry_run {
+        println!(
+            "\nDry run: {} expired entr(ies) would be untracked",
+            expired.len()
+        );
+        return Ok(());
+    }
+
+    let paths: Vec<String> = expired.iter().map(|f| f.original_path.clone()).collect();
+    let mut hook_entries = Vec::new();
+    for file in &expired {
+        let entry = AuditEntry::new("expire", &file.original_path, Some(file.hash.clone()), None);
+        hook_entries.push(entry);
+    }
+
+    if is_database(&storage_type) {
+        // Databases drop content nothing refers to on saving, so it goes to the trash first
+        for file in expired {
+            move_to_trash(&repo_path, &crypto, file)?;
+        }
+        open_database(&repo_path)?.save_repository(&repository)?;
+        sign_manifest(&repo_path, &crypto)?;
+    } else {
+        // Blobs are only let go of once the saved manifest no longer refers to them
+        write_config(&repo_path, &crypto, &repository)?;
+        for file in expired {
+            move_to_trash(&repo_path, &crypto, file)?;
+        }
+    }
+
+    let _ = index::forget(&repo_path, &paths);
+    inventory::update(&repo_path, &crypto, &repository);
+    stats::record(&repo_path, &crypto, &repository);
+    for entry in &hook_entries {
+        audit::record(&repo_path, &crypto, entry)?;
+    }
+    mirror::update(&repo_path);
+    // Hooks may run kitty themselves
+    drop(lock);
+    hooks::run(&repo_path, "post-remove", &hook_entries);
+
+    println!(
+        "{} Untracked {} expired entr(ies); undo with `kitty trash restore <path>`",
+        theme::success("SUCCESS:"),
+        paths.len()
+    );
+
+    Ok(())
+}
@@ -0,0 +1,215 @@
+use crate::{
+    commands::{
+        init::{Crypto, KittyError, Repository},
+        trash::{is_in_trash, purge_from_trash},
+    },
+    repository::keys::unlock_admin,
+    storage::is_database,
+    tr,
+    utils::{
+        audit::{self, AuditEntry},
+        file::{
+            config_backups, get_repository_path, get_storage_type, load_repository,
+            save_repository, write_atomic,
+        },
+        hooks,
+        i18n::confirm,
+        index, inventory,
+        lock::RepoLock,
+        mirror,
+        password::read_repository_password,
+        select::find_by_id,
+        shred::secure_delete,
+        stats, theme,
+    },
+};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Options for the purge command
+#[derive(Default)]
+pub struct PurgeOptions {
+    /// Path of the file to purge
+    pub path: String,
+
+    /// Don't prompt for confirmation
+    pub force: bool,
+}
+
+/// Remove every trace of a file's content from the repository
+///
+/// Meant for secrets tracked by mistake: the entry, all versions and all trashed
+/// copies are shredded, then copies kitty cannot reach are listed.
+pub fn purge_file(options: Option<PurgeOptions>) -> Result<(), KittyError> {
+    let options = options.unwrap_or_default();
+    let repo_path = get_repository_path()?;
+
+    if !repo_path.exists() {
+        return Err(KittyError::RepositoryNotFound);
+    }
+
+    // Get password from user
+    let password = read_repository_password(false)?;
+
+    let storage_type = get_storage_type(&repo_path)?;
+    let crypto = unlock_admin(&repo_path, &password)?;
+
+    let lock = RepoLock::acquire(&repo_path)?;
+    let mut repository = load_repository(&repo_path, &crypto)?;
+
+    // Purging is irreversible, so only exact paths and IDs are accepted
+    let original_path = match find_by_id(&repository.files, &options.path)? {
+        Some(index) => repository.files[index].original_path.clone(),
+        None => Path::new(&options.path)
+            .canonicalize()
+            .unwrap_or_else(|_| PathBuf::from(&options.path))
+            .to_string_lossy()
+            .to_string(),
+    };
+
+    let index = repository
+        .files
+        .iter()
+        .position(|f| f.original_path == original_path);
+    let in_trash = is_in_trash(&repo_path, &crypto, &original_path)?;
+
+    if index.is_none() && !in_trash {
+        return Err(KittyError::FileNotTracked(options.path.clone()));
+    }
+
+    if !options.force {
+        println!(
+            "{}",
+            tr!(
+                "purge-confirm",
+                path = theme::emphasis(&original_path).to_string()
+            )
+        );
+        if !confirm()? {
+            println!("{}", tr!("purge-canceled"));
+            return Ok(());
+        }
+    }
+
+    let mut versions = 0;
+    if let Some(index) = index {
+        let file = repository.files.remove(index);
+        versions = file.versions.len();
+
+        save_repository(&repo_path, &crypto, &repository)?;
+
+        // Database backends drop content no longer in the manifest when it is saved
+        if !is_database(&storage_type) {
+            let blobs =
+                std::iter::once(&file.repo_path).chain(file.versions.iter().map(|v| &v.repo_path));
+            for blob in blobs {
+                let blob_path = repo_path.join(blob);
+                if blob_path.exists() {
+                    secure_delete(&blob_path)?;
+                }
+            }
+            scrub_manifest_backups(&repo_path, &crypto, &original_path)?;
+        }
+        let _ = index::forget(&repo_path, std::slice::from_ref(&original_path));
+    }
+
+    let trashed = purge_from_trash(&repo_path, &crypto, &original_path)?;
+
+    // Restore leaves plaintext backups next to the file
+    let backup = PathBuf::from(format!("{}.bak", original_path));
+    let backup_shredded = backup.exists();
+    if backup_shredded {
+        secure_delete(&backup)?;
+    }
+
+    let entry = AuditEntry::new("purge", &original_path, None, None);
+    audit::record(&repo_path, &crypto, &entry)?;
+    inventory::update(&repo_path, &crypto, &repository);
+    stats::record(&repo_path, &crypto, &repository);
+    mirror::update(&repo_path);
+    // Hooks may run kitty themselves
+    drop(lock);
+    hooks::run(&repo_path, "post-remove", std::slice::from_ref(&entry));
+
+    println!(
+        "{} Purged {}: {} tracked copy, {} version(s), {} trashed copy(ies){}",
+        theme::success("SUCCESS:"),
+        original_path,
+        if index.is_some() { 1 } else { 0 },
+        versions,
+        trashed,
+        if backup_shredded {
+            ", restore backup"
+        } else {
+            ""
+        }
+    );
+
+    report_remaining_copies(&repo_path, &storage_type);
+
+    Ok(())
+}
+
+/// Rewrite the previous manifests kept next to `config.enc` without the purged file
+fn scrub_manifest_backups(
+    repo_path: &Path,
+    crypto: &Crypto,
+    original_path: &str,
+) -> Result<(), KittyError> {
+    for backup in config_backups(repo_path) {
+        let Ok(mut old) = fs::read(&backup)
+            .map_err(KittyError::from)
+            .and_then(|encrypted| crypto.decrypt(&encrypted))
+            .and_then(|decrypted| Ok(serde_json::from_slice::<Repository>(&decrypted)?))
+        else {
+            continue;
+        };
+        if !old.files.iter().any(|f| f.original_path == original_path) {
+            continue;
+        }
+
+        old.files.retain(|f| f.original_path != original_path);
+        let encrypted = crypto.encrypt(&serde_json::to_vec(&old)?)?;
+        secure_delete(&backup)?;
+        write_atomic(&backup, encrypted)?;
+    }
+    Ok(())
+}
+
+/// List copies of the repository that may still hold the purged content
+fn report_remaining_copies(repo_path: &Path, storage_type: &str) {
+    let mut remaining = Vec::new();
+
+    if repo_path.join("kitty.db.bak").exists() {
+        remaining.push(format!(
+            "{} (database replaced by `kitty db restore`)",
+            repo_path.join("kitty.db.bak").display()
+        ));
+    }
+    if repo_path.join("config.enc.damaged").exists() {
+        remaining.push(format!(
+            "{} (manifest kept by `kitty recover`)",
+            repo_path.join("config.enc.damaged").display()
+        ));
+    }
+    if storage_type == "sqlite" {
+        remaining.push("Backups written with `kitty db backup`".to_string());
+    }
+    if storage_type == "redb" {
+        remaining.push(
+            "Free pages in kitty.redb until they are reused; copy the repository to a fresh one to be sure"
+                .to_string(),
+        );
+    }
+    remaining
+        .push("Copies of the repository directory (system backups, snapshots, sync)".to_string());
+
+    println!("\n{}", theme::warning("Copies kitty cannot reach:"));
+    for copy in &remaining {
+        println!("  - {}", copy);
+    }
+    println!("Rotate the secret itself if any of these may have been exposed.");
+    println!("The audit log keeps the path and content hashes, but no content.");
+}
@@ -0,0 +1,518 @@
+use crate::{
+    commands::init::{Crypto, FileVersion, KittyError, Repository, TrackedFile},
+    repository::{
+        format::{read_format, CURRENT_FORMAT},
+        keys::{sign_manifest, unlock_admin, verify_password},
+        salt,
+    },
+    storage::{is_database, open_database, sqlite::SqliteStorage},
+    utils::{
+        audit::{self, read_entries, AuditEntry},
+        file::{
+            config_backups, get_repository_path, get_repository_salt, get_storage_type,
+            load_repository, write_atomic, write_config, write_stored, CONFIG_FILE,
+        },
+        hash::{digest_like, HashAlgorithm},
+        i18n::confirm,
+        inventory,
+        lock::RepoLock,
+        mirror,
+        password::read_repository_password,
+        stats, theme,
+    },
+};
+use chrono::Utc;
+use std::{cmp::Reverse, collections::HashSet, fs, path::Path};
+
+/// Options for the recover command
+#[derive(Default)]
+pub struct RecoverOptions {
+    /// Report what would be recovered without writing anything
+    pub dry_run: bool,
+
+    /// Look for the salt of a repository from before `salt.key` instead
+    pub find_salt: bool,
+}
+
+/// What happened to the repository during recovery
+#[derive(Default)]
+struct RecoveryReport {
+    manifest_rebuilt: bool,
+    kept: usize,
+    dropped: Vec<String>,
+    reconstructed: Vec<String>,
+}
+
+impl RecoveryReport {
+    /// Refuse to go on when nothing decrypts, which is what a wrong password looks like
+    fn check_readable(&self, password_verified: bool) -> Result<(), KittyError> {
+        if self.kept + self.reconstructed.len() > 0
+            || (password_verified && self.dropped.is_empty())
+        {
+            return Ok(());
+        }
+        Err(if password_verified {
+            KittyError::Decryption(
+                "none of the stored files decrypts; recovering would leave an empty repository"
+                    .to_string(),
+            )
+        } else {
+            KittyError::InvalidPassword
+        })
+    }
+
+    /// Ask before writing a manifest that leaves files out
+    fn confirm_losses(&self) -> Result<bool, KittyError> {
+        if self.dropped.is_empty() {
+            return Ok(true);
+        }
+        for path in &self.dropped {
+            println!("{} {}", theme::error("Lost:"), path);
+        }
+        println!(
+            "{} file(s) can no longer be read and will be left out of the manifest.",
+            self.dropped.len()
+        );
+        if !confirm()? {
+            println!("Nothing was changed");
+            return Ok(false);
+        }
+        Ok(true)
+    }
+}
+
+/// Salvage whatever is readable from a damaged repository and write a fresh manifest
+pub fn recover_repository(options: Option<RecoverOptions>) -> Result<(), KittyError> {
+    let options = options.unwrap_or_default();
+    let repo_path = get_repository_path()?;
+
+    if !repo_path.exists() {
+        return Err(KittyError::RepositoryNotFound);
+    }
+
+    // Get password from user
+    let password = read_repository_password(false)?;
+
+    if options.find_salt {
+        return recover_salt(&repo_path, &password, options.dry_run);
+    }
+
+    let storage_type = get_storage_type(&repo_path)?;
+    let salt = get_repository_salt(&repo_path)?;
+    let crypto = unlock_admin(&repo_path, &password)?;
+
+    // A damaged manifest fails this with the right password too, so a failure only
+    // means the password has to prove itself on the stored files below
+    let password_verified = verify_password(&repo_path, &crypto).is_ok();
+
+    let mut report = RecoveryReport::default();
+    let _lock = RepoLock::acquire(&repo_path)?;
+
+    let repository = if is_database(&storage_type) {
+        let mut storage = open_database(&repo_path)?;
+        let repository = match storage.load_repository() {
+            Ok(repository) => repository,
+            Err(e) => {
+                println!(
+                    "{} Manifest could not be loaded: {}",
+                    theme::warning("WARNING:"),
+                    e
+                );
+                report.manifest_rebuilt = true;
+                let files = if storage_type == "sqlite" {
+                    SqliteStorage::new(&repo_path)?.salvage_files()?
+                } else {
+                    Vec::new()
+                };
+                Repository {
+                    created_at: Utc::now(),
+                    salt: salt.trim().to_string(),
+                    files,
+                    // Replaces whatever the database holds now
+                    generation: storage.generation().unwrap_or(0),
+                    password_set_at: None,
+                }
+            }
+        };
+
+        let repository = verify_files(repository, &crypto, &mut report, |path| {
+            storage.get_file(path).ok()
+        });
+
+        report.check_readable(password_verified)?;
+        if !options.dry_run {
+            if !report.confirm_losses()? {
+                return Ok(());
+            }
+            storage.save_repository(&repository)?;
+            drop(storage);
+            sign_manifest(&repo_path, &crypto)?;
+        }
+        repository
+    } else {
+        let config_path = repo_path.join(CONFIG_FILE);
+        let loaded = read_manifest(&config_path, &crypto);
+
+        let mut manifest_damaged = false;
+        let repository = match loaded {
+            Ok(repository) => repository,
+            Err(e) => {
+                println!(
+                    "{} config.enc could not be read: {}",
+                    theme::warning("WARNING:"),
+                    e
+                );
+                manifest_damaged = true;
+
+                // Files added since the copy was written are picked up as orphaned blobs below
+                let backup = config_backups(&repo_path).into_iter().find_map(|path| {
+                    read_manifest(&path, &crypto)
+                        .ok()
+                        .map(|repository| (path, repository))
+                });
+                match backup {
+                    Some((path, repository)) => {
+                        println!("Falling back to the previous manifest {}", path.display());
+                        repository
+                    }
+                    None => {
+                        report.manifest_rebuilt = true;
+                        Repository {
+                            created_at: Utc::now(),
+                            salt: salt.trim().to_string(),
+                            files: Vec::new(),
+                            generation: 0,
+                            password_set_at: None,
+                        }
+                    }
+                }
+            }
+        };
+
+        let mut repository = verify_files(repository, &crypto, &mut report, |path| {
+            fs::read(repo_path.join(path)).ok()
+        });
+        adopt_orphan_blobs(&repo_path, &crypto, &mut repository, &mut report)?;
+
+        report.check_readable(password_verified)?;
+        if !options.dry_run {
+            if !report.confirm_losses()? {
+                return Ok(());
+            }
+            // Keep the damaged manifest around for forensics, out of the rotation
+            if manifest_damaged && config_path.exists() {
+                fs::rename(&config_path, repo_path.join("config.enc.damaged"))?;
+            }
+            write_config(&repo_path, &crypto, &repository)?;
+        }
+        repository
+    };
+
+    // Otherwise the lost files were listed when asking to leave them out
+    if options.dry_run {
+        for path in &report.dropped {
+            println!("{} {}", theme::error("Lost:"), path);
+        }
+    }
+    for path in &report.reconstructed {
+        println!("{} {}", theme::success("Reconstructed:"), path);
+    }
+
+    if options.dry_run {
+        println!(
+            "\nDry run: {} file(s) intact, {} reconstructed, {} lost",
+            report.kept,
+            report.reconstructed.len(),
+            report.dropped.len()
+        );
+        return Ok(());
+    }
+
+    for path in &report.reconstructed {
+        let hash = repository
+            .files
+            .iter()
+            .find(|f| &f.original_path == path)
+            .map(|f| f.hash.clone());
+        audit::record(
+            &repo_path,
+            &crypto,
+            &AuditEntry::new("recover", path, None, hash),
+        )?;
+    }
+    inventory::update(&repo_path, &crypto, &repository);
+    stats::record(&repo_path, &crypto, &repository);
+    mirror::update(&repo_path);
+
+    println!(
+        "{} Repository recovered: {} file(s) intact, {} reconstructed, {} lost",
+        theme::success("SUCCESS:"),
+        report.kept,
+        report.reconstructed.len(),
+        report.dropped.len()
+    );
+
+    Ok(())
+}
+
+/// Find the salt of a repository from before `salt.key` and write it there
+///
+/// Tries each place older kittys kept it until one decrypts the repository
+/// with the password. A salt stored in `config.enc` and the blobs is moved
+/// out of them; the key stays the same, so nothing is encrypted again. The
+/// original `config.enc` is kept as `config.enc.legacy`.
+fn recover_salt(repo_path: &Path, password: &str, dry_run: bool) -> Result<(), KittyError> {
+    let _lock = RepoLock::acquire(repo_path)?;
+
+    if is_database(&get_storage_type(repo_path)?) {
+        // Databases keep the salt in the manifest, which is not encrypted
+        let storage = open_database(repo_path)?;
+        let salt = storage.get_salt()?;
+        let crypto = Crypto::from_password_and_salt(password, &hex::decode(salt.trim())?);
+        if let Some(file) = storage.load_repository()?.files.first() {
+            crypto
+                .decrypt(&storage.get_file(&file.repo_path)?)
+                .map_err(|_| {
+                    KittyError::Decryption(
+                        "the database's salt does not decrypt its files with this password"
+                            .to_string(),
+                    )
+                })?;
+        }
+        let current = fs::read_to_string(repo_path.join("salt.key")).unwrap_or_default();
+        if current.trim() == salt.trim() {
+            println!("salt.key already decrypts the repository; there is nothing to recover.");
+            return Ok(());
+        }
+        println!("Found the salt in the database");
+        if !dry_run {
+            write_atomic(&repo_path.join("salt.key"), salt.trim())?;
+            println!("{} Wrote salt.key", theme::success("SUCCESS:"));
+        }
+        return Ok(());
+    }
+
+    let config_path = repo_path.join(CONFIG_FILE);
+    let config = fs::read(&config_path)?;
+    let Some((candidate, crypto)) =
+        salt::find(salt::candidates(repo_path, &config), password, &config)
+    else {
+        return Err(KittyError::Decryption(
+            "none of the known salt locations decrypts the repository with this password"
+                .to_string(),
+        ));
+    };
+    if candidate.source == "salt.key" {
+        println!("salt.key already decrypts the repository; there is nothing to recover.");
+        return Ok(());
+    }
+    println!("Found the salt in {}", candidate.source);
+
+    // Blobs written alongside an embedded salt carry it as well
+    let mut embedded = Vec::new();
+    if candidate.is_embedded() {
+        let files_dir = repo_path.join("files");
+        for entry in fs::read_dir(&files_dir)?.filter_map(|e| e.ok()) {
+            let data = fs::read(entry.path())?;
+            if crypto.decrypt(&data).is_ok() {
+                continue;
+            }
+            if let Some(ciphertext) = candidate
+                .ciphertext(&data)
+                .filter(|c| crypto.decrypt(c).is_ok())
+            {
+                embedded.push((entry.path(), ciphertext.to_vec()));
+            }
+        }
+    }
+
+    if dry_run {
+        println!(
+            "\nDry run: would write salt.key{}",
+            if candidate.is_embedded() {
+                format!(
+                    " and move the salt out of config.enc and {} blob(s)",
+                    embedded.len()
+                )
+            } else {
+                String::new()
+            }
+        );
+        return Ok(());
+    }
+
+    if candidate.is_embedded() {
+        for (path, ciphertext) in &embedded {
+            write_stored(path, ciphertext)?;
+        }
+        fs::copy(&config_path, repo_path.join("config.enc.legacy"))?;
+        if let Some(ciphertext) = candidate.ciphertext(&config) {
+            write_atomic(&config_path, ciphertext)?;
+        }
+    }
+    write_atomic(&repo_path.join("salt.key"), hex::encode(&candidate.salt))?;
+
+    // Everything else now finds the salt the usual way
+    let repository = load_repository(repo_path, &crypto)?;
+    mirror::update(repo_path);
+    println!(
+        "{} Wrote salt.key; the repository and its {} file(s) can be opened again",
+        theme::success("SUCCESS:"),
+        repository.files.len()
+    );
+    if read_format(repo_path)? < CURRENT_FORMAT {
+        println!("Run `kitty upgrade-repo` to bring it to the current format.");
+    }
+
+    Ok(())
+}
+
+fn read_manifest(path: &Path, crypto: &Crypto) -> Result<Repository, KittyError> {
+    let decrypted = crypto.decrypt(&fs::read(path)?)?;
+    Ok(serde_json::from_slice(&decrypted)?)
+}
+
+/// Drop files and versions whose stored content is missing or no longer decrypts
+fn verify_files(
+    mut repository: Repository,
+    crypto: &Crypto,
+    report: &mut RecoveryReport,
+    read: impl Fn(&str) -> Option<Vec<u8>>,
+) -> Repository {
+    let decrypt = |path: &str| read(path).and_then(|encrypted| crypto.decrypt(&encrypted).ok());
+
+    let mut files = Vec::new();
+    for mut file in repository.files {
+        let Some(content) = decrypt(&file.repo_path) else {
+            report.dropped.push(file.original_path);
+            continue;
+        };
+
+        // Files are updated in place, so an older manifest may record an older hash
+        file.hash = digest_like(&file.hash, &content);
+        file.versions.retain(|v| decrypt(&v.repo_path).is_some());
+        files.push(file);
+    }
+
+    if report.manifest_rebuilt {
+        report.reconstructed = files.iter().map(|f| f.original_path.clone()).collect();
+    } else {
+        report.kept = files.len();
+    }
+
+    repository.files = files;
+    repository
+}
+
+/// Track decryptable blobs that no manifest entry refers to
+///
+/// The original path is looked up in the audit log by content hash; blobs with no
+/// match are tracked under `kitty-recovered/` next to the repository.
+fn adopt_orphan_blobs(
+    repo_path: &Path,
+    crypto: &Crypto,
+    repository: &mut Repository,
+    report: &mut RecoveryReport,
+) -> Result<(), KittyError> {
+    let files_dir = repo_path.join("files");
+    if !files_dir.exists() {
+        return Ok(());
+    }
+
+    let referenced: HashSet<String> = repository
+        .files
+        .iter()
+        .flat_map(|f| {
+            std::iter::once(f.repo_path.clone())
+                .chain(f.versions.iter().map(|v| v.repo_path.clone()))
+        })
+        .collect();
+
+    // A damaged audit log only means fewer paths can be recovered
+    let audit_entries = read_entries(repo_path, crypto).unwrap_or_default();
+    let recovered_dir = repo_path
+        .parent()
+        .unwrap_or(repo_path)
+        .join("kitty-recovered");
+
+    let mut candidates = Vec::new();
+    for entry in fs::read_dir(&files_dir)?.filter_map(|e| e.ok()) {
+        let blob_name = entry.file_name().to_string_lossy().to_string();
+        let blob_repo_path = format!("files/{}", blob_name);
+        if referenced.contains(&blob_repo_path) {
+            continue;
+        }
+
+        let Ok(content) = fs::read(entry.path())
+            .map_err(KittyError::from)
+            .and_then(|encrypted| crypto.decrypt(&encrypted))
+        else {
+            continue;
+        };
+
+        // Audit entries may come from either algorithm
+        let hashes = [HashAlgorithm::Blake3, HashAlgorithm::Sha256].map(|a| a.digest(&content));
+        let known = audit_entries.iter().rev().find(|e| {
+            e.new_hash
+                .as_ref()
+                .is_some_and(|hash| hashes.contains(hash))
+        });
+
+        let original_path = match known {
+            Some(known) => known.path.clone(),
+            None => recovered_dir.join(&blob_name).to_string_lossy().to_string(),
+        };
+        let timestamp = known.map(|k| k.timestamp).unwrap_or_else(Utc::now);
+        let [blake3_hash, _] = hashes;
+        let hash = known
+            .and_then(|k| k.new_hash.clone())
+            .unwrap_or(blake3_hash);
+        candidates.push((original_path, blob_repo_path, timestamp, hash));
+    }
+
+    // When several blobs belong to the same path, the newest is current and the rest become versions
+    candidates.sort_by_key(|c| Reverse(c.2));
+    for (original_path, repo_path, timestamp, hash) in candidates {
+        if let Some(file) = repository
+            .files
+            .iter_mut()
+            .find(|f| f.original_path == original_path)
+        {
+            file.versions.insert(
+                0,
+                FileVersion {
+                    repo_path,
+                    hash,
+                    saved_at: timestamp,
+                },
+            );
+            continue;
+        }
+
+        repository.files.push(TrackedFile {
+            original_path: original_path.clone(),
+            repo_path,
+            added_at: timestamp,
+            last_updated: timestamp,
+            hash,
+            package: None,
+            package_manager: None,
+            versions: Vec::new(),
+            bundle: None,
+            tags: Vec::new(),
+            after: Vec::new(),
+            metadata: None,
+            ignore_drift: false,
+            filters: Vec::new(),
+            conditions: Default::default(),
+            revision: repository.next_generation(),
+            expires_at: None,
+            frozen: false,
+            scope: None,
+            tree: None,
+        });
+        report.reconstructed.push(original_path);
+    }
+
+    Ok(())
+}
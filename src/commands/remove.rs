@@ -1,14 +1,28 @@
 use crate::{
-    commands::init::{Crypto, KittyError},
-    storage::sqlite::SqliteStorage,
-    utils::file::{get_repository_path, get_repository_salt, get_storage_type},
+    commands::{
+        init::{Crypto, KittyError, TrackedFile},
+        trash::move_to_trash,
+    },
+    repository::keys::{sign_manifest, unlock_admin},
+    storage::{is_database, open_database},
+    tr,
+    utils::{
+        audit::{self, AuditEntry},
+        file::{get_repository_path, get_storage_type, load_repository, write_config},
+        hooks,
+        i18n::confirm,
+        index, inventory,
+        lock::RepoLock,
+        mirror,
+        password::read_repository_password,
+        select::select_tracked,
+        shred::secure_delete,
+        stats, theme,
+    },
 };
-use colored::Colorize;
-use rpassword::read_password;
 use std::{
     fs,
-    io::{self, Write},
-    path::Path,
+    path::{Path, PathBuf},
 };
 
 /// Options for the remove command
@@ -21,6 +35,15 @@ pub struct RemoveOptions {
 
     /// Keep the file content in the repository, just stop tracking it
     pub keep_content: bool,
+
+    /// Overwrite stored content and restore backups, deleting them instead of trashing
+    pub shred: bool,
+
+    /// Remove every file the path matches when it matches several
+    pub all: bool,
+
+    /// Treat the path as a regular expression
+    pub regex: bool,
 }
 
 impl Default for RemoveOptions {
@@ -29,11 +52,14 @@ impl Default for RemoveOptions {
             path: String::new(),
             force: false,
             keep_content: false,
+            shred: false,
+            all: false,
+            regex: false,
         }
     }
 }
 
-/// Remove a file from tracking in the repository
+/// Remove files from tracking in the repository
 pub fn remove_file(options: &RemoveOptions) -> Result<(), KittyError> {
     let repo_path = get_repository_path()?;
 
@@ -41,115 +67,154 @@ pub fn remove_file(options: &RemoveOptions) -> Result<(), KittyError> {
         return Err(KittyError::RepositoryNotFound);
     }
 
-    // Resolve the file path
-    let file_path = Path::new(&options.path)
-        .canonicalize()
-        .unwrap_or_else(|_| Path::new(&options.path).to_path_buf());
-    let file_path_str = file_path.to_string_lossy().to_string();
-
     // Get password from user
-    print!("Enter repository password: ");
-    io::stdout().flush()?;
-    let password = read_password()?;
-    println!(); // Add a newline after password input
+    let password = read_repository_password(false)?;
 
     // Get the storage type
     let storage_type = get_storage_type(&repo_path)?;
 
-    // Get salt and create crypto instance
-    let salt_str = get_repository_salt(&repo_path)?;
-    let config_salt = hex::decode(&salt_str)?;
-    let crypto = Crypto::from_password_and_salt(&password, &config_salt);
+    // Unlock the repository key with the password
+    let crypto = unlock_admin(&repo_path, &password)?;
+    let lock = RepoLock::acquire(&repo_path)?;
 
-    // Load repository based on storage type
-    let mut repository = if storage_type == "sqlite" {
-        // Use SQLite storage
-        let storage = SqliteStorage::new(&repo_path)?;
-        storage.load_repository()?
-    } else {
-        // Use file-based storage
-        let encrypted_config = fs::read(repo_path.join("config.enc"))?;
-        let decrypted_config = crypto.decrypt(&encrypted_config)?;
-        serde_json::from_slice(&decrypted_config)?
-    };
+    let mut repository = load_repository(&repo_path, &crypto)?;
 
-    // Find the file in the repository
-    let file_index = repository
-        .files
-        .iter()
-        .position(|f| f.original_path == file_path_str || Path::new(&f.original_path) == file_path);
+    // Find the files in the repository, asking when a partial path is ambiguous
+    let selected: Vec<String> =
+        select_tracked(&repository.files, &options.path, options.all, options.regex)?
+            .into_iter()
+            .map(|index| repository.files[index].original_path.clone())
+            .collect();
 
-    // If file not found, check if it's a partial path match
-    let file_index = match file_index {
-        Some(index) => Some(index),
-        None => repository
-            .files
-            .iter()
-            .position(|f| f.original_path.contains(&options.path)),
-    };
-
-    if let Some(index) = file_index {
-        // Get file information before removing it
-        let original_path = repository.files[index].original_path.clone();
-        let repo_file_path = repository.files[index].repo_path.clone();
-
-        // Get confirmation from user if not forced
-        if !options.force {
+    // Get confirmation from user if not forced
+    if !options.force {
+        for original_path in &selected {
             println!(
-                "About to remove file from tracking: {}",
-                original_path.bold()
+                "{}",
+                tr!(
+                    "remove-confirm",
+                    path = theme::emphasis(original_path).to_string()
+                )
             );
-            print!("Continue? [y/N] ");
-            io::stdout().flush()?;
-
-            let mut input = String::new();
-            io::stdin().read_line(&mut input)?;
-
-            if !input.trim().eq_ignore_ascii_case("y") {
-                println!("Remove operation canceled.");
-                return Ok(());
-            }
         }
+        if !confirm()? {
+            println!("{}", tr!("remove-canceled"));
+            return Ok(());
+        }
+    }
+
+    let mut removed_files = Vec::new();
+    for original_path in &selected {
+        let Some(index) = repository
+            .files
+            .iter()
+            .position(|f| &f.original_path == original_path)
+        else {
+            continue;
+        };
 
         // Remove the file from the repository list
-        repository.files.remove(index);
+        removed_files.push(repository.files.remove(index));
+    }
 
-        // Delete the file content from the repository if keep_content is false
-        if !options.keep_content {
-            let file_repo_path = repo_path.join(&repo_file_path);
-            if file_repo_path.exists() {
-                fs::remove_file(file_repo_path)?;
-            }
-        }
+    // Save repository based on storage type
+    let removed_hashes = if is_database(&storage_type) {
+        // Databases drop content nothing refers to on saving, so it goes to the trash first
+        let removed_hashes = discard_content(&repo_path, &crypto, options, removed_files)?;
+        open_database(&repo_path)?.save_repository(&repository)?;
+        sign_manifest(&repo_path, &crypto)?;
+        removed_hashes
+    } else {
+        // Blobs are only let go of once the saved manifest no longer refers to them
+        write_config(&repo_path, &crypto, &repository)?;
+        discard_content(&repo_path, &crypto, options, removed_files)?
+    };
 
-        // Save repository based on storage type
-        if storage_type == "sqlite" {
-            // Use SQLite storage
-            let mut storage = SqliteStorage::new(&repo_path)?;
-            storage.save_repository(&repository)?;
-        } else {
-            // Use file-based storage
-            let updated_config_json = serde_json::to_string(&repository)?;
-            let encrypted_updated_config = crypto.encrypt(updated_config_json.as_bytes())?;
+    let _ = index::forget(&repo_path, &selected);
 
-            // Write updated encrypted configuration
-            fs::write(repo_path.join("config.enc"), encrypted_updated_config)?;
-        }
+    inventory::update(&repo_path, &crypto, &repository);
+    stats::record(&repo_path, &crypto, &repository);
+
+    let mut hook_entries = Vec::new();
+    for (original_path, old_hash) in removed_hashes {
+        let entry = AuditEntry::new("rm", &original_path, Some(old_hash), None);
+        audit::record(&repo_path, &crypto, &entry)?;
+        hook_entries.push(entry);
 
         println!(
             "{} File removed from tracking: {}",
-            "SUCCESS:".green().bold(),
+            theme::success("SUCCESS:"),
             original_path
         );
 
+        if !options.keep_content && !options.shred {
+            println!(
+                "Moved to trash; undo with `kitty trash restore {}`",
+                original_path
+            );
+        }
+
         // Show a reminder that the actual file wasn't deleted
         println!(
             "Note: The original file at {} was not modified.",
             original_path
         );
+    }
+    mirror::update(&repo_path);
+    // Hooks may run kitty themselves
+    drop(lock);
+    hooks::run(&repo_path, "post-remove", &hook_entries);
 
-        Ok(())
-    } else {
-        Err(KittyError::FileNotTracked(options.path.clone()))
+    Ok(())
+}
+
+/// Trash or delete the content of removed files, returning their paths and last hashes
+fn discard_content(
+    repo_path: &Path,
+    crypto: &Crypto,
+    options: &RemoveOptions,
+    removed_files: Vec<TrackedFile>,
+) -> Result<Vec<(String, String)>, KittyError> {
+    let delete = |path: &Path| {
+        if options.shred {
+            secure_delete(path)
+        } else {
+            fs::remove_file(path)
+        }
+    };
+
+    let mut removed_hashes = Vec::new();
+    for removed in removed_files {
+        let original_path = removed.original_path.clone();
+        let old_hash = removed.hash.clone();
+
+        // Content goes to the trash unless it is kept in place or shredded right away
+        if !options.keep_content && !options.shred {
+            move_to_trash(repo_path, crypto, removed)?;
+        } else if !options.keep_content {
+            let file_repo_path = repo_path.join(&removed.repo_path);
+            if file_repo_path.exists() {
+                delete(&file_repo_path)?;
+            }
+
+            for version in &removed.versions {
+                let version_file = repo_path.join(&version.repo_path);
+                if version_file.exists() {
+                    delete(&version_file)?;
+                }
+            }
+        }
+
+        // Restore leaves plaintext backups next to the file
+        if options.shred {
+            let backup = PathBuf::from(format!("{}.bak", original_path));
+            if backup.exists() {
+                secure_delete(&backup)?;
+                println!("Shredded backup: {}", backup.display());
+            }
+        }
+
+        removed_hashes.push((original_path, old_hash));
     }
+    Ok(removed_hashes)
 }
@@ -0,0 +1,340 @@
+use crate::{
+    collectors::Collector,
+    commands::{add::store_content_as, init::KittyError},
+    repository::keys::unlock,
+    storage::{is_database, open_database},
+    utils::{
+        file::{get_repository_path, get_storage_type, load_repository, read_stored},
+        index,
+        password::read_repository_password,
+        select::select_tracked,
+        table::terminal_width,
+        theme,
+    },
+};
+use colored::Colorize;
+use similar::{ChangeTag, DiffOp, DiffTag, TextDiff};
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Write},
+};
+
+/// Where `kitty resolve` writes the resolved content
+#[derive(Clone, Copy, PartialEq, Default)]
+pub enum ResolveTarget {
+    /// The file on disk
+    Disk,
+
+    /// The repository, as a new version of the file
+    Repository,
+
+    /// Both, so they match afterwards
+    #[default]
+    Both,
+}
+
+impl ResolveTarget {
+    pub fn parse(name: &str) -> Result<Self, KittyError> {
+        match name {
+            "disk" => Ok(ResolveTarget::Disk),
+            "repo" => Ok(ResolveTarget::Repository),
+            "both" => Ok(ResolveTarget::Both),
+            _ => Err(KittyError::Usage(format!(
+                "cannot write to '{}': use disk, repo or both",
+                name
+            ))),
+        }
+    }
+
+    fn disk(self) -> bool {
+        self != ResolveTarget::Repository
+    }
+
+    fn repository(self) -> bool {
+        self != ResolveTarget::Disk
+    }
+}
+
+/// Options for the resolve command
+#[derive(Default)]
+pub struct ResolveOptions {
+    /// Where the resolved content goes
+    pub write: ResolveTarget,
+}
+
+/// The side a change is taken from
+#[derive(Clone, Copy, PartialEq)]
+enum Side {
+    Local,
+    Repository,
+
+    /// Local lines followed by the repository's
+    Both,
+}
+
+/// Ask a question and return the answer, keeping its case since `L` and `l` differ
+fn prompt(question: &str) -> Result<String, KittyError> {
+    print!("{}", question);
+    io::stdout().flush()?;
+
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+    Ok(input.trim().to_string())
+}
+
+/// A line cut or padded to `width` characters, with tabs expanded so columns line up
+fn cell(line: &str, width: usize) -> String {
+    let line = line.trim_end_matches(['\r', '\n']).replace('\t', "    ");
+    let length = line.chars().count();
+    if length > width {
+        let mut cut: String = line.chars().take(width.saturating_sub(1)).collect();
+        cut.push('…');
+        cut
+    } else {
+        format!("{}{}", line, " ".repeat(width - length))
+    }
+}
+
+/// Print one hunk with the local lines on the left and the repository's on the right
+fn print_side_by_side(diff: &TextDiff<'_, '_, '_, str>, hunk: &[DiffOp], width: usize) {
+    let column = width.saturating_sub(3) / 2;
+    for op in hunk {
+        let old: Vec<&str> = diff.old_slices()[op.old_range()].to_vec();
+        let new: Vec<&str> = diff.new_slices()[op.new_range()].to_vec();
+
+        if op.tag() == DiffTag::Equal {
+            for line in old {
+                println!(
+                    "{} {} {}",
+                    cell(line, column).dimmed(),
+                    "│".dimmed(),
+                    cell(line, column).dimmed()
+                );
+            }
+            continue;
+        }
+        for row in 0..old.len().max(new.len()) {
+            let left = match old.get(row) {
+                Some(line) => theme::deletion(&cell(line, column)),
+                None => cell("", column).normal(),
+            };
+            let right = match new.get(row) {
+                Some(line) => theme::addition(&cell(line, column)),
+                None => cell("", column).normal(),
+            };
+            println!("{} {} {}", left, theme::hunk("│"), right);
+        }
+    }
+}
+
+/// Walk through the differing hunks and build the resolved content, or None to abort
+fn resolve_text(local: &str, repository: &str) -> Result<Option<String>, KittyError> {
+    let diff = TextDiff::from_lines(local, repository);
+    let hunks = diff.grouped_ops(3);
+    let width = terminal_width().unwrap_or(120);
+    let column = width.saturating_sub(3) / 2;
+
+    // Changed ops are identified by where they start on both sides
+    let mut picked: HashMap<(usize, usize), Side> = HashMap::new();
+    let mut rest_of_file: Option<Side> = None;
+
+    for (index, hunk) in hunks.iter().enumerate() {
+        let side = match rest_of_file {
+            Some(side) => side,
+            None => {
+                println!(
+                    "\n{} {} {}",
+                    theme::emphasis(&cell("local", column)),
+                    theme::hunk("│"),
+                    theme::emphasis(&cell("repository", column))
+                );
+                print_side_by_side(&diff, hunk, width);
+                loop {
+                    let question = format!(
+                        "Take this change ({}/{}) from [l,r,b,L,R,q,?]? ",
+                        index + 1,
+                        hunks.len()
+                    );
+                    match prompt(&question)?.as_str() {
+                        "l" => break Side::Local,
+                        "r" => break Side::Repository,
+                        "b" => break Side::Both,
+                        "L" => {
+                            rest_of_file = Some(Side::Local);
+                            break Side::Local;
+                        }
+                        "R" => {
+                            rest_of_file = Some(Side::Repository);
+                            break Side::Repository;
+                        }
+                        "q" => return Ok(None),
+                        _ => {
+                            println!("l - keep the local lines");
+                            println!("r - take the repository's lines");
+                            println!("b - keep both, local lines first");
+                            println!("L - keep the local lines here and in every later change");
+                            println!(
+                                "R - take the repository's lines here and in every later change"
+                            );
+                            println!("q - stop without writing anything");
+                        }
+                    }
+                }
+            }
+        };
+
+        for op in hunk.iter().filter(|op| op.tag() != DiffTag::Equal) {
+            picked.insert((op.old_range().start, op.new_range().start), side);
+        }
+    }
+
+    let mut resolved = String::new();
+    for op in diff.ops() {
+        let old = &diff.old_slices()[op.old_range()];
+        let new = &diff.new_slices()[op.new_range()];
+        if op.tag() == DiffTag::Equal {
+            resolved.extend(old.iter().copied());
+            continue;
+        }
+        match picked[&(op.old_range().start, op.new_range().start)] {
+            Side::Local => resolved.extend(old.iter().copied()),
+            Side::Repository => resolved.extend(new.iter().copied()),
+            Side::Both => {
+                resolved.extend(old.iter().copied());
+                resolved.extend(new.iter().copied());
+            }
+        }
+    }
+    Ok(Some(resolved))
+}
+
+/// Print a short summary of how the resolved content differs from each side
+fn print_summary(label: &str, from: &str, resolved: &str) {
+    let diff = TextDiff::from_lines(from, resolved);
+    let (mut added, mut removed) = (0, 0);
+    for change in diff.iter_all_changes() {
+        match change.tag() {
+            ChangeTag::Insert => added += 1,
+            ChangeTag::Delete => removed += 1,
+            ChangeTag::Equal => {}
+        }
+    }
+    println!(
+        "  {}: {} {}",
+        label,
+        theme::addition(&format!("+{}", added)),
+        theme::deletion(&format!("-{}", removed))
+    );
+}
+
+/// Resolve differences between a tracked file on disk and its stored copy
+///
+/// Both versions are shown side by side, hunk by hunk, and each change is
+/// taken from either side or from both. The result is written to disk, to
+/// the repository as a new version, or to both.
+pub fn resolve_file(path: &str, options: Option<ResolveOptions>) -> Result<(), KittyError> {
+    let options = options.unwrap_or_default();
+    let repo_path = get_repository_path()?;
+
+    if !repo_path.exists() {
+        return Err(KittyError::RepositoryNotFound);
+    }
+
+    // Get password from user
+    let password = read_repository_password(false)?;
+
+    let crypto = unlock(&repo_path, &password)?;
+    if options.write.repository() {
+        // Found out before the merge rather than after it
+        crypto.require_admin()?;
+    }
+    let repository = load_repository(&repo_path, &crypto)?;
+
+    let [index] = select_tracked(&repository.files, path, false, false)?[..] else {
+        return Err(KittyError::AmbiguousPath(format!(
+            "'{}' matches several tracked files; resolve one at a time",
+            path
+        )));
+    };
+    let file = &repository.files[index];
+    if Collector::parse(&file.original_path).is_some() {
+        return Err(KittyError::NotAFile {
+            path: file.original_path.clone(),
+        });
+    }
+
+    let local = match fs::read(&file.original_path) {
+        Ok(local) => local,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            println!(
+                "{} no longer exists; recreate it with `kitty restore --missing-only`",
+                file.original_path
+            );
+            return Ok(());
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let encrypted = if is_database(&get_storage_type(&repo_path)?) {
+        open_database(&repo_path)?.get_file(&file.repo_path)?
+    } else {
+        read_stored(&repo_path.join(&file.repo_path))?
+    };
+    let stored = crypto.decrypt(&encrypted)?;
+
+    if local == stored {
+        println!(
+            "{} matches the repository; nothing to resolve",
+            file.original_path
+        );
+        return Ok(());
+    }
+
+    println!("Resolving {}", theme::emphasis(&file.original_path));
+    let resolved = match (std::str::from_utf8(&local), std::str::from_utf8(&stored)) {
+        (Ok(local_text), Ok(stored_text)) => {
+            let Some(resolved) = resolve_text(local_text, stored_text)? else {
+                println!("Stopped; nothing was written");
+                return Ok(());
+            };
+            println!();
+            print_summary("Compared with the local file", local_text, &resolved);
+            print_summary("Compared with the repository", stored_text, &resolved);
+            resolved.into_bytes()
+        }
+        // Binary content can only be taken whole
+        _ => match prompt("This file is binary. Keep [l]ocal, take [r]epository or [q]uit? ")?
+            .as_str()
+        {
+            "l" => local.clone(),
+            "r" => stored.clone(),
+            _ => {
+                println!("Stopped; nothing was written");
+                return Ok(());
+            }
+        },
+    };
+
+    if options.write.disk() && resolved != local {
+        fs::write(&file.original_path, &resolved)?;
+        println!(
+            "{} Wrote {}",
+            theme::success("SUCCESS:"),
+            file.original_path
+        );
+    }
+    if options.write.repository() && resolved != stored {
+        store_content_as(
+            &file.original_path,
+            resolved,
+            &file.hash,
+            &password,
+            "resolve",
+        )?;
+    } else {
+        // Storing refreshes the prompt index; writing only the disk leaves it to us
+        let _ = index::refresh(&repo_path, &[file]);
+    }
+
+    Ok(())
+}
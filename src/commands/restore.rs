@@ -1,18 +1,55 @@
 use crate::{
-    commands::init::{Crypto, KittyError, TrackedFile},
-    storage::sqlite::SqliteStorage,
-    utils::file::{get_repository_path, get_repository_salt, get_storage_type},
+    collectors::{packages::install_command, read_live, Collector},
+    commands::{
+        add::TREE_DELETE,
+        diff::{diff_contents, DiffOptions},
+        freeze::{make_read_only, make_writable},
+        init::{Crypto, FileMetadata, KittyError, Scope, TrackedFile},
+        plan::{Plan, PlanAction, PlannedChange},
+    },
+    repository::{
+        config::RepoConfig,
+        keys::{unlock, verify_content},
+    },
+    storage::{is_database, open_database},
+    tr,
+    utils::{
+        audit::{self, AuditEntry},
+        events::{self, Event, Severity},
+        file::{
+            create_parent_directories, get_repository_path, get_storage_type, load_repository,
+            read_stored, read_stored_files, write_with_privileges,
+        },
+        hash::{digest_file_like, digest_like},
+        hooks,
+        i18n::confirm,
+        index,
+        link::link_file,
+        order::{order_for_restore, refers_to},
+        password::read_repository_password,
+        select::{load_candidates, select_tracked},
+        theme,
+        tree::{trees, Tree},
+        validate::{check, validators},
+        vars::{profile_name, Renderer, VarCatalog},
+    },
 };
 
+use chrono::{DateTime, Local, Utc};
 use colored::Colorize;
-use rpassword::read_password;
+use serde::Serialize;
+use similar::{ChangeTag, DiffOp, DiffTag, TextDiff};
 use std::{
+    collections::{BTreeMap, HashSet},
     fs,
     io::{self, Write},
-    path::Path,
+    os::unix::fs::{MetadataExt, PermissionsExt},
+    path::{Path, PathBuf},
+    time::SystemTime,
 };
 
 /// Options for the restore command
+#[derive(Default)]
 pub struct RestoreOptions {
     /// Path to the file to restore
     pub path: Option<String>,
@@ -23,23 +60,505 @@ pub struct RestoreOptions {
     /// Show what would be restored without actually restoring
     pub dry_run: bool,
 
-    /// Backup existing files before restoring
-    pub backup: bool,
+    /// Backup existing files before restoring (None uses the repository setting)
+    pub backup: Option<bool>,
+
+    /// Install tracked package lists instead of restoring files
+    pub packages: bool,
+
+    /// Show each change and ask whether to restore it
+    pub patch: bool,
+
+    /// Set restored files' mtime to the one recorded when they were added
+    pub preserve_times: bool,
+
+    /// Link files to managed copies in the repository instead of writing them
+    pub link: bool,
+
+    /// Print progress to stderr so the caller can print the report as JSON
+    pub json: bool,
+
+    /// Restore every file the path matches when it matches several
+    pub all: bool,
+
+    /// Treat the path as a regular expression
+    pub regex: bool,
+
+    /// Restore the files of a role from the repository settings instead of a path
+    pub role: Option<String>,
+
+    /// Leave files whose content already matches the repository alone
+    pub skip_unchanged: bool,
+
+    /// Fill in template variables and Vault secrets as files are written
+    pub render: bool,
+
+    /// Profile whose variables to render with (default: KITTY_PROFILE or the hostname)
+    pub profile: Option<String>,
+
+    /// Only recreate files that no longer exist, leaving the others alone
+    pub missing_only: bool,
+
+    /// Make exactly the changes of a saved plan, refusing if anything changed since
+    pub plan: Option<Plan>,
+
+    /// Give files the mode recorded when they were added, and the owner when running as root
+    pub permissions: bool,
+}
+
+/// Print progress to stdout, or to stderr when stdout is reserved for the JSON report
+macro_rules! progress {
+    ($json:expr) => {
+        if $json {
+            eprintln!()
+        } else {
+            println!()
+        }
+    };
+    ($json:expr, $($arg:tt)*) => {
+        if $json {
+            eprintln!($($arg)*)
+        } else {
+            println!($($arg)*)
+        }
+    };
+}
+
+/// A file that could not be restored
+#[derive(Serialize)]
+pub struct RestoreFailure {
+    pub path: String,
+    pub reason: String,
+}
+
+/// What a restore did, for the summary and `--json`
+#[derive(Serialize, Default)]
+pub struct RestoreReport {
+    pub processed: usize,
+    pub restored: usize,
+    pub skipped: usize,
+    pub errors: Vec<RestoreFailure>,
+
+    /// Restored files that did not exist before
+    pub created: usize,
+
+    /// Files whose mode or owner was put back to the recorded one
+    pub permissions: usize,
+
+    /// `post-restore` hooks that were run
+    pub hooks: usize,
+
+    /// Backups written next to the files that were replaced
+    pub backups: Vec<String>,
+
+    /// With a dry run, the changes a restore would make
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub planned: Vec<PlannedChange>,
+
+    /// Files deleted from restored trees because they are not part of them
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub removed: Vec<String>,
+}
+
+impl RestoreReport {
+    /// Report and record a file that could not be restored
+    fn fail(&mut self, path: &str, reason: String, json: bool) {
+        progress!(json, "  {} {}", theme::error("ERROR:"), reason);
+        self.errors.push(RestoreFailure {
+            path: path.to_string(),
+            reason,
+        });
+    }
+}
+
+/// The outcome of walking through a file's changes with `restore --patch`
+struct Selection {
+    /// Content to write, or None when no change was picked
+    content: Option<Vec<u8>>,
+
+    /// Stop after this file
+    quit: bool,
+}
+
+/// Ask a question and return the lowercased answer
+fn prompt(question: &str) -> Result<String, KittyError> {
+    print!("{}", question);
+    io::stdout().flush()?;
+
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+    Ok(input.trim().to_lowercase())
+}
+
+/// Print one hunk, with local lines as removals and repository lines as additions
+fn print_hunk(diff: &TextDiff<'_, '_, '_, str>, hunk: &[DiffOp]) {
+    let (Some(first), Some(last)) = (hunk.first(), hunk.last()) else {
+        return;
+    };
+    println!(
+        "  {}",
+        theme::hunk(&format!(
+            "@@ -{},{} +{},{} @@",
+            first.old_range().start + 1,
+            last.old_range().end - first.old_range().start,
+            first.new_range().start + 1,
+            last.new_range().end - first.new_range().start
+        ))
+    );
+
+    for op in hunk {
+        for change in diff.iter_changes(op) {
+            match change.tag() {
+                ChangeTag::Delete => print!("  {}{}", theme::deletion("-"), change),
+                ChangeTag::Insert => print!("  {}{}", theme::addition("+"), change),
+                ChangeTag::Equal => print!("   {}", change),
+            }
+            if change.missing_newline() {
+                println!();
+            }
+        }
+    }
 }
 
-impl Default for RestoreOptions {
-    fn default() -> Self {
-        Self {
-            path: None,
-            force: false,
-            dry_run: false,
-            backup: true,
+/// Walk through the differences between the local file and the repository copy
+/// like `git checkout -p`, building the content from the changes picked
+fn select_changes(current: &[u8], stored: &[u8]) -> Result<Selection, KittyError> {
+    let (Ok(local), Ok(repository)) = (std::str::from_utf8(current), std::str::from_utf8(stored))
+    else {
+        // Binary content can only be taken whole
+        let answer = prompt("  Restore this binary file [y,n,q]? ")?;
+        return Ok(Selection {
+            content: (answer == "y").then(|| stored.to_vec()),
+            quit: answer == "q",
+        });
+    };
+
+    let diff = TextDiff::from_lines(local, repository);
+    let hunks = diff.grouped_ops(3);
+    println!(
+        "  {} local  {} repository",
+        theme::deletion("-"),
+        theme::addition("+")
+    );
+
+    // Changed ops are identified by where they start on both sides
+    let mut accepted: HashSet<(usize, usize)> = HashSet::new();
+    let mut rest_of_file: Option<bool> = None;
+    let mut quit = false;
+
+    for (index, hunk) in hunks.iter().enumerate() {
+        let take = match rest_of_file {
+            Some(take) => take,
+            None => {
+                print_hunk(&diff, hunk);
+                loop {
+                    let question = format!(
+                        "  Restore this change ({}/{}) [y,n,a,d,q,?]? ",
+                        index + 1,
+                        hunks.len()
+                    );
+                    match prompt(&question)?.as_str() {
+                        "y" => break true,
+                        "n" => break false,
+                        "a" => {
+                            rest_of_file = Some(true);
+                            break true;
+                        }
+                        "d" => {
+                            rest_of_file = Some(false);
+                            break false;
+                        }
+                        "q" => {
+                            quit = true;
+                            rest_of_file = Some(false);
+                            break false;
+                        }
+                        _ => {
+                            println!("  y - restore this change");
+                            println!("  n - keep the local lines");
+                            println!("  a - restore this and all later changes in the file");
+                            println!("  d - keep the local lines for the rest of the file");
+                            println!("  q - stop here, keeping the changes picked so far");
+                        }
+                    }
+                }
+            }
+        };
+
+        if take {
+            for op in hunk.iter().filter(|op| op.tag() != DiffTag::Equal) {
+                accepted.insert((op.old_range().start, op.new_range().start));
+            }
         }
     }
+
+    if accepted.is_empty() {
+        return Ok(Selection {
+            content: None,
+            quit,
+        });
+    }
+
+    let mut merged = String::new();
+    for op in diff.ops() {
+        let take = op.tag() != DiffTag::Equal
+            && accepted.contains(&(op.old_range().start, op.new_range().start));
+        let lines = if take {
+            &diff.new_slices()[op.new_range()]
+        } else {
+            &diff.old_slices()[op.old_range()]
+        };
+        merged.extend(lines.iter().copied());
+    }
+
+    Ok(Selection {
+        content: Some(merged.into_bytes()),
+        quit,
+    })
+}
+
+/// Owner and mode given to a new system file that was added without metadata
+const ROOT_DEFAULTS: FileMetadata = FileMetadata {
+    mode: 0o644,
+    uid: 0,
+    gid: 0,
+    modified: None,
+};
+
+/// Write restored content, with sudo for a system file the user cannot write to
+///
+/// User files are never written with elevated privileges.
+fn write_file(path: &Path, contents: &[u8], scope: Scope) -> Result<(), KittyError> {
+    match fs::write(path, contents) {
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied && scope == Scope::System => {
+            write_with_privileges(path, contents)
+        }
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+            Err(KittyError::PrivilegeRequired(format!(
+                "{} (user files are never written with sudo)",
+                path.display()
+            )))
+        }
+        result => Ok(result?),
+    }
+}
+
+/// Delete the files below a restored tree that were deleted from it, after asking
+///
+/// Only files the audit log records as deleted from the tree on another machine
+/// are removed, and each is first moved to the same place below `<root>.bak`.
+/// Anything else below the root that the tree lacks, like files local to this
+/// machine, is only listed.
+fn remove_strays(
+    repo_path: &Path,
+    crypto: &Crypto,
+    tree: &Tree,
+    options: &RestoreOptions,
+    report: &mut RestoreReport,
+) -> Result<(), KittyError> {
+    let strays = tree.strays();
+    if strays.is_empty() {
+        return Ok(());
+    }
+
+    let deletions: Vec<AuditEntry> = audit::read_entries(repo_path, crypto)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|entry| entry.operation == TREE_DELETE)
+        .collect();
+    let mut deleted = Vec::new();
+    let mut unknown = Vec::new();
+    for path in strays {
+        match deletions
+            .iter()
+            .rev()
+            .find(|entry| Path::new(&entry.path) == path)
+        {
+            Some(entry) => deleted.push((path, entry)),
+            None => unknown.push(path),
+        }
+    }
+
+    if !unknown.is_empty() {
+        progress!(
+            options.json,
+            "\n{} file(s) in {} are not part of the tree and are left alone:",
+            unknown.len(),
+            tree.root
+        );
+        for path in &unknown {
+            progress!(options.json, "  {}", path.display());
+        }
+    }
+    if deleted.is_empty() {
+        return Ok(());
+    }
+
+    progress!(
+        options.json,
+        "\n{} file(s) in {} were deleted from the tree:",
+        deleted.len(),
+        tree.root
+    );
+    for (path, entry) in &deleted {
+        progress!(
+            options.json,
+            "  {} {}",
+            path.display(),
+            format!(
+                "(deleted from the tree {} by {})",
+                entry
+                    .timestamp
+                    .with_timezone(&Local)
+                    .format("%Y-%m-%d %H:%M"),
+                entry.user
+            )
+            .dimmed()
+        );
+    }
+    if options.dry_run {
+        progress!(
+            options.json,
+            "Would delete them, keeping a copy of each in {}.bak",
+            tree.root
+        );
+        return Ok(());
+    }
+    if !options.force && !confirm()? {
+        progress!(options.json, "Keeping them");
+        return Ok(());
+    }
+
+    // Next to the root rather than below it, where the tree would take the copies in
+    let backup_root = PathBuf::from(format!("{}.bak", tree.root));
+    for (path, _) in deleted {
+        let relative = path.strip_prefix(tree.root).unwrap_or(&path);
+        let backup_path = backup_root.join(relative);
+        // The copy is all that is left of it, so the file goes only once it is made
+        let moved = backup_path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| fs::rename(&path, &backup_path));
+        match moved {
+            Ok(()) => {
+                report.backups.push(backup_path.display().to_string());
+                report.removed.push(path.display().to_string());
+            }
+            Err(e) => report.fail(
+                &path.display().to_string(),
+                format!("Failed to delete: {}", e),
+                options.json,
+            ),
+        }
+    }
+    Ok(())
+}
+
+/// Set a file's modification time
+fn set_modified(path: &Path, modified: DateTime<Utc>) -> io::Result<()> {
+    fs::File::options()
+        .write(true)
+        .open(path)?
+        .set_modified(SystemTime::from(modified))
+}
+
+/// Give a file the mode and owner it had when it was added, returning whether anything changed
+///
+/// Frozen files keep their write bits cleared. Only root can give a file to
+/// another user, so otherwise a different owner is left as it is. With
+/// `dry_run`, only reports whether the mode would change.
+fn put_back_permissions(
+    path: &Path,
+    recorded: &FileMetadata,
+    frozen: bool,
+    dry_run: bool,
+) -> io::Result<bool> {
+    let current = fs::metadata(path)?;
+    let mut mode = recorded.mode & 0o7777;
+    if frozen {
+        mode &= !0o222;
+    }
+    if dry_run {
+        return Ok(current.mode() & 0o7777 != mode);
+    }
+
+    // Before the mode, as changing the owner clears setuid bits
+    let mut changed = false;
+    if (current.uid(), current.gid()) != (recorded.uid, recorded.gid) {
+        match std::os::unix::fs::chown(path, Some(recorded.uid), Some(recorded.gid)) {
+            Ok(()) => changed = true,
+            Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {}
+            Err(e) => return Err(e),
+        }
+    }
+    if fs::metadata(path)?.mode() & 0o7777 != mode {
+        fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+        changed = true;
+    }
+    Ok(changed)
+}
+
+/// The role `kitty apply` uses without `--role`: the one named after the machine's profile
+///
+/// Without such a role, every tracked file meant for this machine is applied.
+pub fn host_role(profile: Option<&str>) -> Result<Option<String>, KittyError> {
+    let host = profile_name(profile);
+    let config = RepoConfig::load(&get_repository_path()?)?;
+    if config.roles.contains_key(&host) {
+        eprintln!(
+            "Applying role '{}', named after this machine's profile",
+            host
+        );
+        Ok(Some(host))
+    } else {
+        eprintln!(
+            "No role is named after profile '{}'; applying every tracked file for this machine",
+            host
+        );
+        Ok(None)
+    }
+}
+
+/// Print what `kitty apply` did to bring the machine in line with the repository
+fn print_convergence(report: &RestoreReport) {
+    let title = tr!("apply-report-title");
+    println!("\n{}", title);
+    println!("{}", "=".repeat(title.chars().count()));
+    println!("{}", tr!("apply-report-unchanged", count = report.skipped));
+    println!("{}", tr!("apply-report-created", count = report.created));
+    println!(
+        "{}",
+        tr!(
+            "apply-report-updated",
+            count = report.restored - report.created
+        )
+    );
+    println!(
+        "{}",
+        tr!("apply-report-permissions", count = report.permissions)
+    );
+    println!(
+        "{}",
+        tr!("apply-report-backups", count = report.backups.len())
+    );
+    println!("{}", tr!("apply-report-hooks", count = report.hooks));
+    println!(
+        "{}",
+        tr!("apply-report-errors", count = report.errors.len())
+    );
+
+    if report.errors.is_empty() {
+        println!("\n{}", theme::success(&tr!("apply-converged")));
+    } else {
+        println!(
+            "\n{}",
+            theme::error(&tr!("apply-not-converged", count = report.errors.len()))
+        );
+    }
 }
 
 /// Restore files from the repository
-pub fn restore_files(options: Option<RestoreOptions>) -> Result<(), KittyError> {
+pub fn restore_files(options: Option<RestoreOptions>) -> Result<RestoreReport, KittyError> {
     let options = options.unwrap_or_default();
     let repo_path = get_repository_path()?;
 
@@ -47,144 +566,335 @@ pub fn restore_files(options: Option<RestoreOptions>) -> Result<(), KittyError>
         return Err(KittyError::RepositoryNotFound);
     }
 
-    // Get password from user
-    print!("Enter repository password: ");
-    io::stdout().flush()?;
-    let password = read_password()?;
-    println!(); // Add a newline after password input
+    // Get password from user, keeping stdout for the report with --json
+    let password = read_repository_password(options.json)?;
+
+    restore_files_with_password(options, &password)
+}
+
+/// Restore files from the repository using an already known password
+pub fn restore_files_with_password(
+    options: RestoreOptions,
+    password: &str,
+) -> Result<RestoreReport, KittyError> {
+    let repo_path = get_repository_path()?;
+
+    if !repo_path.exists() {
+        return Err(KittyError::RepositoryNotFound);
+    }
+
+    // Flags override the default from the repository settings
+    let config = RepoConfig::load(&repo_path)?;
+    let backup = options.backup.unwrap_or(config.restore.backup);
 
     // Get the storage type
     let storage_type = get_storage_type(&repo_path)?;
-    println!("Using storage type: {}", storage_type);
+    progress!(options.json, "Using storage type: {}", storage_type);
 
-    // Get salt and create crypto instance
-    let config_salt = hex::decode(get_repository_salt(&repo_path)?)?;
-    let crypto = Crypto::from_password_and_salt(&password, &config_salt);
+    // Unlock the repository key with the password
+    let crypto = unlock(&repo_path, password)?;
 
-    // Load repository based on storage type
-    let repository = if storage_type == "sqlite" {
-        // Use SQLite storage
-        let storage = SqliteStorage::new(&repo_path)?;
-        storage.load_repository()?
-    } else {
-        // Use file-based storage
-        let encrypted_config = fs::read(repo_path.join("config.enc"))?;
-        let decrypted_config = crypto.decrypt(&encrypted_config)?;
-        serde_json::from_slice(&decrypted_config)?
+    // A path only needs the files it can name, not the whole manifest
+    let files = match &options.path {
+        Some(path) => load_candidates(&repo_path, &crypto, path, options.regex)?,
+        None => load_repository(&repo_path, &crypto)?.files,
     };
 
-    if repository.files.is_empty() {
-        println!("No files are currently tracked in the repository.");
-        return Ok(());
+    if files.is_empty() {
+        progress!(options.json, "{}", tr!("no-files-tracked"));
+        return Ok(RestoreReport::default());
     }
 
     // Filter files based on path option
     // Store the files we'll restore in a Vec
-    let files_to_process: Vec<&TrackedFile> = match &options.path {
-        Some(path) => {
-            // If path is provided, find matching files
-            let file_path = Path::new(path)
-                .canonicalize()
-                .unwrap_or_else(|_| Path::new(path).to_path_buf());
-
-            let matching_files: Vec<&TrackedFile> = repository
-                .files
+    let files_to_process: Vec<&TrackedFile> = match (&options.path, &options.role) {
+        (None, Some(role)) => {
+            let entries = config.role(role)?;
+            let members: Vec<&TrackedFile> = files
                 .iter()
-                .filter(|f| {
-                    Path::new(&f.original_path) == file_path || f.original_path.contains(path)
-                })
+                .filter(|f| entries.iter().any(|entry| refers_to(entry, f)))
                 .collect();
 
-            if matching_files.is_empty() {
-                return Err(KittyError::FileNotTracked(path.to_string()));
+            if members.is_empty() {
+                progress!(options.json, "No tracked files belong to role '{}'", role);
+                return Ok(RestoreReport::default());
+            }
+
+            // A saved plan was reviewed already
+            if !options.force && !options.dry_run && !options.patch && options.plan.is_none() {
+                progress!(
+                    options.json,
+                    "Files of role '{}' that differ from the repository will be overwritten ({} tracked).",
+                    role,
+                    members.len()
+                );
+                if !confirm()? {
+                    progress!(options.json, "{}", tr!("restore-canceled"));
+                    return Ok(RestoreReport::default());
+                }
             }
 
-            matching_files
+            // Pinned files differ per machine on purpose, so only restore them by path
+            let pinned = members.iter().filter(|f| f.ignore_drift).count();
+            if pinned > 0 {
+                progress!(
+                    options.json,
+                    "Skipping {} pinned file(s); restore them by path",
+                    pinned
+                );
+            }
+            let elsewhere = members.iter().filter(|f| !f.applies_here()).count();
+            if elsewhere > 0 {
+                progress!(
+                    options.json,
+                    "Skipping {} file(s) meant for other platforms; restore them by path",
+                    elsewhere
+                );
+            }
+            members
+                .into_iter()
+                .filter(|f| !f.ignore_drift && f.applies_here())
+                .collect()
+        }
+        (Some(path), _) => {
+            let selected: Vec<&TrackedFile> =
+                select_tracked(&files, path, options.all, options.regex)?
+                    .into_iter()
+                    .map(|index| &files[index])
+                    .collect();
+
+            // System files can take a service down, so they are confirmed even by path
+            let system = selected
+                .iter()
+                .filter(|f| f.scope() == Scope::System)
+                .count();
+            if system > 0
+                && config.restore.confirm_system
+                && !options.force
+                && !options.dry_run
+                && !options.patch
+                && options.plan.is_none()
+            {
+                progress!(
+                    options.json,
+                    "{} system file(s) will be overwritten.",
+                    system
+                );
+                if !confirm()? {
+                    progress!(options.json, "{}", tr!("restore-canceled"));
+                    return Ok(RestoreReport::default());
+                }
+            }
+            selected
         }
-        None => {
-            // If no path is provided, prompt user for files to restore
-            if !options.force && !options.dry_run {
-                println!("No specific path provided. This will restore all tracked files.");
-                print!("Continue? [y/N] ");
-                io::stdout().flush()?;
-
-                let mut input = String::new();
-                io::stdin().read_line(&mut input)?;
-
-                if !input.trim().eq_ignore_ascii_case("y") {
-                    println!("Restore operation canceled.");
-                    return Ok(());
+        (None, None) => {
+            // If no path is provided, prompt user for files to restore; recreating
+            // deleted files overwrites nothing, so that needs no confirmation
+            if !options.force
+                && !options.dry_run
+                && !options.patch
+                && !options.missing_only
+                && options.plan.is_none()
+            {
+                if options.skip_unchanged {
+                    progress!(
+                        options.json,
+                        "Tracked files that differ from the repository will be overwritten ({} tracked).",
+                        files.len()
+                    );
+                } else {
+                    progress!(options.json, "{}", tr!("restore-all-warning"));
+                }
+                if !confirm()? {
+                    progress!(options.json, "{}", tr!("restore-canceled"));
+                    return Ok(RestoreReport::default());
                 }
             }
 
+            // Pinned files differ per machine on purpose, so only restore them by path
+            let pinned = files.iter().filter(|f| f.ignore_drift).count();
+            if pinned > 0 {
+                progress!(
+                    options.json,
+                    "Skipping {} pinned file(s); restore them by path",
+                    pinned
+                );
+            }
+
+            // Files for other platforms would be written where they do not belong
+            let elsewhere = files.iter().filter(|f| !f.applies_here()).count();
+            if elsewhere > 0 {
+                progress!(
+                    options.json,
+                    "Skipping {} file(s) meant for other platforms; restore them by path",
+                    elsewhere
+                );
+            }
+
             // Restore all files
-            repository.files.iter().collect()
+            files
+                .iter()
+                .filter(|f| !f.ignore_drift && f.applies_here())
+                .collect()
         }
     };
 
-    println!("Files to restore: {}", files_to_process.len());
+    // Write files after the paths and bundles they depend on
+    let files_to_process = order_for_restore(&files_to_process)?;
+
+    // Package lists run the package manager, so they are only installed when asked for
+    let (package_lists, files_to_process): (Vec<&TrackedFile>, Vec<&TrackedFile>) =
+        files_to_process.into_iter().partition(|f| {
+            matches!(
+                Collector::parse(&f.original_path),
+                Some(Collector::Packages(_))
+            )
+        });
+    let files_to_process = if options.packages {
+        if package_lists.is_empty() {
+            progress!(
+                options.json,
+                "No package lists are tracked; add one with `kitty add packages:<manager>`."
+            );
+            return Ok(RestoreReport::default());
+        }
+        package_lists
+    } else {
+        if !package_lists.is_empty() {
+            progress!(
+                options.json,
+                "Skipping {} package list(s); install them with `kitty restore --packages`",
+                package_lists.len()
+            );
+        }
+        files_to_process
+    };
+
+    // Collectors always have live state, so only plain files can go missing
+    let files_to_process = if options.missing_only {
+        let (missing, present): (Vec<&TrackedFile>, Vec<&TrackedFile>) =
+            files_to_process.into_iter().partition(|f| {
+                Collector::parse(&f.original_path).is_none()
+                    && !Path::new(&f.original_path).exists()
+            });
+        if !present.is_empty() {
+            progress!(
+                options.json,
+                "Skipping {} file(s) that still exist",
+                present.len()
+            );
+        }
+        if missing.is_empty() {
+            progress!(options.json, "No tracked files are missing.");
+            return Ok(RestoreReport::default());
+        }
+        missing
+    } else {
+        files_to_process
+    };
+
+    // A saved plan only goes ahead as long as what it was made from is unchanged
+    let files_to_process = match &options.plan {
+        Some(plan) => plan.select(files_to_process)?,
+        None => files_to_process,
+    };
+
+    progress!(options.json, "Files to restore: {}", files_to_process.len());
 
     // Process each file to restore
-    let mut restored_count = 0;
-    let mut skipped_count = 0;
-    let mut error_count = 0;
-    let files_count = files_to_process.len();
+    let mut report = RestoreReport {
+        processed: files_to_process.len(),
+        ..Default::default()
+    };
+    let mut hook_entries = Vec::new();
+    let mut hook_diffs = BTreeMap::new();
+    let diff_for_hooks = hooks::wants_diffs(&repo_path, "post-restore");
+
+    let mut renderer = if options.render {
+        let catalog = VarCatalog::load(&repo_path, &crypto)?;
+        Some(Renderer::new(
+            catalog.resolve(&profile_name(options.profile.as_deref())),
+        ))
+    } else {
+        None
+    };
+
+    // Fetch all content up front; files missing from it are read again below to report why
+    let paths: Vec<&str> = files_to_process
+        .iter()
+        .map(|f| f.repo_path.as_str())
+        .collect();
+    let mut prefetched = read_stored_files(&repo_path, &paths).unwrap_or_default();
 
     for file in &files_to_process {
         let file_path = Path::new(&file.original_path);
-        println!(
+        progress!(
+            options.json,
             "\nProcessing: {} (storage: {})",
-            file.original_path.bold(),
-            if storage_type == "sqlite" {
-                "SQLite".blue()
+            theme::emphasis(&file.original_path),
+            if is_database(&storage_type) {
+                storage_type.as_str().blue()
             } else {
                 "File".green()
             }
         );
 
         // Read the stored file content based on storage type
-        let encrypted_stored_content = if storage_type == "sqlite" {
-            // Use SQLite storage to get the file content
-            match SqliteStorage::new(&repo_path) {
+        let encrypted_stored_content = if let Some(content) = prefetched.remove(&file.repo_path) {
+            progress!(
+                options.json,
+                "  Retrieved {} bytes from {}",
+                content.len(),
+                storage_type
+            );
+            content
+        } else if is_database(&storage_type) {
+            // Use database storage to get the file content
+            match open_database(&repo_path) {
                 Ok(storage) => match storage.get_file(&file.repo_path) {
                     Ok(content) => {
-                        println!("  Retrieved {} bytes from SQLite database", content.len());
+                        progress!(
+                            options.json,
+                            "  Retrieved {} bytes from database",
+                            content.len()
+                        );
                         content
                     }
                     Err(e) => {
-                        println!(
-                            "  {} Could not read file from SQLite database: {}",
-                            "ERROR:".red().bold(),
-                            e
+                        report.fail(
+                            &file.original_path,
+                            format!("Could not read file from database: {}", e),
+                            options.json,
                         );
-                        error_count += 1;
                         continue;
                     }
                 },
                 Err(e) => {
-                    println!(
-                        "  {} Could not connect to SQLite database: {}",
-                        "ERROR:".red().bold(),
-                        e
+                    report.fail(
+                        &file.original_path,
+                        format!("Could not open database: {}", e),
+                        options.json,
                     );
-                    error_count += 1;
                     continue;
                 }
             }
         } else {
             // Use file-based storage
-            match fs::read(repo_path.join(&file.repo_path)) {
+            match read_stored(&repo_path.join(&file.repo_path)) {
                 Ok(content) => {
-                    println!("  Retrieved {} bytes from file storage", content.len());
+                    progress!(
+                        options.json,
+                        "  Retrieved {} bytes from file storage",
+                        content.len()
+                    );
                     content
                 }
                 Err(e) => {
-                    println!(
-                        "  {} Could not read repository file: {}",
-                        "ERROR:".red().bold(),
-                        e
+                    report.fail(
+                        &file.original_path,
+                        format!("Could not read repository file: {}", e),
+                        options.json,
                     );
-                    error_count += 1;
                     continue;
                 }
             }
@@ -194,105 +904,451 @@ pub fn restore_files(options: Option<RestoreOptions>) -> Result<(), KittyError>
         let decrypted_stored_content = match crypto.decrypt(&encrypted_stored_content) {
             Ok(content) => content,
             Err(e) => {
-                println!("  {} Failed to decrypt file: {}", "ERROR:".red().bold(), e);
-                error_count += 1;
+                report.fail(
+                    &file.original_path,
+                    format!("Failed to decrypt file: {}", e),
+                    options.json,
+                );
                 continue;
             }
         };
+        if let Err(e) = verify_content(&crypto, file, &decrypted_stored_content) {
+            report.fail(&file.original_path, e.to_string(), options.json);
+            continue;
+        }
+
+        // Secrets from Vault are fetched now and never stored in the repository
+        let decrypted_stored_content = match &mut renderer {
+            Some(renderer) => match renderer.render_bytes(decrypted_stored_content) {
+                Ok(content) => content,
+                Err(e) => {
+                    report.fail(
+                        &file.original_path,
+                        format!("Could not render: {}", e),
+                        options.json,
+                    );
+                    continue;
+                }
+            },
+            None => decrypted_stored_content,
+        };
+
+        // Settings from dconf or defaults are written back through their tool instead
+        let collector = Collector::parse(&file.original_path);
 
         // Check if the file exists
-        let file_exists = file_path.exists();
+        let file_exists = collector.is_some() || file_path.exists();
+
+        // Converging only touches files that drifted; package lists check their packages below
+        if options.skip_unchanged
+            && file_exists
+            && !matches!(collector, Some(Collector::Packages(_)))
+            && read_live(&file.original_path)
+                .is_ok_and(|current| current == decrypted_stored_content)
+        {
+            progress!(options.json, "  Unchanged");
+            report.skipped += 1;
+            if let Some(recorded) = file
+                .metadata
+                .as_ref()
+                .filter(|_| options.permissions && collector.is_none() && !options.link)
+            {
+                match put_back_permissions(file_path, recorded, file.frozen, options.dry_run) {
+                    Ok(true) if options.dry_run => {
+                        progress!(
+                            options.json,
+                            "  Would set mode {:o}",
+                            recorded.mode & 0o7777
+                        );
+                        report.permissions += 1;
+                    }
+                    Ok(true) => {
+                        progress!(options.json, "  Put back the recorded mode and owner");
+                        report.permissions += 1;
+                    }
+                    Ok(false) => {}
+                    Err(e) => report.fail(
+                        &file.original_path,
+                        format!("Failed to set permissions: {}", e),
+                        options.json,
+                    ),
+                }
+            }
+            continue;
+        }
 
         // If dry run, just report what would happen
         if options.dry_run {
-            if file_exists {
-                println!("  Would restore file (exists)");
+            let mut planned = PlannedChange {
+                path: file.original_path.clone(),
+                action: PlanAction::Update,
+                stored_hash: file.hash.clone(),
+                hash: digest_like(&file.hash, &decrypted_stored_content),
+                current_hash: None,
+                additions: 0,
+                deletions: 0,
+                read_only: file.frozen && collector.is_none(),
+                validate: Vec::new(),
+                command: None,
+            };
+            if let Some(Collector::Packages(manager)) = &collector {
+                match install_command(manager, &decrypted_stored_content) {
+                    Ok(Some(command)) => {
+                        progress!(options.json, "  Would run: {}", command);
+                        planned.action = PlanAction::Install;
+                        planned.command = Some(command.to_string());
+                    }
+                    Ok(None) => progress!(options.json, "  All packages are installed"),
+                    Err(e) => progress!(options.json, "  {} {}", theme::error("ERROR:"), e),
+                }
+            } else if file_exists {
+                // Show the change restore would make, from what is on disk to the stored copy
+                let current = read_live(&file.original_path).unwrap_or_default();
+                let preview = diff_contents(
+                    &file.original_path,
+                    &String::from_utf8_lossy(&current),
+                    &String::from_utf8_lossy(&decrypted_stored_content),
+                    &DiffOptions::default(),
+                );
+                if preview.has_changes {
+                    progress!(
+                        options.json,
+                        "  Would restore file (exists): {} line(s) added, {} removed",
+                        preview.additions,
+                        preview.deletions
+                    );
+                    for line in preview.diff_text.lines() {
+                        progress!(options.json, "    {}", line);
+                    }
+                    planned.current_hash = Some(digest_like(&file.hash, &current));
+                    planned.additions = preview.additions;
+                    planned.deletions = preview.deletions;
+                } else {
+                    progress!(options.json, "  Would leave file unchanged (identical)");
+                }
             } else {
-                println!("  Would restore file (doesn't exist)");
+                progress!(options.json, "  Would restore file (doesn't exist)");
+                planned.action = PlanAction::Create;
+                planned.additions = String::from_utf8_lossy(&decrypted_stored_content)
+                    .lines()
+                    .count();
+            }
+            if collector.is_none() {
+                let commands = validators(&config.validate, file);
+                if let Err(e) = check(&commands, file, &decrypted_stored_content) {
+                    report.fail(&file.original_path, e.to_string(), options.json);
+                    continue;
+                }
+                planned.validate = commands.iter().map(|c| c.to_string()).collect();
+            }
+            if planned.action != PlanAction::Update || planned.current_hash.is_some() {
+                report.planned.push(planned);
             }
-            skipped_count += 1;
+            report.skipped += 1;
             continue;
         }
 
+        // With --patch, only the changes picked interactively are written
+        let mut quit_after = false;
+        let decrypted_stored_content =
+            if options.patch && !matches!(collector, Some(Collector::Packages(_))) {
+                let current = read_live(&file.original_path).unwrap_or_default();
+                if current == decrypted_stored_content {
+                    progress!(options.json, "  Unchanged");
+                    report.skipped += 1;
+                    continue;
+                }
+
+                let selection = select_changes(&current, &decrypted_stored_content)?;
+                quit_after = selection.quit;
+                match selection.content {
+                    Some(content) if content != current => content,
+                    _ => {
+                        progress!(options.json, "  Keeping the local version");
+                        report.skipped += 1;
+                        if quit_after {
+                            break;
+                        }
+                        continue;
+                    }
+                }
+            } else {
+                decrypted_stored_content
+            };
+
         // Create parent directories if they don't exist
-        if let Some(parent) = file_path.parent() {
+        if let Some(parent) = file_path.parent().filter(|_| collector.is_none()) {
             if !parent.exists() {
-                println!("  Creating parent directory: {}", parent.display());
-                if let Err(e) = fs::create_dir_all(parent) {
-                    println!(
-                        "  {} Failed to create directory: {}",
-                        "ERROR:".red().bold(),
-                        e
+                progress!(
+                    options.json,
+                    "  Creating parent directory: {}",
+                    parent.display()
+                );
+                if let Err(e) = create_parent_directories(
+                    file_path,
+                    file.metadata.as_ref(),
+                    file.scope() == Scope::System,
+                ) {
+                    report.fail(
+                        &file.original_path,
+                        format!("Failed to create directory: {}", e),
+                        options.json,
                     );
-                    error_count += 1;
                     continue;
                 }
             }
         }
 
+        // Rendered secrets may have changed since the plan was made
+        if let Some(change) = options
+            .plan
+            .as_ref()
+            .and_then(|p| p.change(&file.original_path))
+        {
+            if change.action != PlanAction::Install
+                && digest_like(&file.hash, &decrypted_stored_content) != change.hash
+            {
+                report.fail(
+                    &file.original_path,
+                    "Content to write differs from the plan; make a new plan".to_string(),
+                    options.json,
+                );
+                continue;
+            }
+        }
+
+        // A broken config is never put in place of a working one
+        if collector.is_none() {
+            let commands = validators(&config.validate, file);
+            if let Err(e) = check(&commands, file, &decrypted_stored_content) {
+                report.fail(&file.original_path, e.to_string(), options.json);
+                continue;
+            }
+            if !commands.is_empty() {
+                progress!(
+                    options.json,
+                    "  Validated with {} command(s)",
+                    commands.len()
+                );
+            }
+        }
+
         // Create backup if file exists and backup option is enabled
-        if file_exists && options.backup {
+        if file_exists && backup && collector.is_none() {
             let backup_path = format!("{}.bak", file_path.to_string_lossy());
-            println!("  Creating backup at {}", backup_path);
+            progress!(options.json, "  Creating backup at {}", backup_path);
             match fs::copy(file_path, &backup_path) {
-                Ok(_) => {}
-                Err(e) => println!(
+                Ok(_) => report.backups.push(backup_path),
+                Err(e) => progress!(
+                    options.json,
                     "  {} Failed to create backup: {}",
-                    "WARNING:".yellow().bold(),
+                    theme::warning("WARNING:"),
                     e
                 ),
             }
         }
 
-        // Check if we need elevated privileges to write to the file
-        let needs_privileges = if file_exists {
-            let metadata = fs::metadata(&file_path).ok();
-            metadata
-                .map(|m| !m.permissions().readonly())
-                .unwrap_or(false)
+        // Remember what was on disk so the audit log shows what the restore replaced
+        let previous_hash = match &collector {
+            Some(_) => read_live(&file.original_path)
+                .ok()
+                .map(|content| digest_like(&file.hash, &content)),
+            None => digest_file_like(&file.hash, file_path).ok(),
+        };
+        // Only read when the hooks take diffs, as the content ends up in plaintext
+        let previous_content = if !diff_for_hooks {
+            None
+        } else if file_exists {
+            read_live(&file.original_path).ok()
         } else {
-            false
+            Some(Vec::new())
         };
 
-        if needs_privileges {
-            // TODO: Implement privilege escalation
-            println!(
-                "  {} This file may require elevated privileges to modify.",
-                "NOTE:".yellow()
-            );
-            println!("  Consider running the command with sudo.");
+        // A frozen file is made writable for the restore only, and frozen again once written
+        let frozen = file.frozen && collector.is_none() && !options.link;
+        if frozen && file_exists {
+            if let Err(e) = make_writable(file_path, file.metadata.as_ref()) {
+                report.fail(
+                    &file.original_path,
+                    format!("Failed to unfreeze file: {}", e),
+                    options.json,
+                );
+                continue;
+            }
         }
 
         // Write the file content
-        match fs::write(file_path, &decrypted_stored_content) {
+        let written = match &collector {
+            Some(collector) => collector.apply(&decrypted_stored_content),
+            None if options.link => link_file(&repo_path, file_path, &decrypted_stored_content),
+            None => write_file(file_path, &decrypted_stored_content, file.scope()),
+        };
+        // Frozen again whether or not the write went through
+        if frozen && file_path.exists() {
+            if let Err(e) = make_read_only(file_path) {
+                progress!(
+                    options.json,
+                    "  {} Failed to freeze file again: {}",
+                    theme::warning("WARNING:"),
+                    e
+                );
+            }
+        }
+        match written {
             Ok(_) => {
-                println!(
+                progress!(
+                    options.json,
                     "  {} File restored successfully ({} bytes)",
-                    "SUCCESS:".green().bold(),
+                    theme::success("SUCCESS:"),
                     decrypted_stored_content.len()
                 );
-                restored_count += 1;
+                report.restored += 1;
+                if !file_exists {
+                    report.created += 1;
+                }
+
+                // A new system file without recorded metadata belongs to root, like its neighbours
+                let recorded = file.metadata.or_else(|| {
+                    (file.scope() == Scope::System && !file_exists).then_some(ROOT_DEFAULTS)
+                });
+                if let Some(recorded) = recorded
+                    .as_ref()
+                    .filter(|_| options.permissions && collector.is_none() && !options.link)
+                {
+                    match put_back_permissions(file_path, recorded, frozen, false) {
+                        Ok(true) => report.permissions += 1,
+                        Ok(false) => {}
+                        // The defaults are a courtesy; a file sudo wrote is root's already
+                        Err(e)
+                            if file.metadata.is_none()
+                                && e.kind() == io::ErrorKind::PermissionDenied => {}
+                        Err(e) => progress!(
+                            options.json,
+                            "  {} Failed to set permissions: {}",
+                            theme::warning("WARNING:"),
+                            e
+                        ),
+                    }
+                }
+
+                // Tools like make compare mtimes, so put back the one the file had when added
+                if options.preserve_times && collector.is_none() {
+                    let modified = file
+                        .metadata
+                        .and_then(|m| m.modified)
+                        .unwrap_or(file.last_updated);
+                    if let Err(e) = set_modified(file_path, modified) {
+                        progress!(
+                            options.json,
+                            "  {} Failed to set modification time: {}",
+                            theme::warning("WARNING:"),
+                            e
+                        );
+                    }
+                }
+
+                let entry = AuditEntry::new(
+                    "restore",
+                    &file.original_path,
+                    previous_hash,
+                    Some(digest_like(&file.hash, &decrypted_stored_content)),
+                );
+                if let Err(e) = audit::record(&repo_path, &crypto, &entry) {
+                    progress!(
+                        options.json,
+                        "  {} Failed to write audit log: {}",
+                        theme::warning("WARNING:"),
+                        e
+                    );
+                }
+                if let Some(diff) = previous_content.and_then(|previous| {
+                    hooks::unified_diff(&file.original_path, &previous, &decrypted_stored_content)
+                }) {
+                    hook_diffs.insert(file.original_path.clone(), diff);
+                }
+                hook_entries.push(entry);
             }
             Err(e) => {
-                println!("  {} Failed to write file: {}", "ERROR:".red().bold(), e);
-                error_count += 1;
+                report.fail(
+                    &file.original_path,
+                    format!("Failed to write file: {}", e),
+                    options.json,
+                );
             }
         }
+
+        if quit_after {
+            break;
+        }
     }
 
-    // Print summary
-    println!("\nRestore Summary");
-    println!("==============");
-    println!("Files processed: {}", files_count);
-    println!("Restored: {} file(s)", restored_count);
-    println!("Skipped: {} file(s)", skipped_count);
-    println!("Errors: {} file(s)", error_count);
+    // Only whole trees are put back as they were added, never a file picked from one
+    if !options.patch && !options.missing_only && options.plan.is_none() {
+        let whole = |root: &str| match &options.path {
+            Some(path) => Path::new(path)
+                .canonicalize()
+                .is_ok_and(|p| p == Path::new(root)),
+            None => true,
+        };
+        for tree in trees(&files).into_values().filter(|tree| {
+            whole(tree.root)
+                && files_to_process
+                    .iter()
+                    .any(|f| f.tree.as_deref() == Some(tree.root))
+        }) {
+            remove_strays(&repo_path, &crypto, &tree, &options, &mut report)?;
+        }
+    }
 
-    if storage_type == "sqlite" {
-        println!("\nStorage: SQLite database");
-    } else {
-        println!("\nStorage: File-based");
+    if !options.dry_run {
+        let _ = index::refresh(&repo_path, &files_to_process);
     }
+    let applied: Vec<Event> = hook_entries
+        .iter()
+        .map(|entry| {
+            Event::new(
+                "apply",
+                Severity::Notice,
+                format!("Restored {}", entry.path),
+            )
+            .field("path", &entry.path)
+            .field("old_hash", entry.old_hash.as_deref().unwrap_or(""))
+            .field("new_hash", entry.new_hash.as_deref().unwrap_or(""))
+        })
+        .collect();
+    events::emit(&repo_path, &applied);
+    report.hooks = hooks::run_with_diffs(&repo_path, "post-restore", &hook_entries, &hook_diffs);
 
-    Ok(())
+    // With --json the caller prints the report instead
+    if !options.json && options.skip_unchanged && !options.dry_run {
+        print_convergence(&report);
+    } else if !options.json {
+        let title = tr!("restore-summary-title");
+        println!("\n{}", title);
+        println!("{}", "=".repeat(title.chars().count()));
+        println!(
+            "{}",
+            tr!("restore-summary-processed", count = report.processed)
+        );
+        println!(
+            "{}",
+            tr!("restore-summary-restored", count = report.restored)
+        );
+        println!("{}", tr!("restore-summary-skipped", count = report.skipped));
+        println!(
+            "{}",
+            tr!("restore-summary-errors", count = report.errors.len())
+        );
+        if !report.removed.is_empty() {
+            println!("Deleted from trees: {}", report.removed.len());
+        }
+
+        if is_database(&storage_type) {
+            println!("\nStorage: {} database", storage_type);
+        } else {
+            println!("\nStorage: File-based");
+        }
+    }
+
+    Ok(report)
 }
@@ -0,0 +1,298 @@
+use crate::{
+    commands::init::KittyError,
+    repository::keys::{unlock, verify_password},
+    tr,
+    utils::{file::get_repository_path, shred::private_temp_dir},
+};
+use chrono::{DateTime, Duration, Local, Utc};
+use rand::{rngs::OsRng, Rng};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    fs,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::Shutdown,
+    os::unix::net::{UnixListener, UnixStream},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    thread,
+};
+
+/// Environment variable carrying the session's socket and token, as `<socket>#<token>`
+pub const SESSION_ENV: &str = "KITTY_SESSION";
+
+/// What `kitty session start` hands the agent on its stdin
+#[derive(Serialize, Deserialize)]
+struct Handoff {
+    token: String,
+    repository: PathBuf,
+    password: String,
+    expires_at: DateTime<Utc>,
+}
+
+/// A request to the agent: one line of JSON, answered by one line of JSON
+#[derive(Serialize, Deserialize)]
+struct SessionRequest {
+    /// `password`, `status` or `stop`
+    command: String,
+    token: String,
+    repository: PathBuf,
+}
+
+/// Options for starting a session
+pub struct SessionOptions {
+    /// Minutes until the session ends by itself
+    pub ttl: u64,
+}
+
+impl Default for SessionOptions {
+    fn default() -> Self {
+        Self { ttl: 15 }
+    }
+}
+
+/// Unlock the repository once and print the shell line that makes later commands use it
+///
+/// The password stays with a small agent process for the length of the
+/// session. Everything is printed to stderr except the `export` line, so the
+/// command is used as `eval "$(kitty session start)"`.
+pub fn start_session(options: Option<SessionOptions>) -> Result<(), KittyError> {
+    let options = options.unwrap_or_default();
+    let repo_path = repository_path()?;
+
+    if options.ttl == 0 {
+        return Err(KittyError::Session(
+            "--ttl must be at least one minute".to_string(),
+        ));
+    }
+
+    eprint!("{} ", tr!("password-prompt"));
+    io::stderr().flush()?;
+    let password = rpassword::read_password()?;
+    eprintln!(); // Add a newline after password input
+
+    // A session with the wrong password would only fail in the commands it was meant for
+    let crypto = unlock(&repo_path, &password)?;
+    verify_password(&repo_path, &crypto)?;
+
+    let mut bytes = [0u8; 32];
+    OsRng.fill(&mut bytes);
+    let handoff = Handoff {
+        token: hex::encode(bytes),
+        repository: repo_path.clone(),
+        password,
+        expires_at: Utc::now() + Duration::minutes(options.ttl as i64),
+    };
+
+    let socket_path = private_temp_dir("kitty-session")?.join("agent.sock");
+    let mut agent = {
+        use std::os::unix::process::CommandExt;
+        Command::new(std::env::current_exe()?)
+            .args(["session", "agent", "--socket"])
+            .arg(&socket_path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            // Keep Ctrl-C in the shell from ending the session
+            .process_group(0)
+            .spawn()?
+    };
+    if let Some(mut stdin) = agent.stdin.take() {
+        writeln!(stdin, "{}", serde_json::to_string(&handoff)?)?;
+    }
+
+    // The agent is ready once its socket answers
+    let ready = (0..50).any(|_| {
+        thread::sleep(std::time::Duration::from_millis(100));
+        UnixStream::connect(&socket_path).is_ok()
+    });
+    if !ready {
+        let _ = agent.kill();
+        let _ = fs::remove_dir_all(socket_path.parent().unwrap_or(&socket_path));
+        return Err(KittyError::Session(
+            "the session agent did not start".to_string(),
+        ));
+    }
+
+    let value = format!("{}#{}", socket_path.display(), handoff.token);
+    println!("export {}='{}';", SESSION_ENV, value.replace('\'', r"'\''"));
+    eprintln!(
+        "Unlocked {} until {}; run `eval \"$(kitty session stop)\"` to lock it again",
+        repo_path.display(),
+        handoff.expires_at.with_timezone(&Local).format("%H:%M:%S")
+    );
+    Ok(())
+}
+
+/// End the shell's session and print the shell line that forgets it
+pub fn stop_session() -> Result<(), KittyError> {
+    let repo_path = repository_path()?;
+    let result = ask_agent("stop", &repo_path);
+
+    // Forget the session even when the agent has already gone
+    println!("unset {};", SESSION_ENV);
+    result?;
+    eprintln!("Locked {}", repo_path.display());
+    Ok(())
+}
+
+/// Show whether this shell has an unlocked session, and for how long
+pub fn session_status() -> Result<(), KittyError> {
+    let repo_path = repository_path()?;
+    let answer = ask_agent("status", &repo_path)?;
+
+    let expires_at = answer["expires_at"]
+        .as_str()
+        .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+        .map(|t| t.with_timezone(&Utc))
+        .unwrap_or_else(Utc::now);
+    let minutes = (expires_at - Utc::now()).num_minutes();
+    println!(
+        "Unlocked {} until {} ({} minute(s) left)",
+        repo_path.display(),
+        expires_at.with_timezone(&Local).format("%H:%M:%S"),
+        minutes.max(0)
+    );
+    Ok(())
+}
+
+/// The password of this shell's session, when one is unlocked for the repository
+///
+/// Any problem reaching the agent means asking for the password as usual, so
+/// an expired session never stops a command.
+pub fn session_password(repo_path: &Path) -> Option<String> {
+    std::env::var(SESSION_ENV).ok().filter(|v| !v.is_empty())?;
+    match ask_agent("password", repo_path) {
+        Ok(answer) => answer["password"].as_str().map(str::to_string),
+        Err(KittyError::Session(reason)) => {
+            eprintln!("Note: {}; asking for the password instead", reason);
+            None
+        }
+        Err(_) => None,
+    }
+}
+
+/// Serve the password to the shell's commands until the session ends
+///
+/// Started by `kitty session start`, which passes the password and token on stdin.
+pub fn run_agent(socket_path: &Path) -> Result<(), KittyError> {
+    let mut line = String::new();
+    io::stdin().read_line(&mut line)?;
+    let handoff: Handoff = serde_json::from_str(line.trim())?;
+
+    let listener = UnixListener::bind(socket_path)?;
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(socket_path, fs::Permissions::from_mode(0o600))?;
+    }
+    listener.set_nonblocking(true)?;
+
+    while Utc::now() < handoff.expires_at {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                if handle_request(stream, &handoff) {
+                    break;
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                thread::sleep(std::time::Duration::from_millis(100));
+            }
+            Err(_) => break,
+        }
+    }
+
+    drop(listener);
+    let _ = fs::remove_dir_all(socket_path.parent().unwrap_or(socket_path));
+    Ok(())
+}
+
+/// Answer one request; true when the session should end
+fn handle_request(stream: UnixStream, handoff: &Handoff) -> bool {
+    // A client that never finishes its request must not stall the agent
+    let _ = stream.set_nonblocking(false);
+    let _ = stream.set_read_timeout(Some(std::time::Duration::from_secs(1)));
+
+    let mut line = String::new();
+    let request = BufReader::new(&stream)
+        .read_line(&mut line)
+        .ok()
+        .and_then(|_| serde_json::from_str::<SessionRequest>(line.trim()).ok());
+
+    let (answer, stop) = match request {
+        Some(request) if request.token != handoff.token => (
+            json!({ "ok": false, "error": "the session token does not match" }),
+            false,
+        ),
+        Some(request) if request.repository != handoff.repository => (
+            json!({
+                "ok": false,
+                "error": format!("the kitty session is for {}", handoff.repository.display())
+            }),
+            false,
+        ),
+        Some(request) => {
+            let mut answer = json!({
+                "ok": true,
+                "expires_at": handoff.expires_at.to_rfc3339(),
+            });
+            match request.command.as_str() {
+                "password" => answer["password"] = json!(handoff.password),
+                "status" | "stop" => {}
+                other => {
+                    answer = json!({ "ok": false, "error": format!("unknown command '{}'", other) })
+                }
+            }
+            (answer, request.command == "stop")
+        }
+        None => (json!({ "ok": false, "error": "malformed request" }), false),
+    };
+    let _ = writeln!(&stream, "{}", answer);
+    stop
+}
+
+/// Send a command to the agent named in `KITTY_SESSION`
+fn ask_agent(command: &str, repo_path: &Path) -> Result<Value, KittyError> {
+    let session = std::env::var(SESSION_ENV)
+        .ok()
+        .filter(|v| !v.is_empty())
+        .ok_or_else(|| {
+            KittyError::Session(
+                "no session in this shell; run `eval \"$(kitty session start)\"`".to_string(),
+            )
+        })?;
+    let (socket_path, token) = session
+        .rsplit_once('#')
+        .ok_or_else(|| KittyError::Session(format!("{} is malformed", SESSION_ENV)))?;
+
+    let mut stream = UnixStream::connect(socket_path)
+        .map_err(|_| KittyError::Session("the kitty session has ended".to_string()))?;
+    let request = SessionRequest {
+        command: command.to_string(),
+        token: token.to_string(),
+        repository: canonical(repo_path),
+    };
+    writeln!(stream, "{}", serde_json::to_string(&request)?)?;
+    stream.shutdown(Shutdown::Write)?;
+
+    let mut answer = String::new();
+    stream.read_to_string(&mut answer)?;
+    let answer: Value = serde_json::from_str(answer.trim())?;
+    if answer["ok"] != Value::Bool(true) {
+        let error = answer["error"].as_str().unwrap_or("no reason given");
+        return Err(KittyError::Session(error.to_string()));
+    }
+    Ok(answer)
+}
+
+/// The repository path, the same way however it was spelled
+fn repository_path() -> Result<PathBuf, KittyError> {
+    let repo_path = get_repository_path()?;
+    if !repo_path.exists() {
+        return Err(KittyError::RepositoryNotFound);
+    }
+    Ok(canonical(&repo_path))
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
@@ -0,0 +1,297 @@
+use crate::{
+    collectors::Collector,
+    commands::init::{KittyError, TrackedFile},
+    repository::keys::unlock,
+    storage::{is_database, open_database},
+    utils::{
+        age,
+        audit::{self, AuditEntry},
+        file::{
+            format_size, get_repository_path, get_storage_type, load_repository, read_stored,
+            read_stored_files, write_private,
+        },
+        password::read_repository_password,
+        select::select_tracked,
+        theme,
+    },
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{DateTime, Local, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeSet,
+    fs,
+    os::unix::fs::PermissionsExt,
+    path::{Component, Path, PathBuf},
+};
+
+/// Version of the JSON document inside a share bundle
+const FORMAT_VERSION: u32 = 1;
+
+/// A few tracked files handed to someone else, encrypted with age
+///
+/// The bundle is a plain age file, so `age --decrypt` opens it without kitty.
+#[derive(Serialize, Deserialize)]
+pub struct ShareBundle {
+    pub format: u32,
+    pub shared_at: DateTime<Utc>,
+    pub files: Vec<SharedFile>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SharedFile {
+    /// Where the file goes, relative to the directory it is received in
+    pub name: String,
+
+    /// Where the file is tracked on the machine it was shared from
+    pub original_path: String,
+
+    /// Permission bits, when they were recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<u32>,
+
+    /// Content in base64
+    pub content: String,
+}
+
+/// Options for the share command
+#[derive(Default)]
+pub struct ShareOptions {
+    /// age recipients who can open the bundle
+    pub recipients: Vec<String>,
+
+    /// Overwrite the output file if it exists
+    pub force: bool,
+}
+
+/// Options for the receive command
+pub struct ReceiveOptions {
+    /// age identity file to decrypt the bundle with
+    pub identity: String,
+
+    /// Directory to write the files to
+    pub output_dir: String,
+
+    /// Only list the files in the bundle
+    pub list: bool,
+
+    /// Overwrite files that already exist
+    pub force: bool,
+}
+
+impl Default for ReceiveOptions {
+    fn default() -> Self {
+        Self {
+            identity: String::new(),
+            output_dir: ".".to_string(),
+            list: false,
+            force: false,
+        }
+    }
+}
+
+/// The deepest directory holding every path
+fn common_parent(paths: &[&Path]) -> PathBuf {
+    let mut parent = paths
+        .first()
+        .and_then(|path| path.parent())
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+    while !paths.iter().all(|path| path.starts_with(&parent)) && parent.pop() {}
+    parent
+}
+
+/// Write tracked files to a bundle only the given age recipients can open
+///
+/// Files are named relative to the deepest directory they share, so a single
+/// file keeps just its name. The read-only password is enough to share.
+pub fn share_files(
+    paths: &[String],
+    output: &str,
+    options: Option<ShareOptions>,
+) -> Result<(), KittyError> {
+    let options = options.unwrap_or_default();
+    let repo_path = get_repository_path()?;
+
+    if !repo_path.exists() {
+        return Err(KittyError::RepositoryNotFound);
+    }
+
+    if options.recipients.is_empty() {
+        return Err(KittyError::Share(
+            "pass at least one --recipient to encrypt the bundle for".to_string(),
+        ));
+    }
+
+    if Path::new(output).exists() && !options.force {
+        return Err(KittyError::OutputExists {
+            paths: vec![output.to_string()],
+        });
+    }
+
+    // Get password from user
+    let password = read_repository_password(false)?;
+
+    let storage_type = get_storage_type(&repo_path)?;
+    let crypto = unlock(&repo_path, &password)?;
+    let repository = load_repository(&repo_path, &crypto)?;
+
+    let mut indices = BTreeSet::new();
+    for path in paths {
+        indices.extend(select_tracked(&repository.files, path, false, false)?);
+    }
+    let selected: Vec<&TrackedFile> = indices.iter().map(|&i| &repository.files[i]).collect();
+    if let Some(file) = selected
+        .iter()
+        .find(|f| Collector::parse(&f.original_path).is_some())
+    {
+        return Err(KittyError::Share(format!(
+            "{} is not a file and cannot be shared",
+            file.original_path
+        )));
+    }
+
+    let originals: Vec<&Path> = selected
+        .iter()
+        .map(|f| Path::new(f.original_path.as_str()))
+        .collect();
+    let parent = common_parent(&originals);
+
+    let repo_paths: Vec<&str> = selected.iter().map(|f| f.repo_path.as_str()).collect();
+    let mut stored = read_stored_files(&repo_path, &repo_paths)?;
+
+    let mut files = Vec::new();
+    for file in &selected {
+        let encrypted = match stored.remove(&file.repo_path) {
+            Some(encrypted) => encrypted,
+            None if is_database(&storage_type) => {
+                open_database(&repo_path)?.get_file(&file.repo_path)?
+            }
+            None => read_stored(&repo_path.join(&file.repo_path))?,
+        };
+        let name = Path::new(&file.original_path)
+            .strip_prefix(&parent)
+            .unwrap_or(Path::new(&file.original_path));
+        files.push(SharedFile {
+            name: name.to_string_lossy().to_string(),
+            original_path: file.original_path.clone(),
+            mode: file.metadata.as_ref().map(|m| m.mode),
+            content: STANDARD.encode(crypto.decrypt(&encrypted)?),
+        });
+    }
+
+    let bundle = ShareBundle {
+        format: FORMAT_VERSION,
+        shared_at: Utc::now(),
+        files,
+    };
+    let encrypted = age::encrypt(&serde_json::to_vec(&bundle)?, &options.recipients)?;
+    write_private(Path::new(output), encrypted)?;
+
+    for file in &selected {
+        audit::record(
+            &repo_path,
+            &crypto,
+            &AuditEntry::new("share", &file.original_path, Some(file.hash.clone()), None),
+        )?;
+    }
+
+    println!(
+        "{} Shared {} file(s) with {} recipient(s) in {}",
+        theme::success("SUCCESS:"),
+        bundle.files.len(),
+        options.recipients.len(),
+        output
+    );
+    Ok(())
+}
+
+/// Where a bundle's file goes, refusing names that would leave the output directory
+fn target_path(dir: &Path, name: &str) -> Result<PathBuf, KittyError> {
+    let relative = Path::new(name);
+    let safe = !name.is_empty()
+        && relative
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)));
+    if !safe {
+        return Err(KittyError::Share(format!(
+            "the bundle names a file outside the output directory: {}",
+            name
+        )));
+    }
+    Ok(dir.join(relative))
+}
+
+/// Decrypt a bundle from `kitty share` and write its files; needs no repository
+pub fn receive_bundle(bundle: &str, options: Option<ReceiveOptions>) -> Result<(), KittyError> {
+    let options = options.unwrap_or_default();
+
+    let plaintext = age::decrypt(Path::new(bundle), &options.identity)?;
+    let bundle: ShareBundle = serde_json::from_slice(&plaintext)
+        .map_err(|e| KittyError::Share(format!("not a kitty share bundle: {}", e)))?;
+    if bundle.format > FORMAT_VERSION {
+        return Err(KittyError::Share(format!(
+            "the bundle has format {}, this kitty reads up to {}; upgrade kitty",
+            bundle.format, FORMAT_VERSION
+        )));
+    }
+
+    let dir = Path::new(&options.output_dir);
+    let mut targets = Vec::new();
+    for file in &bundle.files {
+        let content = STANDARD
+            .decode(&file.content)
+            .map_err(|e| KittyError::Share(format!("{}: {}", file.name, e)))?;
+        targets.push((file, target_path(dir, &file.name)?, content));
+    }
+
+    if options.list {
+        println!(
+            "Bundle shared {}:",
+            bundle
+                .shared_at
+                .with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M")
+        );
+        for (file, _, content) in &targets {
+            println!(
+                "  {:<40} {:>10}  {}",
+                file.name,
+                format_size(content.len() as u64),
+                file.original_path
+            );
+        }
+        return Ok(());
+    }
+
+    // Check everything first, so a refused bundle leaves nothing half written
+    if !options.force {
+        let existing: Vec<String> = targets
+            .iter()
+            .filter(|(_, target, _)| target.exists())
+            .map(|(_, target, _)| target.display().to_string())
+            .collect();
+        if !existing.is_empty() {
+            return Err(KittyError::OutputExists { paths: existing });
+        }
+    }
+
+    for (file, target, content) in &targets {
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        write_private(target, content)?;
+        if let Some(mode) = file.mode {
+            fs::set_permissions(target, fs::Permissions::from_mode(mode))?;
+        }
+        println!("Received {}", target.display());
+    }
+
+    println!(
+        "{} Received {} file(s) in {}",
+        theme::success("SUCCESS:"),
+        targets.len(),
+        dir.display()
+    );
+    Ok(())
+}
@@ -0,0 +1,80 @@
+use crate::{
+    collectors::sops,
+    commands::init::KittyError,
+    repository::keys::unlock_admin,
+    storage::{is_database, open_database},
+    utils::{
+        file::{get_repository_path, get_storage_type, load_repository, read_stored},
+        password::read_repository_password,
+        select::select_tracked,
+        theme,
+    },
+};
+use std::{fs, path::Path};
+
+/// Options for `kitty export-sops`
+#[derive(Default)]
+pub struct ExportSopsOptions {
+    /// age recipients to encrypt for, instead of the `.sops.yaml` creation rules
+    pub age: Vec<String>,
+
+    /// Overwrite the output file if it exists
+    pub force: bool,
+}
+
+/// Write a tracked file's stored content as a SOPS-encrypted file
+///
+/// The format follows the output's extension: YAML, JSON, dotenv, INI, or
+/// binary for anything else.
+pub fn export_sops(
+    path: &str,
+    output: &str,
+    options: Option<ExportSopsOptions>,
+) -> Result<(), KittyError> {
+    let options = options.unwrap_or_default();
+    let repo_path = get_repository_path()?;
+
+    if !repo_path.exists() {
+        return Err(KittyError::RepositoryNotFound);
+    }
+
+    if Path::new(output).exists() && !options.force {
+        return Err(KittyError::OutputExists {
+            paths: vec![output.to_string()],
+        });
+    }
+
+    // Get password from user
+    let password = read_repository_password(false)?;
+
+    let crypto = unlock_admin(&repo_path, &password)?;
+    let repository = load_repository(&repo_path, &crypto)?;
+
+    let [index] = select_tracked(&repository.files, path, false, false)?[..] else {
+        return Err(KittyError::AmbiguousPath(format!(
+            "'{}' matches several tracked files; export one at a time",
+            path
+        )));
+    };
+    let file = &repository.files[index];
+
+    let encrypted = if is_database(&get_storage_type(&repo_path)?) {
+        open_database(&repo_path)?.get_file(&file.repo_path)?
+    } else {
+        read_stored(&repo_path.join(&file.repo_path))?
+    };
+    let content = crypto.decrypt(&encrypted)?;
+
+    // sops matches creation rules against the absolute path
+    let output_path = std::path::absolute(output)?;
+    let exported = sops::encrypt(&output_path.to_string_lossy(), &content, &options.age)?;
+    fs::write(&output_path, exported)?;
+
+    println!(
+        "{} Exported {} to {}",
+        theme::success("SUCCESS:"),
+        file.original_path,
+        output_path.display()
+    );
+    Ok(())
+}
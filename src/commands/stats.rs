@@ -0,0 +1,142 @@
+use crate::{
+    commands::init::KittyError,
+    repository::keys::unlock,
+    utils::{
+        file::{format_size, get_repository_path, load_repository},
+        password::read_repository_password,
+        stats::{measure, read_samples, StatsSample},
+        table::terminal_width,
+        theme,
+    },
+};
+use chrono::Local;
+
+/// Options for the stats command
+#[derive(Default)]
+pub struct StatsOptions {
+    /// Show how the repository grew, one row per day
+    pub history: bool,
+}
+
+/// Width of the label column
+const LABEL_WIDTH: usize = 16;
+
+/// Width of the size columns in the history table
+const SIZE_WIDTH: usize = 12;
+
+/// Bars of a sparkline, lowest first
+const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// One bar per value, scaled between the smallest and largest
+fn sparkline(values: &[u64]) -> String {
+    let (Some(&min), Some(&max)) = (values.iter().min(), values.iter().max()) else {
+        return String::new();
+    };
+    values
+        .iter()
+        .map(|&value| match max - min {
+            0 => BARS[BARS.len() / 2],
+            range => BARS[((value - min) * (BARS.len() as u64 - 1) / range) as usize],
+        })
+        .collect()
+}
+
+/// The last sample of each day, oldest first
+fn daily(samples: Vec<StatsSample>) -> Vec<StatsSample> {
+    let mut days: Vec<StatsSample> = Vec::new();
+    for sample in samples {
+        let day = sample.taken_at.with_timezone(&Local).date_naive();
+        match days.last_mut() {
+            Some(last) if last.taken_at.with_timezone(&Local).date_naive() == day => *last = sample,
+            _ => days.push(sample),
+        }
+    }
+    days
+}
+
+/// Print one sparkline row over the latest `width` values, with the first and last of them
+fn print_trend(label: &str, values: &[u64], width: usize, format: impl Fn(u64) -> String) {
+    let shown = &values[values.len().saturating_sub(width)..];
+    println!(
+        "{:<LABEL_WIDTH$}{}  {} → {}",
+        label,
+        theme::hunk(&sparkline(shown)),
+        format(shown[0]),
+        format(shown[shown.len() - 1])
+    );
+}
+
+/// Show how many files the repository holds and how much space they take
+///
+/// With `history`, the samples recorded after every change are shown as
+/// sparklines, followed by a table with one row per day.
+pub fn show_stats(options: Option<StatsOptions>) -> Result<(), KittyError> {
+    let options = options.unwrap_or_default();
+    let repo_path = get_repository_path()?;
+
+    if !repo_path.exists() {
+        return Err(KittyError::RepositoryNotFound);
+    }
+
+    // Get password from user
+    let password = read_repository_password(false)?;
+
+    let crypto = unlock(&repo_path, &password)?;
+    let repository = load_repository(&repo_path, &crypto)?;
+
+    if !options.history {
+        let current = measure(&repo_path, &repository)?;
+        println!("{:<LABEL_WIDTH$}{}", "Files", current.files);
+        println!("{:<LABEL_WIDTH$}{}", "Versions", current.versions);
+        println!(
+            "{:<LABEL_WIDTH$}{}",
+            "Plaintext size",
+            format_size(current.plaintext_bytes)
+        );
+        println!(
+            "{:<LABEL_WIDTH$}{}",
+            "Stored size",
+            format_size(current.stored_bytes)
+        );
+        return Ok(());
+    }
+
+    let samples = read_samples(&repo_path, &crypto)?;
+    if samples.is_empty() {
+        println!("No history yet; a sample is recorded after every change to the repository");
+        return Ok(());
+    }
+
+    // Room for the label and the first and last value
+    let width = terminal_width()
+        .unwrap_or(80)
+        .saturating_sub(LABEL_WIDTH + 30)
+        .max(10);
+    let files: Vec<u64> = samples.iter().map(|s| s.files as u64).collect();
+    let plaintext: Vec<u64> = samples.iter().map(|s| s.plaintext_bytes).collect();
+    let stored: Vec<u64> = samples.iter().map(|s| s.stored_bytes).collect();
+    print_trend("Files", &files, width, |n| n.to_string());
+    print_trend("Plaintext size", &plaintext, width, format_size);
+    print_trend("Stored size", &stored, width, format_size);
+
+    println!(
+        "\n{:<10} {:>8} {:>8} {:>SIZE_WIDTH$} {:>SIZE_WIDTH$}",
+        "Date", "Files", "Versions", "Plaintext", "Stored"
+    );
+    println!(
+        "{:<10} {:>8} {:>8} {:>SIZE_WIDTH$} {:>SIZE_WIDTH$}",
+        "----", "-----", "--------", "---------", "------"
+    );
+    for day in &daily(samples) {
+        println!(
+            "{:<10} {:>8} {:>8} {:>SIZE_WIDTH$} {:>SIZE_WIDTH$}",
+            day.taken_at.with_timezone(&Local).format("%Y-%m-%d"),
+            day.files,
+            day.versions,
+            format_size(day.plaintext_bytes),
+            format_size(day.stored_bytes)
+        );
+    }
+
+    Ok(())
+}
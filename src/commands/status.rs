@@ -0,0 +1,234 @@
+use crate::{
+    commands::{
+        check::{compute_drift, DriftState, FileDrift},
+        freeze::is_thawed,
+        init::KittyError,
+    },
+    repository::{config::RepoConfig, keys::unlock},
+    tr,
+    utils::{
+        file::{get_repository_path, load_repository},
+        index,
+        moves::detect_moves,
+        password::read_repository_password,
+        policy,
+        select::short_ids,
+        theme,
+        tree::trees,
+    },
+};
+use chrono::{Duration, Local, Utc};
+use colored::Colorize;
+use std::{collections::BTreeMap, path::Path};
+
+/// Entries expiring within this many days are pointed out
+const EXPIRY_WARNING_DAYS: i64 = 7;
+
+/// Show which tracked files differ from the repository
+pub fn show_status() -> Result<(), KittyError> {
+    let repo_path = get_repository_path()?;
+
+    if !repo_path.exists() {
+        return Err(KittyError::RepositoryNotFound);
+    }
+
+    // Get password from user
+    let password = read_repository_password(false)?;
+
+    let crypto = unlock(&repo_path, &password)?;
+    let repository = load_repository(&repo_path, &crypto)?;
+
+    if repository.files.is_empty() {
+        println!("{}", tr!("no-files-tracked"));
+        return Ok(());
+    }
+
+    let mut drifted = 0;
+    let mut missing = 0;
+    let mut pinned = 0;
+    let ids = short_ids(&repository.files);
+
+    // Files meant for other platforms would only ever show up as missing
+    let (files, ids): (Vec<_>, Vec<_>) = repository
+        .files
+        .iter()
+        .zip(ids)
+        .filter(|(file, _)| file.applies_here())
+        .unzip();
+    let elsewhere = repository.files.len() - files.len();
+    let drift: Vec<FileDrift> = files.iter().copied().map(compute_drift).collect();
+    let _ = index::replace(&repo_path, &drift);
+
+    // A missing file may only have been moved or renamed; looking is best effort
+    let missing_files: Vec<_> = files
+        .iter()
+        .zip(&drift)
+        .filter(|(_, d)| d.state == DriftState::Missing)
+        .map(|(&file, _)| file)
+        .collect();
+    let moved = if missing_files.is_empty() {
+        Default::default()
+    } else {
+        detect_moves(&repo_path, &repository.files, &missing_files).unwrap_or_default()
+    };
+
+    // Files in a tracked directory are summed up per tree instead of listed one by one
+    let mut tree_changes: BTreeMap<&str, usize> = BTreeMap::new();
+    // New or deleted files in a tree are only recorded by adding it again
+    let mut tree_outdated = false;
+
+    for ((file, id), tracked) in drift.iter().zip(&ids).zip(&files) {
+        let code = file.state.code();
+        if let Some(root) = tracked.tree.as_deref() {
+            let changes = tree_changes.entry(root).or_default();
+            match file.state {
+                DriftState::Modified | DriftState::Unreadable => drifted += 1,
+                DriftState::Missing => {
+                    missing += 1;
+                    tree_outdated = true;
+                }
+                DriftState::Clean | DriftState::Pinned => {}
+            }
+            if matches!(
+                file.state,
+                DriftState::Modified | DriftState::Unreadable | DriftState::Missing
+            ) {
+                *changes += 1;
+                continue;
+            }
+        }
+        match file.state {
+            DriftState::Clean => continue,
+            DriftState::Pinned => {
+                pinned += 1;
+                println!(
+                    "{} {:<7} {}",
+                    code.dimmed(),
+                    id.dimmed(),
+                    file.path.dimmed()
+                );
+            }
+            DriftState::Modified => {
+                drifted += 1;
+                println!("{} {:<7} {}", theme::warning(code), id, file.path);
+            }
+            DriftState::Missing => {
+                missing += 1;
+                match moved.get(&file.path) {
+                    Some(to) => println!(
+                        "{} {:<7} {} {}",
+                        theme::error(code),
+                        id,
+                        file.path,
+                        tr!("status-moved", path = to.display().to_string()).dimmed()
+                    ),
+                    None => println!("{} {:<7} {}", theme::error(code), id, file.path),
+                }
+            }
+            DriftState::Unreadable => {
+                drifted += 1;
+                println!("{} {:<7} {}", theme::error(code), id, file.path);
+            }
+        }
+    }
+
+    for (root, tree) in trees(files.iter().copied()) {
+        let new = tree.strays().len();
+        let changes = tree_changes.get(root).copied().unwrap_or(0) + new;
+        if changes == 0 {
+            continue;
+        }
+        tree_outdated |= new > 0;
+        println!(
+            "{} {:<7} {}/ {}",
+            theme::warning(DriftState::Modified.code()),
+            "",
+            root,
+            tr!("status-tree", count = changes, name = tree.name()).dimmed()
+        );
+    }
+
+    if drifted == 0 && missing == 0 && pinned == 0 && !tree_outdated {
+        println!("{}", tr!("status-clean", count = drift.len()));
+    } else {
+        println!(
+            "\n{}",
+            tr!(
+                "status-summary",
+                tracked = drift.len(),
+                drifted = drifted,
+                missing = missing,
+                pinned = pinned
+            )
+        );
+    }
+    if tree_outdated {
+        println!("{}", tr!("status-tree-hint"));
+    }
+    if !moved.is_empty() {
+        println!("{}", tr!("status-moved-hint"));
+    } else if missing > 0 {
+        println!("{}", tr!("status-missing-hint"));
+    }
+    if elsewhere > 0 {
+        println!(
+            "{}",
+            format!("{} file(s) for other platforms not checked", elsewhere).dimmed()
+        );
+    }
+
+    let frozen: Vec<_> = files.iter().filter(|f| f.frozen).collect();
+    if !frozen.is_empty() {
+        println!("{}", tr!("status-frozen", count = frozen.len()).dimmed());
+    }
+    for file in frozen {
+        if is_thawed(Path::new(&file.original_path)) {
+            println!(
+                "{} {}",
+                theme::warning("WARNING:"),
+                tr!("status-thawed", path = file.original_path.clone())
+            );
+        }
+    }
+
+    let policy = policy::evaluate(&RepoConfig::load(&repo_path)?.policy, &repository);
+    for violation in &policy.violations {
+        println!("{} {}", theme::warning("WARNING:"), violation);
+    }
+    for reminder in &policy.reminders {
+        println!("{}", reminder);
+    }
+
+    let now = Utc::now();
+    for file in &repository.files {
+        let Some(expires_at) = file.expires_at else {
+            continue;
+        };
+        let date = expires_at
+            .with_timezone(&Local)
+            .format("%Y-%m-%d %H:%M")
+            .to_string();
+        if expires_at <= now {
+            println!(
+                "{} {}",
+                theme::warning("WARNING:"),
+                tr!(
+                    "status-expired",
+                    path = file.original_path.clone(),
+                    date = date
+                )
+            );
+        } else if expires_at <= now + Duration::days(EXPIRY_WARNING_DAYS) {
+            println!(
+                "{}",
+                tr!(
+                    "status-expiring",
+                    path = file.original_path.clone(),
+                    date = date
+                )
+            );
+        }
+    }
+
+    Ok(())
+}
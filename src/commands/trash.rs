@@ -0,0 +1,376 @@
+use crate::{
+    commands::init::{Crypto, KittyError, TrackedFile},
+    repository::{config::RepoConfig, keys::unlock_admin},
+    storage::{is_database, open_database},
+    tr,
+    utils::{
+        audit::{self, AuditEntry},
+        file::{
+            get_repository_path, get_storage_type, load_repository, save_repository, write_atomic,
+        },
+        hooks,
+        i18n::confirm,
+        inventory,
+        lock::RepoLock,
+        mirror,
+        password::read_repository_password,
+        shred::secure_delete,
+        stats, theme,
+    },
+};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Directory holding trashed content blobs and the encrypted index
+const TRASH_DIR: &str = "trash";
+
+/// Encrypted list of trashed files
+const TRASH_INDEX: &str = "index.enc";
+
+/// A removed file waiting in the trash
+#[derive(Serialize, Deserialize)]
+struct TrashEntry {
+    /// The manifest entry as it was when removed
+    file: TrackedFile,
+    removed_at: DateTime<Utc>,
+}
+
+/// Repository paths of a file's current content and all of its versions
+fn blob_paths(file: &TrackedFile) -> Vec<String> {
+    std::iter::once(file.repo_path.clone())
+        .chain(file.versions.iter().map(|v| v.repo_path.clone()))
+        .collect()
+}
+
+/// Where a stored blob is kept while in the trash
+fn trash_blob(repo_path: &Path, blob_path: &str) -> PathBuf {
+    let name = Path::new(blob_path)
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| blob_path.replace('/', "_"));
+    repo_path.join(TRASH_DIR).join(name)
+}
+
+fn load_trash(repo_path: &Path, crypto: &Crypto) -> Result<Vec<TrashEntry>, KittyError> {
+    let index_path = repo_path.join(TRASH_DIR).join(TRASH_INDEX);
+    if !index_path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let decrypted = crypto.decrypt(&fs::read(index_path)?)?;
+    Ok(serde_json::from_slice(&decrypted)?)
+}
+
+fn save_trash(repo_path: &Path, crypto: &Crypto, entries: &[TrashEntry]) -> Result<(), KittyError> {
+    let trash_dir = repo_path.join(TRASH_DIR);
+    fs::create_dir_all(&trash_dir)?;
+
+    let json = serde_json::to_string(entries)?;
+    write_atomic(
+        &trash_dir.join(TRASH_INDEX),
+        crypto.encrypt(json.as_bytes())?,
+    )?;
+    Ok(())
+}
+
+/// Delete a trashed file's content blobs
+fn delete_blobs(repo_path: &Path, file: &TrackedFile, shred: bool) -> Result<(), KittyError> {
+    for blob_path in blob_paths(file) {
+        let blob = trash_blob(repo_path, &blob_path);
+        if !blob.exists() {
+            continue;
+        }
+        if shred {
+            secure_delete(&blob)?;
+        } else {
+            fs::remove_file(blob)?;
+        }
+    }
+    Ok(())
+}
+
+/// Shred every trashed copy of a file, returning how many there were
+pub fn purge_from_trash(
+    repo_path: &Path,
+    crypto: &Crypto,
+    original_path: &str,
+) -> Result<usize, KittyError> {
+    let (purged, kept): (Vec<TrashEntry>, Vec<TrashEntry>) = load_trash(repo_path, crypto)?
+        .into_iter()
+        .partition(|entry| entry.file.original_path == original_path);
+
+    for entry in &purged {
+        delete_blobs(repo_path, &entry.file, true)?;
+    }
+    if !purged.is_empty() {
+        save_trash(repo_path, crypto, &kept)?;
+    }
+
+    Ok(purged.len())
+}
+
+/// Whether the trash holds a copy of a file
+pub fn is_in_trash(
+    repo_path: &Path,
+    crypto: &Crypto,
+    original_path: &str,
+) -> Result<bool, KittyError> {
+    Ok(load_trash(repo_path, crypto)?
+        .iter()
+        .any(|entry| entry.file.original_path == original_path))
+}
+
+/// Move a removed file's content and metadata into the trash
+///
+/// With a database backend this must run before the manifest without the file
+/// is saved, since databases drop content that is no longer referenced.
+pub fn move_to_trash(
+    repo_path: &Path,
+    crypto: &Crypto,
+    file: TrackedFile,
+) -> Result<(), KittyError> {
+    fs::create_dir_all(repo_path.join(TRASH_DIR))?;
+
+    if is_database(&get_storage_type(repo_path)?) {
+        let storage = open_database(repo_path)?;
+        for blob_path in blob_paths(&file) {
+            if let Ok(content) = storage.get_file(&blob_path) {
+                write_atomic(&trash_blob(repo_path, &blob_path), content)?;
+            }
+        }
+    } else {
+        for blob_path in blob_paths(&file) {
+            let stored = repo_path.join(&blob_path);
+            if stored.exists() {
+                fs::rename(stored, trash_blob(repo_path, &blob_path))?;
+            }
+        }
+    }
+
+    let mut entries = load_trash(repo_path, crypto)?;
+    entries.push(TrashEntry {
+        file,
+        removed_at: Utc::now(),
+    });
+    save_trash(repo_path, crypto, &entries)?;
+
+    expire_trash(repo_path, crypto)?;
+    Ok(())
+}
+
+/// Delete trashed files older than the configured expiry
+fn expire_trash(repo_path: &Path, crypto: &Crypto) -> Result<(), KittyError> {
+    let keep_days = RepoConfig::load(repo_path)?.trash.keep_days;
+    if keep_days == 0 {
+        return Ok(());
+    }
+
+    let cutoff = Utc::now() - Duration::days(keep_days as i64);
+    let (expired, kept): (Vec<TrashEntry>, Vec<TrashEntry>) = load_trash(repo_path, crypto)?
+        .into_iter()
+        .partition(|entry| entry.removed_at < cutoff);
+
+    if expired.is_empty() {
+        return Ok(());
+    }
+
+    for entry in &expired {
+        delete_blobs(repo_path, &entry.file, false)?;
+        audit::record(
+            repo_path,
+            crypto,
+            &AuditEntry::new("trash-expire", &entry.file.original_path, None, None),
+        )?;
+    }
+    save_trash(repo_path, crypto, &kept)?;
+
+    println!(
+        "Expired {} file(s) trashed more than {} days ago",
+        expired.len(),
+        keep_days
+    );
+    Ok(())
+}
+
+/// Prompt for the password and derive the repository key
+fn unlock() -> Result<(PathBuf, Crypto), KittyError> {
+    let repo_path = get_repository_path()?;
+
+    if !repo_path.exists() {
+        return Err(KittyError::RepositoryNotFound);
+    }
+
+    // Get password from user
+    let password = read_repository_password(false)?;
+
+    let crypto = unlock_admin(&repo_path, &password)?;
+    Ok((repo_path, crypto))
+}
+
+/// Show the files in the trash
+pub fn list_trash() -> Result<(), KittyError> {
+    let (repo_path, crypto) = unlock()?;
+    expire_trash(&repo_path, &crypto)?;
+
+    let entries = load_trash(&repo_path, &crypto)?;
+    if entries.is_empty() {
+        println!("The trash is empty.");
+        return Ok(());
+    }
+
+    let keep_days = RepoConfig::load(&repo_path)?.trash.keep_days;
+
+    println!(
+        "\n{:<50} {:<20} {:<9} {}",
+        theme::emphasis("Path"),
+        theme::emphasis("Removed"),
+        theme::emphasis("Versions"),
+        theme::emphasis("Expires")
+    );
+    for entry in &entries {
+        let expires = if keep_days == 0 {
+            "never".to_string()
+        } else {
+            (entry.removed_at + Duration::days(keep_days as i64))
+                .format("%Y-%m-%d")
+                .to_string()
+        };
+        println!(
+            "{:<50} {:<20} {:<9} {}",
+            entry.file.original_path,
+            entry.removed_at.format("%Y-%m-%d %H:%M:%S"),
+            entry.file.versions.len(),
+            expires
+        );
+    }
+    println!("\nFiles in trash: {}", entries.len());
+
+    Ok(())
+}
+
+/// Track a file from the trash again, with its content and history
+pub fn restore_from_trash(path: &str) -> Result<(), KittyError> {
+    let (repo_path, crypto) = unlock()?;
+    let lock = RepoLock::acquire(&repo_path)?;
+
+    let mut entries = load_trash(&repo_path, &crypto)?;
+    let file_path = Path::new(path)
+        .canonicalize()
+        .unwrap_or_else(|_| Path::new(path).to_path_buf());
+
+    // Prefer an exact match, and the most recently trashed copy of it
+    let index = entries
+        .iter()
+        .rposition(|e| Path::new(&e.file.original_path) == file_path)
+        .or_else(|| {
+            entries
+                .iter()
+                .rposition(|e| e.file.original_path.contains(path))
+        })
+        .ok_or_else(|| KittyError::NotInTrash {
+            path: path.to_string(),
+        })?;
+
+    let mut repository = load_repository(&repo_path, &crypto)?;
+    let original_path = entries[index].file.original_path.clone();
+    if repository
+        .files
+        .iter()
+        .any(|f| f.original_path == original_path)
+    {
+        return Err(KittyError::TrackedAgain {
+            path: original_path,
+        });
+    }
+
+    let entry = entries.remove(index);
+    let blobs = blob_paths(&entry.file);
+    repository.files.push(entry.file);
+    repository.touch(repository.files.len() - 1);
+
+    if is_database(&get_storage_type(&repo_path)?) {
+        // The file row has to exist before its content can be stored
+        save_repository(&repo_path, &crypto, &repository)?;
+        let storage = open_database(&repo_path)?;
+        for blob_path in &blobs {
+            let blob = trash_blob(&repo_path, blob_path);
+            if blob.exists() {
+                storage.save_file(blob_path, &fs::read(&blob)?)?;
+                fs::remove_file(blob)?;
+            }
+        }
+    } else {
+        for blob_path in &blobs {
+            let blob = trash_blob(&repo_path, blob_path);
+            if blob.exists() {
+                fs::rename(blob, repo_path.join(blob_path))?;
+            }
+        }
+        if let Err(e) = save_repository(&repo_path, &crypto, &repository) {
+            // Put the content back, so the trashed copy stays whole
+            for blob_path in &blobs {
+                let _ = fs::rename(repo_path.join(blob_path), trash_blob(&repo_path, blob_path));
+            }
+            return Err(e);
+        }
+    }
+    save_trash(&repo_path, &crypto, &entries)?;
+
+    let hash = repository.files.last().map(|f| f.hash.clone());
+    let entry = AuditEntry::new("trash-restore", &original_path, None, hash);
+    audit::record(&repo_path, &crypto, &entry)?;
+    inventory::update(&repo_path, &crypto, &repository);
+    stats::record(&repo_path, &crypto, &repository);
+    mirror::update(&repo_path);
+    // Hooks may run kitty themselves
+    drop(lock);
+    hooks::run(&repo_path, "post-add", std::slice::from_ref(&entry));
+
+    println!(
+        "{} Tracking {} again",
+        theme::success("SUCCESS:"),
+        original_path
+    );
+    Ok(())
+}
+
+/// Permanently delete everything in the trash
+pub fn empty_trash(force: bool) -> Result<(), KittyError> {
+    let (repo_path, crypto) = unlock()?;
+    let _lock = RepoLock::acquire(&repo_path)?;
+
+    let entries = load_trash(&repo_path, &crypto)?;
+    if entries.is_empty() {
+        println!("The trash is empty.");
+        return Ok(());
+    }
+
+    if !force {
+        println!("{}", tr!("trash-empty-confirm", count = entries.len()));
+        if !confirm()? {
+            println!("Operation canceled.");
+            return Ok(());
+        }
+    }
+
+    for entry in &entries {
+        delete_blobs(&repo_path, &entry.file, false)?;
+        audit::record(
+            &repo_path,
+            &crypto,
+            &AuditEntry::new("trash-empty", &entry.file.original_path, None, None),
+        )?;
+    }
+    save_trash(&repo_path, &crypto, &[])?;
+
+    println!(
+        "{} Deleted {} file(s) from the trash",
+        theme::success("SUCCESS:"),
+        entries.len()
+    );
+    Ok(())
+}
@@ -0,0 +1,102 @@
+use crate::{
+    commands::init::KittyError,
+    utils::{
+        file::get_repository_path,
+        link::{is_linked, LINKED_DIR},
+        shred::secure_delete,
+        theme,
+    },
+};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+use walkdir::WalkDir;
+
+/// Options for the unlink command
+#[derive(Default)]
+pub struct UnlinkOptions {
+    /// Only remove links at or below this path
+    pub path: Option<String>,
+
+    /// Replace each link with a regular copy of the file instead of removing it
+    pub copy: bool,
+}
+
+/// Undo `restore --link` by removing the links and their managed copies
+///
+/// No password is needed: the managed directory mirrors the linked paths.
+pub fn unlink_files(options: Option<UnlinkOptions>) -> Result<(), KittyError> {
+    let options = options.unwrap_or_default();
+    let repo_path = get_repository_path()?;
+
+    if !repo_path.exists() {
+        return Err(KittyError::RepositoryNotFound);
+    }
+
+    let linked_dir = repo_path.join(LINKED_DIR);
+    if !linked_dir.exists() {
+        println!("No files are restored as links.");
+        return Ok(());
+    }
+
+    let filter = options
+        .path
+        .as_deref()
+        .map(std::path::absolute)
+        .transpose()?;
+
+    let mut unlinked = 0;
+    for entry in WalkDir::new(&linked_dir).into_iter().filter_map(|e| e.ok()) {
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let Ok(relative) = entry.path().strip_prefix(&linked_dir) else {
+            continue;
+        };
+        let original = Path::new("/").join(relative);
+        if let Some(filter) = &filter {
+            if !original.starts_with(filter) {
+                continue;
+            }
+        }
+
+        if is_linked(&repo_path, &original) {
+            fs::remove_file(&original)?;
+            if options.copy {
+                fs::copy(entry.path(), &original)?;
+            }
+            println!(
+                "{} {}",
+                if options.copy { "Copied" } else { "Unlinked" },
+                original.display()
+            );
+            unlinked += 1;
+        }
+        secure_delete(entry.path())?;
+    }
+
+    remove_empty_dirs(&linked_dir);
+
+    println!(
+        "{} {} link(s) removed",
+        theme::success("SUCCESS:"),
+        unlinked
+    );
+    Ok(())
+}
+
+/// Remove directories left empty below the linked directory, deepest first
+fn remove_empty_dirs(linked_dir: &Path) {
+    let dirs: Vec<PathBuf> = WalkDir::new(linked_dir)
+        .contents_first(true)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_dir())
+        .map(|e| e.into_path())
+        .collect();
+    for dir in dirs {
+        // Fails harmlessly on directories that still hold links kept by the path filter
+        let _ = fs::remove_dir(dir);
+    }
+}
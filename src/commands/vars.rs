@@ -0,0 +1,241 @@
+use crate::{
+    commands::init::{Crypto, KittyError},
+    repository::keys::unlock,
+    storage::{is_database, open_database},
+    utils::{
+        file::{get_repository_path, get_storage_type, load_repository, read_stored},
+        lock::RepoLock,
+        mirror,
+        password::read_repository_password,
+        select::select_tracked,
+        theme,
+        vars::{check_name, profile_name, Renderer, VarCatalog, VarSource},
+    },
+};
+use rpassword::read_password;
+use std::{
+    io::{self, Write},
+    path::PathBuf,
+};
+
+/// Options for `kitty vars list`
+#[derive(Default)]
+pub struct ListVarsOptions {
+    /// Profile whose variables to show, instead of this machine's
+    pub profile: Option<String>,
+
+    /// Show every profile instead of the variables one profile sees
+    pub all: bool,
+
+    /// Print the variables as JSON
+    pub json: bool,
+}
+
+/// Which variables `set` and `unset` change
+pub enum VarScope {
+    /// Shared by every profile
+    Global,
+
+    /// The named profile, or this machine's when None
+    Profile(Option<String>),
+}
+
+/// Ask for the password on stderr, keeping stdout for output meant for other programs
+fn open_repository() -> Result<(PathBuf, Crypto), KittyError> {
+    let repo_path = get_repository_path()?;
+
+    if !repo_path.exists() {
+        return Err(KittyError::RepositoryNotFound);
+    }
+
+    let password = read_repository_password(true)?;
+
+    let crypto = unlock(&repo_path, &password)?;
+
+    // Fail on a wrong password before anything is read or written
+    load_repository(&repo_path, &crypto)?;
+    Ok((repo_path, crypto))
+}
+
+/// Show the variables a profile sees, or every profile's
+pub fn list_vars(options: Option<ListVarsOptions>) -> Result<(), KittyError> {
+    let options = options.unwrap_or_default();
+    let (repo_path, crypto) = open_repository()?;
+    let catalog = VarCatalog::load(&repo_path, &crypto)?;
+
+    if options.all {
+        if options.json {
+            println!("{}", serde_json::to_string_pretty(&catalog)?);
+            return Ok(());
+        }
+        if catalog.global.is_empty() && catalog.profiles.is_empty() {
+            println!("No variables are set; add one with `kitty vars set <name> <value>`");
+            return Ok(());
+        }
+        print_scope("Global", &catalog.global);
+        for (profile, vars) in &catalog.profiles {
+            print_scope(&format!("Profile {}", profile), vars);
+        }
+        return Ok(());
+    }
+
+    let profile = profile_name(options.profile.as_deref());
+    let resolved = catalog.resolve(&profile);
+
+    if options.json {
+        let vars: serde_json::Map<String, serde_json::Value> = resolved
+            .iter()
+            .map(|(name, (value, source))| {
+                (
+                    name.clone(),
+                    serde_json::json!({ "value": value, "source": source }),
+                )
+            })
+            .collect();
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&serde_json::json!({ "profile": profile, "vars": vars }))?
+        );
+        return Ok(());
+    }
+
+    println!("Variables for profile {}", theme::emphasis(&profile));
+    if resolved.is_empty() {
+        println!("  (none)");
+        return Ok(());
+    }
+    let width = resolved.keys().map(|name| name.len()).max().unwrap_or(0);
+    for (name, (value, source)) in &resolved {
+        let source = match source {
+            VarSource::Global => "global",
+            VarSource::Profile => "profile",
+        };
+        println!("  {:<width$}  {:<7}  {}", name, source, value);
+    }
+    Ok(())
+}
+
+fn print_scope(title: &str, vars: &std::collections::BTreeMap<String, String>) {
+    if vars.is_empty() {
+        return;
+    }
+    println!("{}", theme::emphasis(title));
+    let width = vars.keys().map(|name| name.len()).max().unwrap_or(0);
+    for (name, value) in vars {
+        println!("  {:<width$}  {}", name, value);
+    }
+}
+
+/// Set a variable, asking for its value when none is given so it stays out of shell history
+pub fn set_var(name: &str, value: Option<&str>, scope: VarScope) -> Result<(), KittyError> {
+    check_name(name)?;
+    let (repo_path, crypto) = open_repository()?;
+    crypto.require_admin()?;
+
+    let value = match value {
+        Some(value) => value.to_string(),
+        None => {
+            eprint!("Value for {}: ", name);
+            io::stderr().flush()?;
+            let value = read_password()?;
+            eprintln!();
+            value
+        }
+    };
+
+    let _lock = RepoLock::acquire(&repo_path)?;
+    let mut catalog = VarCatalog::load(&repo_path, &crypto)?;
+    let profile = match &scope {
+        VarScope::Global => None,
+        VarScope::Profile(profile) => Some(profile_name(profile.as_deref())),
+    };
+    catalog
+        .scope_mut(profile.as_deref())
+        .insert(name.to_string(), value);
+    catalog.save(&repo_path, &crypto)?;
+    mirror::update(&repo_path);
+
+    println!(
+        "{} Set {} for {}",
+        theme::success("SUCCESS:"),
+        name,
+        describe(profile.as_deref())
+    );
+    Ok(())
+}
+
+/// Remove a variable from the global variables or a profile
+pub fn unset_var(name: &str, scope: VarScope) -> Result<(), KittyError> {
+    let (repo_path, crypto) = open_repository()?;
+    crypto.require_admin()?;
+    let _lock = RepoLock::acquire(&repo_path)?;
+    let mut catalog = VarCatalog::load(&repo_path, &crypto)?;
+
+    let profile = match &scope {
+        VarScope::Global => None,
+        VarScope::Profile(profile) => Some(profile_name(profile.as_deref())),
+    };
+    if catalog.scope_mut(profile.as_deref()).remove(name).is_none() {
+        return Err(KittyError::Template(format!(
+            "{} is not set for {}",
+            name,
+            describe(profile.as_deref())
+        )));
+    }
+
+    // Drop profiles left without variables
+    catalog.profiles.retain(|_, vars| !vars.is_empty());
+    catalog.save(&repo_path, &crypto)?;
+    mirror::update(&repo_path);
+
+    println!(
+        "{} Unset {} for {}",
+        theme::success("SUCCESS:"),
+        name,
+        describe(profile.as_deref())
+    );
+    Ok(())
+}
+
+fn describe(profile: Option<&str>) -> String {
+    match profile {
+        Some(profile) => format!("profile {}", profile),
+        None => "all profiles".to_string(),
+    }
+}
+
+/// Print a tracked file's stored content with its variables filled in, without restoring it
+pub fn render_file(path: &str, profile: Option<&str>) -> Result<(), KittyError> {
+    let (repo_path, crypto) = open_repository()?;
+    let repository = load_repository(&repo_path, &crypto)?;
+
+    let [index] = select_tracked(&repository.files, path, false, false)?[..] else {
+        return Err(KittyError::AmbiguousPath(format!(
+            "'{}' matches several tracked files; render one at a time",
+            path
+        )));
+    };
+    let file = &repository.files[index];
+
+    let encrypted = if is_database(&get_storage_type(&repo_path)?) {
+        open_database(&repo_path)?.get_file(&file.repo_path)?
+    } else {
+        read_stored(&repo_path.join(&file.repo_path))?
+    };
+    let content = crypto.decrypt(&encrypted)?;
+    let template = String::from_utf8(content)
+        .map_err(|_| KittyError::Template(format!("{} is not a text file", file.original_path)))?;
+
+    let catalog = VarCatalog::load(&repo_path, &crypto)?;
+    let rendered = Renderer::new(catalog.resolve(&profile_name(profile))).render(&template)?;
+
+    let mut stdout = io::stdout().lock();
+    match stdout
+        .write_all(rendered.as_bytes())
+        .and_then(|_| stdout.flush())
+    {
+        // Piping into head and the like closes stdout early
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => Ok(result?),
+    }
+}
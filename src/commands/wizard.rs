@@ -0,0 +1,152 @@
+use crate::{
+    commands::init::{init_repository_with_options, InitOptions, KittyError},
+    repository::config::RepoConfig,
+    utils::{
+        file::{expand_home, repository_path_from_env, REPOSITORY_DIR},
+        hash::HashAlgorithm,
+        i18n::confirm,
+        platform::hostname,
+        theme,
+        vars::PROFILE_ENV,
+    },
+};
+use std::{
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+
+/// Walk through the choices `kitty init` takes as flags, then initialize the repository
+///
+/// Answers left empty take the default shown in brackets.
+pub fn run_wizard() -> Result<(), KittyError> {
+    println!("{}", theme::emphasis("Set up a kitty repository"));
+    println!("Press Enter to accept the default in brackets.\n");
+
+    let mut options = InitOptions {
+        backend: ask_choice(
+            "Storage backend: file keeps one encrypted file per version, sqlite and redb one database",
+            &["file", "sqlite", "redb"],
+        )?,
+        ..Default::default()
+    };
+
+    match repository_path_from_env() {
+        Some(repo_path) => println!("Location: {} (from KITTY_REPO)", repo_path.display()),
+        None => {
+            let current_dir = std::env::current_dir()?;
+            let parent = ask(
+                "Directory to create the repository in",
+                &current_dir.display().to_string(),
+            )?;
+            let parent = expand_home(&parent);
+            if Path::new(&parent) != current_dir {
+                options.path = Some(parent);
+            }
+            let dir_name = ask("Name of the repository directory", REPOSITORY_DIR)?;
+            if dir_name != REPOSITORY_DIR {
+                options.dir_name = Some(dir_name);
+            }
+        }
+    }
+
+    // This kitty has one key derivation and one cipher, so these are shown rather than asked
+    println!(
+        "Encryption: ChaCha20-Poly1305, with the key derived from your password by \
+         PBKDF2-SHA256 (100,000 iterations) and a random salt"
+    );
+
+    let hash_algorithm = match ask_choice(
+        "Hash for detecting changes: blake3 is faster, sha256 matches other tools",
+        &["blake3", "sha256"],
+    )?
+    .as_str()
+    {
+        "sha256" => HashAlgorithm::Sha256,
+        _ => HashAlgorithm::Blake3,
+    };
+    let mut settings = RepoConfig {
+        hash_algorithm,
+        ..Default::default()
+    };
+
+    let profile = ask("Profile name for this machine", &hostname())?;
+    let files = ask_files()?;
+
+    if !files.is_empty() {
+        println!(
+            "A role named '{}' lets `kitty apply` restore these files here without --role.",
+            profile
+        );
+        if confirm()? {
+            settings.roles.insert(profile.clone(), files.clone());
+        }
+    }
+
+    options.settings = Some(settings);
+    options.track = files;
+    println!();
+    init_repository_with_options(&options)?;
+
+    if profile != hostname() {
+        println!(
+            "Set {}={} on this machine so kitty uses the '{}' profile",
+            PROFILE_ENV, profile, profile
+        );
+    }
+    Ok(())
+}
+
+/// Ask a question, returning the default when the answer is empty
+fn ask(question: &str, default: &str) -> Result<String, KittyError> {
+    print!("{} [{}]: ", question, default);
+    io::stdout().flush()?;
+
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+    let answer = input.trim();
+    Ok(if answer.is_empty() {
+        default.to_string()
+    } else {
+        answer.to_string()
+    })
+}
+
+/// Ask until the answer is one of `choices`; the first is the default
+fn ask_choice(question: &str, choices: &[&str]) -> Result<String, KittyError> {
+    println!("{} ({})", question, choices.join(", "));
+    loop {
+        let answer = ask("Choice", choices[0])?.to_lowercase();
+        if choices.contains(&answer.as_str()) {
+            return Ok(answer);
+        }
+        println!(
+            "{} Expected one of {}",
+            theme::warning("Warning:"),
+            choices.join(", ")
+        );
+    }
+}
+
+/// Ask for the first files to track, leaving out any that do not exist
+fn ask_files() -> Result<Vec<String>, KittyError> {
+    print!("Files to track now, separated by spaces (empty for none): ");
+    io::stdout().flush()?;
+
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+
+    let mut files = Vec::new();
+    for path in input.split_whitespace().map(expand_home) {
+        // Roles match the absolute paths files are tracked under
+        match fs::canonicalize(&path) {
+            Ok(absolute) if absolute.is_file() => files.push(absolute.display().to_string()),
+            _ => println!(
+                "{} Skipping {}: not a file",
+                theme::warning("Warning:"),
+                path
+            ),
+        }
+    }
+    Ok(files)
+}
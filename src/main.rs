@@ -1,35 +1,105 @@
+mod collectors;
 mod commands;
+mod repository;
 mod storage;
 mod utils;
 
-use clap::{Parser, Subcommand};
+#[cfg(not(unix))]
+compile_error!("kitty supports Unix-like systems only");
+
+use clap::{CommandFactory, Parser, Subcommand};
 use commands::{
     add::add_file,
-    init::{init_repository_with_options, InitOptions, KittyError},
+    init::{init_repository_with_options, ContentFilter, InitOptions, KittyError},
     list::list_files,
     remove::remove_file,
 };
+use std::path::Path;
 
 #[derive(Parser)]
 #[command(author, version, about = "A Git-like configuration management tool")]
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+
+    /// Answer yes to every confirmation prompt (also KITTY_ASSUME_YES=1)
+    #[arg(long, global = true, visible_alias = "non-interactive")]
+    yes: bool,
+
+    /// Report how long key derivation, encryption, hashing, storage IO and network calls took
+    #[arg(long, global = true)]
+    timings: bool,
 }
 
 #[derive(Subcommand)]
 enum Commands {
     /// Initialize a new kitty repository
     Init {
-        /// Use SQLite for storage instead of files
+        /// Use SQLite for storage instead of files (same as --backend sqlite)
         #[arg(long)]
         sqlite: bool,
+
+        /// Storage backend: file, sqlite or redb
+        #[arg(long, default_value = "file")]
+        backend: String,
+
+        /// Track the files listed in a JSON manifest, or every file in a directory
+        #[arg(long)]
+        adopt: Option<String>,
+
+        /// Fill the repository from a file written by `kitty dump`
+        #[arg(long, value_name = "FILE", conflicts_with = "adopt")]
+        import: Option<String>,
+
+        /// Directory to create the repository in (e.g. /srv/config-repo)
+        #[arg(long)]
+        path: Option<String>,
+
+        /// Name of the repository directory (default: .kitty)
+        #[arg(long)]
+        dir_name: Option<String>,
+
+        /// Also set a read-only password that can restore but not change the repository
+        #[arg(long)]
+        read_key: bool,
+
+        /// Ask for the backend, location, settings and first files step by step
+        #[arg(long, conflicts_with_all = ["sqlite", "backend", "adopt", "import", "path", "dir_name", "read_key"])]
+        interactive: bool,
     },
 
     /// Add a file to track in the repository
     Add {
-        /// Path to the file to add
+        /// Path to the file to add, or a directory to track as one tree
         path: String,
+
+        /// Path or bundle that must be restored before this file (repeatable)
+        #[arg(long)]
+        after: Vec<String>,
+
+        /// Track the file even if it exceeds the repository's size limit
+        #[arg(long)]
+        force: bool,
+
+        /// Drop lines matching this regex before storing or comparing (repeatable)
+        #[arg(long, value_name = "REGEX")]
+        strip: Vec<String>,
+
+        /// Replace matches of a regex before storing or comparing (repeatable)
+        #[arg(long, num_args = 2, value_names = ["REGEX", "REPLACEMENT"])]
+        replace: Vec<String>,
+
+        /// Only use the file on matching machines, e.g. os=macos or distro=debian,fedora (repeatable)
+        #[arg(long, value_name = "KEY=VALUE")]
+        only: Vec<String>,
+
+        /// Track the file temporarily, e.g. for 30d, 12h or 2w or until a date like 2025-06-30
+        #[arg(long, value_name = "DURATION")]
+        expires: Option<String>,
+
+        /// Treat the file as a system or user file instead of deciding by its path
+        #[arg(long, value_parser = ["user", "system"])]
+        scope: Option<String>,
     },
 
     /// Remove a file from tracking
@@ -44,11 +114,65 @@ enum Commands {
         /// Keep the file content in the repository, just stop tracking it
         #[arg(long)]
         keep_content: bool,
+
+        /// Overwrite stored content and restore backups, deleting them instead of trashing
+        #[arg(long)]
+        shred: bool,
+
+        /// Remove every file a partial path matches
+        #[arg(long)]
+        all: bool,
+
+        /// Treat the path as a regular expression
+        #[arg(long)]
+        regex: bool,
     },
 
     /// Show the status of tracked files
     Status,
 
+    /// Print a short drift summary for shell prompts, without asking for the password
+    Prompt,
+
+    /// Stop reporting a file that differs per machine as drifted
+    Pin {
+        /// Path of the tracked file
+        path: String,
+    },
+
+    /// Report a pinned file's changes as drift again
+    Unpin {
+        /// Path of the tracked file
+        path: String,
+    },
+
+    /// Make the live copies of tracked files read-only
+    Freeze {
+        /// Path of the tracked file, or a bundle
+        target: String,
+    },
+
+    /// Let frozen files be edited again, putting back their recorded mode
+    Unfreeze {
+        /// Path of the tracked file, or a bundle
+        target: String,
+    },
+
+    /// Move a tracked file, or record where missing files were moved to
+    Mv {
+        /// Tracked file to move
+        #[arg(required_unless_present = "accept_detected")]
+        from: Option<String>,
+
+        /// New path, or a directory to move the file into
+        #[arg(required_unless_present = "accept_detected")]
+        to: Option<String>,
+
+        /// Record the moves `kitty status` detects for missing files
+        #[arg(long, conflicts_with_all = ["from", "to"])]
+        accept_detected: bool,
+    },
+
     /// Show differences between tracked files and their current state
     Diff {
         /// Path to the file to diff
@@ -69,12 +193,33 @@ enum Commands {
         /// Number of context lines to show
         #[arg(long, default_value = "3")]
         context_lines: usize,
+
+        /// Compare against the owning package's pristine version instead of the repository
+        #[arg(long)]
+        against_package: bool,
+
+        /// Diff every file a partial path matches
+        #[arg(long)]
+        all: bool,
+
+        /// Treat the path as a regular expression
+        #[arg(long)]
+        regex: bool,
+
+        /// Compare the stored copy against another file, e.g. a candidate config
+        #[arg(
+            long,
+            value_name = "FILE",
+            requires = "path",
+            conflicts_with = "against_package"
+        )]
+        with: Option<String>,
     },
 
     /// Restore files from the repository
     Restore {
-        /// Path to the file to restore
-        path: String,
+        /// Path to the file to restore (all tracked files if omitted)
+        path: Option<String>,
 
         /// Don't prompt for confirmation
         #[arg(long)]
@@ -84,14 +229,159 @@ enum Commands {
         #[arg(long)]
         dry_run: bool,
 
-        /// Backup existing files before restoring
-        #[arg(long, default_value = "true")]
+        /// Copy files to <path>.bak before overwriting them (the default)
+        #[arg(long, overrides_with = "no_backup")]
         backup: bool,
+
+        /// Overwrite files without keeping a .bak copy
+        #[arg(long, overrides_with = "backup")]
+        no_backup: bool,
+
+        /// Install missing packages from tracked package lists
+        #[arg(long)]
+        packages: bool,
+
+        /// Show each change and ask whether to restore it
+        #[arg(short, long)]
+        patch: bool,
+
+        /// Give restored files the mtime they had when added
+        #[arg(long)]
+        preserve_times: bool,
+
+        /// Symlink files to plaintext copies in the repository instead of writing them
+        #[arg(long)]
+        link: bool,
+
+        /// Print the summary as JSON
+        #[arg(long)]
+        json: bool,
+
+        /// Restore every file a partial path matches
+        #[arg(long)]
+        all: bool,
+
+        /// Treat the path as a regular expression
+        #[arg(long)]
+        regex: bool,
+
+        /// Fill in template variables and secrets from Vault or AWS while writing
+        #[arg(long, conflicts_with = "link")]
+        render: bool,
+
+        /// Profile whose variables to render with (default: KITTY_PROFILE or the hostname)
+        #[arg(long, requires = "render")]
+        profile: Option<String>,
+
+        /// Only recreate tracked files that were deleted
+        #[arg(long, conflicts_with_all = ["packages", "patch"])]
+        missing_only: bool,
+    },
+
+    /// Pick changes from a tracked file and its stored copy, side by side
+    Resolve {
+        /// Tracked file to resolve
+        path: String,
+
+        /// Write the result to the file on disk, the repository, or both
+        #[arg(long, default_value = "both", value_parser = ["disk", "repo", "both"])]
+        write: String,
+    },
+
+    /// Bring the files of a machine role in line with the repository
+    Apply {
+        /// Role from "roles" in the repository settings, e.g. webserver
+        /// (default: the role named after the profile, else every file for this machine)
+        #[arg(long)]
+        role: Option<String>,
+
+        /// Don't prompt for confirmation
+        #[arg(long)]
+        force: bool,
+
+        /// Show what would change without writing anything
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Copy files to <path>.bak before overwriting them (the default)
+        #[arg(long, overrides_with = "no_backup")]
+        backup: bool,
+
+        /// Overwrite files without keeping a .bak copy
+        #[arg(long, overrides_with = "backup")]
+        no_backup: bool,
+
+        /// Install missing packages from the role's package lists instead of restoring files
+        #[arg(long)]
+        packages: bool,
+
+        /// Fill in template variables and secrets from Vault or AWS while writing
+        #[arg(long)]
+        render: bool,
+
+        /// Profile that picks the role and whose variables to render with
+        /// (default: KITTY_PROFILE or the hostname)
+        #[arg(long)]
+        profile: Option<String>,
+
+        /// Print the summary as JSON
+        #[arg(long)]
+        json: bool,
+
+        /// Print what would change, as text or with --json as JSON, without writing anything
+        #[arg(long, conflicts_with = "from_plan")]
+        plan: bool,
+
+        /// Also save the plan to this file, to apply it later with --from-plan
+        #[arg(long, value_name = "FILE", requires = "plan")]
+        save_plan: Option<String>,
+
+        /// Make exactly the changes of a saved plan, refusing if anything changed since
+        #[arg(
+            long,
+            value_name = "FILE",
+            conflicts_with_all = ["role", "packages", "render", "profile", "dry_run"]
+        )]
+        from_plan: Option<String>,
+    },
+
+    /// List tracked files with their state, in a stable format for editor plugins
+    LsFiles {
+        /// Only list files at or below this path
+        path: Option<String>,
+
+        /// Print one JSON document instead of a line per file
+        #[arg(long)]
+        json: bool,
+
+        /// Read the repository password from a file instead of KITTY_PASSWORD or a prompt
+        #[arg(long)]
+        password_file: Option<String>,
+    },
+
+    /// Print the repository's copy of a tracked file, exactly as stored
+    Blob {
+        /// Full path or @id of the tracked file
+        path: String,
+
+        /// Read the repository password from a file instead of KITTY_PASSWORD or a prompt
+        #[arg(long)]
+        password_file: Option<String>,
+    },
+
+    /// Remove links made by `restore --link`
+    Unlink {
+        /// Only remove links at or below this path
+        path: Option<String>,
+
+        /// Replace each link with a regular copy of the file
+        #[arg(long)]
+        copy: bool,
     },
 
     /// List all tracked files
     List {
-        /// Filter files by path (partial match)
+        /// Filter files by path (partial match or glob)
         #[arg(long)]
         path: Option<String>,
 
@@ -99,50 +389,729 @@ enum Commands {
         #[arg(long)]
         date: Option<String>,
 
-        /// Group files by path components
+        /// Group files by directory (same as --group-by dir)
         #[arg(long)]
         group: bool,
 
+        /// Group files by dir, date, tag, bundle or tree; several, comma-separated, nest
+        #[arg(long, value_delimiter = ',', value_parser = ["dir", "date", "tag", "bundle", "tree"])]
+        group_by: Vec<String>,
+
+        /// Show the N largest files by stored size (default 10)
+        #[arg(long, num_args = 0..=1, default_missing_value = "10")]
+        largest: Option<usize>,
+
+        /// Treat the path filter as a regular expression
+        #[arg(long)]
+        regex: bool,
+
+        /// Print only the tracked paths, one per line, for scripts
+        #[arg(long, conflicts_with_all = ["group", "group_by", "largest"])]
+        paths_only: bool,
+
+        /// End each path with NUL instead of a newline (for xargs -0)
+        #[arg(short = '0', long, requires = "paths_only")]
+        null: bool,
+
+        /// Show each file's state: clean, modified, missing, unreadable or pinned
+        #[arg(long, conflicts_with_all = ["group", "group_by", "largest", "paths_only"])]
+        status: bool,
+
         /// Use SQLite storage (experimental)
         #[arg(long)]
         sqlite: bool,
     },
     
+    /// Show the audit log of repository operations
+    Audit {
+        /// Filter entries by path (partial match)
+        #[arg(long)]
+        path: Option<String>,
+
+        /// Filter entries by date (format: YYYY-MM-DD)
+        #[arg(long)]
+        date: Option<String>,
+
+        /// Emit entries as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Show how many files the repository holds and how much space they take
+    Stats {
+        /// Show how the repository grew over time
+        #[arg(long)]
+        history: bool,
+    },
+
+    /// Check tracked files for drift without prompting (for cron and CI)
+    Check {
+        /// Only check files whose path contains this string
+        path: Option<String>,
+
+        /// Emit the report as JSON
+        #[arg(long)]
+        json: bool,
+
+        /// Read the repository password from a file instead of KITTY_PASSWORD
+        #[arg(long)]
+        password_file: Option<String>,
+
+        /// Number of drifted files tolerated before exiting with status 1
+        #[arg(long, default_value = "0")]
+        max_drift: usize,
+
+        /// Write Prometheus textfile collector metrics to this file
+        #[arg(long)]
+        metrics_file: Option<String>,
+
+        /// Send the report when drift is found, through the smtp settings of the repository
+        #[arg(long, value_parser = ["email"], requires = "to")]
+        report: Option<String>,
+
+        /// Address to mail the report to (repeatable)
+        #[arg(long, value_name = "ADDRESS", requires = "report")]
+        to: Vec<String>,
+    },
+
+    /// Watch tracked files for drift and serve a local control API, or control a running daemon
+    #[command(args_conflicts_with_subcommands = true)]
+    Daemon {
+        #[command(subcommand)]
+        command: Option<DaemonCommands>,
+
+        /// Address for the HTTP API to listen on
+        #[arg(long, default_value = "127.0.0.1:7373")]
+        listen: String,
+
+        /// Serve the API on a unix socket instead of TCP
+        #[arg(long)]
+        socket: Option<String>,
+
+        /// Seconds between drift checks
+        #[arg(long, default_value = "30")]
+        interval: u64,
+
+        /// Read the repository password from a file instead of KITTY_PASSWORD
+        #[arg(long)]
+        password_file: Option<String>,
+
+        /// Snapshot drifted files on this cron schedule, e.g. "0 * * * *"
+        #[arg(long)]
+        snapshot_schedule: Option<String>,
+
+        /// Only check on the interval, without watching for file changes
+        #[arg(long)]
+        no_watch: bool,
+    },
+
+    /// Remove old versions of tracked files
+    Prune {
+        /// Keep this many of the most recent versions of each file
+        #[arg(long)]
+        keep_last: Option<usize>,
+
+        /// Keep versions saved within this many days
+        #[arg(long)]
+        keep_days: Option<u64>,
+
+        /// Untrack entries added with --expires whose time has run out
+        #[arg(long)]
+        expired: bool,
+
+        /// Show what would be removed without removing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Run a command with tracked files decrypted into a private directory
+    Exec {
+        /// Tracked file to expose, as PATH or NAME=PATH (repeatable)
+        #[arg(long = "file")]
+        files: Vec<String>,
+
+        /// Expose every file in this bundle
+        #[arg(long)]
+        bundle: Option<String>,
+
+        /// Command to run; {NAME} in its arguments is replaced with the file's path
+        #[arg(trailing_var_arg = true, required = true)]
+        command: Vec<String>,
+    },
+
+    /// Write a tracked file as a SOPS-encrypted file, e.g. for a team using SOPS
+    ExportSops {
+        /// Tracked file to export
+        path: String,
+
+        /// File to write; its extension picks YAML, JSON, dotenv, INI or binary
+        output: String,
+
+        /// age recipient to encrypt for instead of the .sops.yaml rules (repeatable)
+        #[arg(long)]
+        age: Vec<String>,
+
+        /// Overwrite the output file if it exists
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Apply a unified diff to the stored copy of a file, leaving the live file alone
+    Patch {
+        /// Tracked file to patch
+        path: String,
+
+        /// Read the patch from this file instead of stdin
+        #[arg(long)]
+        file: Option<String>,
+
+        /// Show what the patch changes without storing it
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Show which stored version introduced each line of a file, and who stored it
+    Blame {
+        /// Tracked file to annotate
+        path: String,
+    },
+
+    /// Look at tracked files as they were at a point in time, without restoring anything
+    At {
+        /// A date (the end of that day), "YYYY-MM-DD HH:MM", or an age like 3d
+        when: String,
+
+        #[command(subcommand)]
+        command: AtCommands,
+    },
+
+    /// Browse tracked files read-only through a FUSE mount, decrypted as they are read
+    Mount {
+        /// Empty directory to mount on
+        dir: String,
+
+        /// Show the files as they were at this time: a date, "YYYY-MM-DD HH:MM" or an age like 3d
+        #[arg(long)]
+        at: Option<String>,
+    },
+
+    /// Write tracked files to a bundle encrypted for age recipients, e.g. for a colleague
+    Share {
+        /// Tracked files to share; a directory tree or glob shares every file in it
+        #[arg(required = true)]
+        paths: Vec<String>,
+
+        /// age recipient who can open the bundle (repeatable)
+        #[arg(long, short, required = true)]
+        recipient: Vec<String>,
+
+        /// File to write
+        #[arg(long, short)]
+        output: String,
+
+        /// Overwrite the output file if it exists
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Write the files from a `kitty share` bundle; needs no repository
+    Receive {
+        /// Bundle to open
+        bundle: String,
+
+        /// age identity file holding the key the bundle was shared with
+        #[arg(long, short)]
+        identity: String,
+
+        /// Directory to write the files to
+        #[arg(long, short = 'C', default_value = ".")]
+        output_dir: String,
+
+        /// Only list the files in the bundle
+        #[arg(long)]
+        list: bool,
+
+        /// Overwrite files that already exist
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Erase a file and all of its history, e.g. a secret tracked by mistake
+    Purge {
+        /// Path of the file to purge
+        path: String,
+
+        /// Don't prompt for confirmation
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Inspect or recover files removed with rm
+    Trash {
+        #[command(subcommand)]
+        command: TrashCommands,
+    },
+
+    /// Maintain the SQLite database (for SQLite storage mode)
+    Db {
+        #[command(subcommand)]
+        command: DbCommands,
+    },
+
+    /// Manage template variables, shared or per host profile
+    Vars {
+        #[command(subcommand)]
+        command: VarsCommands,
+    },
+
+    /// Print a tracked file with its template variables filled in, without restoring it
+    Render {
+        /// Tracked file to render
+        path: String,
+
+        /// Profile whose variables to use (default: KITTY_PROFILE or the hostname)
+        #[arg(long)]
+        profile: Option<String>,
+    },
+
+    /// Write or verify the signed plaintext inventory of tracked files
+    Inventory {
+        #[command(subcommand)]
+        command: InventoryCommands,
+    },
+
+    /// Keep a copy of the encrypted repository in another directory
+    Mirror {
+        /// Directory to mirror to, e.g. a NAS mount or a Syncthing folder (all mirrors if omitted)
+        dir: Option<String>,
+
+        /// Stop updating the mirror in this directory
+        #[arg(long, requires = "dir")]
+        stop: bool,
+    },
+
+    /// Write the whole repository to one encrypted file, for `init --import`
+    Dump {
+        /// File to write
+        #[arg(long, short)]
+        output: String,
+
+        /// Overwrite the output file if it exists
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Unlock the repository once for the commands that follow in this shell
+    Session {
+        #[command(subcommand)]
+        command: SessionCommands,
+    },
+
+    /// Salvage whatever is readable from a damaged repository
+    Recover {
+        /// Report what would be recovered without writing anything
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Find the salt of a repository made before salt.key and write it there
+        #[arg(long)]
+        find_salt: bool,
+    },
+
+    /// Migrate a repository created by an older kitty to the current format
+    UpgradeRepo,
+
     /// Migrate file content to SQLite database (for SQLite storage mode)
     MigrateSqlite {
         /// Run migration without prompt
         #[arg(long)]
         force: bool,
     },
+
+    /// Generate man pages or a markdown reference for every command
+    GenDocs {
+        /// Output format: man or markdown
+        format: String,
+
+        /// Directory to write the documentation to
+        #[arg(long)]
+        out: String,
+    },
+}
+
+impl Commands {
+    /// Whether the command was asked for JSON output, which errors then follow
+    fn json(&self) -> bool {
+        match self {
+            Commands::Restore { json, .. }
+            | Commands::Apply { json, .. }
+            | Commands::LsFiles { json, .. }
+            | Commands::Audit { json, .. }
+            | Commands::Check { json, .. } => *json,
+            Commands::Daemon {
+                command: Some(DaemonCommands::Status { json }),
+                ..
+            } => *json,
+            Commands::Vars {
+                command: VarsCommands::List { json, .. },
+            } => *json,
+            Commands::Inventory {
+                command: InventoryCommands::Verify { json, .. },
+            } => *json,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum AtCommands {
+    /// List the files tracked then, marking those changed since
+    List {
+        /// Only list files whose path contains this string
+        path: Option<String>,
+    },
+
+    /// Print a file as it was stored then
+    Cat {
+        /// Tracked file to print
+        path: String,
+    },
+
+    /// Show how files on disk differ from how they were stored then
+    Diff {
+        /// Path to the file to diff
+        path: Option<String>,
+
+        /// Show files with changes only
+        #[arg(long)]
+        only_changed: bool,
+
+        /// Show summary of changes
+        #[arg(long)]
+        summary: bool,
+
+        /// Diff every file the path matches when it matches several
+        #[arg(long)]
+        all: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum SessionCommands {
+    /// Ask for the password and print the line that unlocks this shell, for `eval`
+    Start {
+        /// Minutes until the session locks by itself
+        #[arg(long, default_value = "15")]
+        ttl: u64,
+    },
+
+    /// Lock the repository again and print the line that forgets the session
+    Stop,
+
+    /// Show whether this shell has an unlocked session
+    Status,
+
+    /// Hold the password for a session; started by `session start`
+    #[command(hide = true)]
+    Agent {
+        #[arg(long)]
+        socket: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum DaemonCommands {
+    /// Show what the running daemon last saw
+    Status {
+        /// Print the daemon's answer as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Stop checks, auto-adds and snapshots until resumed
+    Pause,
+
+    /// Continue after a pause, starting with a drift check
+    Resume,
+
+    /// Check changed files now instead of waiting for them to settle
+    Flush,
+}
+
+#[derive(Subcommand)]
+enum TrashCommands {
+    /// Show removed files and when they expire
+    List,
+
+    /// Track a removed file again, with its history
+    Restore {
+        /// Path of the removed file
+        path: String,
+    },
+
+    /// Permanently delete everything in the trash
+    Empty {
+        /// Don't prompt for confirmation
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum DbCommands {
+    /// Rebuild the database to reclaim space left by deleted content
+    Vacuum,
+
+    /// Run an integrity check on the database
+    Check,
+
+    /// Write a consistent copy of the database while it is in use
+    Backup {
+        /// File to write the backup to
+        path: String,
+    },
+
+    /// Replace the database with a backup
+    Restore {
+        /// Backup file to restore from
+        path: String,
+
+        /// Don't prompt for confirmation
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum VarsCommands {
+    /// Show the variables a profile sees and where each comes from
+    List {
+        /// Profile to show (default: KITTY_PROFILE or the hostname)
+        #[arg(long)]
+        profile: Option<String>,
+
+        /// Show the global variables and every profile
+        #[arg(long, conflicts_with = "profile")]
+        all: bool,
+
+        /// Print the variables as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Set a variable for this machine's profile, another profile, or all of them
+    Set {
+        /// Variable name, used as {{ name }} in templates
+        name: String,
+
+        /// Value (asked for without echo when omitted)
+        value: Option<String>,
+
+        /// Profile to set it for (default: KITTY_PROFILE or the hostname)
+        #[arg(long)]
+        profile: Option<String>,
+
+        /// Set it for every profile
+        #[arg(long, conflicts_with = "profile")]
+        global: bool,
+    },
+
+    /// Remove a variable from a profile or from the global variables
+    Unset {
+        /// Variable name
+        name: String,
+
+        /// Profile to remove it from (default: KITTY_PROFILE or the hostname)
+        #[arg(long)]
+        profile: Option<String>,
+
+        /// Remove the global variable
+        #[arg(long, conflicts_with = "profile")]
+        global: bool,
+    },
 }
 
-fn main() -> Result<(), KittyError> {
-    let cli = Cli::parse();
+#[derive(Subcommand)]
+enum InventoryCommands {
+    /// Write and sign the inventory now
+    Write,
+
+    /// Check the inventory against its signature (no password needed)
+    Verify {
+        /// Directory holding inventory.json and inventory.sig, if not the repository
+        #[arg(long)]
+        dir: Option<String>,
+
+        /// Public key to check against, in hex or as a file holding it
+        #[arg(long)]
+        key: Option<String>,
+
+        /// Print the verified inventory as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+fn main() {
+    if let Err(e) = run() {
+        utils::errors::report(&e);
+        exit(1);
+    }
+    utils::timings::report();
+}
+
+/// Exit with `code`, after printing the `--timings` report
+fn exit(code: i32) -> ! {
+    utils::timings::report();
+    std::process::exit(code)
+}
+
+fn run() -> Result<(), KittyError> {
+    let command = Cli::command();
+    let is_builtin = |name: &str| {
+        name == "help"
+            || command.get_subcommands().any(|sub| {
+                sub.get_name() == name || sub.get_all_aliases().any(|alias| alias == name)
+            })
+    };
+    let args = match utils::alias::expand(std::env::args_os().collect(), is_builtin)? {
+        utils::alias::Expansion::Args(args) => args,
+        expansion => std::process::exit(utils::alias::run(expansion)?),
+    };
+    let cli = Cli::parse_from(args);
+    if cli.yes {
+        utils::i18n::set_assume_yes();
+    }
+    if cli.timings {
+        utils::timings::enable();
+    }
+    if cli.command.json() {
+        utils::errors::set_json();
+    }
+
+    // Never touch a repository written by a newer kitty
+    if !matches!(
+        cli.command,
+        Commands::Init { .. } | Commands::UpgradeRepo | Commands::GenDocs { .. } | Commands::Prompt
+    ) {
+        if let Err(e) = repository::format::check_repository_format() {
+            if matches!(cli.command, Commands::Check { .. }) {
+                utils::errors::report(&e);
+                exit(commands::check::EXIT_ERROR);
+            }
+            return Err(e);
+        }
+    }
 
     match &cli.command {
-        Commands::Init { sqlite } => {
+        Commands::Init {
+            interactive: true, ..
+        } => commands::wizard::run_wizard(),
+        Commands::Init {
+            sqlite,
+            backend,
+            adopt,
+            import,
+            path,
+            dir_name,
+            read_key,
+            ..
+        } => {
             let options = InitOptions {
-                use_sqlite: *sqlite,
+                backend: if *sqlite {
+                    "sqlite".to_string()
+                } else {
+                    backend.clone()
+                },
+                adopt: adopt.clone(),
+                import: import.clone(),
+                path: path.clone(),
+                dir_name: dir_name.clone(),
+                read_key: *read_key,
+                ..Default::default()
             };
             init_repository_with_options(&options)
         }
-        Commands::Add { path } => add_file(path),
+        Commands::Add {
+            path,
+            after,
+            force,
+            strip,
+            replace,
+            only,
+            expires,
+            scope,
+        } => {
+            let filters = strip
+                .iter()
+                .map(|pattern| ContentFilter::Strip(pattern.clone()))
+                .chain(replace.chunks(2).map(|pair| ContentFilter::Replace {
+                    pattern: pair[0].clone(),
+                    with: pair[1].clone(),
+                }))
+                .collect();
+            let mut conditions = utils::platform::Conditions::default();
+            for spec in only {
+                conditions.add(spec)?;
+            }
+            let labels = commands::add::FileLabels {
+                after: after.clone(),
+                filters,
+                conditions,
+                expires_at: expires
+                    .as_deref()
+                    .map(commands::add::parse_expiry)
+                    .transpose()?,
+                scope: scope.as_deref().map(|s| match s {
+                    "system" => commands::init::Scope::System,
+                    _ => commands::init::Scope::User,
+                }),
+                ..Default::default()
+            };
+            add_file(path, &labels, *force)
+        }
         Commands::Rm {
             path,
             force,
             keep_content,
+            shred,
+            all,
+            regex,
         } => {
             let options = commands::remove::RemoveOptions {
                 path: path.clone(),
                 force: *force,
                 keep_content: *keep_content,
+                shred: *shred,
+                all: *all,
+                regex: *regex,
             };
             remove_file(&options)
         }
-        Commands::Status => {
-            println!("Checking status of tracked files...");
-            // TODO: Implement status functionality
-            Ok(())
+        Commands::Unlink { path, copy } => {
+            let options = commands::unlink::UnlinkOptions {
+                path: path.clone(),
+                copy: *copy,
+            };
+            commands::unlink::unlink_files(Some(options))
+        }
+        Commands::Status => commands::status::show_status(),
+        Commands::Prompt => commands::prompt::show_prompt(),
+        Commands::Pin { path } => commands::pin::pin_file(path, true),
+        Commands::Unpin { path } => commands::pin::pin_file(path, false),
+        Commands::Freeze { target } => commands::freeze::freeze_files(target, true),
+        Commands::Unfreeze { target } => commands::freeze::freeze_files(target, false),
+        Commands::Mv {
+            from,
+            to,
+            accept_detected,
+        } => {
+            let options = commands::mv::MoveOptions {
+                from: from.clone(),
+                to: to.clone(),
+                accept_detected: *accept_detected,
+            };
+            commands::mv::move_file(Some(options))
         }
         Commands::Diff {
             path,
@@ -150,6 +1119,10 @@ fn main() -> Result<(), KittyError> {
             summary,
             context,
             context_lines,
+            against_package,
+            all,
+            regex,
+            with,
         } => {
             let options = commands::diff::DiffOptions {
                 path: path.clone(),
@@ -157,6 +1130,11 @@ fn main() -> Result<(), KittyError> {
                 summary: *summary,
                 context: *context,
                 context_lines: *context_lines,
+                against_package: *against_package,
+                all: *all,
+                regex: *regex,
+                with: with.clone(),
+                at: None,
             };
             commands::diff::diff_files(Some(options))
         }
@@ -165,32 +1143,452 @@ fn main() -> Result<(), KittyError> {
             force,
             dry_run,
             backup,
+            no_backup,
+            packages,
+            patch,
+            preserve_times,
+            link,
+            json,
+            all,
+            regex,
+            render,
+            profile,
+            missing_only,
+        } => {
+            let options = commands::restore::RestoreOptions {
+                path: path.clone(),
+                force: *force,
+                dry_run: *dry_run,
+                backup: match (backup, no_backup) {
+                    (true, _) => Some(true),
+                    (_, true) => Some(false),
+                    _ => None,
+                },
+                packages: *packages,
+                patch: *patch,
+                preserve_times: *preserve_times,
+                link: *link,
+                json: *json,
+                all: *all,
+                regex: *regex,
+                role: None,
+                skip_unchanged: false,
+                render: *render,
+                profile: profile.clone(),
+                missing_only: *missing_only,
+                plan: None,
+                permissions: false,
+            };
+            let report = commands::restore::restore_files(Some(options))?;
+            if *json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            }
+
+            // Let scripts notice files that could not be restored
+            if !report.errors.is_empty() {
+                exit(1);
+            }
+            Ok(())
+        }
+        Commands::Resolve { path, write } => {
+            let options = commands::resolve::ResolveOptions {
+                write: commands::resolve::ResolveTarget::parse(write)?,
+            };
+            commands::resolve::resolve_file(path, Some(options))
+        }
+        Commands::Apply {
+            role,
+            force,
+            dry_run,
+            backup,
+            no_backup,
+            packages,
+            render,
+            profile,
+            json,
+            plan,
+            save_plan,
+            from_plan,
         } => {
+            // A saved plan brings its own role and rendering
+            let saved = from_plan
+                .as_deref()
+                .map(|path| commands::plan::Plan::load(Path::new(path)))
+                .transpose()?;
+            let role = match (&saved, role) {
+                (Some(plan), _) => Some(plan.role.clone()).filter(|role| !role.is_empty()),
+                (None, Some(role)) => Some(role.clone()),
+                (None, None) => commands::restore::host_role(profile.as_deref())?,
+            };
             let options = commands::restore::RestoreOptions {
-                path: Some(path.clone()),
+                role,
+                skip_unchanged: true,
+                permissions: true,
+                render: saved.as_ref().map_or(*render, |p| p.render),
+                profile: saved
+                    .as_ref()
+                    .map_or(profile.clone(), |p| p.profile.clone()),
                 force: *force,
                 dry_run: *dry_run,
-                backup: *backup,
+                backup: match (backup, no_backup) {
+                    (true, _) => Some(true),
+                    (_, true) => Some(false),
+                    _ => None,
+                },
+                packages: saved.as_ref().map_or(*packages, |p| p.packages),
+                json: *json,
+                plan: saved,
+                ..Default::default()
             };
-            commands::restore::restore_files(Some(options))
+
+            if *plan {
+                let (plan, report) = commands::plan::make_plan(options)?;
+                if let Some(path) = save_plan {
+                    plan.save(Path::new(path))?;
+                    eprintln!(
+                        "Plan saved to {}; apply it with `kitty apply --from-plan {}`",
+                        path, path
+                    );
+                }
+                if *json {
+                    println!("{}", serde_json::to_string_pretty(&plan)?);
+                } else {
+                    plan.print();
+                }
+                if !report.errors.is_empty() {
+                    exit(1);
+                }
+                return Ok(());
+            }
+
+            let report = commands::restore::restore_files(Some(options))?;
+            if *json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            }
+
+            if !report.errors.is_empty() {
+                exit(1);
+            }
+            Ok(())
+        }
+        Commands::LsFiles {
+            path,
+            json,
+            password_file,
+        } => {
+            let options = commands::ls_files::LsFilesOptions {
+                path: path.clone(),
+                json: *json,
+                password_file: password_file.clone(),
+            };
+            commands::ls_files::ls_files(Some(options))
         }
+        Commands::Blob {
+            path,
+            password_file,
+        } => commands::blob::print_blob(path, password_file.as_deref()),
         Commands::List {
             path,
             date,
             group,
+            group_by,
+            largest,
+            regex,
+            paths_only,
+            null,
+            status,
             sqlite,
         } => {
+            let mut group_by = group_by
+                .iter()
+                .map(|name| commands::list::GroupBy::parse(name))
+                .collect::<Result<Vec<_>, _>>()?;
+            if *group && group_by.is_empty() {
+                group_by.push(commands::list::GroupBy::Directory);
+            }
             let options = commands::list::ListOptions {
                 path: path.clone(),
                 date: date.clone(),
-                group: *group,
+                group_by,
+                largest: *largest,
+                regex: *regex,
+                paths_only: *paths_only,
+                null: *null,
+                status: *status,
             };
-            if *sqlite {
+            if *sqlite && !*paths_only {
                 println!("Note: Using experimental SQLite storage");
                 // TODO: Implement SQLite storage integration
             }
             list_files(Some(options))
         }
+        Commands::Audit { path, date, json } => {
+            let options = commands::audit::AuditOptions {
+                path: path.clone(),
+                date: date.clone(),
+                json: *json,
+            };
+            commands::audit::show_audit_log(Some(options))
+        }
+        Commands::Stats { history } => {
+            let options = commands::stats::StatsOptions { history: *history };
+            commands::stats::show_stats(Some(options))
+        }
+        Commands::Check {
+            path,
+            json,
+            password_file,
+            max_drift,
+            metrics_file,
+            report,
+            to,
+        } => {
+            let options = commands::check::CheckOptions {
+                path: path.clone(),
+                json: *json,
+                password_file: password_file.clone(),
+                max_drift: *max_drift,
+                metrics_file: metrics_file.clone(),
+                email_to: if report.as_deref() == Some("email") {
+                    to.clone()
+                } else {
+                    Vec::new()
+                },
+            };
+            // Exit codes are part of the interface for monitoring systems
+            let code = match commands::check::check_files(Some(options)) {
+                Ok(code) => code,
+                Err(e) => {
+                    utils::errors::report(&e);
+                    commands::check::EXIT_ERROR
+                }
+            };
+            exit(code)
+        }
+        Commands::Daemon {
+            command: Some(command),
+            ..
+        } => match command {
+            DaemonCommands::Status { json } => commands::control::control_daemon("status", *json),
+            DaemonCommands::Pause => commands::control::control_daemon("pause", false),
+            DaemonCommands::Resume => commands::control::control_daemon("resume", false),
+            DaemonCommands::Flush => commands::control::control_daemon("flush", false),
+        },
+        Commands::Daemon {
+            command: None,
+            listen,
+            socket,
+            interval,
+            password_file,
+            snapshot_schedule,
+            no_watch,
+        } => {
+            let options = commands::daemon::DaemonOptions {
+                listen: listen.clone(),
+                socket: socket.clone(),
+                interval: *interval,
+                password_file: password_file.clone(),
+                snapshot_schedule: snapshot_schedule.clone(),
+                watch: !*no_watch,
+            };
+            commands::daemon::run_daemon(Some(options))
+        }
+        Commands::Prune {
+            keep_last,
+            keep_days,
+            expired,
+            dry_run,
+        } => {
+            let options = commands::prune::PruneOptions {
+                keep_last: *keep_last,
+                keep_days: *keep_days,
+                expired: *expired,
+                dry_run: *dry_run,
+            };
+            commands::prune::prune_versions(Some(options))
+        }
+        Commands::Exec {
+            files,
+            bundle,
+            command,
+        } => {
+            let options = commands::exec::ExecOptions {
+                files: files.clone(),
+                bundle: bundle.clone(),
+                command: command.clone(),
+            };
+            match commands::exec::exec_with_files(Some(options)) {
+                Ok(code) => exit(code),
+                Err(e) => Err(e),
+            }
+        }
+        Commands::Purge { path, force } => {
+            let options = commands::purge::PurgeOptions {
+                path: path.clone(),
+                force: *force,
+            };
+            commands::purge::purge_file(Some(options))
+        }
+        Commands::Trash { command } => match command {
+            TrashCommands::List => commands::trash::list_trash(),
+            TrashCommands::Restore { path } => commands::trash::restore_from_trash(path),
+            TrashCommands::Empty { force } => commands::trash::empty_trash(*force),
+        },
+        Commands::Db { command } => match command {
+            DbCommands::Vacuum => commands::db::vacuum_database(),
+            DbCommands::Check => commands::db::check_database(),
+            DbCommands::Backup { path } => commands::db::backup_database(path),
+            DbCommands::Restore { path, force } => commands::db::restore_database(path, *force),
+        },
+        Commands::Vars { command } => {
+            let scope = |profile: &Option<String>, global: bool| {
+                if global {
+                    commands::vars::VarScope::Global
+                } else {
+                    commands::vars::VarScope::Profile(profile.clone())
+                }
+            };
+            match command {
+                VarsCommands::List { profile, all, json } => {
+                    let options = commands::vars::ListVarsOptions {
+                        profile: profile.clone(),
+                        all: *all,
+                        json: *json,
+                    };
+                    commands::vars::list_vars(Some(options))
+                }
+                VarsCommands::Set {
+                    name,
+                    value,
+                    profile,
+                    global,
+                } => commands::vars::set_var(name, value.as_deref(), scope(profile, *global)),
+                VarsCommands::Unset {
+                    name,
+                    profile,
+                    global,
+                } => commands::vars::unset_var(name, scope(profile, *global)),
+            }
+        }
+        Commands::ExportSops {
+            path,
+            output,
+            age,
+            force,
+        } => {
+            let options = commands::sops::ExportSopsOptions {
+                age: age.clone(),
+                force: *force,
+            };
+            commands::sops::export_sops(path, output, Some(options))
+        }
+        Commands::Patch {
+            path,
+            file,
+            dry_run,
+        } => {
+            let options = commands::patch::PatchOptions {
+                file: file.clone(),
+                dry_run: *dry_run,
+            };
+            commands::patch::patch_file(path, Some(options))
+        }
+        Commands::Blame { path } => commands::blame::blame_file(path),
+        Commands::At { when, command } => match command {
+            AtCommands::List { path } => commands::at::list_at(when, path.as_deref()),
+            AtCommands::Cat { path } => commands::at::cat_at(when, path),
+            AtCommands::Diff {
+                path,
+                only_changed,
+                summary,
+                all,
+            } => {
+                let options = commands::diff::DiffOptions {
+                    path: path.clone(),
+                    only_changed: *only_changed,
+                    summary: *summary,
+                    all: *all,
+                    ..Default::default()
+                };
+                commands::at::diff_at(when, Some(options))
+            }
+        },
+        Commands::Mount { dir, at } => {
+            let options = commands::mount::MountOptions { at: at.clone() };
+            commands::mount::mount_repository(dir, Some(options))
+        }
+        Commands::Share {
+            paths,
+            recipient,
+            output,
+            force,
+        } => {
+            let options = commands::share::ShareOptions {
+                recipients: recipient.clone(),
+                force: *force,
+            };
+            commands::share::share_files(paths, output, Some(options))
+        }
+        Commands::Receive {
+            bundle,
+            identity,
+            output_dir,
+            list,
+            force,
+        } => {
+            let options = commands::share::ReceiveOptions {
+                identity: identity.clone(),
+                output_dir: output_dir.clone(),
+                list: *list,
+                force: *force,
+            };
+            commands::share::receive_bundle(bundle, Some(options))
+        }
+        Commands::Render { path, profile } => commands::vars::render_file(path, profile.as_deref()),
+        Commands::Mirror { dir, stop } => {
+            let options = commands::mirror::MirrorOptions {
+                dir: dir.clone(),
+                stop: *stop,
+            };
+            commands::mirror::mirror_repository(Some(options))
+        }
+        Commands::Inventory { command } => match command {
+            InventoryCommands::Write => commands::inventory::write_inventory(),
+            InventoryCommands::Verify { dir, key, json } => {
+                let options = commands::inventory::VerifyOptions {
+                    dir: dir.clone(),
+                    key: key.clone(),
+                    json: *json,
+                };
+                commands::inventory::verify_inventory(Some(options))
+            }
+        },
+        Commands::Dump { output, force } => {
+            let options = commands::dump::DumpOptions { force: *force };
+            commands::dump::dump_repository(output, Some(options))
+        }
+        Commands::Session { command } => match command {
+            SessionCommands::Start { ttl } => {
+                let options = commands::session::SessionOptions { ttl: *ttl };
+                commands::session::start_session(Some(options))
+            }
+            SessionCommands::Stop => commands::session::stop_session(),
+            SessionCommands::Status => commands::session::session_status(),
+            SessionCommands::Agent { socket } => {
+                commands::session::run_agent(std::path::Path::new(socket))
+            }
+        },
+        Commands::Recover { dry_run, find_salt } => {
+            let options = commands::recover::RecoverOptions {
+                dry_run: *dry_run,
+                find_salt: *find_salt,
+            };
+            commands::recover::recover_repository(Some(options))
+        }
+        Commands::UpgradeRepo => repository::format::upgrade_repository(),
+        Commands::GenDocs { format, out } => {
+            commands::docs::generate_docs(Cli::command(), format, std::path::Path::new(out))
+        }
         Commands::MigrateSqlite { force } => {
             use std::process::Command;
             
@@ -207,15 +1605,10 @@ fn main() -> Result<(), KittyError> {
             }
             
             if !*force {
-                use std::io::{self, Write};
-                
-                print!("This will migrate file content from the filesystem to the SQLite database. Continue? [y/N] ");
-                io::stdout().flush()?;
-                
-                let mut input = String::new();
-                io::stdin().read_line(&mut input)?;
-                
-                if !["y", "yes"].contains(&input.trim().to_lowercase().as_str()) {
+                println!(
+                    "This will migrate file content from the filesystem to the SQLite database."
+                );
+                if !utils::i18n::confirm()? {
                     println!("Migration aborted.");
                     return Ok(());
                 }
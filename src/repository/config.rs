@@ -0,0 +1,534 @@
+use crate::{
+    commands::init::KittyError,
+    utils::{
+        file::{get_repository_path, write_atomic},
+        hash::HashAlgorithm,
+    },
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, path::Path};
+
+/// Plaintext repository settings file (no secrets are stored here)
+const CONFIG_FILE: &str = "config.json";
+
+/// How many old versions of each file to keep when pruning
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct RetentionPolicy {
+    /// Always keep this many of the most recent versions
+    #[serde(default)]
+    pub keep_last: Option<usize>,
+
+    /// Always keep versions saved within this many days
+    #[serde(default)]
+    pub keep_days: Option<u64>,
+}
+
+impl RetentionPolicy {
+    pub fn is_empty(&self) -> bool {
+        self.keep_last.is_none() && self.keep_days.is_none()
+    }
+}
+
+/// How long removed files stay in the trash
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TrashPolicy {
+    /// Days before a trashed file is deleted for good (0 keeps it until `trash empty`)
+    #[serde(default = "default_trash_days")]
+    pub keep_days: u64,
+}
+
+fn default_trash_days() -> u64 {
+    30
+}
+
+impl Default for TrashPolicy {
+    fn default() -> Self {
+        Self {
+            keep_days: default_trash_days(),
+        }
+    }
+}
+
+/// Defaults for `kitty restore`
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RestoreDefaults {
+    /// Copy a file to `<path>.bak` before overwriting it, unless `--no-backup` is given
+    #[serde(default = "default_backup")]
+    pub backup: bool,
+
+    /// Ask before restoring system files by path, unless `--force` is given
+    #[serde(default = "default_confirm_system")]
+    pub confirm_system: bool,
+}
+
+fn default_backup() -> bool {
+    true
+}
+
+fn default_confirm_system() -> bool {
+    true
+}
+
+impl Default for RestoreDefaults {
+    fn default() -> Self {
+        Self {
+            backup: default_backup(),
+            confirm_system: default_confirm_system(),
+        }
+    }
+}
+
+/// Snapshots `kitty daemon` takes of drifted files on a schedule
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SnapshotSchedule {
+    /// Cron expression in local time, e.g. `0 * * * *` for hourly (unset disables snapshots)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<String>,
+
+    /// Apply the retention policy after each scheduled snapshot
+    #[serde(default = "default_snapshot_prune")]
+    pub prune: bool,
+}
+
+fn default_snapshot_prune() -> bool {
+    true
+}
+
+impl Default for SnapshotSchedule {
+    fn default() -> Self {
+        Self {
+            schedule: None,
+            prune: default_snapshot_prune(),
+        }
+    }
+}
+
+/// How `kitty daemon` turns filesystem events into drift checks
+#[derive(Serialize, Deserialize, Clone)]
+pub struct WatchSettings {
+    /// Milliseconds a file must stay unchanged before it is checked
+    #[serde(default = "default_quiet_ms")]
+    pub quiet_ms: u64,
+
+    /// Check a file at the latest this many milliseconds after it starts changing
+    #[serde(default = "default_max_delay_ms")]
+    pub max_delay_ms: u64,
+
+    /// Globs for files whose events are ignored, on top of editor temporaries
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ignore: Vec<String>,
+}
+
+fn default_quiet_ms() -> u64 {
+    500
+}
+
+fn default_max_delay_ms() -> u64 {
+    5000
+}
+
+impl Default for WatchSettings {
+    fn default() -> Self {
+        Self {
+            quiet_ms: default_quiet_ms(),
+            max_delay_ms: default_max_delay_ms(),
+            ignore: Vec::new(),
+        }
+    }
+}
+
+/// Size thresholds checked by `kitty add`, in bytes
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SizeLimits {
+    /// Warn when a file is larger than this (0 disables the warning)
+    #[serde(default = "default_warn_bytes")]
+    pub warn_bytes: u64,
+
+    /// Refuse files larger than this unless `--force` is given (0 disables the limit)
+    #[serde(default = "default_max_bytes")]
+    pub max_bytes: u64,
+}
+
+fn default_warn_bytes() -> u64 {
+    10 * 1024 * 1024
+}
+
+fn default_max_bytes() -> u64 {
+    100 * 1024 * 1024
+}
+
+impl Default for SizeLimits {
+    fn default() -> Self {
+        Self {
+            warn_bytes: default_warn_bytes(),
+            max_bytes: default_max_bytes(),
+        }
+    }
+}
+
+/// Colors for each kind of output, by name (`red`, `bright blue`, ...) or `none`
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ThemeConfig {
+    /// Added lines in diffs
+    #[serde(default = "default_addition")]
+    pub addition: String,
+
+    /// Removed lines in diffs
+    #[serde(default = "default_deletion")]
+    pub deletion: String,
+
+    /// Hunk headers when restoring part of a file
+    #[serde(default = "default_hunk")]
+    pub hunk: String,
+
+    /// SUCCESS labels
+    #[serde(default = "default_success")]
+    pub success: String,
+
+    /// ERROR labels and missing files
+    #[serde(default = "default_error")]
+    pub error: String,
+
+    /// WARNING labels and modified files
+    #[serde(default = "default_warning")]
+    pub warning: String,
+
+    /// Whether labels and paths are printed in bold
+    #[serde(default = "default_bold")]
+    pub bold: bool,
+}
+
+fn default_addition() -> String {
+    "green".to_string()
+}
+
+fn default_deletion() -> String {
+    "red".to_string()
+}
+
+fn default_hunk() -> String {
+    "cyan".to_string()
+}
+
+fn default_success() -> String {
+    "green".to_string()
+}
+
+fn default_error() -> String {
+    "red".to_string()
+}
+
+fn default_warning() -> String {
+    "yellow".to_string()
+}
+
+fn default_bold() -> bool {
+    true
+}
+
+impl Default for ThemeConfig {
+    fn default() -> Self {
+        Self {
+            addition: default_addition(),
+            deletion: default_deletion(),
+            hunk: default_hunk(),
+            success: default_success(),
+            error: default_error(),
+            warning: default_warning(),
+            bold: default_bold(),
+        }
+    }
+}
+
+/// Where `kitty status` looks for tracked files that disappeared from their path
+#[derive(Serialize, Deserialize, Clone)]
+pub struct MoveSearch {
+    /// Directories to search; without any, the nearest existing directory the file was in
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub roots: Vec<String>,
+
+    /// Levels to look into, counting each directory's own files as the first (0 turns detection off)
+    #[serde(default = "default_move_depth")]
+    pub max_depth: usize,
+}
+
+fn default_move_depth() -> usize {
+    2
+}
+
+impl Default for MoveSearch {
+    fn default() -> Self {
+        Self {
+            roots: Vec::new(),
+            max_depth: default_move_depth(),
+        }
+    }
+}
+
+/// How long kitty waits for another kitty working on the same repository
+#[derive(Serialize, Deserialize, Clone)]
+pub struct LockSettings {
+    /// Seconds to wait for the repository lock before giving up
+    #[serde(default = "default_lock_wait")]
+    pub wait_secs: u64,
+
+    /// Seconds after which a lock is taken to be left behind, when its owner cannot be checked
+    #[serde(default = "default_lock_stale")]
+    pub stale_after_secs: u64,
+}
+
+fn default_lock_wait() -> u64 {
+    10
+}
+
+fn default_lock_stale() -> u64 {
+    600
+}
+
+impl Default for LockSettings {
+    fn default() -> Self {
+        Self {
+            wait_secs: default_lock_wait(),
+            stale_after_secs: default_lock_stale(),
+        }
+    }
+}
+
+/// Credential rules an organization sets for the repository, reported by `status` and `check`
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct PasswordPolicy {
+    /// Days after which the repository password must be changed
+    #[serde(default)]
+    pub max_password_age_days: Option<u64>,
+
+    /// Fewest PBKDF2 iterations the key may be derived with
+    #[serde(default)]
+    pub min_kdf_iterations: Option<u32>,
+
+    /// Make `kitty check` fail while the policy is violated
+    #[serde(default)]
+    pub fail_check: bool,
+}
+
+/// Where structured events about drift, restores and mirror syncs are logged
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EventLog {
+    /// Not logged
+    #[default]
+    Off,
+    /// The local syslog daemon, through `/dev/log`
+    Syslog,
+    /// The systemd journal, with every field of an event searchable
+    Journald,
+}
+
+/// How the connection to the mail server is secured
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpSecurity {
+    /// Plain connection upgraded with STARTTLS, usually on port 587
+    #[default]
+    Starttls,
+    /// TLS from the start, usually on port 465
+    Tls,
+    /// No encryption, for a relay on the same machine
+    None,
+}
+
+/// Mail server `kitty check --report email` sends through
+///
+/// The password is never stored here; it is read from `KITTY_SMTP_PASSWORD`.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SmtpSettings {
+    /// Host name of the mail server
+    pub host: String,
+
+    /// Port, the default one for the security setting when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+
+    #[serde(default)]
+    pub security: SmtpSecurity,
+
+    /// User to log in as, when the server wants a login
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+
+    /// Sender address of reports
+    pub from: String,
+}
+
+fn default_manifest_backups() -> usize {
+    3
+}
+
+/// Repository settings stored in `.kitty/config.json`
+#[derive(Serialize, Deserialize)]
+pub struct RepoConfig {
+    /// Default retention policy used by `kitty prune`
+    #[serde(default)]
+    pub retention: RetentionPolicy,
+
+    /// Expiry of files removed with `kitty rm`
+    #[serde(default)]
+    pub trash: TrashPolicy,
+
+    /// Guard against tracking large files by accident
+    #[serde(default)]
+    pub limits: SizeLimits,
+
+    /// Defaults for `kitty restore`
+    #[serde(default)]
+    pub restore: RestoreDefaults,
+
+    /// Scheduled snapshots taken by `kitty daemon`
+    #[serde(default)]
+    pub snapshots: SnapshotSchedule,
+
+    /// Filesystem event handling in `kitty daemon`
+    #[serde(default)]
+    pub watch: WatchSettings,
+
+    /// Where moved files are looked for
+    #[serde(default)]
+    pub moves: MoveSearch,
+
+    /// Waiting for and breaking the repository lock
+    #[serde(default)]
+    pub lock: LockSettings,
+
+    /// Password age and key derivation strength the repository must meet
+    #[serde(default)]
+    pub policy: PasswordPolicy,
+
+    /// Paths, directories or bundles whose changes `kitty daemon` stores as soon as it sees them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub auto_add: Vec<String>,
+
+    /// Shell commands run after repository changes, keyed by event, e.g. `"post-add"`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub hooks: BTreeMap<String, Vec<String>>,
+
+    /// Pass `post-add` and `post-restore` hooks a unified diff of what changed, file content included
+    #[serde(default)]
+    pub hook_diffs: bool,
+
+    /// Commands that check new content before restore writes it, keyed by path, directory or
+    /// bundle, e.g. `"/etc/nginx": "nginx -t -c {file}"`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub validate: BTreeMap<String, String>,
+
+    /// Machine roles, e.g. `"webserver"`, each listing the paths, directories or bundles it needs
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub roles: BTreeMap<String, Vec<String>>,
+
+    /// Directories kept as copies of the encrypted repository, updated after every change
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mirrors: Vec<String>,
+
+    /// Keep a signed plaintext inventory of paths and hashes next to the repository
+    #[serde(default)]
+    pub inventory: bool,
+
+    /// Previous copies of `config.enc` to keep as `config.enc.1`, `config.enc.2`, ... (0 keeps none)
+    #[serde(default = "default_manifest_backups")]
+    pub manifest_backups: usize,
+
+    /// Algorithm for new content hashes; files keep theirs until they are added again
+    #[serde(default)]
+    pub hash_algorithm: HashAlgorithm,
+
+    /// Output colors
+    #[serde(default)]
+    pub theme: ThemeConfig,
+
+    /// System log that drift, restores and mirror syncs are reported to
+    #[serde(default)]
+    pub event_log: EventLog,
+
+    /// Mail server for emailed check reports
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub smtp: Option<SmtpSettings>,
+
+    /// Language for messages, e.g. `de`, overriding `LANG`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locale: Option<String>,
+}
+
+impl Default for RepoConfig {
+    fn default() -> Self {
+        Self {
+            retention: RetentionPolicy::default(),
+            trash: TrashPolicy::default(),
+            limits: SizeLimits::default(),
+            restore: RestoreDefaults::default(),
+            snapshots: SnapshotSchedule::default(),
+            watch: WatchSettings::default(),
+            moves: MoveSearch::default(),
+            lock: LockSettings::default(),
+            policy: PasswordPolicy::default(),
+            auto_add: Vec::new(),
+            hooks: BTreeMap::new(),
+            hook_diffs: false,
+            validate: BTreeMap::new(),
+            roles: BTreeMap::new(),
+            mirrors: Vec::new(),
+            inventory: false,
+            manifest_backups: default_manifest_backups(),
+            hash_algorithm: HashAlgorithm::default(),
+            theme: ThemeConfig::default(),
+            event_log: EventLog::Off,
+            smtp: None,
+            locale: None,
+        }
+    }
+}
+
+impl RepoConfig {
+    /// Load the repository settings, falling back to defaults when none are written yet
+    pub fn load(repo_path: &Path) -> Result<Self, KittyError> {
+        let config_path = repo_path.join(CONFIG_FILE);
+        if !config_path.exists() {
+            return Ok(Self::default());
+        }
+
+        let contents = fs::read_to_string(config_path)?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    /// Settings of the repository in use, for options needed before a command runs
+    ///
+    /// Returns None when there is no repository or its settings cannot be read;
+    /// the command itself reports those problems.
+    pub fn current() -> Option<Self> {
+        let repo_path = get_repository_path().ok()?;
+        if !repo_path.exists() {
+            return None;
+        }
+        Self::load(&repo_path).ok()
+    }
+
+    /// The paths, directories and bundles of a role
+    pub fn role(&self, name: &str) -> Result<&[String], KittyError> {
+        match self.roles.get(name) {
+            Some(entries) => Ok(entries),
+            None if self.roles.is_empty() => Err(KittyError::Role(format!(
+                "'{}' is not defined; add roles to \"roles\" in config.json",
+                name
+            ))),
+            None => Err(KittyError::Role(format!(
+                "'{}' is not defined (known roles: {})",
+                name,
+                self.roles.keys().cloned().collect::<Vec<_>>().join(", ")
+            ))),
+        }
+    }
+
+    /// Write the repository settings
+    pub fn save(&self, repo_path: &Path) -> Result<(), KittyError> {
+        let contents = serde_json::to_string_pretty(self)?;
+        write_atomic(&repo_path.join(CONFIG_FILE), contents)?;
+        Ok(())
+    }
+}
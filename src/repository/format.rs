@@ -0,0 +1,132 @@
+use crate::{
+    commands::init::KittyError,
+    repository::config::RepoConfig,
+    utils::file::{get_repository_path, write_atomic},
+};
+use std::{fs, path::Path};
+
+/// Plaintext file holding the repository format version
+const FORMAT_FILE: &str = "format.version";
+
+/// Format version written by this build of kitty.
+///
+/// 1. Original layout (no format file)
+/// 2. Repository settings in config.json, previous file versions and the audit log
+/// 3. Manifest generation and revisions, file scopes, trees, and signed manifests with a read key
+/// 4. SHA-256 content hashes, marked `sha256:`, alongside bare BLAKE3 ones
+pub const CURRENT_FORMAT: u32 = 4;
+
+/// Read the repository format version; repositories without a stamp predate versioning
+pub fn read_format(repo_path: &Path) -> Result<u32, KittyError> {
+    let format_path = repo_path.join(FORMAT_FILE);
+    if !format_path.exists() {
+        return Ok(1);
+    }
+
+    let contents = fs::read_to_string(format_path)?;
+    contents.trim().parse().map_err(|_| {
+        KittyError::UnsupportedFormat(format!("invalid format version {}", contents.trim()))
+    })
+}
+
+/// Stamp the repository with the current format version
+pub fn write_format(repo_path: &Path) -> Result<(), KittyError> {
+    write_atomic(&repo_path.join(FORMAT_FILE), CURRENT_FORMAT.to_string())?;
+    Ok(())
+}
+
+fn newer_format_error(format: u32) -> KittyError {
+    KittyError::UnsupportedFormat(format!(
+        "format {} is newer than this kitty supports ({}); please upgrade kitty",
+        format, CURRENT_FORMAT
+    ))
+}
+
+/// Refuse to operate on repositories written by a newer kitty
+///
+/// Older repositories still work, but a hint to run `kitty upgrade-repo` is printed.
+pub fn check_repository_format() -> Result<(), KittyError> {
+    let repo_path = get_repository_path()?;
+
+    // Commands report a missing repository themselves
+    if !repo_path.exists() {
+        return Ok(());
+    }
+
+    let format = read_format(&repo_path)?;
+    if format > CURRENT_FORMAT {
+        return Err(newer_format_error(format));
+    }
+
+    if format < CURRENT_FORMAT {
+        eprintln!(
+            "Note: Repository format {} is older than the current format {}. Run `kitty upgrade-repo` to migrate it.",
+            format, CURRENT_FORMAT
+        );
+    }
+
+    Ok(())
+}
+
+/// Migrate an older repository to the current format
+pub fn upgrade_repository() -> Result<(), KittyError> {
+    let repo_path = get_repository_path()?;
+
+    if !repo_path.exists() {
+        return Err(KittyError::RepositoryNotFound);
+    }
+
+    let format = read_format(&repo_path)?;
+    if format > CURRENT_FORMAT {
+        return Err(newer_format_error(format));
+    }
+
+    if format == CURRENT_FORMAT {
+        println!("Repository is already at format {}.", CURRENT_FORMAT);
+        return Ok(());
+    }
+
+    for note in migrate(&repo_path, format)? {
+        println!("{}", note);
+    }
+    println!(
+        "Repository upgraded from format {} to {}.",
+        format, CURRENT_FORMAT
+    );
+
+    Ok(())
+}
+
+/// Bring a repository from `format` to the current one, returning what was done
+fn migrate(repo_path: &Path, format: u32) -> Result<Vec<&'static str>, KittyError> {
+    let mut notes = Vec::new();
+
+    // Format 2: settings file. Versions and the audit log need no conversion, and
+    // SQLite schema changes are applied whenever the database is opened.
+    if format < 2 && !repo_path.join("config.json").exists() {
+        RepoConfig::default().save(repo_path)?;
+        notes.push("Created default settings in config.json");
+    }
+
+    // Format 3: the new manifest fields default to what older manifests meant,
+    // and keys.json is only written by `init --read-key`. The stamp keeps older
+    // kittys, which would drop the fields on their next save, out.
+
+    // Format 4: hashes stay as they are. Older kittys take `sha256:` hashes for
+    // BLAKE3 ones and would see every such file as changed.
+
+    write_format(repo_path)?;
+    Ok(notes)
+}
+
+/// Migrate a repository older than the current format before a save writes current fields
+///
+/// Every save records the manifest generation, so once this kitty has saved a
+/// manifest, only kittys that know the current format may open it.
+pub fn raise_format(repo_path: &Path) -> Result<(), KittyError> {
+    let format = read_format(repo_path)?;
+    if format < CURRENT_FORMAT {
+        migrate(repo_path, format)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,98 @@
+use crate::commands::init::{KittyError, TrackedFile};
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+
+/// Stored content of a tracked file, as it was from when it was captured until the next change
+pub struct Revision<'a> {
+    pub repo_path: &'a str,
+    pub hash: &'a str,
+
+    /// When the content was captured
+    pub saved_at: DateTime<Utc>,
+
+    /// Whether this is the content the file has now
+    pub current: bool,
+}
+
+/// Every kept revision of a file, oldest first and the current content last
+pub fn revisions(file: &TrackedFile) -> Vec<Revision<'_>> {
+    let mut revisions: Vec<Revision> = file
+        .versions
+        .iter()
+        .map(|version| Revision {
+            repo_path: &version.repo_path,
+            hash: &version.hash,
+            saved_at: version.saved_at,
+            current: false,
+        })
+        .collect();
+    revisions.sort_by_key(|revision| revision.saved_at);
+    revisions.push(Revision {
+        repo_path: &file.repo_path,
+        hash: &file.hash,
+        saved_at: file.last_updated,
+        current: true,
+    });
+    revisions
+}
+
+/// The revision a file had at `when`
+///
+/// None when the file was not tracked yet, or the versions from then were pruned.
+pub fn revision_at(file: &TrackedFile, when: DateTime<Utc>) -> Option<Revision<'_>> {
+    if file.added_at > when {
+        return None;
+    }
+    revisions(file)
+        .into_iter()
+        .rev()
+        .find(|revision| revision.saved_at <= when)
+}
+
+/// A point in the repository's past, for commands that look at it as it was then
+///
+/// Takes a date, meaning the end of that day, a local date and time, an RFC 3339
+/// timestamp, or how long ago, like 12h, 3d or 2w.
+pub fn parse_point_in_time(spec: &str) -> Result<DateTime<Utc>, KittyError> {
+    let invalid = || {
+        KittyError::PointInTime(format!(
+            "{} is not a date, time or age (expected e.g. 2025-03-03, \"2025-03-03 14:30\" or 3d)",
+            spec
+        ))
+    };
+    let local = |time: NaiveDateTime| {
+        Local
+            .from_local_datetime(&time)
+            .earliest()
+            .map(|t| t.with_timezone(&Utc))
+            .ok_or_else(invalid)
+    };
+
+    if let Ok(time) = DateTime::parse_from_rfc3339(spec) {
+        return Ok(time.with_timezone(&Utc));
+    }
+    for format in [
+        "%Y-%m-%d %H:%M:%S",
+        "%Y-%m-%d %H:%M",
+        "%Y-%m-%dT%H:%M:%S",
+        "%Y-%m-%dT%H:%M",
+    ] {
+        if let Ok(time) = NaiveDateTime::parse_from_str(spec, format) {
+            return local(time);
+        }
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(spec, "%Y-%m-%d") {
+        return local(date.and_hms_opt(23, 59, 59).ok_or_else(invalid)?);
+    }
+
+    let (count, unit) = spec.split_at(spec.find(|c: char| !c.is_ascii_digit()).unwrap_or(0));
+    let count: i64 = count.parse().map_err(|_| invalid())?;
+    let duration = match unit {
+        "h" => Duration::try_hours(count),
+        "d" => Duration::try_days(count),
+        "w" => Duration::try_weeks(count),
+        _ => None,
+    };
+    duration
+        .and_then(|d| Utc::now().checked_sub_signed(d))
+        .ok_or_else(invalid)
+}
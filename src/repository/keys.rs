@@ -0,0 +1,300 @@
+use crate::{
+    commands::init::{Crypto, KittyError, Repository, TrackedFile},
+    storage::is_database,
+    utils::{
+        file::{
+            get_repository_salt, get_storage_type, load_repository, read_manifest,
+            read_stored_files, write_atomic,
+        },
+        hash::digest_like,
+    },
+};
+use ring::{
+    rand::SystemRandom,
+    signature::{Ed25519KeyPair, KeyPair, UnparsedPublicKey, ED25519},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{fs, path::Path};
+
+/// Where the read key is kept, next to `salt.key`
+pub const KEYS_FILE: &str = "keys.json";
+
+/// Hex Ed25519 signature over the manifest, made with the admin's signing key
+pub const SIGNATURE_FILE: &str = "manifest.sig";
+
+/// The repository key wrapped with the read password, and the key that signs manifests
+///
+/// The admin password derives the repository key from `salt.key` as it always
+/// has, so it needs no entry here. The read password unlocks a copy of the
+/// same key, which decrypts everything but could also encrypt a manifest. So
+/// every manifest is signed with a key only the admin password unwraps, and
+/// kitty refuses manifests without that signature.
+#[derive(Serialize, Deserialize)]
+struct KeyFile {
+    read: WrappedKey,
+
+    /// Missing in repositories whose read key was made before manifests were signed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    signing: Option<SigningKey>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SigningKey {
+    /// Salt the admin password is derived with to unwrap the key, hex encoded
+    salt: String,
+
+    /// The encrypted PKCS#8 key pair, hex encoded
+    key: String,
+
+    /// Public key signatures are checked against, hex encoded
+    public: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WrappedKey {
+    /// Salt the read password is derived with, hex encoded
+    salt: String,
+
+    /// The encrypted repository key, hex encoded
+    key: String,
+}
+
+/// Whether the repository has a read key besides its admin password
+pub fn has_read_key(repo_path: &Path) -> bool {
+    repo_path.join(KEYS_FILE).exists()
+}
+
+/// Wrap the key `admin` holds with `read_password`, replacing any earlier read key
+///
+/// A new signing key is wrapped with `admin_password` alongside it, and the
+/// manifest as it is now is signed with it.
+pub fn write_read_key(
+    repo_path: &Path,
+    admin: &Crypto,
+    admin_password: &str,
+    read_password: &str,
+) -> Result<(), KittyError> {
+    admin.require_admin()?;
+    let wrapping = Crypto::new_from_password(read_password);
+
+    let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new())
+        .map_err(|_| KittyError::Encryption("could not generate a signing key".to_string()))?;
+    let signer = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref())
+        .map_err(|e| KittyError::Encryption(format!("invalid signing key: {}", e)))?;
+    let signing_wrapping = Crypto::new_from_password(admin_password);
+
+    let key_file = KeyFile {
+        read: WrappedKey {
+            salt: hex::encode(wrapping.salt()),
+            key: hex::encode(admin.wrap_key(&wrapping)?),
+        },
+        signing: Some(SigningKey {
+            salt: hex::encode(signing_wrapping.salt()),
+            key: hex::encode(signing_wrapping.encrypt(pkcs8.as_ref())?),
+            public: hex::encode(signer.public_key().as_ref()),
+        }),
+    };
+    write_atomic(
+        &repo_path.join(KEYS_FILE),
+        serde_json::to_string_pretty(&key_file)?,
+    )?;
+
+    sign_manifest(repo_path, &unlock(repo_path, admin_password)?)
+}
+
+/// The key for a password, read-only when it is the read password
+///
+/// A repository without a read key derives it straight from the password.
+/// With one, the key carries what manifests are signed with, and the admin
+/// password also unwraps the signing key.
+pub fn unlock(repo_path: &Path, password: &str) -> Result<Crypto, KittyError> {
+    let config_salt = hex::decode(get_repository_salt(repo_path)?.trim())?;
+
+    if !has_read_key(repo_path) {
+        return Ok(Crypto::from_password_and_salt(password, &config_salt));
+    }
+
+    let key_file: KeyFile = serde_json::from_slice(&fs::read(repo_path.join(KEYS_FILE))?)?;
+    let public = key_file
+        .signing
+        .as_ref()
+        .map(|signing| hex::decode(&signing.public))
+        .transpose()?;
+    let wrapping = Crypto::from_password_and_salt(password, &hex::decode(&key_file.read.salt)?);
+    // The key only decrypts with the read password; anything else may be the admin password
+    if let Ok(crypto) =
+        Crypto::unwrap_read_key(&hex::decode(&key_file.read.key)?, &wrapping, &config_salt)
+    {
+        return Ok(match public {
+            Some(public) => crypto.with_manifest_key(public, None),
+            None => crypto,
+        });
+    }
+
+    let crypto = Crypto::from_password_and_salt(password, &config_salt);
+    let (Some(signing), Some(public)) = (&key_file.signing, public) else {
+        return Ok(crypto);
+    };
+    let wrapping = Crypto::from_password_and_salt(password, &hex::decode(&signing.salt)?);
+    // Neither the read nor the admin password: no key in keys.json opens with it
+    let pkcs8 = wrapping
+        .decrypt(&hex::decode(&signing.key)?)
+        .map_err(|_| KittyError::InvalidPassword)?;
+    let signer = Ed25519KeyPair::from_pkcs8(&pkcs8)
+        .map_err(|e| KittyError::Decryption(format!("invalid signing key: {}", e)))?;
+    if signer.public_key().as_ref() != public.as_slice() {
+        return Err(KittyError::Signature(format!(
+            "the public key in {} is not the admin's",
+            KEYS_FILE
+        )));
+    }
+    Ok(crypto.with_manifest_key(public, Some(signer)))
+}
+
+/// The key for a command that changes the repository, refusing the read password
+pub fn unlock_admin(repo_path: &Path, password: &str) -> Result<Crypto, KittyError> {
+    let crypto = unlock(repo_path, password)?;
+    crypto.require_admin()?;
+    Ok(crypto)
+}
+
+/// Fail on a wrong password, on every backend
+pub fn verify_password(repo_path: &Path, crypto: &Crypto) -> Result<(), KittyError> {
+    let repository = load_repository(repo_path, crypto).map_err(|e| match e {
+        KittyError::Decryption(_) => KittyError::InvalidPassword,
+        e => e,
+    })?;
+    if !is_database(&get_storage_type(repo_path)?) {
+        // Loading the manifest of a file-based repository already decrypted it
+        return Ok(());
+    }
+
+    // Database manifests are not encrypted, so decrypt one stored file instead
+    let Some(file) = repository.files.first() else {
+        return Ok(());
+    };
+    let stored = read_stored_files(repo_path, &[file.repo_path.as_str()])?;
+    if let Some(encrypted) = stored.get(&file.repo_path) {
+        crypto
+            .decrypt(encrypted)
+            .map_err(|_| KittyError::InvalidPassword)?;
+    }
+    Ok(())
+}
+
+/// What a manifest signature covers: which content each path gets, and how it is put there
+///
+/// Built from the fields rather than the whole manifest, so the signature holds
+/// across backends and when later kittys add bookkeeping fields. Every field
+/// that changes what a command does with a file is in it, down to whether its
+/// drift is reported, when it expires and which revision a point in time picks.
+/// Times count in whole seconds, as not every backend keeps more.
+fn manifest_digest(repository: &Repository) -> Result<Vec<u8>, KittyError> {
+    let mut files: Vec<&TrackedFile> = repository.files.iter().collect();
+    files.sort_by(|a, b| a.original_path.cmp(&b.original_path));
+    let entries: Vec<Value> = files
+        .into_iter()
+        .map(|file| {
+            let mut versions: Vec<(&str, &str, i64)> = file
+                .versions
+                .iter()
+                .map(|v| {
+                    (
+                        v.repo_path.as_str(),
+                        v.hash.as_str(),
+                        v.saved_at.timestamp(),
+                    )
+                })
+                .collect();
+            versions.sort();
+            json!({
+                "path": file.original_path,
+                "content": file.repo_path,
+                "hash": file.hash,
+                "versions": versions,
+                "added_at": file.added_at.timestamp(),
+                "last_updated": file.last_updated.timestamp(),
+                "package": file.package,
+                "package_manager": file.package_manager,
+                "bundle": file.bundle,
+                "tags": file.tags,
+                "metadata": file.metadata,
+                "ignore_drift": file.ignore_drift,
+                "scope": file.scope,
+                "filters": file.filters,
+                "conditions": file.conditions,
+                "after": file.after,
+                "frozen": file.frozen,
+                "expires_at": file.expires_at.map(|t| t.timestamp()),
+                "tree": file.tree,
+            })
+        })
+        .collect();
+    Ok(serde_json::to_vec(&json!({
+        "salt": repository.salt,
+        "files": entries,
+    }))?)
+}
+
+/// Sign the manifest as it is stored now, for repositories that sign theirs
+pub fn sign_manifest(repo_path: &Path, crypto: &Crypto) -> Result<(), KittyError> {
+    if crypto.manifest_key().is_none() {
+        return Ok(());
+    }
+    let signer = crypto.signer().ok_or_else(|| {
+        KittyError::ReadOnly("only the admin password can sign the manifest".to_string())
+    })?;
+    let repository = read_manifest(repo_path, crypto)?;
+    let signature = signer.sign(&manifest_digest(&repository)?);
+    write_atomic(
+        &repo_path.join(SIGNATURE_FILE),
+        format!("{}\n", hex::encode(signature.as_ref())),
+    )?;
+    Ok(())
+}
+
+/// Fail unless the manifest carries the admin's signature, for repositories that sign theirs
+pub fn verify_manifest(
+    repo_path: &Path,
+    crypto: &Crypto,
+    repository: &Repository,
+) -> Result<(), KittyError> {
+    let Some(public) = crypto.manifest_key() else {
+        return Ok(());
+    };
+    let signature = fs::read_to_string(repo_path.join(SIGNATURE_FILE)).map_err(|_| {
+        KittyError::Signature(format!(
+            "the manifest is not signed; {} is missing",
+            SIGNATURE_FILE
+        ))
+    })?;
+    let signature = hex::decode(signature.trim())
+        .map_err(|_| KittyError::Signature(format!("{} is not a signature", SIGNATURE_FILE)))?;
+    UnparsedPublicKey::new(&ED25519, public)
+        .verify(&manifest_digest(repository)?, &signature)
+        .map_err(|_| {
+            KittyError::Signature(
+                "the manifest was changed without the admin password, possibly with the read password"
+                    .to_string(),
+            )
+        })
+}
+
+/// Fail when stored content is not what the signed manifest says it is
+///
+/// The read password can encrypt content too, so for repositories that sign
+/// their manifest, a file's content is only trusted if its hash matches.
+pub fn verify_content(
+    crypto: &Crypto,
+    file: &TrackedFile,
+    content: &[u8],
+) -> Result<(), KittyError> {
+    if crypto.manifest_key().is_none() || digest_like(&file.hash, content) == file.hash {
+        return Ok(());
+    }
+    Err(KittyError::Signature(format!(
+        "the stored content of {} does not match its signed hash",
+        file.original_path
+    )))
+}
@@ -1 +1,6 @@
 pub mod config;
+pub mod format;
+pub mod history;
+pub mod keys;
+pub mod salt;
+pub mod session;
@@ -0,0 +1,120 @@
+use crate::commands::init::Crypto;
+use std::{fs, path::Path};
+
+/// Length of the salt kitty derives keys from
+const SALT_LEN: usize = 32;
+
+/// Salt files written by early builds, before it settled on `salt.key`
+const LEGACY_SALT_FILES: [&str; 5] = ["salt", ".salt", "salt.txt", "salt.hex", "config.salt"];
+
+/// A place a repository from before `salt.key` may have kept its salt
+pub struct SaltCandidate {
+    /// Where the salt came from, for the user
+    pub source: String,
+    pub salt: Vec<u8>,
+
+    /// Bytes around the ciphertext in `config.enc` and the blobs, when the salt was stored there
+    pub prefix: usize,
+    pub suffix: usize,
+}
+
+impl SaltCandidate {
+    fn new(source: impl Into<String>, salt: Vec<u8>) -> Self {
+        Self {
+            source: source.into(),
+            salt,
+            prefix: 0,
+            suffix: 0,
+        }
+    }
+
+    /// Whether stored files carry the salt next to their ciphertext
+    pub fn is_embedded(&self) -> bool {
+        self.prefix > 0 || self.suffix > 0
+    }
+
+    /// The ciphertext of a stored file, without any salt around it
+    pub fn ciphertext<'a>(&self, data: &'a [u8]) -> Option<&'a [u8]> {
+        data.get(self.prefix..data.len().checked_sub(self.suffix)?)
+    }
+}
+
+/// A salt as kept in a file: hex, or the raw bytes
+fn parse_salt(contents: &[u8]) -> Option<Vec<u8>> {
+    let text = String::from_utf8_lossy(contents);
+    match hex::decode(text.trim()) {
+        Ok(salt) if salt.len() == SALT_LEN => Some(salt),
+        _ if contents.len() == SALT_LEN => Some(contents.to_vec()),
+        _ => None,
+    }
+}
+
+/// Every salt an older kitty may have used for a file-based repository, most likely first
+///
+/// `config` is the content of `config.enc`.
+pub fn candidates(repo_path: &Path, config: &[u8]) -> Vec<SaltCandidate> {
+    let mut candidates = Vec::new();
+
+    if let Ok(contents) = fs::read(repo_path.join("salt.key")) {
+        if let Some(salt) = parse_salt(&contents) {
+            candidates.push(SaltCandidate::new("salt.key", salt));
+        }
+        // A salt written as text and used without decoding the hex
+        let text = String::from_utf8_lossy(&contents);
+        if text.trim().len() >= SALT_LEN {
+            let salt = text.trim().as_bytes()[..SALT_LEN].to_vec();
+            candidates.push(SaltCandidate::new("salt.key, used as text", salt));
+        }
+    }
+    for name in LEGACY_SALT_FILES {
+        if let Some(salt) = fs::read(repo_path.join(name))
+            .ok()
+            .and_then(|c| parse_salt(&c))
+        {
+            candidates.push(SaltCandidate::new(name, salt));
+        }
+    }
+
+    // What `get_repository_salt` falls back to when there is no salt.key
+    candidates.push(SaltCandidate::new(
+        "the all-zero placeholder",
+        vec![0; SALT_LEN],
+    ));
+
+    // The salt stored in front of or behind the ciphertext, raw or in hex
+    if config.len() > 2 * SALT_LEN {
+        candidates.push(SaltCandidate {
+            prefix: SALT_LEN,
+            ..SaltCandidate::new("the start of config.enc", config[..SALT_LEN].to_vec())
+        });
+        candidates.push(SaltCandidate {
+            suffix: SALT_LEN,
+            ..SaltCandidate::new(
+                "the end of config.enc",
+                config[config.len() - SALT_LEN..].to_vec(),
+            )
+        });
+        if let Some(salt) = parse_salt(&config[..2 * SALT_LEN]) {
+            candidates.push(SaltCandidate {
+                prefix: 2 * SALT_LEN,
+                ..SaltCandidate::new("the start of config.enc, in hex", salt)
+            });
+        }
+    }
+
+    candidates
+}
+
+/// The first candidate that decrypts `config` with `password`, and the key it gives
+pub fn find(
+    candidates: Vec<SaltCandidate>,
+    password: &str,
+    config: &[u8],
+) -> Option<(SaltCandidate, Crypto)> {
+    candidates.into_iter().find_map(|candidate| {
+        let ciphertext = candidate.ciphertext(config)?;
+        let crypto = Crypto::from_password_and_salt(password, &candidate.salt);
+        crypto.decrypt(ciphertext).ok()?;
+        Some((candidate, crypto))
+    })
+}
@@ -0,0 +1,188 @@
+use crate::{
+    commands::{
+        init::{Crypto, KittyError, Repository, TrackedFile},
+        trash::move_to_trash,
+    },
+    repository::keys::{sign_manifest, verify_manifest},
+    storage::{is_database, open_database, Storage},
+    utils::{
+        file::{
+            get_storage_type, link_stored, load_repository, read_stored, write_config, write_stored,
+        },
+        lock::RepoLock,
+    },
+};
+use std::path::{Path, PathBuf};
+
+/// A locked repository with its manifest in memory, for commands that change it in several steps
+///
+/// Storage is opened and the manifest decrypted once, however many files the
+/// command touches. Changes to `repository`, content staged with
+/// [`RepoSession::put_file`] and files untracked with [`RepoSession::untrack`]
+/// are only written by [`RepoSession::commit`].
+pub struct RepoSession {
+    pub repo_path: PathBuf,
+    pub crypto: Crypto,
+    pub repository: Repository,
+
+    /// Whether the repository keeps everything in a database
+    database: bool,
+
+    /// The open database, released after every commit
+    storage: Option<Box<dyn Storage>>,
+
+    /// Encrypted content to store on commit, keyed by repo path
+    staged: Vec<(String, Vec<u8>)>,
+
+    /// Repo paths to store as links to content already stored under another, on commit
+    linked: Vec<(String, String)>,
+
+    /// Untracked files whose content goes to the trash on commit
+    trashed: Vec<TrackedFile>,
+
+    lock: Option<RepoLock>,
+}
+
+impl RepoSession {
+    /// Lock the repository and load its manifest, which the read key may not change
+    pub fn open(repo_path: &Path, crypto: Crypto) -> Result<Self, KittyError> {
+        crypto.require_admin()?;
+        let lock = RepoLock::acquire(repo_path)?;
+        let database = is_database(&get_storage_type(repo_path)?);
+        let (storage, repository) = if database {
+            let storage = open_database(repo_path)?;
+            let repository = storage.load_repository()?;
+            verify_manifest(repo_path, &crypto, &repository)?;
+            (Some(storage), repository)
+        } else {
+            (None, load_repository(repo_path, &crypto)?)
+        };
+
+        Ok(Self {
+            repo_path: repo_path.to_path_buf(),
+            crypto,
+            repository,
+            database,
+            storage,
+            staged: Vec::new(),
+            linked: Vec::new(),
+            trashed: Vec::new(),
+            lock: Some(lock),
+        })
+    }
+
+    pub fn uses_database(&self) -> bool {
+        self.database
+    }
+
+    /// The database, opened again if a commit released it
+    fn storage(&mut self) -> Result<&mut Box<dyn Storage>, KittyError> {
+        let storage = match self.storage.take() {
+            Some(storage) => storage,
+            None => open_database(&self.repo_path)?,
+        };
+        Ok(self.storage.insert(storage))
+    }
+
+    /// Encrypted content stored under a repo path, including content staged in this session
+    pub fn get_file(&mut self, path: &str) -> Result<Vec<u8>, KittyError> {
+        if let Some((_, content)) = self.staged.iter().rev().find(|(staged, _)| staged == path) {
+            return Ok(content.clone());
+        }
+        if let Some((_, existing)) = self.linked.iter().rev().find(|(linked, _)| linked == path) {
+            let existing = existing.clone();
+            return self.get_file(&existing);
+        }
+        if self.database {
+            self.storage()?.get_file(path)
+        } else {
+            Ok(read_stored(&self.repo_path.join(path))?)
+        }
+    }
+
+    /// Whether content is stored or staged under a repo path, for the file backend
+    pub fn has_file(&self, path: &str) -> bool {
+        self.staged.iter().any(|(staged, _)| staged == path)
+            || self.linked.iter().any(|(linked, _)| linked == path)
+            || self.repo_path.join(path).exists()
+    }
+
+    /// Store encrypted content under a repo path when the session is committed
+    pub fn put_file(&mut self, path: &str, encrypted: Vec<u8>) {
+        self.staged.push((path.to_string(), encrypted));
+    }
+
+    /// Store the content already stored under `existing` under `path` too, when the session is committed
+    ///
+    /// Files share one blob through a hard link; databases store the content again.
+    pub fn link_file(&mut self, path: &str, existing: &str) -> Result<(), KittyError> {
+        if self.database {
+            let content = self.get_file(existing)?;
+            self.put_file(path, content);
+        } else {
+            self.linked.push((path.to_string(), existing.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Stop tracking a file, moving its content to the trash when the session is committed
+    pub fn untrack(&mut self, index: usize) -> TrackedFile {
+        let file = self.repository.files.remove(index);
+        self.trashed.push(file.clone());
+        file
+    }
+
+    /// Write the staged content and the manifest
+    ///
+    /// Files get their new content before the manifest that points at it is
+    /// saved. Databases save the manifest first, as content is attached to
+    /// the rows it creates. The session can go on and commit again.
+    ///
+    /// The database is closed afterwards, as redb allows only one open handle
+    /// and what follows a commit, like recording statistics, opens its own.
+    pub fn commit(&mut self) -> Result<(), KittyError> {
+        let staged = std::mem::take(&mut self.staged);
+        let linked = std::mem::take(&mut self.linked);
+        let trashed = std::mem::take(&mut self.trashed);
+        if self.database {
+            // Databases drop content nothing refers to on saving, so it goes to the trash first
+            if !trashed.is_empty() {
+                self.storage = None;
+                for file in trashed {
+                    move_to_trash(&self.repo_path, &self.crypto, file)?;
+                }
+            }
+            let mut storage = match self.storage.take() {
+                Some(storage) => storage,
+                None => open_database(&self.repo_path)?,
+            };
+            storage.save_repository(&self.repository)?;
+            for (path, content) in &staged {
+                storage.save_file(path, content)?;
+            }
+            // Signing reads the manifest back, which needs the only redb handle
+            drop(storage);
+            sign_manifest(&self.repo_path, &self.crypto)?;
+        } else {
+            for (path, content) in &staged {
+                write_stored(&self.repo_path.join(path), content)?;
+            }
+            // After the writes, as a link may point at content staged in this session
+            for (path, existing) in &linked {
+                link_stored(&self.repo_path.join(existing), &self.repo_path.join(path))?;
+            }
+            write_config(&self.repo_path, &self.crypto, &self.repository)?;
+            // Blobs are only let go of once the saved manifest no longer refers to them
+            for file in trashed {
+                move_to_trash(&self.repo_path, &self.crypto, file)?;
+            }
+        }
+        self.repository.generation = self.repository.next_generation();
+        Ok(())
+    }
+
+    /// Let other kittys change the repository again, e.g. before running hooks that may call kitty
+    pub fn unlock(&mut self) {
+        self.lock = None;
+    }
+}
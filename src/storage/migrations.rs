@@ -0,0 +1,291 @@
+use crate::commands::init::KittyError;
+use chrono::Utc;
+use rusqlite::{params, Connection};
+
+/// A single step in the evolution of the SQLite schema
+struct Migration {
+    version: i64,
+    description: &'static str,
+    apply: fn(&Connection) -> Result<(), KittyError>,
+}
+
+/// Every schema change, in the order it was introduced.
+///
+/// Databases created before migrations were tracked have no record of which
+/// steps ran, so each step must be safe to run against a schema that already
+/// contains its changes.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "create repository and files tables",
+        apply: create_base_tables,
+    },
+    Migration {
+        version: 2,
+        description: "record the package owning each file",
+        apply: add_package_columns,
+    },
+    Migration {
+        version: 3,
+        description: "keep previous versions of files",
+        apply: create_versions_table,
+    },
+    Migration {
+        version: 4,
+        description: "group files into bundles and tag them",
+        apply: add_label_columns,
+    },
+    Migration {
+        version: 5,
+        description: "order files during restore",
+        apply: add_after_column,
+    },
+    Migration {
+        version: 6,
+        description: "record file mode and ownership",
+        apply: add_metadata_column,
+    },
+    Migration {
+        version: 7,
+        description: "pin files that differ per machine",
+        apply: add_ignore_drift_column,
+    },
+    Migration {
+        version: 8,
+        description: "filter volatile and sensitive content",
+        apply: add_filters_column,
+    },
+    Migration {
+        version: 9,
+        description: "restrict files to platforms",
+        apply: add_conditions_column,
+    },
+    Migration {
+        version: 10,
+        description: "count changes to the repository and its files",
+        apply: add_generation_columns,
+    },
+    Migration {
+        version: 11,
+        description: "look up files by path",
+        apply: add_path_indexes,
+    },
+    Migration {
+        version: 12,
+        description: "expire temporary files",
+        apply: add_expires_at_column,
+    },
+    Migration {
+        version: 13,
+        description: "freeze live files",
+        apply: add_frozen_column,
+    },
+    Migration {
+        version: 14,
+        description: "separate system and user files",
+        apply: add_scope_column,
+    },
+    Migration {
+        version: 15,
+        description: "track directory trees",
+        apply: add_tree_column,
+    },
+    Migration {
+        version: 16,
+        description: "record when the password was set",
+        apply: add_password_set_at_column,
+    },
+];
+
+/// Schema version recorded in the database (0 if none was ever recorded)
+pub fn current_version(conn: &Connection) -> Result<i64, KittyError> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS schema_migrations (
+            version INTEGER PRIMARY KEY,
+            description TEXT NOT NULL,
+            applied_at TEXT NOT NULL
+        )",
+        [],
+    )
+    .map_err(|e| KittyError::Database(e.to_string()))?;
+
+    conn.query_row(
+        "SELECT COALESCE(MAX(version), 0) FROM schema_migrations",
+        [],
+        |row| row.get(0),
+    )
+    .map_err(|e| KittyError::Database(e.to_string()))
+}
+
+/// Apply every migration newer than the database's schema version
+pub fn run_migrations(conn: &mut Connection) -> Result<(), KittyError> {
+    let current = current_version(conn)?;
+
+    for migration in MIGRATIONS.iter().filter(|m| m.version > current) {
+        // Each step and its record are committed together
+        let tx = conn
+            .transaction()
+            .map_err(|e| KittyError::Database(e.to_string()))?;
+
+        (migration.apply)(&tx)?;
+
+        tx.execute(
+            "INSERT INTO schema_migrations (version, description, applied_at) VALUES (?1, ?2, ?3)",
+            params![
+                migration.version,
+                migration.description,
+                Utc::now().to_rfc3339()
+            ],
+        )
+        .map_err(|e| KittyError::Database(e.to_string()))?;
+
+        tx.commit()
+            .map_err(|e| KittyError::Database(e.to_string()))?;
+    }
+
+    Ok(())
+}
+
+fn create_base_tables(conn: &Connection) -> Result<(), KittyError> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS repository (
+            id INTEGER PRIMARY KEY,
+            created_at TEXT NOT NULL,
+            salt TEXT NOT NULL
+        )",
+        [],
+    )
+    .map_err(|e| KittyError::Database(e.to_string()))?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS files (
+            id INTEGER PRIMARY KEY,
+            original_path TEXT NOT NULL,
+            repo_path TEXT NOT NULL,
+            added_at TEXT NOT NULL,
+            last_updated TEXT NOT NULL,
+            hash TEXT NOT NULL,
+            content BLOB
+        )",
+        [],
+    )
+    .map_err(|e| KittyError::Database(e.to_string()))?;
+
+    Ok(())
+}
+
+fn add_package_columns(conn: &Connection) -> Result<(), KittyError> {
+    ensure_column(conn, "files", "package", "TEXT")?;
+    ensure_column(conn, "files", "package_manager", "TEXT")
+}
+
+fn create_versions_table(conn: &Connection) -> Result<(), KittyError> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS versions (
+            id INTEGER PRIMARY KEY,
+            file_repo_path TEXT NOT NULL,
+            repo_path TEXT NOT NULL,
+            hash TEXT NOT NULL,
+            saved_at TEXT NOT NULL,
+            content BLOB
+        )",
+        [],
+    )
+    .map_err(|e| KittyError::Database(e.to_string()))?;
+
+    Ok(())
+}
+
+fn add_label_columns(conn: &Connection) -> Result<(), KittyError> {
+    ensure_column(conn, "files", "bundle", "TEXT")?;
+    ensure_column(conn, "files", "tags", "TEXT")
+}
+
+fn add_after_column(conn: &Connection) -> Result<(), KittyError> {
+    ensure_column(conn, "files", "after", "TEXT")
+}
+
+fn add_metadata_column(conn: &Connection) -> Result<(), KittyError> {
+    ensure_column(conn, "files", "metadata", "TEXT")
+}
+
+fn add_ignore_drift_column(conn: &Connection) -> Result<(), KittyError> {
+    ensure_column(conn, "files", "ignore_drift", "INTEGER NOT NULL DEFAULT 0")
+}
+
+fn add_filters_column(conn: &Connection) -> Result<(), KittyError> {
+    ensure_column(conn, "files", "filters", "TEXT")
+}
+
+fn add_conditions_column(conn: &Connection) -> Result<(), KittyError> {
+    ensure_column(conn, "files", "conditions", "TEXT")
+}
+
+fn add_generation_columns(conn: &Connection) -> Result<(), KittyError> {
+    ensure_column(
+        conn,
+        "repository",
+        "generation",
+        "INTEGER NOT NULL DEFAULT 0",
+    )?;
+    ensure_column(conn, "files", "revision", "INTEGER NOT NULL DEFAULT 0")
+}
+
+fn add_path_indexes(conn: &Connection) -> Result<(), KittyError> {
+    conn.execute_batch(
+        "CREATE INDEX IF NOT EXISTS idx_files_original_path ON files (original_path);
+         CREATE INDEX IF NOT EXISTS idx_versions_file_repo_path ON versions (file_repo_path);",
+    )
+    .map_err(|e| KittyError::Database(e.to_string()))
+}
+
+fn add_expires_at_column(conn: &Connection) -> Result<(), KittyError> {
+    ensure_column(conn, "files", "expires_at", "TEXT")
+}
+
+fn add_frozen_column(conn: &Connection) -> Result<(), KittyError> {
+    ensure_column(conn, "files", "frozen", "INTEGER NOT NULL DEFAULT 0")
+}
+
+fn add_scope_column(conn: &Connection) -> Result<(), KittyError> {
+    ensure_column(conn, "files", "scope", "TEXT")
+}
+
+fn add_tree_column(conn: &Connection) -> Result<(), KittyError> {
+    ensure_column(conn, "files", "tree", "TEXT")
+}
+
+fn add_password_set_at_column(conn: &Connection) -> Result<(), KittyError> {
+    ensure_column(conn, "repository", "password_set_at", "TEXT")
+}
+
+/// Add a column to an existing table if it is missing
+fn ensure_column(
+    conn: &Connection,
+    table: &str,
+    column: &str,
+    column_type: &str,
+) -> Result<(), KittyError> {
+    let mut stmt = conn
+        .prepare(&format!("PRAGMA table_info({})", table))
+        .map_err(|e| KittyError::Database(e.to_string()))?;
+
+    let exists = stmt
+        .query_map([], |row| row.get::<_, String>(1))
+        .map_err(|e| KittyError::Database(e.to_string()))?
+        .filter_map(|name| name.ok())
+        .any(|name| name == column);
+
+    if !exists {
+        conn.execute(
+            &format!(
+                "ALTER TABLE {} ADD COLUMN {} {}",
+                table, column, column_type
+            ),
+            [],
+        )
+        .map_err(|e| KittyError::Database(e.to_string()))?;
+    }
+
+    Ok(())
+}
@@ -1,2 +1,155 @@
 pub mod memory;
-pub mod sqlite;
\ No newline at end of file
+pub mod migrations;
+pub mod redb;
+pub mod sqlite;
+
+use crate::{
+    commands::init::{KittyError, Repository, TrackedFile},
+    repository::format::raise_format,
+    utils::{
+        file::get_storage_type,
+        timings::{self, Phase},
+    },
+};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+/// Which tracked files to load, for commands that only touch some of them
+pub enum ManifestFilter {
+    /// Files tracked under one of these exact paths
+    Paths(Vec<String>),
+
+    /// Files whose tracked path starts with this, e.g. the literal start of a glob
+    Prefix(String),
+}
+
+impl ManifestFilter {
+    pub fn matches(&self, original_path: &str) -> bool {
+        match self {
+            ManifestFilter::Paths(paths) => paths.iter().any(|path| path == original_path),
+            ManifestFilter::Prefix(prefix) => original_path.starts_with(prefix.as_str()),
+        }
+    }
+}
+
+/// A database backend holding the repository manifest and encrypted file content
+pub trait Storage {
+    /// Save repository information
+    fn save_repository(&mut self, repository: &Repository) -> Result<(), KittyError>;
+
+    /// Load repository information
+    fn load_repository(&self) -> Result<Repository, KittyError>;
+
+    /// Load only the tracked files a filter matches, with their versions
+    fn load_files(&self, filter: &ManifestFilter) -> Result<Vec<TrackedFile>, KittyError> {
+        let mut files = self.load_repository()?.files;
+        files.retain(|f| filter.matches(&f.original_path));
+        Ok(files)
+    }
+
+    /// Generation of the saved manifest, bumped on every save
+    fn generation(&self) -> Result<u64, KittyError>;
+
+    /// Size in bytes of the stored content for a file or version
+    fn content_size(&self, path: &str) -> Result<u64, KittyError>;
+
+    /// Get the salt from the repository
+    fn get_salt(&self) -> Result<String, KittyError>;
+
+    /// Save an encrypted file to the repository
+    fn save_file(&self, path: &str, encrypted_data: &[u8]) -> Result<(), KittyError>;
+
+    /// Get an encrypted file from the repository
+    fn get_file(&self, path: &str) -> Result<Vec<u8>, KittyError>;
+
+    /// Get the encrypted content of many files at once, keyed by path
+    ///
+    /// Paths without content are left out; `get_file` tells why.
+    fn get_files(&self, paths: &[&str]) -> Result<HashMap<String, Vec<u8>>, KittyError> {
+        Ok(paths
+            .iter()
+            .filter_map(|path| Some((path.to_string(), self.get_file(path).ok()?)))
+            .collect())
+    }
+}
+
+/// Returns true for storage types that keep everything in a database rather than `files/`
+pub fn is_database(storage_type: &str) -> bool {
+    storage_type != "file"
+}
+
+/// Open the database backend of a repository that uses one
+pub fn open_database(repo_path: &Path) -> Result<Box<dyn Storage>, KittyError> {
+    let storage: Box<dyn Storage> = match get_storage_type(repo_path)?.as_str() {
+        "sqlite" => Box::new(timings::time(Phase::StorageIo, || {
+            sqlite::SqliteStorage::new(repo_path)
+        })?),
+        "redb" => Box::new(timings::time(Phase::StorageIo, || {
+            redb::RedbStorage::new(repo_path)
+        })?),
+        other => {
+            return Err(KittyError::StorageType(format!(
+                "Storage type {} does not use a database",
+                other
+            )))
+        }
+    };
+    Ok(Box::new(Timed {
+        storage,
+        repo_path: repo_path.to_path_buf(),
+    }))
+}
+
+/// A repository's backend, whose calls count as storage IO for `--timings`
+///
+/// Saving also raises the repository to the current format, as the saved
+/// manifest is in it.
+struct Timed {
+    storage: Box<dyn Storage>,
+    repo_path: PathBuf,
+}
+
+impl Storage for Timed {
+    fn save_repository(&mut self, repository: &Repository) -> Result<(), KittyError> {
+        raise_format(&self.repo_path)?;
+        timings::time(Phase::StorageIo, || {
+            self.storage.save_repository(repository)
+        })
+    }
+
+    fn load_repository(&self) -> Result<Repository, KittyError> {
+        timings::time(Phase::StorageIo, || self.storage.load_repository())
+    }
+
+    fn load_files(&self, filter: &ManifestFilter) -> Result<Vec<TrackedFile>, KittyError> {
+        timings::time(Phase::StorageIo, || self.storage.load_files(filter))
+    }
+
+    fn generation(&self) -> Result<u64, KittyError> {
+        timings::time(Phase::StorageIo, || self.storage.generation())
+    }
+
+    fn content_size(&self, path: &str) -> Result<u64, KittyError> {
+        timings::time(Phase::StorageIo, || self.storage.content_size(path))
+    }
+
+    fn get_salt(&self) -> Result<String, KittyError> {
+        timings::time(Phase::StorageIo, || self.storage.get_salt())
+    }
+
+    fn save_file(&self, path: &str, encrypted_data: &[u8]) -> Result<(), KittyError> {
+        timings::time(Phase::StorageIo, || {
+            self.storage.save_file(path, encrypted_data)
+        })
+    }
+
+    fn get_file(&self, path: &str) -> Result<Vec<u8>, KittyError> {
+        timings::time(Phase::StorageIo, || self.storage.get_file(path))
+    }
+
+    fn get_files(&self, paths: &[&str]) -> Result<HashMap<String, Vec<u8>>, KittyError> {
+        timings::time(Phase::StorageIo, || self.storage.get_files(paths))
+    }
+}
@@ -0,0 +1,240 @@
+use crate::{
+    commands::init::{KittyError, Repository, TrackedFile},
+    storage::{ManifestFilter, Storage},
+};
+use chrono::{DateTime, Utc};
+use redb::{Database, ReadableTable, TableDefinition};
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+
+/// Repository information: created_at, salt and generation
+const REPOSITORY: TableDefinition<&str, &str> = TableDefinition::new("repository");
+
+/// Tracked file metadata as JSON, keyed by position so the manifest order is kept
+const FILES: TableDefinition<u64, &[u8]> = TableDefinition::new("files");
+
+/// Encrypted content of files and their previous versions, keyed by repo_path
+const CONTENT: TableDefinition<&str, &[u8]> = TableDefinition::new("content");
+
+fn db_error(e: impl std::fmt::Display) -> KittyError {
+    KittyError::Database(e.to_string())
+}
+
+/// Generation recorded in the repository table; databases from before it was kept have none
+fn read_generation(
+    info: &impl ReadableTable<&'static str, &'static str>,
+) -> Result<u64, KittyError> {
+    Ok(info
+        .get("generation")
+        .map_err(db_error)?
+        .and_then(|generation| generation.value().parse().ok())
+        .unwrap_or(0))
+}
+
+/// Pure-Rust embedded storage for the kitty repository, built on redb
+pub struct RedbStorage {
+    database: Database,
+}
+
+impl RedbStorage {
+    /// Create a new redb storage
+    pub fn new(repo_path: &Path) -> Result<Self, KittyError> {
+        let database = Database::create(repo_path.join("kitty.redb")).map_err(db_error)?;
+
+        // Create the tables up front so read transactions never find them missing
+        let tx = database.begin_write().map_err(db_error)?;
+        tx.open_table(REPOSITORY).map_err(db_error)?;
+        tx.open_table(FILES).map_err(db_error)?;
+        tx.open_table(CONTENT).map_err(db_error)?;
+        tx.commit().map_err(db_error)?;
+
+        Ok(Self { database })
+    }
+}
+
+impl Storage for RedbStorage {
+    fn save_repository(&mut self, repository: &Repository) -> Result<(), KittyError> {
+        let tx = self.database.begin_write().map_err(db_error)?;
+        {
+            let mut info = tx.open_table(REPOSITORY).map_err(db_error)?;
+            let mut files = tx.open_table(FILES).map_err(db_error)?;
+
+            // Never overwrite a manifest saved since this one was read
+            let saved = read_generation(&info)?;
+            repository.check_saved(saved, |since| {
+                files
+                    .iter()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|entry| entry.ok())
+                    .filter_map(|(_, metadata)| {
+                        serde_json::from_slice::<TrackedFile>(metadata.value()).ok()
+                    })
+                    .filter(|file| file.revision > since)
+                    .map(|file| file.original_path)
+                    .collect()
+            })?;
+
+            info.insert("created_at", repository.created_at.to_rfc3339().as_str())
+                .map_err(db_error)?;
+            info.insert("salt", repository.salt.as_str())
+                .map_err(db_error)?;
+            info.insert(
+                "generation",
+                repository.next_generation().to_string().as_str(),
+            )
+            .map_err(db_error)?;
+            if let Some(password_set_at) = repository.password_set_at {
+                info.insert("password_set_at", password_set_at.to_rfc3339().as_str())
+                    .map_err(db_error)?;
+            }
+
+            files.retain(|_, _| false).map_err(db_error)?;
+            for (index, file) in repository.files.iter().enumerate() {
+                let metadata = serde_json::to_vec(file)?;
+                files
+                    .insert(index as u64, metadata.as_slice())
+                    .map_err(db_error)?;
+            }
+
+            // Content of files and versions no longer in the manifest is dropped
+            let referenced: HashSet<&str> = repository
+                .files
+                .iter()
+                .flat_map(|f| {
+                    std::iter::once(f.repo_path.as_str())
+                        .chain(f.versions.iter().map(|v| v.repo_path.as_str()))
+                })
+                .collect();
+            let mut content = tx.open_table(CONTENT).map_err(db_error)?;
+            content
+                .retain(|path, _| referenced.contains(path))
+                .map_err(db_error)?;
+        }
+        tx.commit().map_err(db_error)?;
+
+        Ok(())
+    }
+
+    fn load_repository(&self) -> Result<Repository, KittyError> {
+        let tx = self.database.begin_read().map_err(db_error)?;
+
+        let info = tx.open_table(REPOSITORY).map_err(db_error)?;
+        let created_at = info
+            .get("created_at")
+            .map_err(db_error)?
+            .ok_or(KittyError::RepositoryNotFound)?;
+        let created_at = DateTime::parse_from_rfc3339(created_at.value())
+            .map_err(db_error)?
+            .with_timezone(&Utc);
+        let salt = self.get_salt()?;
+        let generation = read_generation(&info)?;
+        let password_set_at = info
+            .get("password_set_at")
+            .map_err(db_error)?
+            .and_then(|value| DateTime::parse_from_rfc3339(value.value()).ok())
+            .map(|t| t.with_timezone(&Utc));
+
+        let table = tx.open_table(FILES).map_err(db_error)?;
+        let mut files = Vec::new();
+        for entry in table.iter().map_err(db_error)? {
+            let (_, metadata) = entry.map_err(db_error)?;
+            let file: TrackedFile = serde_json::from_slice(metadata.value())?;
+            files.push(file);
+        }
+
+        Ok(Repository {
+            created_at,
+            salt,
+            files,
+            generation,
+            password_set_at,
+        })
+    }
+
+    fn load_files(&self, filter: &ManifestFilter) -> Result<Vec<TrackedFile>, KittyError> {
+        // Only the path is read from entries the filter rejects
+        #[derive(serde::Deserialize)]
+        struct Entry {
+            original_path: String,
+        }
+
+        let tx = self.database.begin_read().map_err(db_error)?;
+        let table = tx.open_table(FILES).map_err(db_error)?;
+        let mut files = Vec::new();
+        for entry in table.iter().map_err(db_error)? {
+            let (_, metadata) = entry.map_err(db_error)?;
+            let path: Entry = serde_json::from_slice(metadata.value())?;
+            if filter.matches(&path.original_path) {
+                files.push(serde_json::from_slice(metadata.value())?);
+            }
+        }
+
+        Ok(files)
+    }
+
+    fn generation(&self) -> Result<u64, KittyError> {
+        let tx = self.database.begin_read().map_err(db_error)?;
+        read_generation(&tx.open_table(REPOSITORY).map_err(db_error)?)
+    }
+
+    fn content_size(&self, path: &str) -> Result<u64, KittyError> {
+        let tx = self.database.begin_read().map_err(db_error)?;
+        let content = tx.open_table(CONTENT).map_err(db_error)?;
+        let size = content
+            .get(path)
+            .map_err(db_error)?
+            .map(|data| data.value().len() as u64)
+            .unwrap_or(0);
+
+        Ok(size)
+    }
+
+    fn get_salt(&self) -> Result<String, KittyError> {
+        let tx = self.database.begin_read().map_err(db_error)?;
+        let info = tx.open_table(REPOSITORY).map_err(db_error)?;
+        let salt = info
+            .get("salt")
+            .map_err(db_error)?
+            .ok_or(KittyError::RepositoryNotFound)?;
+
+        Ok(salt.value().to_string())
+    }
+
+    fn save_file(&self, path: &str, encrypted_data: &[u8]) -> Result<(), KittyError> {
+        let tx = self.database.begin_write().map_err(db_error)?;
+        {
+            let mut content = tx.open_table(CONTENT).map_err(db_error)?;
+            content.insert(path, encrypted_data).map_err(db_error)?;
+        }
+        tx.commit().map_err(db_error)?;
+
+        Ok(())
+    }
+
+    fn get_file(&self, path: &str) -> Result<Vec<u8>, KittyError> {
+        let tx = self.database.begin_read().map_err(db_error)?;
+        let content = tx.open_table(CONTENT).map_err(db_error)?;
+        let data = content
+            .get(path)
+            .map_err(db_error)?
+            .ok_or_else(|| KittyError::FileNotTracked(path.to_string()))?;
+
+        Ok(data.value().to_vec())
+    }
+
+    fn get_files(&self, paths: &[&str]) -> Result<HashMap<String, Vec<u8>>, KittyError> {
+        let tx = self.database.begin_read().map_err(db_error)?;
+        let content = tx.open_table(CONTENT).map_err(db_error)?;
+        let mut files = HashMap::with_capacity(paths.len());
+        for path in paths {
+            if let Some(data) = content.get(*path).map_err(db_error)? {
+                files.insert(path.to_string(), data.value().to_vec());
+            }
+        }
+
+        Ok(files)
+    }
+}
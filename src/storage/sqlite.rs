@@ -1,7 +1,62 @@
-use crate::commands::init::{KittyError, Repository, TrackedFile};
+use crate::{
+    commands::init::{FileMetadata, FileVersion, KittyError, Repository, Scope, TrackedFile},
+    storage::{migrations::run_migrations, ManifestFilter, Storage},
+    utils::platform::Conditions,
+};
 use chrono::{DateTime, Utc};
-use rusqlite::{params, types::Type, Connection};
-use std::path::Path;
+use rusqlite::{params, types::Type, Connection, ToSql};
+use serde::de::DeserializeOwned;
+use std::{collections::HashMap, path::Path};
+
+/// Paths looked up per query by `get_files`, well below SQLite's limit on parameters
+const BATCH_SIZE: usize = 500;
+
+/// Tags, ordering constraints and filters are stored as JSON arrays; rows written
+/// before those columns existed have none
+fn parse_list<T: DeserializeOwned>(value: Option<String>) -> Vec<T> {
+    value
+        .and_then(|v| serde_json::from_str(&v).ok())
+        .unwrap_or_default()
+}
+
+/// Platform conditions are stored as JSON; files from before they existed apply everywhere
+fn parse_conditions(value: Option<String>) -> Conditions {
+    value
+        .and_then(|v| serde_json::from_str(&v).ok())
+        .unwrap_or_default()
+}
+
+/// Mode and ownership are stored as JSON; files added before they were recorded have none
+fn parse_metadata(value: Option<String>) -> Option<FileMetadata> {
+    value.and_then(|v| serde_json::from_str(&v).ok())
+}
+
+/// Optional times, such as expiries, are stored as RFC 3339 text or NULL
+fn parse_time_opt(value: Option<String>) -> Option<DateTime<Utc>> {
+    value
+        .and_then(|v| DateTime::parse_from_rfc3339(&v).ok())
+        .map(|t| t.with_timezone(&Utc))
+}
+
+/// Entries from before scopes were recorded have none, and take the one their path implies
+fn parse_scope(value: Option<String>) -> Option<Scope> {
+    match value.as_deref() {
+        Some("system") => Some(Scope::System),
+        Some("user") => Some(Scope::User),
+        _ => None,
+    }
+}
+
+/// Paths of files changed in a later generation than `generation`
+fn changed_since(connection: &Connection, generation: u64) -> Vec<String> {
+    let Ok(mut stmt) = connection.prepare("SELECT original_path FROM files WHERE revision > ?1")
+    else {
+        return Vec::new();
+    };
+    stmt.query_map(params![generation as i64], |row| row.get(0))
+        .map(|rows| rows.filter_map(|row| row.ok()).collect())
+        .unwrap_or_default()
+}
 
 /// SQLite storage for the kitty repository
 pub struct SqliteStorage {
@@ -11,65 +66,332 @@ pub struct SqliteStorage {
 impl SqliteStorage {
     /// Create a new SQLite storage
     pub fn new(repo_path: &Path) -> Result<Self, KittyError> {
-        let db_path = repo_path.join("kitty.db");
-        let connection =
+        Self::open(&repo_path.join("kitty.db"))
+    }
+
+    /// Open a kitty database file directly
+    pub fn open(db_path: &Path) -> Result<Self, KittyError> {
+        let mut connection =
             Connection::open(db_path).map_err(|e| KittyError::Database(e.to_string()))?;
 
-        // Initialize the database if needed
-        Self::initialize_db(&connection)?;
+        // Zero deleted content instead of leaving it in free pages
+        connection
+            .pragma_update(None, "secure_delete", "ON")
+            .map_err(|e| KittyError::Database(e.to_string()))?;
+
+        // Bring the schema up to date
+        run_migrations(&mut connection)?;
 
         Ok(Self { connection })
     }
 
-    /// Initialize the database schema
-    fn initialize_db(conn: &Connection) -> Result<(), KittyError> {
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS repository (
-                id INTEGER PRIMARY KEY,
-                created_at TEXT NOT NULL,
-                salt TEXT NOT NULL
-            )",
-            [],
-        )
-        .map_err(|e| KittyError::Database(e.to_string()))?;
+    /// Size of the database file in bytes
+    pub fn database_size(&self) -> Result<u64, KittyError> {
+        let path = self
+            .connection
+            .path()
+            .ok_or_else(|| KittyError::Database("Database has no file path".to_string()))?;
+        Ok(std::fs::metadata(path)?.len())
+    }
 
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS files (
-                id INTEGER PRIMARY KEY,
-                original_path TEXT NOT NULL,
-                repo_path TEXT NOT NULL,
-                added_at TEXT NOT NULL,
-                last_updated TEXT NOT NULL,
-                hash TEXT NOT NULL,
-                content BLOB
-            )",
-            [],
-        )
-        .map_err(|e| {
-            KittyError::Io(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                e.to_string(),
-            ))
-        })?;
+    /// Rebuild the database file, releasing space left by deleted rows
+    pub fn vacuum(&self) -> Result<(), KittyError> {
+        self.connection
+            .execute_batch("VACUUM")
+            .map_err(|e| KittyError::Database(e.to_string()))
+    }
 
+    /// Write a consistent copy of the database to `path`, which must not exist yet
+    pub fn backup_to(&self, path: &Path) -> Result<(), KittyError> {
+        self.connection
+            .execute("VACUUM INTO ?1", params![path.to_string_lossy()])
+            .map_err(|e| KittyError::Database(e.to_string()))?;
         Ok(())
     }
 
+    /// Run SQLite's integrity check, returning the problems found (empty when healthy)
+    pub fn integrity_check(&self) -> Result<Vec<String>, KittyError> {
+        let mut stmt = self
+            .connection
+            .prepare("PRAGMA integrity_check")
+            .map_err(|e| KittyError::Database(e.to_string()))?;
+
+        let messages = stmt
+            .query_map([], |row| row.get::<_, String>(0))
+            .map_err(|e| KittyError::Database(e.to_string()))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| KittyError::Database(e.to_string()))?;
+
+        Ok(messages.into_iter().filter(|m| m != "ok").collect())
+    }
+
+    /// Read whatever file rows are still usable, substituting placeholders for bad metadata
+    ///
+    /// Used by `kitty recover` when the manifest can no longer be loaded normally.
+    pub fn salvage_files(&self) -> Result<Vec<TrackedFile>, KittyError> {
+        let mut stmt = self
+            .connection
+            .prepare("SELECT original_path, repo_path, added_at, last_updated, hash, package, package_manager, bundle, tags, after, metadata, ignore_drift, filters, conditions, expires_at, frozen, scope, revision, tree FROM files")
+            .map_err(|e| KittyError::Database(e.to_string()))?;
+
+        let mut rows = stmt
+            .query([])
+            .map_err(|e| KittyError::Database(e.to_string()))?;
+
+        let parse_time = |value: Option<String>| {
+            value
+                .and_then(|v| DateTime::parse_from_rfc3339(&v).ok())
+                .map(|t| t.with_timezone(&Utc))
+                .unwrap_or_else(Utc::now)
+        };
+
+        let mut files = Vec::new();
+        while let Ok(Some(row)) = rows.next() {
+            let original_path: Option<String> = row.get(0).ok();
+            let repo_path: Option<String> = row.get(1).ok();
+            let (Some(original_path), Some(repo_path)) = (original_path, repo_path) else {
+                continue;
+            };
+
+            files.push(TrackedFile {
+                original_path,
+                repo_path,
+                added_at: parse_time(row.get(2).ok()),
+                last_updated: parse_time(row.get(3).ok()),
+                hash: row.get(4).unwrap_or_default(),
+                package: row.get(5).ok().flatten(),
+                package_manager: row.get(6).ok().flatten(),
+                versions: Vec::new(),
+                bundle: row.get(7).ok().flatten(),
+                tags: parse_list(row.get(8).ok().flatten()),
+                after: parse_list(row.get(9).ok().flatten()),
+                metadata: parse_metadata(row.get(10).ok().flatten()),
+                ignore_drift: row.get(11).unwrap_or(false),
+                filters: parse_list(row.get(12).ok().flatten()),
+                conditions: parse_conditions(row.get(13).ok().flatten()),
+                revision: row.get::<_, i64>(17).map(|r| r as u64).unwrap_or(0),
+                expires_at: parse_time_opt(row.get(14).ok().flatten()),
+                frozen: row.get(15).unwrap_or(false),
+                scope: parse_scope(row.get(16).ok().flatten()),
+                tree: row.get(18).ok().flatten(),
+            });
+        }
+
+        // The versions table is separate, so its rows usually survive damage to the files table
+        for file in &mut files {
+            file.versions = self.salvage_versions(&file.repo_path);
+        }
+
+        Ok(files)
+    }
+
+    /// Whatever previous versions of a file are still readable, oldest first
+    fn salvage_versions(&self, file_repo_path: &str) -> Vec<FileVersion> {
+        let Ok(mut stmt) = self.connection.prepare(
+            "SELECT repo_path, hash, saved_at FROM versions WHERE file_repo_path = ? ORDER BY saved_at",
+        ) else {
+            return Vec::new();
+        };
+        let Ok(mut rows) = stmt.query(params![file_repo_path]) else {
+            return Vec::new();
+        };
+
+        let mut versions = Vec::new();
+        while let Ok(Some(row)) = rows.next() {
+            let saved_at = row
+                .get::<_, String>(2)
+                .ok()
+                .and_then(|v| DateTime::parse_from_rfc3339(&v).ok());
+            if let (Ok(repo_path), Ok(hash), Some(saved_at)) = (row.get(0), row.get(1), saved_at) {
+                versions.push(FileVersion {
+                    repo_path,
+                    hash,
+                    saved_at: saved_at.with_timezone(&Utc),
+                });
+            }
+        }
+        versions
+    }
+
+    /// Store content for a previous version, returning false if no such version exists
+    fn update_version_content(
+        &self,
+        path: &str,
+        encrypted_data: &[u8],
+    ) -> Result<bool, KittyError> {
+        let updated = self
+            .connection
+            .execute(
+                "UPDATE versions SET content = ? WHERE repo_path = ?",
+                params![encrypted_data, path],
+            )
+            .map_err(|e| KittyError::Database(e.to_string()))?;
+
+        Ok(updated > 0)
+    }
+
+    /// Tracked files selected by a `WHERE` clause (or all of them), with their versions
+    fn query_files(
+        &self,
+        condition: &str,
+        params: &[&dyn ToSql],
+    ) -> Result<Vec<TrackedFile>, KittyError> {
+        let mut files = Vec::new();
+        let mut stmt = self
+            .connection
+            .prepare(&format!("SELECT original_path, repo_path, added_at, last_updated, hash, package, package_manager, bundle, tags, after, metadata, ignore_drift, filters, conditions, revision, expires_at, frozen, scope, tree FROM files {}", condition))
+            .map_err(|e| {
+                KittyError::Io(std::io::Error::new(
+                    std::io::ErrorKind::Other,
+                    e.to_string(),
+                ))
+            })?;
+
+        let file_rows = stmt
+            .query_map(params, |row| {
+                let added_at_str: String = row.get(2)?;
+                let last_updated_str: String = row.get(3)?;
+
+                let added_at = DateTime::parse_from_rfc3339(&added_at_str)
+                    .map_err(|e| {
+                        rusqlite::Error::FromSqlConversionFailure(0, Type::Text, Box::new(e))
+                    })?
+                    .with_timezone(&Utc);
+
+                let last_updated = DateTime::parse_from_rfc3339(&last_updated_str)
+                    .map_err(|e| {
+                        rusqlite::Error::FromSqlConversionFailure(0, Type::Text, Box::new(e))
+                    })?
+                    .with_timezone(&Utc);
+
+                Ok(TrackedFile {
+                    original_path: row.get(0)?,
+                    repo_path: row.get(1)?,
+                    added_at,
+                    last_updated,
+                    hash: row.get(4)?,
+                    package: row.get(5)?,
+                    package_manager: row.get(6)?,
+                    versions: Vec::new(),
+                    bundle: row.get(7)?,
+                    tags: parse_list(row.get(8)?),
+                    after: parse_list(row.get(9)?),
+                    metadata: parse_metadata(row.get(10)?),
+                    ignore_drift: row.get(11)?,
+                    filters: parse_list(row.get(12)?),
+                    conditions: parse_conditions(row.get(13)?),
+                    revision: row.get::<_, i64>(14)? as u64,
+                    expires_at: parse_time_opt(row.get(15)?),
+                    frozen: row.get(16)?,
+                    scope: parse_scope(row.get(17)?),
+                    tree: row.get(18)?,
+                })
+            })
+            .map_err(|e| {
+                KittyError::Io(std::io::Error::new(
+                    std::io::ErrorKind::Other,
+                    e.to_string(),
+                ))
+            })?;
+
+        for file_result in file_rows {
+            files.push(file_result.map_err(|e| {
+                KittyError::Io(std::io::Error::new(
+                    std::io::ErrorKind::Other,
+                    e.to_string(),
+                ))
+            })?);
+        }
+
+        // Attach previous versions to their files, oldest first
+        let mut stmt = self
+            .connection
+            .prepare(&format!(
+                "SELECT file_repo_path, repo_path, hash, saved_at FROM versions
+                 WHERE file_repo_path IN (SELECT repo_path FROM files {})
+                 ORDER BY saved_at",
+                condition
+            ))
+            .map_err(|e| KittyError::Database(e.to_string()))?;
+
+        let version_rows = stmt
+            .query_map(params, |row| {
+                let saved_at_str: String = row.get(3)?;
+                let saved_at = DateTime::parse_from_rfc3339(&saved_at_str)
+                    .map_err(|e| {
+                        rusqlite::Error::FromSqlConversionFailure(3, Type::Text, Box::new(e))
+                    })?
+                    .with_timezone(&Utc);
+
+                Ok((
+                    row.get::<_, String>(0)?,
+                    FileVersion {
+                        repo_path: row.get(1)?,
+                        hash: row.get(2)?,
+                        saved_at,
+                    },
+                ))
+            })
+            .map_err(|e| KittyError::Database(e.to_string()))?;
+
+        for version_result in version_rows {
+            let (file_repo_path, version) =
+                version_result.map_err(|e| KittyError::Database(e.to_string()))?;
+            if let Some(file) = files.iter_mut().find(|f| f.repo_path == file_repo_path) {
+                file.versions.push(version);
+            }
+        }
+
+        Ok(files)
+    }
+
+    /// Content stored for a previous version, if any
+    fn version_content(&self, path: &str) -> Option<Vec<u8>> {
+        self.connection
+            .query_row(
+                "SELECT content FROM versions WHERE repo_path = ?",
+                params![path],
+                |row| row.get::<_, Option<Vec<u8>>>(0),
+            )
+            .ok()
+            .flatten()
+    }
+}
+
+impl Storage for SqliteStorage {
     /// Save repository information
-    pub fn save_repository(&mut self, repository: &Repository) -> Result<(), KittyError> {
+    fn save_repository(&mut self, repository: &Repository) -> Result<(), KittyError> {
         // Use a transaction to ensure database consistency
         let tx = self
             .connection
             .transaction()
             .map_err(|e| KittyError::Database(e.to_string()))?;
 
+        // Never overwrite a manifest saved since this one was read
+        let saved: i64 = tx
+            .query_row(
+                "SELECT generation FROM repository WHERE id = 1",
+                [],
+                |row| row.get(0),
+            )
+            .or_else(|e| match e {
+                rusqlite::Error::QueryReturnedNoRows => Ok(0),
+                _ => Err(KittyError::Database(e.to_string())),
+            })?;
+        repository.check_saved(saved as u64, |since| changed_since(&tx, since))?;
+
         // Update repository info
         tx.execute("DELETE FROM repository", [])
             .map_err(|e| KittyError::Database(e.to_string()))?;
 
         tx.execute(
-            "INSERT INTO repository (id, created_at, salt) VALUES (1, ?1, ?2)",
-            params![repository.created_at.to_rfc3339(), repository.salt],
+            "INSERT INTO repository (id, created_at, salt, generation, password_set_at) VALUES (1, ?1, ?2, ?3, ?4)",
+            params![
+                repository.created_at.to_rfc3339(),
+                repository.salt,
+                repository.next_generation() as i64,
+                repository.password_set_at.map(|t| t.to_rfc3339())
+            ],
         )
         .map_err(|e| KittyError::Database(e.to_string()))?;
 
@@ -98,6 +420,29 @@ impl SqliteStorage {
             file_contents
         }; // stmt is dropped here, releasing the borrow on tx
 
+        // Previous versions keep their content the same way
+        let version_contents = {
+            let mut stmt = tx
+                .prepare("SELECT repo_path, content FROM versions")
+                .map_err(|e| KittyError::Database(e.to_string()))?;
+
+            let version_rows = stmt
+                .query_map([], |row| {
+                    let repo_path: String = row.get(0)?;
+                    let content: Option<Vec<u8>> = row.get(1)?;
+                    Ok((repo_path, content))
+                })
+                .map_err(|e| KittyError::Database(e.to_string()))?;
+
+            let mut version_contents = std::collections::HashMap::new();
+            for version_result in version_rows {
+                let (repo_path, content) =
+                    version_result.map_err(|e| KittyError::Database(e.to_string()))?;
+                version_contents.insert(repo_path, content);
+            }
+            version_contents
+        };
+
         // Now update the files table
         tx.execute("DELETE FROM files", [])
             .map_err(|e| KittyError::Database(e.to_string()))?;
@@ -105,39 +450,97 @@ impl SqliteStorage {
         for file in &repository.files {
             // Look up content for this file
             let content = file_contents.get(&file.repo_path);
+            let tags = serde_json::to_string(&file.tags)?;
+            let after = serde_json::to_string(&file.after)?;
+            let filters = serde_json::to_string(&file.filters)?;
+            let conditions = serde_json::to_string(&file.conditions)?;
+            let metadata = file
+                .metadata
+                .map(|m| serde_json::to_string(&m))
+                .transpose()?;
 
             if let Some(Some(content_data)) = content {
                 // The file has content, preserve it
                 tx.execute(
-                        "INSERT INTO files (original_path, repo_path, added_at, last_updated, hash, content)
-                         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                        "INSERT INTO files (original_path, repo_path, added_at, last_updated, hash, content, package, package_manager, bundle, tags, after, metadata, ignore_drift, filters, conditions, revision, expires_at, frozen, scope, tree)
+                         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20)",
                         params![
                             file.original_path,
                             file.repo_path,
                             file.added_at.to_rfc3339(),
                             file.last_updated.to_rfc3339(),
                             file.hash,
-                            content_data
+                            content_data,
+                            file.package,
+                            file.package_manager,
+                            file.bundle,
+                            tags,
+                            after,
+                            metadata,
+                            file.ignore_drift,
+                            filters,
+                            conditions,
+                            file.revision as i64,
+                            file.expires_at.map(|t| t.to_rfc3339()),
+                            file.frozen,
+                            file.scope.map(Scope::as_str),
+                            file.tree
                         ],
                     )
                     .map_err(|e| KittyError::Database(e.to_string()))?;
             } else {
                 // No content available, insert with NULL content
                 tx.execute(
-                        "INSERT INTO files (original_path, repo_path, added_at, last_updated, hash, content)
-                         VALUES (?1, ?2, ?3, ?4, ?5, NULL)",
+                        "INSERT INTO files (original_path, repo_path, added_at, last_updated, hash, content, package, package_manager, bundle, tags, after, metadata, ignore_drift, filters, conditions, revision, expires_at, frozen, scope, tree)
+                         VALUES (?1, ?2, ?3, ?4, ?5, NULL, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19)",
                         params![
                             file.original_path,
                             file.repo_path,
                             file.added_at.to_rfc3339(),
                             file.last_updated.to_rfc3339(),
-                            file.hash
+                            file.hash,
+                            file.package,
+                            file.package_manager,
+                            file.bundle,
+                            tags,
+                            after,
+                            metadata,
+                            file.ignore_drift,
+                            filters,
+                            conditions,
+                            file.revision as i64,
+                            file.expires_at.map(|t| t.to_rfc3339()),
+                            file.frozen,
+                            file.scope.map(Scope::as_str),
+                            file.tree
                         ],
                     )
                     .map_err(|e| KittyError::Database(e.to_string()))?;
             }
         }
 
+        // Versions dropped from the manifest (e.g. by pruning) are deleted here
+        tx.execute("DELETE FROM versions", [])
+            .map_err(|e| KittyError::Database(e.to_string()))?;
+
+        for file in &repository.files {
+            for version in &file.versions {
+                let content = version_contents.get(&version.repo_path).cloned().flatten();
+                tx.execute(
+                    "INSERT INTO versions (file_repo_path, repo_path, hash, saved_at, content)
+                     VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![
+                        file.repo_path,
+                        version.repo_path,
+                        version.hash,
+                        version.saved_at.to_rfc3339(),
+                        content
+                    ],
+                )
+                .map_err(|e| KittyError::Database(e.to_string()))?;
+            }
+        }
+
         // Commit the transaction
         tx.commit()
             .map_err(|e| KittyError::Database(e.to_string()))?;
@@ -146,10 +549,12 @@ impl SqliteStorage {
     }
 
     /// Load repository information
-    pub fn load_repository(&self) -> Result<Repository, KittyError> {
+    fn load_repository(&self) -> Result<Repository, KittyError> {
         let mut stmt = self
             .connection
-            .prepare("SELECT created_at, salt FROM repository WHERE id = 1")
+            .prepare(
+                "SELECT created_at, salt, generation, password_set_at FROM repository WHERE id = 1",
+            )
             .map_err(|e| {
                 KittyError::Io(std::io::Error::new(
                     std::io::ErrorKind::Other,
@@ -197,68 +602,83 @@ impl SqliteStorage {
             ))
         })?;
 
-        // Get files
-        let mut files = Vec::new();
-        let mut stmt = self
-            .connection
-            .prepare("SELECT original_path, repo_path, added_at, last_updated, hash FROM files")
-            .map_err(|e| {
-                KittyError::Io(std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    e.to_string(),
-                ))
-            })?;
-
-        let file_rows = stmt
-            .query_map([], |row| {
-                let added_at_str: String = row.get(2)?;
-                let last_updated_str: String = row.get(3)?;
-
-                let added_at = DateTime::parse_from_rfc3339(&added_at_str)
-                    .map_err(|e| {
-                        rusqlite::Error::FromSqlConversionFailure(0, Type::Text, Box::new(e))
-                    })?
-                    .with_timezone(&Utc);
+        let generation: i64 = row
+            .get(2)
+            .map_err(|e| KittyError::Database(e.to_string()))?;
+        let password_set_at = parse_time_opt(
+            row.get(3)
+                .map_err(|e| KittyError::Database(e.to_string()))?,
+        );
 
-                let last_updated = DateTime::parse_from_rfc3339(&last_updated_str)
-                    .map_err(|e| {
-                        rusqlite::Error::FromSqlConversionFailure(0, Type::Text, Box::new(e))
-                    })?
-                    .with_timezone(&Utc);
-
-                Ok(TrackedFile {
-                    original_path: row.get(0)?,
-                    repo_path: row.get(1)?,
-                    added_at,
-                    last_updated,
-                    hash: row.get(4)?,
-                })
-            })
-            .map_err(|e| {
-                KittyError::Io(std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    e.to_string(),
-                ))
-            })?;
-
-        for file_result in file_rows {
-            files.push(file_result.map_err(|e| {
-                KittyError::Io(std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    e.to_string(),
-                ))
-            })?);
-        }
+        let files = self.query_files("", &[])?;
 
         Ok(Repository {
             created_at,
             salt,
             files,
+            generation: generation as u64,
+            password_set_at,
         })
     }
 
+    /// Load only the files a filter matches, looked up through the index on their path
+    fn load_files(&self, filter: &ManifestFilter) -> Result<Vec<TrackedFile>, KittyError> {
+        match filter {
+            ManifestFilter::Paths(paths) => {
+                let placeholders = vec!["?"; paths.len()].join(", ");
+                let params: Vec<&dyn ToSql> = paths.iter().map(|p| p as &dyn ToSql).collect();
+                self.query_files(
+                    &format!("WHERE original_path IN ({})", placeholders),
+                    &params,
+                )
+            }
+            ManifestFilter::Prefix(prefix) => {
+                // Every path starting with the prefix sorts between these two
+                let end = format!("{}{}", prefix, char::MAX);
+                let mut files = self.query_files(
+                    "WHERE original_path >= ?1 AND original_path < ?2",
+                    &[prefix, &end],
+                )?;
+                files.retain(|f| filter.matches(&f.original_path));
+                Ok(files)
+            }
+        }
+    }
+
+    /// Generation of the saved manifest, bumped on every save
+    fn generation(&self) -> Result<u64, KittyError> {
+        let generation: i64 = self
+            .connection
+            .query_row(
+                "SELECT generation FROM repository WHERE id = 1",
+                [],
+                |row| row.get(0),
+            )
+            .map_err(|e| match e {
+                rusqlite::Error::QueryReturnedNoRows => KittyError::RepositoryNotFound,
+                _ => KittyError::Database(e.to_string()),
+            })?;
+        Ok(generation as u64)
+    }
+
+    /// Size in bytes of the stored content for a file or version
+    fn content_size(&self, path: &str) -> Result<u64, KittyError> {
+        let size: Option<i64> = self
+            .connection
+            .query_row(
+                "SELECT length(content) FROM files WHERE repo_path = ?1
+                 UNION ALL
+                 SELECT length(content) FROM versions WHERE repo_path = ?1",
+                params![path],
+                |row| row.get(0),
+            )
+            .unwrap_or(None);
+
+        Ok(size.unwrap_or(0) as u64)
+    }
+
     /// Get the salt from the repository
-    pub fn get_salt(&self) -> Result<String, KittyError> {
+    fn get_salt(&self) -> Result<String, KittyError> {
         let mut stmt = self
             .connection
             .prepare("SELECT salt FROM repository WHERE id = 1")
@@ -273,7 +693,7 @@ impl SqliteStorage {
     }
 
     /// Save an encrypted file to the repository
-    pub fn save_file(&self, path: &str, encrypted_data: &[u8]) -> Result<(), KittyError> {
+    fn save_file(&self, path: &str, encrypted_data: &[u8]) -> Result<(), KittyError> {
         println!("Saving file content to database for path: {}", path);
         println!("Content size: {} bytes", encrypted_data.len());
 
@@ -313,6 +733,9 @@ impl SqliteStorage {
                     content_size
                 );
             }
+            Err(_) if self.update_version_content(path, encrypted_data)? => {
+                println!("Stored content of previous version: {}", path);
+            }
             Err(e) => {
                 println!("File not found in database: {}", e);
                 // File not found in database, but this is unlikely since we should
@@ -339,7 +762,7 @@ impl SqliteStorage {
     }
 
     /// Get an encrypted file from the repository
-    pub fn get_file(&self, path: &str) -> Result<Vec<u8>, KittyError> {
+    fn get_file(&self, path: &str) -> Result<Vec<u8>, KittyError> {
         println!("Getting file content from database for path: {}", path);
 
         // Try to get the file content directly from the database
@@ -385,6 +808,12 @@ impl SqliteStorage {
                 }
             }
             Err(e) => {
+                // Previous versions live in their own table
+                if let Some(data) = self.version_content(path) {
+                    println!("Found content of previous version: {} bytes", data.len());
+                    return Ok(data);
+                }
+
                 println!("Error finding file in database: {}", e);
                 // Try with original_path if repo_path didn't work
                 let result = self.connection.query_row(
@@ -427,4 +856,35 @@ impl SqliteStorage {
             }
         }
     }
+
+    /// Get the content of many files and versions with one query per table and chunk
+    fn get_files(&self, paths: &[&str]) -> Result<HashMap<String, Vec<u8>>, KittyError> {
+        let mut files = HashMap::with_capacity(paths.len());
+        for chunk in paths.chunks(BATCH_SIZE) {
+            let placeholders = vec!["?"; chunk.len()].join(", ");
+            for table in ["files", "versions"] {
+                let mut stmt = self
+                    .connection
+                    .prepare(&format!(
+                        "SELECT repo_path, content FROM {} WHERE repo_path IN ({})",
+                        table, placeholders
+                    ))
+                    .map_err(|e| KittyError::Database(e.to_string()))?;
+                let rows = stmt
+                    .query_map(rusqlite::params_from_iter(chunk), |row| {
+                        Ok((row.get::<_, String>(0)?, row.get::<_, Option<Vec<u8>>>(1)?))
+                    })
+                    .map_err(|e| KittyError::Database(e.to_string()))?;
+                for row in rows {
+                    let (path, content) = row.map_err(|e| KittyError::Database(e.to_string()))?;
+                    // Rows without content are left to `get_file`, which falls back to `files/`
+                    if let Some(content) = content.filter(|c| !c.is_empty()) {
+                        files.insert(path, content);
+                    }
+                }
+            }
+        }
+
+        Ok(files)
+    }
 }
@@ -0,0 +1,70 @@
+use crate::commands::init::KittyError;
+use std::{
+    io::Write,
+    path::Path,
+    process::{Command, Stdio},
+    thread,
+};
+
+/// Encrypt content for age recipients with the `age` CLI
+///
+/// Recipients are anything `age -r` accepts, such as `age1...` keys or SSH
+/// public keys.
+pub fn encrypt(content: &[u8], recipients: &[String]) -> Result<Vec<u8>, KittyError> {
+    let mut args: Vec<&str> = Vec::new();
+    for recipient in recipients {
+        args.extend(["--recipient", recipient.as_str()]);
+    }
+    run(&args, Some(content))
+}
+
+/// Decrypt an age file with the identity file of one of its recipients
+pub fn decrypt(path: &Path, identity: &str) -> Result<Vec<u8>, KittyError> {
+    let path = path.to_string_lossy();
+    run(&["--decrypt", "--identity", identity, &path], None)
+}
+
+/// Run `age`, passing `input` on its stdin, and return its stdout
+fn run(args: &[&str], input: Option<&[u8]>) -> Result<Vec<u8>, KittyError> {
+    let mut child = Command::new("age")
+        .args(args)
+        .stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| {
+            KittyError::Share(format!(
+                "could not run age: {}; install it from https://age-encryption.org",
+                e
+            ))
+        })?;
+
+    // age writes as it reads, so the input goes in from another thread while its output is read
+    let writer = match (child.stdin.take(), input) {
+        (Some(mut stdin), Some(input)) => {
+            let input = input.to_vec();
+            Some(thread::spawn(move || stdin.write_all(&input)))
+        }
+        _ => None,
+    };
+
+    let output = child.wait_with_output()?;
+    // When age fails it stops reading, so its own message says more than the broken pipe
+    if !output.status.success() {
+        return Err(KittyError::Share(format!(
+            "age failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    if let Some(writer) = writer {
+        writer
+            .join()
+            .map_err(|_| KittyError::Share("could not pass the bundle to age".to_string()))??;
+    }
+
+    Ok(output.stdout)
+}
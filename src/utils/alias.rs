@@ -0,0 +1,223 @@
+use crate::{commands::init::KittyError, utils::file::user_config_dir};
+use serde::Deserialize;
+use std::{collections::BTreeMap, ffi::OsString, fs, process::Command};
+
+/// How deeply aliases may refer to other aliases before kitty gives up
+const MAX_DEPTH: usize = 10;
+
+/// Set for kitty processes started by a chained alias, so chains cannot recurse forever
+const DEPTH_ENV: &str = "KITTY_ALIAS_DEPTH";
+
+/// The user's own kitty settings, in `config.json` of the user config directory
+#[derive(Deserialize, Default)]
+struct UserConfig {
+    /// Command aliases, e.g. `"st": "status"`, expanded before arguments are parsed
+    #[serde(default)]
+    aliases: BTreeMap<String, String>,
+}
+
+/// What a command line turns into once aliases are expanded
+pub enum Expansion {
+    /// Arguments to parse as a single kitty command
+    Args(Vec<OsString>),
+
+    /// Kitty commands joined with `&&`, run one after another until one fails
+    Chain(Vec<Vec<OsString>>),
+}
+
+/// Expand an alias given as the command, the way git does
+///
+/// Built-in commands always win, so an alias cannot change what `kitty restore`
+/// means. Arguments after the alias are appended to its expansion. Aliases are
+/// read from the user's `config.json`, never from a repository, so a directory
+/// with a `.kitty` in it cannot change what a command does.
+pub fn expand(
+    mut args: Vec<OsString>,
+    is_builtin: impl Fn(&str) -> bool,
+) -> Result<Expansion, KittyError> {
+    let is_alias = |args: &[OsString]| {
+        args.get(1)
+            .and_then(|arg| arg.to_str())
+            .is_some_and(|arg| !arg.starts_with('-') && !is_builtin(arg))
+    };
+    if !is_alias(&args) {
+        return Ok(Expansion::Args(args));
+    }
+
+    let aliases = load_aliases()?;
+    let mut expanded: Vec<String> = Vec::new();
+
+    while is_alias(&args) {
+        let name = args[1].to_string_lossy().to_string();
+        let Some(definition) = aliases.get(&name) else {
+            // Let clap report the unknown command
+            break;
+        };
+        if expanded.contains(&name) || expanded.len() == MAX_DEPTH {
+            expanded.push(name);
+            return Err(KittyError::Alias(format!(
+                "aliases refer to each other: {}",
+                expanded.join(" -> ")
+            )));
+        }
+        expanded.push(name.clone());
+
+        let rest = args.split_off(2);
+        if definition.starts_with('!') {
+            return Err(KittyError::Alias(format!(
+                "{}: aliases run kitty commands only, not shell commands",
+                name
+            )));
+        }
+
+        let words =
+            split_words(definition).map_err(|e| KittyError::Alias(format!("{}: {}", name, e)))?;
+        let mut commands = words
+            .split(|word| *word == Word::And)
+            .map(|command| {
+                if command.is_empty() {
+                    return Err(KittyError::Alias(format!("{}: empty command", name)));
+                }
+                Ok(command
+                    .iter()
+                    .filter_map(|word| match word {
+                        Word::Text(text) => Some(OsString::from(text)),
+                        Word::And => None,
+                    })
+                    .collect::<Vec<_>>())
+            })
+            .collect::<Result<Vec<_>, KittyError>>()?;
+
+        if commands.len() > 1 {
+            if let Some(last) = commands.last_mut() {
+                last.extend(rest);
+            }
+            return Ok(Expansion::Chain(commands));
+        }
+
+        args.truncate(1);
+        args.append(&mut commands[0]);
+        args.extend(rest);
+    }
+
+    Ok(Expansion::Args(args))
+}
+
+/// Run a chained alias, returning the exit code kitty should finish with
+pub fn run(expansion: Expansion) -> Result<i32, KittyError> {
+    match expansion {
+        // Nothing to run; the caller parses these itself
+        Expansion::Args(_) => Ok(0),
+        Expansion::Chain(commands) => {
+            let depth: usize = std::env::var(DEPTH_ENV)
+                .ok()
+                .and_then(|depth| depth.parse().ok())
+                .unwrap_or(0);
+            if depth >= MAX_DEPTH {
+                return Err(KittyError::Alias(
+                    "chained aliases are nested too deeply".to_string(),
+                ));
+            }
+
+            let kitty = std::env::current_exe()?;
+            for command in commands {
+                let status = Command::new(&kitty)
+                    .args(&command)
+                    .env(DEPTH_ENV, (depth + 1).to_string())
+                    .status()?;
+                if !status.success() {
+                    return Ok(status.code().unwrap_or(1));
+                }
+            }
+            Ok(0)
+        }
+    }
+}
+
+/// Aliases the user defined, if they have a config file
+fn load_aliases() -> Result<BTreeMap<String, String>, KittyError> {
+    let Some(config_path) = user_config_dir().map(|dir| dir.join("config.json")) else {
+        return Ok(BTreeMap::new());
+    };
+    if !config_path.exists() {
+        return Ok(BTreeMap::new());
+    }
+    let config: UserConfig = serde_json::from_slice(&fs::read(&config_path)?)
+        .map_err(|e| KittyError::Alias(format!("{}: {}", config_path.display(), e)))?;
+    Ok(config.aliases)
+}
+
+/// A word of an alias definition
+#[derive(PartialEq)]
+enum Word {
+    Text(String),
+
+    /// An unquoted `&&` between two commands
+    And,
+}
+
+/// Split an alias definition into words, honouring quotes and backslashes like a shell
+fn split_words(definition: &str) -> Result<Vec<Word>, String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut chars = definition.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err("unterminated single quote".to_string()),
+                    }
+                }
+            }
+            '"' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c) => word.push(c),
+                            None => return Err("unterminated double quote".to_string()),
+                        },
+                        Some(c) => word.push(c),
+                        None => return Err("unterminated double quote".to_string()),
+                    }
+                }
+            }
+            '\\' => {
+                in_word = true;
+                if let Some(c) = chars.next() {
+                    word.push(c);
+                }
+            }
+            '&' if chars.peek() == Some(&'&') => {
+                chars.next();
+                if in_word {
+                    words.push(Word::Text(std::mem::take(&mut word)));
+                    in_word = false;
+                }
+                words.push(Word::And);
+            }
+            c if c.is_whitespace() => {
+                if in_word {
+                    words.push(Word::Text(std::mem::take(&mut word)));
+                    in_word = false;
+                }
+            }
+            c => {
+                in_word = true;
+                word.push(c);
+            }
+        }
+    }
+    if in_word {
+        words.push(Word::Text(word));
+    }
+
+    Ok(words)
+}
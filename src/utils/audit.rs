@@ -0,0 +1,82 @@
+use crate::commands::init::{Crypto, KittyError};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::Path,
+};
+
+/// Append-only log of repository operations, one encrypted entry per line
+const AUDIT_LOG: &str = "audit.log";
+
+/// A single recorded repository operation
+#[derive(Serialize, Deserialize, Clone)]
+pub struct AuditEntry {
+    pub timestamp: DateTime<Utc>,
+    pub user: String,
+    /// The user who invoked sudo, when running elevated
+    #[serde(default)]
+    pub sudo_user: Option<String>,
+    pub operation: String,
+    pub path: String,
+    pub old_hash: Option<String>,
+    pub new_hash: Option<String>,
+}
+
+impl AuditEntry {
+    /// Create an entry for the current user and time
+    pub fn new(
+        operation: &str,
+        path: &str,
+        old_hash: Option<String>,
+        new_hash: Option<String>,
+    ) -> Self {
+        let user = std::env::var("USER")
+            .or_else(|_| std::env::var("USERNAME"))
+            .unwrap_or_else(|_| "unknown".to_string());
+
+        Self {
+            timestamp: Utc::now(),
+            user,
+            sudo_user: std::env::var("SUDO_USER").ok(),
+            operation: operation.to_string(),
+            path: path.to_string(),
+            old_hash,
+            new_hash,
+        }
+    }
+}
+
+/// Append an entry to the repository's audit log
+pub fn record(repo_path: &Path, crypto: &Crypto, entry: &AuditEntry) -> Result<(), KittyError> {
+    let json = serde_json::to_vec(entry)?;
+    let encrypted = crypto.encrypt(&json)?;
+
+    let mut log = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(repo_path.join(AUDIT_LOG))?;
+    writeln!(log, "{}", hex::encode(encrypted))?;
+
+    Ok(())
+}
+
+/// Read and decrypt every entry in the audit log, oldest first
+pub fn read_entries(repo_path: &Path, crypto: &Crypto) -> Result<Vec<AuditEntry>, KittyError> {
+    let log_path = repo_path.join(AUDIT_LOG);
+    if !log_path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut entries = Vec::new();
+    for line in fs::read_to_string(log_path)?.lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let decrypted = crypto.decrypt(&hex::decode(line.trim())?)?;
+        entries.push(serde_json::from_slice(&decrypted)?);
+    }
+
+    Ok(entries)
+}
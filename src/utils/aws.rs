@@ -0,0 +1,146 @@
+use crate::{
+    commands::init::KittyError,
+    utils::timings::{self, Phase},
+};
+use serde_json::Value;
+use std::{collections::HashMap, process::Command};
+
+/// Prefix of template placeholders read from SSM Parameter Store, e.g. `{{ ssm:/myapp/db/password }}`
+pub const SSM_PREFIX: &str = "ssm:";
+
+/// Prefix of placeholders read from Secrets Manager, e.g. `{{ secretsmanager:myapp/db#password }}`
+pub const SECRETS_MANAGER_PREFIX: &str = "secretsmanager:";
+
+/// Reads parameters and secrets through the AWS CLI
+///
+/// The CLI finds credentials the usual way: environment, profiles, SSO, or
+/// the instance role on EC2. Its region settings apply as well.
+#[derive(Default)]
+pub struct Aws {
+    /// Values already read, by placeholder, so each is fetched once per run
+    cache: HashMap<String, String>,
+}
+
+impl Aws {
+    /// Read a parameter, decrypting SecureString values
+    pub fn parameter(&mut self, name: &str) -> Result<String, KittyError> {
+        let key = format!("{}{}", SSM_PREFIX, name);
+        if let Some(value) = self.cache.get(&key) {
+            return Ok(value.clone());
+        }
+
+        let value = query(
+            &[
+                "ssm",
+                "get-parameter",
+                "--name",
+                name,
+                "--with-decryption",
+                "--query",
+                "Parameter.Value",
+            ],
+            name,
+            "ssm:GetParameter",
+        )?;
+        let value = string(value, name)?;
+        self.cache.insert(key, value.clone());
+        Ok(value)
+    }
+
+    /// Read a secret's string, or one key of a secret holding a JSON object, as `id#key`
+    pub fn secret(&mut self, reference: &str) -> Result<String, KittyError> {
+        let (id, key) = match reference.split_once('#') {
+            Some((id, key)) => (id, Some(key)),
+            None => (reference, None),
+        };
+
+        let cache_key = format!("{}{}", SECRETS_MANAGER_PREFIX, id);
+        let secret = match self.cache.get(&cache_key) {
+            Some(secret) => secret.clone(),
+            None => {
+                let value = query(
+                    &[
+                        "secretsmanager",
+                        "get-secret-value",
+                        "--secret-id",
+                        id,
+                        "--query",
+                        "SecretString",
+                    ],
+                    id,
+                    "secretsmanager:GetSecretValue",
+                )?;
+                let secret = string(value, id)?;
+                self.cache.insert(cache_key, secret.clone());
+                secret
+            }
+        };
+
+        let Some(key) = key else {
+            return Ok(secret);
+        };
+        let fields: Value = serde_json::from_str(&secret).map_err(|_| {
+            KittyError::Aws(format!(
+                "{} is not a JSON object, so it has no key '{}'",
+                id, key
+            ))
+        })?;
+        match fields.get(key) {
+            Some(Value::String(value)) => Ok(value.clone()),
+            Some(value) => Ok(value.to_string()),
+            None => Err(KittyError::Aws(format!("{} has no key '{}'", id, key))),
+        }
+    }
+}
+
+/// Run an AWS CLI query and parse its JSON output
+///
+/// JSON keeps values exactly as stored, where text output would drop a trailing newline.
+fn query(args: &[&str], name: &str, action: &str) -> Result<Value, KittyError> {
+    let output = timings::time(Phase::Network, || {
+        Command::new("aws")
+            .args(args)
+            .args(["--output", "json"])
+            .output()
+    })
+    .map_err(|e| {
+        KittyError::Aws(format!(
+            "could not run aws ({}); install the AWS CLI to read {}",
+            e, name
+        ))
+    })?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let stderr = stderr.trim();
+        return Err(KittyError::Aws(if stderr.contains("AccessDenied") {
+            format!(
+                    "access to {} was denied; the credentials in use need {} (and kms:Decrypt for encrypted values): {}",
+                    name, action, stderr
+                )
+        } else if stderr.contains("ParameterNotFound")
+            || stderr.contains("ResourceNotFoundException")
+        {
+            format!("{} does not exist in this account and region", name)
+        } else if stderr.contains("Unable to locate credentials") {
+            format!(
+                "no AWS credentials found for {}; attach an instance role or configure a profile",
+                name
+            )
+        } else {
+            format!("reading {} failed: {}", name, stderr)
+        }));
+    }
+
+    Ok(serde_json::from_slice(&output.stdout)?)
+}
+
+fn string(value: Value, name: &str) -> Result<String, KittyError> {
+    match value {
+        Value::String(value) => Ok(value),
+        _ => Err(KittyError::Aws(format!(
+            "{} has no string value (binary secrets are not supported)",
+            name
+        ))),
+    }
+}
@@ -0,0 +1,18 @@
+use crate::{commands::init::KittyError, tr};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static JSON: AtomicBool = AtomicBool::new(false);
+
+/// Report errors as JSON from now on, for commands run with `--json`
+pub fn set_json() {
+    JSON.store(true, Ordering::Relaxed);
+}
+
+/// Print an error on stderr: one line of JSON with `--json`, a translated message otherwise
+pub fn report(error: &KittyError) {
+    if JSON.load(Ordering::Relaxed) {
+        eprintln!("{}", error.to_json());
+    } else {
+        eprintln!("{}", tr!("error", message = error.localized()));
+    }
+}
@@ -0,0 +1,139 @@
+use crate::repository::config::{EventLog, RepoConfig};
+use chrono::Local;
+use std::os::unix::net::UnixDatagram;
+use std::path::Path;
+
+/// Name the events are logged under
+const IDENTIFIER: &str = "kitty";
+
+/// Socket of the systemd journal's native protocol
+const JOURNAL_SOCKET: &str = "/run/systemd/journal/socket";
+
+/// Sockets of the local syslog daemon, Linux first
+const SYSLOG_SOCKETS: [&str; 2] = ["/dev/log", "/var/run/syslog"];
+
+/// Syslog facility the events are logged with, LOG_DAEMON
+const FACILITY: u8 = 3;
+
+/// Syslog severity of an event
+#[derive(Clone, Copy)]
+pub enum Severity {
+    Warning = 4,
+    Notice = 5,
+    Info = 6,
+}
+
+/// Something kitty did or found, for the system log
+///
+/// Besides the message, every field is logged on its own so log pipelines can
+/// filter on it: as `KITTY_<NAME>` in the journal and as `name=value` in syslog.
+pub struct Event {
+    name: &'static str,
+    severity: Severity,
+    message: String,
+    fields: Vec<(&'static str, String)>,
+}
+
+impl Event {
+    pub fn new(name: &'static str, severity: Severity, message: String) -> Self {
+        Self {
+            name,
+            severity,
+            message,
+            fields: Vec::new(),
+        }
+    }
+
+    /// Add a field; names are lowercase letters, digits and underscores
+    pub fn field(mut self, name: &'static str, value: impl ToString) -> Self {
+        self.fields.push((name, value.to_string()));
+        self
+    }
+}
+
+/// Log events where the `event_log` setting of the repository says
+///
+/// Logging is best effort: a missing journal or syslog daemon never fails the
+/// command, since the change the event describes has already happened.
+pub fn emit(repo_path: &Path, events: &[Event]) {
+    let target = RepoConfig::load(repo_path)
+        .map(|config| config.event_log)
+        .unwrap_or_default();
+    for event in events {
+        match target {
+            EventLog::Off => return,
+            EventLog::Journald => send(&[JOURNAL_SOCKET], &journal_entry(repo_path, event)),
+            EventLog::Syslog => send(&SYSLOG_SOCKETS, syslog_line(repo_path, event).as_bytes()),
+        }
+    }
+}
+
+/// An entry in the journal's native format, with values that hold newlines length-prefixed
+fn journal_entry(repo_path: &Path, event: &Event) -> Vec<u8> {
+    let mut fields = vec![
+        ("MESSAGE".to_string(), event.message.clone()),
+        ("PRIORITY".to_string(), (event.severity as u8).to_string()),
+        ("SYSLOG_IDENTIFIER".to_string(), IDENTIFIER.to_string()),
+        ("KITTY_EVENT".to_string(), event.name.to_string()),
+        ("KITTY_REPO".to_string(), repo_path.display().to_string()),
+    ];
+    fields.extend(
+        event
+            .fields
+            .iter()
+            .map(|(name, value)| (format!("KITTY_{}", name.to_uppercase()), value.clone())),
+    );
+
+    let mut entry = Vec::new();
+    for (name, value) in fields {
+        entry.extend_from_slice(name.as_bytes());
+        if value.contains('\n') {
+            entry.push(b'\n');
+            entry.extend_from_slice(&(value.len() as u64).to_le_bytes());
+        } else {
+            entry.push(b'=');
+        }
+        entry.extend_from_slice(value.as_bytes());
+        entry.push(b'\n');
+    }
+    entry
+}
+
+/// A BSD syslog line with the fields appended as `name=value` pairs
+fn syslog_line(repo_path: &Path, event: &Event) -> String {
+    let mut line = format!(
+        "<{}>{} {}[{}]: {} event={} repo={}",
+        FACILITY * 8 + event.severity as u8,
+        Local::now().format("%b %e %H:%M:%S"),
+        IDENTIFIER,
+        std::process::id(),
+        event.message.replace('\n', " "),
+        event.name,
+        quote(&repo_path.display().to_string())
+    );
+    for (name, value) in &event.fields {
+        line.push_str(&format!(" {}={}", name, quote(value)));
+    }
+    line
+}
+
+/// A value as it goes after `name=`, quoted when it has spaces or quotes
+fn quote(value: &str) -> String {
+    if value.is_empty() || value.contains([' ', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\\\"").replace('\n', " "))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Send a datagram to the first socket that takes it
+fn send(sockets: &[&str], datagram: &[u8]) {
+    let Ok(socket) = UnixDatagram::unbound() else {
+        return;
+    };
+    for path in sockets {
+        if socket.send_to(datagram, path).is_ok() {
+            return;
+        }
+    }
+}
@@ -1,15 +1,95 @@
+use std::collections::HashMap;
 use std::fs;
 use std::io;
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
 use std::path::{Path, PathBuf};
 use std::process::Command;
 
-use crate::commands::init::KittyError;
+use crate::commands::init::{Crypto, FileMetadata, KittyError, Repository, TrackedFile};
+use crate::repository::{
+    config::RepoConfig,
+    format::raise_format,
+    keys::{sign_manifest, verify_manifest},
+};
+use crate::storage::{is_database, open_database, ManifestFilter};
+use crate::utils::shred::{private_temp_dir, secure_delete_dir};
+use crate::utils::timings::{self, Phase};
 
-const REPOSITORY_DIR: &str = ".kitty";
+pub const REPOSITORY_DIR: &str = ".kitty";
 
+/// Most threads `read_stored_files` reads `files/` with
+const READ_THREADS: usize = 8;
+
+/// Environment variable naming the repository directory to use
+pub const REPOSITORY_ENV: &str = "KITTY_REPO";
+
+/// Mode for directories created on restore, before the umask is applied
+const DIRECTORY_MODE: u32 = 0o755;
+
+/// System-wide pointer to the repository, written by hand for shared setups
+const SYSTEM_LOCATION_FILE: &str = "/etc/kitty/repository";
+
+/// Resolve the repository directory
+///
+/// `KITTY_REPO` wins, then `./.kitty` in the current directory, then the location
+/// recorded by `kitty init --path/--dir-name` (per user, then system-wide).
 pub fn get_repository_path() -> Result<PathBuf, KittyError> {
-    let current_dir = std::env::current_dir()?;
-    Ok(current_dir.join(REPOSITORY_DIR))
+    if let Some(path) = repository_path_from_env() {
+        return Ok(path);
+    }
+
+    let local = std::env::current_dir()?.join(REPOSITORY_DIR);
+    if local.exists() {
+        return Ok(local);
+    }
+
+    let recorded = [
+        user_location_file(),
+        Some(PathBuf::from(SYSTEM_LOCATION_FILE)),
+    ]
+    .into_iter()
+    .flatten()
+    .filter_map(|file| fs::read_to_string(file).ok())
+    .map(|location| PathBuf::from(location.trim()))
+    .find(|path| !path.as_os_str().is_empty());
+
+    Ok(recorded.unwrap_or(local))
+}
+
+/// Repository directory from `KITTY_REPO`, if set
+pub fn repository_path_from_env() -> Option<PathBuf> {
+    std::env::var_os(REPOSITORY_ENV)
+        .filter(|value| !value.is_empty())
+        .map(PathBuf::from)
+}
+
+/// Directory of the per-user settings below the config directory
+///
+/// Not `kitty`, which belongs to the kitty terminal emulator.
+const USER_CONFIG_DIR: &str = "kitty-dotfiles";
+
+/// Per-user kitty settings, under `XDG_CONFIG_HOME` or `~/.config`
+pub fn user_config_dir() -> Option<PathBuf> {
+    let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|value| !value.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_dir.join(USER_CONFIG_DIR))
+}
+
+/// Per-user file recording where the repository lives
+fn user_location_file() -> Option<PathBuf> {
+    Some(user_config_dir()?.join("repository"))
+}
+
+/// Record the repository location so later commands find it from any directory
+pub fn record_repository_location(repo_path: &Path) -> Result<PathBuf, KittyError> {
+    let location_file = user_location_file().ok_or(KittyError::NoHome)?;
+    if let Some(parent) = location_file.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    write_atomic(&location_file, format!("{}\n", repo_path.display()))?;
+    Ok(location_file)
 }
 
 /// Get the storage type for the repository
@@ -27,11 +107,334 @@ pub fn get_storage_type(repo_path: &Path) -> Result<String, KittyError> {
     // Trim and validate the storage type
     let storage_type = storage_type.trim();
     match storage_type {
-        "file" | "sqlite" => Ok(storage_type.to_string()),
+        "file" | "sqlite" | "redb" => Ok(storage_type.to_string()),
         _ => Err(KittyError::StorageType(format!("Invalid storage type: {}", storage_type)))
     }
 }
 
+/// Load the repository manifest from whichever storage backend is in use
+///
+/// Repositories with a read key only give a manifest the admin signed.
+pub fn load_repository(repo_path: &Path, crypto: &Crypto) -> Result<Repository, KittyError> {
+    let repository = read_manifest(repo_path, crypto)?;
+    verify_manifest(repo_path, crypto, &repository)?;
+    Ok(repository)
+}
+
+/// Read the manifest without checking its signature, for signing and repairing it
+pub fn read_manifest(repo_path: &Path, crypto: &Crypto) -> Result<Repository, KittyError> {
+    if is_database(&get_storage_type(repo_path)?) {
+        let storage = open_database(repo_path)?;
+        storage.load_repository()
+    } else {
+        let encrypted_config = read_stored(&repo_path.join(CONFIG_FILE))?;
+        let decrypted_config = crypto.decrypt(&encrypted_config)?;
+        Ok(serde_json::from_slice(&decrypted_config)?)
+    }
+}
+
+/// Load only the tracked files a filter matches
+///
+/// Database backends read just those entries. The manifest of file-based
+/// repositories is one encrypted document, so it is still read whole.
+pub fn load_files(
+    repo_path: &Path,
+    crypto: &Crypto,
+    filter: &ManifestFilter,
+) -> Result<Vec<TrackedFile>, KittyError> {
+    // A signature covers the whole manifest, so only a whole one can be checked
+    if is_database(&get_storage_type(repo_path)?) && crypto.manifest_key().is_none() {
+        return open_database(repo_path)?.load_files(filter);
+    }
+    let mut files = load_repository(repo_path, crypto)?.files;
+    files.retain(|f| filter.matches(&f.original_path));
+    Ok(files)
+}
+
+/// Write the repository manifest to whichever storage backend is in use
+pub fn save_repository(
+    repo_path: &Path,
+    crypto: &Crypto,
+    repository: &Repository,
+) -> Result<(), KittyError> {
+    if is_database(&get_storage_type(repo_path)?) {
+        // The database is written before it is signed, so the read key must not get that far
+        crypto.require_admin()?;
+        open_database(repo_path)?.save_repository(repository)?;
+        // Signing reads the manifest back, which needs the only redb handle
+        sign_manifest(repo_path, crypto)
+    } else {
+        timings::time(Phase::StorageIo, || {
+            write_config(repo_path, crypto, repository)
+        })
+    }
+}
+
+/// Encrypted manifest of file-based repositories
+pub const CONFIG_FILE: &str = "config.enc";
+
+/// Replace `config.enc` without ever leaving a truncated or undecryptable manifest
+///
+/// The new manifest is written next to the old one and read back before it takes
+/// its place. The previous manifests are kept as `config.enc.1` (newest) up to the
+/// number set in the repository settings.
+///
+/// The manifest is only replaced if it is still at the generation `repository`
+/// was read at, so a change saved by another kitty in the meantime, e.g. from
+/// another machine sharing the repository over NFS, is never overwritten.
+/// A key unlocked with the read password never writes it.
+pub fn write_config(
+    repo_path: &Path,
+    crypto: &Crypto,
+    repository: &Repository,
+) -> Result<(), KittyError> {
+    crypto.require_admin()?;
+    raise_format(repo_path)?;
+    let config_path = repo_path.join(CONFIG_FILE);
+    let temp_path = temp_path(&config_path);
+
+    check_generation(&config_path, crypto, repository)?;
+    let mut config_json = serde_json::to_value(repository)?;
+    config_json["generation"] = repository.next_generation().into();
+    let config_json = serde_json::to_vec(&config_json)?;
+    write_synced(&temp_path, &crypto.encrypt(&config_json)?, None)?;
+
+    let written = fs::read(&temp_path)
+        .map_err(KittyError::from)
+        .and_then(|encrypted| crypto.decrypt(&encrypted));
+    if !matches!(&written, Ok(decrypted) if *decrypted == config_json) {
+        let _ = fs::remove_file(&temp_path);
+        return Err(KittyError::Encryption(
+            "the new manifest did not read back correctly; config.enc was left unchanged"
+                .to_string(),
+        ));
+    }
+
+    let keep = RepoConfig::load(repo_path)?.manifest_backups;
+    if config_path.exists() && keep > 0 {
+        for n in (1..keep).rev() {
+            let older = config_backup_path(repo_path, n);
+            if older.exists() {
+                fs::rename(&older, config_backup_path(repo_path, n + 1))?;
+            }
+        }
+        fs::copy(&config_path, config_backup_path(repo_path, 1))?;
+    }
+
+    // Drop copies beyond the limit, e.g. after it was lowered
+    let mut n = keep + 1;
+    while config_backup_path(repo_path, n).exists() {
+        fs::remove_file(config_backup_path(repo_path, n))?;
+        n += 1;
+    }
+
+    fs::rename(&temp_path, &config_path)?;
+    sync_parent(&config_path);
+    sign_manifest(repo_path, crypto)
+}
+
+/// Fail if the manifest on disk was saved since `repository` was read
+///
+/// A manifest that cannot be read is left to `kitty recover`, which replaces it.
+fn check_generation(
+    config_path: &Path,
+    crypto: &Crypto,
+    repository: &Repository,
+) -> Result<(), KittyError> {
+    #[derive(serde::Deserialize)]
+    struct Stored {
+        #[serde(default)]
+        generation: u64,
+        #[serde(default)]
+        files: Vec<StoredFile>,
+    }
+
+    #[derive(serde::Deserialize)]
+    struct StoredFile {
+        original_path: String,
+        #[serde(default)]
+        revision: u64,
+    }
+
+    let Some(stored) = fs::read(config_path)
+        .ok()
+        .and_then(|encrypted| crypto.decrypt(&encrypted).ok())
+        .and_then(|decrypted| serde_json::from_slice::<Stored>(&decrypted).ok())
+    else {
+        return Ok(());
+    };
+    repository.check_saved(stored.generation, |since| {
+        stored
+            .files
+            .into_iter()
+            .filter(|f| f.revision > since)
+            .map(|f| f.original_path)
+            .collect()
+    })
+}
+
+/// Read stored content of a file-based repository, counted as storage IO by `--timings`
+pub fn read_stored(path: &Path) -> io::Result<Vec<u8>> {
+    timings::time(Phase::StorageIo, || fs::read(path))
+}
+
+/// Get the stored content of many files at once, keyed by repo path
+///
+/// Database backends fetch it in a few queries; `files/` is read on several
+/// threads. Paths that cannot be read are left out, for the caller to read
+/// again one by one and report why.
+pub fn read_stored_files(
+    repo_path: &Path,
+    paths: &[&str],
+) -> Result<HashMap<String, Vec<u8>>, KittyError> {
+    if is_database(&get_storage_type(repo_path)?) {
+        return open_database(repo_path)?.get_files(paths);
+    }
+
+    let threads = std::thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .min(READ_THREADS);
+    let chunk_size = paths.len().div_ceil(threads).max(1);
+    Ok(timings::time(Phase::StorageIo, || {
+        std::thread::scope(|scope| {
+            let readers: Vec<_> = paths
+                .chunks(chunk_size)
+                .map(|chunk| {
+                    scope.spawn(move || {
+                        chunk
+                            .iter()
+                            .filter_map(|path| {
+                                let content = fs::read(repo_path.join(path)).ok()?;
+                                Some((path.to_string(), content))
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            readers
+                .into_iter()
+                .flat_map(|reader| reader.join().unwrap_or_default())
+                .collect()
+        })
+    }))
+}
+
+/// Like [`write_atomic`], for stored content of a file-based repository
+pub fn write_stored(path: &Path, contents: &[u8]) -> io::Result<()> {
+    timings::time(Phase::StorageIo, || write_atomic(path, contents))
+}
+
+/// Store a blob under `path` as a hard link to the blob at `existing`
+///
+/// Entries with the same content then take the space of one blob, while each
+/// keeps a file of its own in `files/`. On filesystems without hard links the
+/// blob is copied instead.
+pub fn link_stored(existing: &Path, path: &Path) -> io::Result<()> {
+    timings::time(Phase::StorageIo, || match fs::hard_link(existing, path) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Err(e),
+        Err(_) => {
+            let temp_path = temp_path(path);
+            fs::copy(existing, &temp_path)?;
+            fs::rename(&temp_path, path)
+        }
+    })
+}
+
+/// Sibling path a file is written to before it replaces the original
+pub fn temp_path(path: &Path) -> PathBuf {
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+    PathBuf::from(temp_path)
+}
+
+/// Replace a file so that a crash or a full disk leaves either the old or the new content
+///
+/// The content goes to a sibling `.tmp` file, is flushed to disk and then renamed
+/// over `path`. An existing file's permissions carry over.
+pub fn write_atomic(path: &Path, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    let mode = fs::metadata(path).ok().map(|m| m.permissions());
+    replace_file(path, contents.as_ref(), mode)
+}
+
+/// Like [`write_atomic`], for files only the owner may read
+pub fn write_private(path: &Path, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    let mode = Some(fs::Permissions::from_mode(0o600));
+    replace_file(path, contents.as_ref(), mode)
+}
+
+fn replace_file(path: &Path, contents: &[u8], mode: Option<fs::Permissions>) -> io::Result<()> {
+    let temp_path = temp_path(path);
+    write_synced(&temp_path, contents, mode)?;
+    fs::rename(&temp_path, path)?;
+    sync_parent(path);
+    Ok(())
+}
+
+/// Write a file and flush it to disk, removing it again if that fails
+fn write_synced(path: &Path, contents: &[u8], mode: Option<fs::Permissions>) -> io::Result<()> {
+    let written = (|| {
+        let mut file = fs::File::create(path)?;
+        if let Some(mode) = mode {
+            file.set_permissions(mode)?;
+        }
+        io::Write::write_all(&mut file, contents)?;
+        file.sync_all()
+    })();
+    if written.is_err() {
+        let _ = fs::remove_file(path);
+    }
+    written
+}
+
+/// Make a rename durable; not every platform can sync a directory, so failures are ignored
+pub fn sync_parent(path: &Path) {
+    if let Some(parent) = path.parent() {
+        if let Ok(directory) = fs::File::open(parent) {
+            let _ = directory.sync_all();
+        }
+    }
+}
+
+/// Path of the `n`th previous manifest, 1 being the most recent
+pub fn config_backup_path(repo_path: &Path, n: usize) -> PathBuf {
+    repo_path.join(format!("{}.{}", CONFIG_FILE, n))
+}
+
+/// The previous manifests that exist, newest first
+pub fn config_backups(repo_path: &Path) -> Vec<PathBuf> {
+    (1..)
+        .map(|n| config_backup_path(repo_path, n))
+        .take_while(|path| path.exists())
+        .collect()
+}
+
+/// Expand a leading `~/` so paths can be written the way users think of their dotfiles
+pub fn expand_home(path: &str) -> String {
+    match (path.strip_prefix("~/"), std::env::var("HOME")) {
+        (Some(rest), Ok(home)) => format!("{}/{}", home.trim_end_matches('/'), rest),
+        _ => path.to_string(),
+    }
+}
+
+/// Human-readable size, e.g. `12.3 MiB`
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
 pub fn get_repository_salt(repo_path: &Path) -> Result<String, KittyError> {
     // First try to extract salt from a separate salt file (simpler approach)
     let salt_path = repo_path.join("salt.key");
@@ -55,6 +458,10 @@ pub fn get_repository_salt(repo_path: &Path) -> Result<String, KittyError> {
     // Return a placeholder salt as a fallback
     // This will fail for existing repositories, but that's expected
     // as we're changing the salt storage mechanism
+    eprintln!(
+        "Note: {} has no salt.key. If the password is not accepted, run `kitty recover --find-salt`.",
+        repo_path.display()
+    );
     Ok("0000000000000000000000000000000000000000000000000000000000000000".to_string())
 }
 
@@ -74,6 +481,36 @@ pub fn run_with_sudo(command: &[&str]) -> Result<(), KittyError> {
     Ok(())
 }
 
+/// Read a file the current user has no permission to, with `sudo cat`
+pub fn read_with_privileges(path: &Path) -> Result<Vec<u8>, KittyError> {
+    let output = Command::new("sudo")
+        .arg("cat")
+        .arg("--")
+        .arg(path)
+        .stderr(std::process::Stdio::inherit())
+        .output()?;
+
+    if !output.status.success() {
+        return Err(KittyError::PrivilegedRead {
+            path: path.display().to_string(),
+        });
+    }
+    Ok(output.stdout)
+}
+
+/// Write a file the current user has no permission to, copying it into place with sudo
+///
+/// The content goes through a private temporary file that is shredded afterwards.
+pub fn write_with_privileges(path: &Path, contents: &[u8]) -> Result<(), KittyError> {
+    let temp_dir = private_temp_dir("kitty-write")?;
+    let temp_file = temp_dir.join("content");
+    let result = write_private(&temp_file, contents)
+        .map_err(KittyError::from)
+        .and_then(|()| copy_file_with_privileges(&temp_file, path));
+    let _ = secure_delete_dir(&temp_dir);
+    result
+}
+
 pub fn copy_file_with_privileges(source: &Path, dest: &Path) -> Result<(), KittyError> {
     // First try to copy directly
     let copy_result = fs::copy(source, dest);
@@ -90,3 +527,78 @@ pub fn copy_file_with_privileges(source: &Path, dest: &Path) -> Result<(), Kitty
         Ok(())
     }
 }
+
+/// Create the missing parent directories of a file being restored
+///
+/// New directories get mode 0755 less the umask. Their owner comes from the file's
+/// recorded metadata, or else from the nearest existing ancestor, so restoring as root
+/// doesn't leave root-owned directories in a home directory. With `escalate`, trees the
+/// current user cannot write to, such as those under /etc, are created with `sudo mkdir`.
+pub fn create_parent_directories(
+    file_path: &Path,
+    metadata: Option<&FileMetadata>,
+    escalate: bool,
+) -> Result<(), KittyError> {
+    let Some(parent) = file_path.parent() else {
+        return Ok(());
+    };
+
+    // Directories to create, outermost first
+    let mut missing: Vec<&Path> = parent.ancestors().take_while(|dir| !dir.exists()).collect();
+    missing.reverse();
+
+    let Some(outermost) = missing.first() else {
+        return Ok(());
+    };
+    let owner = metadata
+        .map(|m| (m.uid, m.gid))
+        .or_else(|| outermost.parent().and_then(owner_of));
+
+    for dir in missing {
+        let dir_str = dir.to_string_lossy();
+        match create_directory(dir) {
+            Ok(()) => {
+                if let Some((uid, gid)) = owner {
+                    set_owner(dir, uid, gid);
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::PermissionDenied && escalate => {
+                println!(
+                    "Permission denied, creating {} with elevated privileges...",
+                    dir_str
+                );
+                run_with_sudo(&["mkdir", &dir_str])?;
+                if let Some((uid, gid)) = owner.filter(|&owner| owner != (0, 0)) {
+                    run_with_sudo(&["chown", &format!("{}:{}", uid, gid), &dir_str])?;
+                }
+            }
+            Err(e) => return Err(KittyError::Io(e)),
+        }
+    }
+
+    Ok(())
+}
+
+fn create_directory(dir: &Path) -> io::Result<()> {
+    fs::DirBuilder::new().mode(DIRECTORY_MODE).create(dir)
+}
+
+fn owner_of(path: &Path) -> Option<(u32, u32)> {
+    FileMetadata::from_path(path).map(|m| (m.uid, m.gid))
+}
+
+/// Hand a directory to its intended owner, which only works when running as root
+fn set_owner(dir: &Path, uid: u32, gid: u32) {
+    if owner_of(dir) == Some((uid, gid)) {
+        return;
+    }
+    if let Err(e) = std::os::unix::fs::chown(dir, Some(uid), Some(gid)) {
+        println!(
+            "Warning: could not give {} to {}:{}: {}",
+            dir.display(),
+            uid,
+            gid,
+            e
+        );
+    }
+}
@@ -0,0 +1,43 @@
+use crate::{
+    collectors::read_live,
+    commands::init::{ContentFilter, KittyError, TrackedFile},
+};
+use regex::Regex;
+
+fn compile(pattern: &str) -> Result<Regex, KittyError> {
+    Regex::new(pattern).map_err(|e| KittyError::Filter(format!("{}: {}", pattern, e)))
+}
+
+/// Rewrite content with a file's filters, in the order they were added
+///
+/// Filters work on text, so binary content is returned unchanged.
+pub fn apply_filters(content: &[u8], filters: &[ContentFilter]) -> Result<Vec<u8>, KittyError> {
+    if filters.is_empty() {
+        return Ok(content.to_vec());
+    }
+    let Ok(text) = std::str::from_utf8(content) else {
+        return Ok(content.to_vec());
+    };
+
+    let mut text = text.to_string();
+    for filter in filters {
+        text = match filter {
+            ContentFilter::Strip(pattern) => {
+                let regex = compile(pattern)?;
+                text.split_inclusive('\n')
+                    .filter(|line| !regex.is_match(line.trim_end_matches(['\r', '\n'])))
+                    .collect()
+            }
+            ContentFilter::Replace { pattern, with } => compile(pattern)?
+                .replace_all(&text, with.as_str())
+                .into_owned(),
+        };
+    }
+
+    Ok(text.into_bytes())
+}
+
+/// Current content of a tracked entry as its filters leave it, ready to compare with the stored copy
+pub fn read_filtered(file: &TrackedFile) -> Result<Vec<u8>, KittyError> {
+    apply_filters(&read_live(&file.original_path)?, &file.filters)
+}
@@ -0,0 +1,106 @@
+use crate::utils::timings::{self, Phase};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    fs::File,
+    io::{self, Read},
+    path::Path,
+};
+
+/// Prefix marking a SHA-256 hash; blake3 hashes are stored bare, as they always were
+const SHA256_PREFIX: &str = "sha256:";
+
+/// Content at least this large is hashed with BLAKE3 on several threads
+const PARALLEL_THRESHOLD: usize = 128 * 1024;
+
+/// How much of a file is read at a time to hash it
+const READ_CHUNK: usize = 1024 * 1024;
+
+/// Algorithm used to hash file content
+///
+/// Every stored hash says which algorithm made it, so a repository can move
+/// from one to the other file by file.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Default, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgorithm {
+    #[default]
+    Blake3,
+    Sha256,
+}
+
+impl HashAlgorithm {
+    /// The algorithm a stored hash was made with
+    pub fn of(hash: &str) -> Self {
+        if hash.starts_with(SHA256_PREFIX) {
+            HashAlgorithm::Sha256
+        } else {
+            HashAlgorithm::Blake3
+        }
+    }
+
+    /// Hash content, in the form it is stored in
+    pub fn digest(self, content: &[u8]) -> String {
+        timings::time(Phase::Hashing, || match self {
+            HashAlgorithm::Blake3 if content.len() >= PARALLEL_THRESHOLD => {
+                let mut hasher = blake3::Hasher::new();
+                hasher.update_rayon(content);
+                hasher.finalize().to_hex().to_string()
+            }
+            HashAlgorithm::Blake3 => blake3::hash(content).to_hex().to_string(),
+            HashAlgorithm::Sha256 => {
+                format!("{}{}", SHA256_PREFIX, hex::encode(Sha256::digest(content)))
+            }
+        })
+    }
+
+    /// Hash a file without reading all of it into memory
+    ///
+    /// The file is read in chunks, never memory-mapped: tracked files are live,
+    /// and one truncated while mapped would kill kitty with SIGBUS. BLAKE3
+    /// hashes each chunk on several threads.
+    pub fn digest_file(self, path: &Path) -> io::Result<String> {
+        timings::time(Phase::Hashing, || {
+            let mut file = File::open(path)?;
+            match self {
+                HashAlgorithm::Blake3 => {
+                    let mut hasher = blake3::Hasher::new();
+                    let mut buffer = vec![0; READ_CHUNK];
+                    loop {
+                        let read = match file.read(&mut buffer) {
+                            Ok(0) => break,
+                            Ok(read) => read,
+                            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                            Err(e) => return Err(e),
+                        };
+                        hasher.update_rayon(&buffer[..read]);
+                    }
+                    Ok(hasher.finalize().to_hex().to_string())
+                }
+                HashAlgorithm::Sha256 => {
+                    let mut hasher = Sha256::new();
+                    io::copy(&mut file, &mut hasher)?;
+                    Ok(format!(
+                        "{}{}",
+                        SHA256_PREFIX,
+                        hex::encode(hasher.finalize())
+                    ))
+                }
+            }
+        })
+    }
+}
+
+/// The hex digits of a stored hash, without its algorithm prefix
+pub fn hex_digits(hash: &str) -> &str {
+    hash.strip_prefix(SHA256_PREFIX).unwrap_or(hash)
+}
+
+/// Hash content with the algorithm `stored` was made with, so the two can be compared
+pub fn digest_like(stored: &str, content: &[u8]) -> String {
+    HashAlgorithm::of(stored).digest(content)
+}
+
+/// Hash a file with the algorithm `stored` was made with, without reading it all into memory
+pub fn digest_file_like(stored: &str, path: &Path) -> io::Result<String> {
+    HashAlgorithm::of(stored).digest_file(path)
+}
@@ -0,0 +1,185 @@
+use crate::{
+    repository::config::RepoConfig,
+    utils::{
+        audit::AuditEntry,
+        shred::{private_temp_dir, secure_delete_dir},
+        theme,
+    },
+};
+use serde::Serialize;
+use similar::TextDiff;
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, ErrorKind, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+/// Largest content diffed for hooks; bigger files are passed without a diff
+const MAX_DIFF_INPUT: usize = 1024 * 1024;
+
+/// What a hook reads on stdin
+#[derive(Serialize)]
+struct HookPayload<'a> {
+    event: &'a str,
+    repository: String,
+    files: &'a [AuditEntry],
+
+    /// Unified diffs of the changed files, keyed by path
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    diffs: &'a BTreeMap<String, String>,
+}
+
+/// Whether hooks for `event` take diffs, so callers only read old content when they do
+pub fn wants_diffs(repo_path: &Path, event: &str) -> bool {
+    RepoConfig::load(repo_path).is_ok_and(|config| {
+        config.hook_diffs && config.hooks.get(event).is_some_and(|c| !c.is_empty())
+    })
+}
+
+/// A unified diff from `old` to `new`, as hooks and mailed reports get it
+///
+/// None when nothing changed, or for binary or very large content.
+pub fn unified_diff(path: &str, old: &[u8], new: &[u8]) -> Option<String> {
+    if old == new || old.len() > MAX_DIFF_INPUT || new.len() > MAX_DIFF_INPUT {
+        return None;
+    }
+    let (Ok(old), Ok(new)) = (std::str::from_utf8(old), std::str::from_utf8(new)) else {
+        return None;
+    };
+    Some(
+        TextDiff::from_lines(old, new)
+            .unified_diff()
+            .header(&format!("a{}", path), &format!("b{}", path))
+            .to_string(),
+    )
+}
+
+/// Run the commands configured for `event`, passing the affected files as JSON on stdin
+///
+/// Hooks run once the change is saved, so a failing hook is reported but does not
+/// fail the command that triggered it. Returns how many hooks were run.
+pub fn run(repo_path: &Path, event: &str, files: &[AuditEntry]) -> usize {
+    run_with_diffs(repo_path, event, files, &BTreeMap::new())
+}
+
+/// Run the hooks for `event` with the diffs of what changed, keyed by path
+///
+/// The diffs are part of the JSON on stdin, and are also written together to a
+/// private file named in `KITTY_DIFF_FILE`, which is shredded once the hooks ran.
+pub fn run_with_diffs(
+    repo_path: &Path,
+    event: &str,
+    files: &[AuditEntry],
+    diffs: &BTreeMap<String, String>,
+) -> usize {
+    if files.is_empty() {
+        return 0;
+    }
+    let Ok(config) = RepoConfig::load(repo_path) else {
+        return 0;
+    };
+    let Some(commands) = config.hooks.get(event).filter(|c| !c.is_empty()) else {
+        return 0;
+    };
+
+    let payload = HookPayload {
+        event,
+        repository: repo_path.display().to_string(),
+        files,
+        diffs,
+    };
+    let Ok(payload) = serde_json::to_vec(&payload) else {
+        return 0;
+    };
+
+    let diff_dir = if diffs.is_empty() {
+        None
+    } else {
+        match write_diff_file(diffs) {
+            Ok(dir) => Some(dir),
+            Err(e) => {
+                eprintln!(
+                    "{} Could not write the diff for {} hooks: {}",
+                    theme::warning("WARNING:"),
+                    event,
+                    e
+                );
+                None
+            }
+        }
+    };
+    let diff_file = diff_dir.as_ref().map(|dir| dir.join(DIFF_FILE));
+
+    for command in commands {
+        if let Err(e) = run_command(repo_path, event, command, &payload, diff_file.as_deref()) {
+            eprintln!(
+                "{} {} hook `{}` failed: {}",
+                theme::warning("WARNING:"),
+                event,
+                command,
+                e
+            );
+        }
+    }
+
+    if let Some(dir) = diff_dir {
+        if let Err(e) = secure_delete_dir(&dir) {
+            eprintln!(
+                "{} Could not clean up {}: {}",
+                theme::warning("WARNING:"),
+                dir.display(),
+                e
+            );
+        }
+    }
+    commands.len()
+}
+
+/// Name of the file holding every diff, in the private directory `KITTY_DIFF_FILE` points into
+const DIFF_FILE: &str = "changes.diff";
+
+fn write_diff_file(diffs: &BTreeMap<String, String>) -> io::Result<PathBuf> {
+    let dir = private_temp_dir("kitty-hook")?;
+    let contents: String = diffs.values().map(String::as_str).collect();
+    fs::write(dir.join(DIFF_FILE), contents)?;
+    Ok(dir)
+}
+
+fn run_command(
+    repo_path: &Path,
+    event: &str,
+    command: &str,
+    payload: &[u8],
+    diff_file: Option<&Path>,
+) -> io::Result<()> {
+    // Hook output goes to stderr so it never mixes with output meant for scripts
+    let mut shell = Command::new("sh");
+    shell
+        .arg("-c")
+        .arg(command)
+        .env("KITTY_EVENT", event)
+        .env("KITTY_REPO", repo_path)
+        .stdin(Stdio::piped())
+        .stdout(io::stderr());
+    if let Some(diff_file) = diff_file {
+        shell.env("KITTY_DIFF_FILE", diff_file);
+    }
+    let mut child = shell.spawn()?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        // A hook that does not read its input closes the pipe early, which is fine
+        if let Err(e) = stdin.write_all(payload) {
+            if e.kind() != ErrorKind::BrokenPipe {
+                return Err(e);
+            }
+        }
+    }
+
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(io::Error::other(status.to_string()));
+    }
+    Ok(())
+}
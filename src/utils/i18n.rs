@@ -0,0 +1,178 @@
+use crate::repository::config::RepoConfig;
+use fluent_bundle::{concurrent::FluentBundle, FluentArgs, FluentResource, FluentValue};
+use std::{
+    io::{self, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        OnceLock,
+    },
+};
+use unic_langid::LanguageIdentifier;
+
+/// Language used when nothing else is requested, and for messages a catalog lacks
+const FALLBACK_LOCALE: &str = "en-US";
+
+/// Answers yes to every confirmation when set to anything but empty, `0`, `false` or `no`
+pub const ASSUME_YES_ENV: &str = "KITTY_ASSUME_YES";
+
+/// Set by the global `--yes` flag
+static ASSUME_YES: AtomicBool = AtomicBool::new(false);
+
+/// Message catalogs compiled into the binary, keyed by locale
+const CATALOGS: [(&str, &str); 2] = [
+    ("en-US", include_str!("../../locales/en-US.ftl")),
+    ("de", include_str!("../../locales/de.ftl")),
+];
+
+/// Look up a message in the user's language, with optional `name = value` arguments
+///
+/// ```ignore
+/// println!("{}", tr!("list-total", count = files.len()));
+/// ```
+#[macro_export]
+macro_rules! tr {
+    ($id:expr) => {
+        $crate::utils::i18n::message($id, &[])
+    };
+    ($id:expr, $($name:ident = $value:expr),+ $(,)?) => {
+        $crate::utils::i18n::message(
+            $id,
+            &[$((stringify!($name), fluent_bundle::FluentValue::from($value))),+],
+        )
+    };
+}
+
+/// Bundles to search, the requested language first and English last
+struct Catalog {
+    bundles: Vec<FluentBundle<FluentResource>>,
+}
+
+static CATALOG: OnceLock<Catalog> = OnceLock::new();
+
+impl Catalog {
+    fn load() -> Self {
+        let requested = requested_locale();
+        let mut locales = Vec::new();
+        if let Some(locale) = requested
+            .as_ref()
+            .and_then(|requested| best_match(requested))
+        {
+            locales.push(locale);
+        }
+        if !locales.contains(&FALLBACK_LOCALE) {
+            locales.push(FALLBACK_LOCALE);
+        }
+
+        let bundles = locales
+            .into_iter()
+            .filter_map(|locale| {
+                let (_, source) = CATALOGS.iter().find(|(name, _)| *name == locale)?;
+                let langid: LanguageIdentifier = locale.parse().ok()?;
+                let resource = FluentResource::try_new(source.to_string())
+                    .unwrap_or_else(|(resource, _)| resource);
+
+                let mut bundle = FluentBundle::new_concurrent(vec![langid]);
+                // Unicode isolation marks show up as junk in most terminals
+                bundle.set_use_isolating(false);
+                let _ = bundle.add_resource(resource);
+                Some(bundle)
+            })
+            .collect();
+
+        Catalog { bundles }
+    }
+}
+
+/// Format a message, falling back to English and then to the message ID itself
+pub fn message(id: &str, args: &[(&str, FluentValue)]) -> String {
+    let catalog = CATALOG.get_or_init(Catalog::load);
+
+    let mut fluent_args = FluentArgs::new();
+    for (name, value) in args {
+        fluent_args.set(*name, value.clone());
+    }
+
+    for bundle in &catalog.bundles {
+        if let Some(pattern) = bundle.get_message(id).and_then(|message| message.value()) {
+            let mut errors = Vec::new();
+            return bundle
+                .format_pattern(pattern, Some(&fluent_args), &mut errors)
+                .into_owned();
+        }
+    }
+    id.to_string()
+}
+
+/// Answer yes to every confirmation from now on
+pub fn set_assume_yes() {
+    ASSUME_YES.store(true, Ordering::Relaxed);
+}
+
+/// Whether confirmations are answered without asking, through `--yes` or `KITTY_ASSUME_YES`
+pub fn assume_yes() -> bool {
+    ASSUME_YES.load(Ordering::Relaxed)
+        || std::env::var(ASSUME_YES_ENV).is_ok_and(|value| {
+            !matches!(
+                value.trim().to_lowercase().as_str(),
+                "" | "0" | "false" | "no"
+            )
+        })
+}
+
+/// Ask the user to confirm, accepting the answers the current language counts as yes
+///
+/// With `--yes` the prompt is printed together with the answer, so logs show what was agreed to.
+pub fn confirm() -> io::Result<bool> {
+    print!("{} ", message("confirm-prompt", &[]));
+    if assume_yes() {
+        let yes = message("confirm-yes", &[]);
+        println!("{}", yes.split(',').next().unwrap_or_default().trim());
+        return Ok(true);
+    }
+    io::stdout().flush()?;
+
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+    let answer = input.trim().to_lowercase();
+
+    Ok(message("confirm-yes", &[])
+        .split(',')
+        .any(|yes| !answer.is_empty() && yes.trim() == answer))
+}
+
+/// The language asked for: `locale` in config.json, then `LC_ALL`, `LC_MESSAGES` and `LANG`
+fn requested_locale() -> Option<LanguageIdentifier> {
+    let configured = RepoConfig::current().and_then(|config| config.locale);
+
+    configured
+        .into_iter()
+        .chain(
+            ["LC_ALL", "LC_MESSAGES", "LANG"]
+                .iter()
+                .filter_map(|name| std::env::var(name).ok()),
+        )
+        .find(|value| !value.is_empty())
+        .and_then(|value| {
+            // POSIX locales look like de_DE.UTF-8@euro; "C" and "POSIX" fail to parse
+            let name = value.split(['.', '@']).next().unwrap_or_default();
+            name.replace('_', "-").parse().ok()
+        })
+}
+
+/// The catalog for the requested language, preferring an exact region match
+fn best_match(requested: &LanguageIdentifier) -> Option<&'static str> {
+    let available: Vec<(&'static str, LanguageIdentifier)> = CATALOGS
+        .iter()
+        .filter_map(|(name, _)| Some((*name, name.parse().ok()?)))
+        .collect();
+
+    available
+        .iter()
+        .find(|(_, langid)| langid == requested)
+        .or_else(|| {
+            available
+                .iter()
+                .find(|(_, langid)| langid.language == requested.language)
+        })
+        .map(|(name, _)| *name)
+}
@@ -0,0 +1,130 @@
+use crate::{
+    commands::{
+        check::{compute_drift, FileDrift},
+        init::{KittyError, TrackedFile},
+    },
+    utils::file::write_private,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path, time::UNIX_EPOCH};
+
+/// Unencrypted summary of the last drift check, read by `kitty prompt`
+///
+/// It holds paths and file sizes but no hashes or content, so it can be read
+/// without the password.
+const INDEX_FILE: &str = "index.json";
+
+/// What a tracked file looked like when its drift was last worked out
+#[derive(Serialize, Deserialize, Clone)]
+pub struct IndexEntry {
+    pub path: String,
+
+    /// Whether the file had drifted at the time
+    pub drifted: bool,
+
+    /// Size and mtime seen at the time; None for missing files and collectors
+    pub size: Option<u64>,
+    pub modified_ns: Option<u128>,
+}
+
+impl IndexEntry {
+    fn new(drift: &FileDrift) -> Self {
+        let (size, modified_ns) = stat(&drift.path);
+        Self {
+            path: drift.path.clone(),
+            drifted: drift.state.is_drifted(),
+            size,
+            modified_ns,
+        }
+    }
+
+    /// Whether the file has drifted, judged by its size and mtime instead of its content
+    ///
+    /// A file touched since the last check counts as drifted until a command
+    /// that can hash it says otherwise.
+    pub fn is_drifted(&self) -> bool {
+        if self.drifted {
+            return true;
+        }
+        // Collectors have nothing to stat; trust the last check
+        if self.size.is_none() && self.modified_ns.is_none() {
+            return false;
+        }
+        stat(&self.path) != (self.size, self.modified_ns)
+    }
+}
+
+/// The index as a whole
+#[derive(Serialize, Deserialize)]
+pub struct DriftIndex {
+    pub updated_at: DateTime<Utc>,
+    pub files: Vec<IndexEntry>,
+}
+
+fn stat(path: &str) -> (Option<u64>, Option<u128>) {
+    match fs::metadata(path) {
+        Ok(metadata) => (
+            Some(metadata.len()),
+            metadata
+                .modified()
+                .ok()
+                .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                .map(|since| since.as_nanos()),
+        ),
+        Err(_) => (None, None),
+    }
+}
+
+/// Read the index, or None when no command has written one yet
+pub fn load(repo_path: &Path) -> Option<DriftIndex> {
+    let contents = fs::read(repo_path.join(INDEX_FILE)).ok()?;
+    serde_json::from_slice(&contents).ok()
+}
+
+fn save(repo_path: &Path, files: Vec<IndexEntry>) -> Result<(), KittyError> {
+    let index = DriftIndex {
+        updated_at: Utc::now(),
+        files,
+    };
+
+    // Readers never see a half-written index
+    write_private(&repo_path.join(INDEX_FILE), serde_json::to_vec(&index)?)?;
+    Ok(())
+}
+
+/// Replace the index with the result of a check over every tracked file
+pub fn replace(repo_path: &Path, drift: &[FileDrift]) -> Result<(), KittyError> {
+    save(repo_path, drift.iter().map(IndexEntry::new).collect())
+}
+
+/// Update the entries of some files, leaving the others as they were
+pub fn update(repo_path: &Path, drift: &[FileDrift]) -> Result<(), KittyError> {
+    let mut files = load(repo_path).map(|index| index.files).unwrap_or_default();
+    for entry in drift.iter().map(IndexEntry::new) {
+        match files.iter_mut().find(|f| f.path == entry.path) {
+            Some(existing) => *existing = entry,
+            None => files.push(entry),
+        }
+    }
+    save(repo_path, files)
+}
+
+/// Check files that were just written or tracked and update their entries
+pub fn refresh(repo_path: &Path, files: &[&TrackedFile]) -> Result<(), KittyError> {
+    let drift: Vec<FileDrift> = files.iter().map(|file| compute_drift(file)).collect();
+    update(repo_path, &drift)
+}
+
+/// Drop the entries of files that are no longer tracked
+pub fn forget(repo_path: &Path, paths: &[String]) -> Result<(), KittyError> {
+    let Some(index) = load(repo_path) else {
+        return Ok(());
+    };
+    let files = index
+        .files
+        .into_iter()
+        .filter(|f| !paths.contains(&f.path))
+        .collect();
+    save(repo_path, files)
+}
@@ -0,0 +1,168 @@
+use crate::{
+    commands::init::{Crypto, KittyError, Repository},
+    repository::config::RepoConfig,
+    utils::{
+        file::{write_atomic, write_private},
+        theme,
+    },
+};
+use chrono::{DateTime, Utc};
+use ring::{
+    rand::SystemRandom,
+    signature::{Ed25519KeyPair, KeyPair, UnparsedPublicKey, ED25519},
+};
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
+
+/// Plaintext list of what the repository holds, for tools that have no password
+pub const INVENTORY_FILE: &str = "inventory.json";
+
+/// Hex Ed25519 signature over the exact bytes of the inventory
+pub const SIGNATURE_FILE: &str = "inventory.sig";
+
+/// Hex public key the signature is checked against
+pub const PUBLIC_KEY_FILE: &str = "inventory.pub";
+
+/// The signing key, encrypted like the rest of the repository
+const SECRET_KEY_FILE: &str = "inventory.key";
+
+/// A stored version, without its content
+#[derive(Serialize, Deserialize)]
+pub struct InventoryVersion {
+    pub hash: String,
+    pub saved_at: DateTime<Utc>,
+}
+
+/// One tracked file, without its content
+#[derive(Serialize, Deserialize)]
+pub struct InventoryEntry {
+    pub path: String,
+    pub hash: String,
+    pub added_at: DateTime<Utc>,
+    pub last_updated: DateTime<Utc>,
+    pub versions: Vec<InventoryVersion>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Inventory {
+    pub generated_at: DateTime<Utc>,
+    pub files: Vec<InventoryEntry>,
+}
+
+impl Inventory {
+    fn new(repository: &Repository) -> Self {
+        let files = repository
+            .files
+            .iter()
+            .map(|file| InventoryEntry {
+                path: file.original_path.clone(),
+                hash: file.hash.clone(),
+                added_at: file.added_at,
+                last_updated: file.last_updated,
+                versions: file
+                    .versions
+                    .iter()
+                    .map(|version| InventoryVersion {
+                        hash: version.hash.clone(),
+                        saved_at: version.saved_at,
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        Self {
+            generated_at: Utc::now(),
+            files,
+        }
+    }
+}
+
+/// Load the signing key, creating one the first time an inventory is written
+fn signing_key(repo_path: &Path, crypto: &Crypto) -> Result<Ed25519KeyPair, KittyError> {
+    let key_path = repo_path.join(SECRET_KEY_FILE);
+    let pkcs8 = if key_path.exists() {
+        crypto.decrypt(&fs::read(&key_path)?)?
+    } else {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new())
+            .map_err(|_| KittyError::Inventory("Could not generate a signing key".to_string()))?;
+        write_private(&key_path, &crypto.encrypt(pkcs8.as_ref())?)?;
+        pkcs8.as_ref().to_vec()
+    };
+
+    let key_pair = Ed25519KeyPair::from_pkcs8(&pkcs8)
+        .map_err(|e| KittyError::Inventory(format!("Invalid signing key: {}", e)))?;
+
+    let public_key = hex::encode(key_pair.public_key().as_ref());
+    let public_path = repo_path.join(PUBLIC_KEY_FILE);
+    if fs::read_to_string(&public_path)
+        .ok()
+        .as_deref()
+        .map(str::trim)
+        != Some(&public_key)
+    {
+        write_atomic(&public_path, format!("{}\n", public_key).as_bytes())?;
+    }
+
+    Ok(key_pair)
+}
+
+/// Write and sign the inventory of the repository as it is now
+pub fn write(repo_path: &Path, crypto: &Crypto, repository: &Repository) -> Result<(), KittyError> {
+    let key_pair = signing_key(repo_path, crypto)?;
+    let contents = serde_json::to_vec_pretty(&Inventory::new(repository))?;
+    let signature = key_pair.sign(&contents);
+
+    write_atomic(&repo_path.join(INVENTORY_FILE), &contents)?;
+    write_atomic(
+        &repo_path.join(SIGNATURE_FILE),
+        format!("{}\n", hex::encode(signature.as_ref())).as_bytes(),
+    )?;
+    Ok(())
+}
+
+/// Rewrite the inventory after a change, when the repository asks for one
+///
+/// The change is already saved, so a failure is reported instead of returned.
+pub fn update(repo_path: &Path, crypto: &Crypto, repository: &Repository) {
+    let enabled = RepoConfig::load(repo_path)
+        .map(|config| config.inventory)
+        .unwrap_or(false);
+    if !enabled {
+        return;
+    }
+
+    if let Err(e) = write(repo_path, crypto, repository) {
+        eprintln!(
+            "{} Failed to update the inventory: {}",
+            theme::warning("WARNING:"),
+            e
+        );
+    }
+}
+
+/// Check the inventory in `dir` against its signature and return it
+///
+/// `public_key` pins the key to check against; without it the key stored next to
+/// the inventory is used, which only shows the files belong together.
+pub fn verify(dir: &Path, public_key: Option<&str>) -> Result<Inventory, KittyError> {
+    let read = |name: &str| {
+        fs::read(dir.join(name)).map_err(|e| {
+            KittyError::Inventory(format!("Cannot read {}: {}", dir.join(name).display(), e))
+        })
+    };
+
+    let contents = read(INVENTORY_FILE)?;
+    let signature = hex::decode(String::from_utf8_lossy(&read(SIGNATURE_FILE)?).trim())?;
+    let public_key = match public_key {
+        Some(key) => hex::decode(key.trim())?,
+        None => hex::decode(String::from_utf8_lossy(&read(PUBLIC_KEY_FILE)?).trim())?,
+    };
+
+    UnparsedPublicKey::new(&ED25519, &public_key)
+        .verify(&contents, &signature)
+        .map_err(|_| {
+            KittyError::Inventory("The signature does not match the inventory".to_string())
+        })?;
+
+    Ok(serde_json::from_slice(&contents)?)
+}
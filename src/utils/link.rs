@@ -0,0 +1,73 @@
+use crate::commands::init::KittyError;
+use std::{
+    fs,
+    io::Write,
+    os::unix::fs::{DirBuilderExt, OpenOptionsExt, PermissionsExt},
+    path::{Component, Path, PathBuf},
+};
+
+/// Directory in the repository holding the plaintext copies that restored links point to
+pub const LINKED_DIR: &str = "linked";
+
+/// Where the managed copy of a tracked file lives, mirroring its absolute path
+///
+/// Always absolute, since links are created from anywhere in the filesystem.
+pub fn linked_path(repo_path: &Path, original_path: &Path) -> PathBuf {
+    let repo_path = repo_path
+        .canonicalize()
+        .unwrap_or_else(|_| repo_path.to_path_buf());
+    let relative: PathBuf = original_path
+        .components()
+        .filter(|c| matches!(c, Component::Normal(_)))
+        .collect();
+    repo_path.join(LINKED_DIR).join(relative)
+}
+
+/// The tracked path a managed copy stands in for, if `path` is inside the linked directory
+///
+/// Resolving a restored link leads into the repository, so callers use this to
+/// get back to the path the user meant.
+pub fn original_for_linked(repo_path: &Path, path: &Path) -> Option<PathBuf> {
+    let linked_dir = repo_path.canonicalize().ok()?.join(LINKED_DIR);
+    path.strip_prefix(linked_dir)
+        .ok()
+        .map(|relative| Path::new("/").join(relative))
+}
+
+/// Write a file's content into the linked directory and point `path` at it
+pub fn link_file(repo_path: &Path, path: &Path, content: &[u8]) -> Result<(), KittyError> {
+    let linked_dir = repo_path.join(LINKED_DIR);
+    if !linked_dir.exists() {
+        // The copies are plaintext, so keep them away from other users
+        fs::DirBuilder::new().mode(0o700).create(&linked_dir)?;
+    }
+
+    let managed = linked_path(repo_path, path);
+    if let Some(parent) = managed.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut copy = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&managed)?;
+    // A copy written by an older kitty keeps its mode unless it is set again
+    copy.set_permissions(fs::Permissions::from_mode(0o600))?;
+    copy.write_all(content)?;
+
+    // Replace whatever is at the destination, including an older link
+    if path.symlink_metadata().is_ok() {
+        fs::remove_file(path)?;
+    }
+    std::os::unix::fs::symlink(&managed, path)?;
+
+    Ok(())
+}
+
+/// Whether `path` is a link to its managed copy
+pub fn is_linked(repo_path: &Path, path: &Path) -> bool {
+    fs::read_link(path)
+        .map(|target| target == linked_path(repo_path, path))
+        .unwrap_or(false)
+}
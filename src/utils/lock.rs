@@ -0,0 +1,211 @@
+use crate::{
+    commands::init::KittyError,
+    repository::config::RepoConfig,
+    utils::{platform::hostname, theme},
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// File in the repository directory that exists while a kitty is changing the repository
+pub const LOCK_FILE: &str = "lock";
+
+/// How often a waiting kitty looks at the lock again
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Who holds the repository lock, as written to the lock file
+#[derive(Serialize, Deserialize)]
+pub struct LockOwner {
+    pub host: String,
+    pub pid: u32,
+    pub user: String,
+    pub acquired_at: DateTime<Utc>,
+
+    /// Tells this lock apart from a later one by the same process after it was broken
+    pub token: String,
+}
+
+impl LockOwner {
+    fn current() -> Self {
+        Self {
+            host: hostname(),
+            pid: std::process::id(),
+            user: std::env::var("USER")
+                .or_else(|_| std::env::var("USERNAME"))
+                .unwrap_or_else(|_| "unknown".to_string()),
+            acquired_at: Utc::now(),
+            token: uuid::Uuid::new_v4().to_string(),
+        }
+    }
+
+    /// Why this lock can be broken, or None while its owner may still be working
+    ///
+    /// A lock taken on this machine is held as long as its process runs, however
+    /// long that takes. Age only counts when the owner cannot be looked at, as
+    /// on another host.
+    fn staleness(&self, stale_after: chrono::Duration) -> Option<String> {
+        if self.host == hostname() {
+            match process_alive(self.pid) {
+                Some(true) => return None,
+                Some(false) => {
+                    return Some(format!("process {} on {} is gone", self.pid, self.host))
+                }
+                None => {}
+            }
+        }
+        let age = Utc::now() - self.acquired_at;
+        (age > stale_after).then(|| format!("it is {} seconds old", age.num_seconds()))
+    }
+}
+
+impl std::fmt::Display for LockOwner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}@{} (pid {}) since {}",
+            self.user,
+            self.host,
+            self.pid,
+            self.acquired_at.format("%Y-%m-%d %H:%M:%S UTC")
+        )
+    }
+}
+
+/// Whether a process on this machine is still running, when that can be told
+fn process_alive(pid: u32) -> Option<bool> {
+    if cfg!(target_os = "linux") {
+        Some(Path::new("/proc").join(pid.to_string()).exists())
+    } else {
+        None
+    }
+}
+
+/// Exclusive access to a repository while it is changed, released when dropped
+///
+/// `flock` cannot be trusted on NFS or SMB, so the lock is a file created with
+/// `O_EXCL`, which those filesystems do honour. It names its owner so that a
+/// lock left behind by a crashed kitty can be recognised and broken.
+pub struct RepoLock {
+    path: PathBuf,
+    token: String,
+}
+
+impl RepoLock {
+    /// Take the lock on a repository, waiting for another kitty to finish first
+    pub fn acquire(repo_path: &Path) -> Result<Self, KittyError> {
+        let settings = RepoConfig::load(repo_path)?.lock;
+        let path = repo_path.join(LOCK_FILE);
+        let owner = LockOwner::current();
+        let contents = serde_json::to_vec_pretty(&owner)?;
+        let stale_after = chrono::Duration::seconds(settings.stale_after_secs as i64);
+        let deadline = Instant::now() + Duration::from_secs(settings.wait_secs);
+        let mut waiting = false;
+
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    if let Err(e) = file.write_all(&contents).and_then(|_| file.sync_all()) {
+                        let _ = fs::remove_file(&path);
+                        return Err(e.into());
+                    }
+                    return Ok(Self {
+                        path,
+                        token: owner.token,
+                    });
+                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+                Err(e) => return Err(e.into()),
+            }
+
+            let holder = read_owner(&path);
+            match &holder {
+                Some(holder) => {
+                    if let Some(reason) = holder.staleness(stale_after) {
+                        eprintln!(
+                            "{} Breaking the repository lock held by {}: {}",
+                            theme::warning("WARNING:"),
+                            holder,
+                            reason
+                        );
+                        break_lock(&path, &holder.token)?;
+                        continue;
+                    }
+                }
+                // Written but not filled in yet, or left empty by a crash while it was
+                None => {
+                    let age = fs::metadata(&path)
+                        .and_then(|m| m.modified())
+                        .ok()
+                        .and_then(|modified| modified.elapsed().ok());
+                    if age.is_some_and(|age| age > Duration::from_secs(settings.stale_after_secs)) {
+                        break_lock(&path, "")?;
+                        continue;
+                    }
+                }
+            }
+
+            if Instant::now() >= deadline {
+                let holder = holder
+                    .map(|holder| holder.to_string())
+                    .unwrap_or_else(|| "another kitty".to_string());
+                return Err(KittyError::Locked(format!(
+                    "{} is holding it; remove {} if that kitty is no longer running",
+                    holder,
+                    path.display()
+                )));
+            }
+            if !waiting {
+                if let Some(holder) = read_owner(&path) {
+                    eprintln!("Waiting for the repository lock held by {}", holder);
+                }
+                waiting = true;
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+impl Drop for RepoLock {
+    fn drop(&mut self) {
+        // A lock broken as stale may have been taken by someone else since
+        if read_owner(&self.path).is_some_and(|owner| owner.token == self.token) {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// The owner named in a lock file, if it can be read
+fn read_owner(path: &Path) -> Option<LockOwner> {
+    serde_json::from_slice(&fs::read(path).ok()?).ok()
+}
+
+/// Remove a stale lock without removing one that replaced it in the meantime
+///
+/// The lock is renamed away first; only one of several kittys breaking it at
+/// once succeeds, and a rename that picked up a fresh lock puts it back.
+fn break_lock(path: &Path, token: &str) -> Result<(), KittyError> {
+    let mut broken = path.as_os_str().to_owned();
+    broken.push(format!(".stale-{}", uuid::Uuid::new_v4()));
+    let broken = PathBuf::from(broken);
+
+    match fs::rename(path, &broken) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    }
+    let taken = read_owner(&broken)
+        .map(|owner| owner.token)
+        .unwrap_or_default();
+    if taken != token {
+        // Someone took the lock between looking at it and breaking it
+        let _ = fs::hard_link(&broken, path);
+    }
+    let _ = fs::remove_file(&broken);
+    Ok(())
+}
@@ -0,0 +1,74 @@
+use crate::{
+    commands::init::KittyError,
+    repository::config::{SmtpSecurity, SmtpSettings},
+};
+use lettre::{
+    message::{header::ContentType, Attachment, Mailbox, MultiPart, SinglePart},
+    transport::smtp::authentication::Credentials,
+    Message, SmtpTransport, Transport,
+};
+use std::{env, time::Duration};
+
+/// Environment variable holding the password for the SMTP login
+pub const SMTP_PASSWORD_ENV: &str = "KITTY_SMTP_PASSWORD";
+
+/// A plain text mail with text files attached
+pub struct Mail {
+    pub subject: String,
+    pub body: String,
+
+    /// File names and their text content
+    pub attachments: Vec<(String, String)>,
+}
+
+fn mailbox(address: &str) -> Result<Mailbox, KittyError> {
+    address
+        .parse()
+        .map_err(|e| KittyError::Mail(format!("'{}' is not a mail address: {}", address, e)))
+}
+
+/// Send a mail through the configured server
+pub fn send(settings: &SmtpSettings, to: &[String], mail: Mail) -> Result<(), KittyError> {
+    let mut builder = Message::builder()
+        .from(mailbox(&settings.from)?)
+        .subject(mail.subject);
+    for address in to {
+        builder = builder.to(mailbox(address)?);
+    }
+
+    let mut parts = MultiPart::mixed().singlepart(SinglePart::plain(mail.body));
+    for (name, content) in mail.attachments {
+        let content_type = ContentType::parse("text/x-diff; charset=utf-8")
+            .map_err(|e| KittyError::Mail(e.to_string()))?;
+        parts = parts.singlepart(Attachment::new(name).body(content, content_type));
+    }
+    let message = builder
+        .multipart(parts)
+        .map_err(|e| KittyError::Mail(e.to_string()))?;
+
+    let transport = match settings.security {
+        SmtpSecurity::Starttls => SmtpTransport::starttls_relay(&settings.host),
+        SmtpSecurity::Tls => SmtpTransport::relay(&settings.host),
+        SmtpSecurity::None => Ok(SmtpTransport::builder_dangerous(&settings.host)),
+    }
+    .map_err(|e| KittyError::Mail(format!("{}: {}", settings.host, e)))?;
+    let mut transport = transport.timeout(Some(Duration::from_secs(30)));
+    if let Some(port) = settings.port {
+        transport = transport.port(port);
+    }
+    if let Some(username) = &settings.username {
+        let password = env::var(SMTP_PASSWORD_ENV).map_err(|_| {
+            KittyError::Mail(format!(
+                "set {} to log in to {} as {}",
+                SMTP_PASSWORD_ENV, settings.host, username
+            ))
+        })?;
+        transport = transport.credentials(Credentials::new(username.clone(), password));
+    }
+
+    transport
+        .build()
+        .send(&message)
+        .map_err(|e| KittyError::Mail(format!("{}: {}", settings.host, e)))?;
+    Ok(())
+}
@@ -0,0 +1,108 @@
+use crate::{commands::init::KittyError, utils::file::write_atomic};
+use chrono::Utc;
+use std::{fmt::Write as _, fs, path::Path};
+use walkdir::WalkDir;
+
+const LAST_SUCCESS_METRIC: &str = "kitty_last_successful_check_timestamp_seconds";
+
+/// Values exported after a drift check
+pub struct CheckMetrics {
+    pub tracked_files: usize,
+    pub drifted_files: usize,
+    pub missing_files: usize,
+    pub passed: bool,
+}
+
+/// Total size in bytes of everything stored in the repository directory
+pub fn repository_size(repo_path: &Path) -> u64 {
+    WalkDir::new(repo_path)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.metadata().ok())
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+/// Write metrics in the Prometheus textfile collector format
+///
+/// The file is written to a temporary path and renamed into place so the
+/// node exporter never scrapes a partially written file. When the check did
+/// not pass, the last successful timestamp is carried over from the previous file.
+pub fn write_check_metrics(
+    metrics_path: &Path,
+    repo_path: &Path,
+    metrics: &CheckMetrics,
+) -> Result<(), KittyError> {
+    let now = Utc::now().timestamp();
+    let last_success = if metrics.passed {
+        Some(now)
+    } else {
+        previous_last_success(metrics_path)
+    };
+
+    let mut out = String::new();
+    gauge(
+        &mut out,
+        "kitty_tracked_files",
+        "Number of files tracked in the repository",
+        metrics.tracked_files as i64,
+    );
+    gauge(
+        &mut out,
+        "kitty_drifted_files",
+        "Number of tracked files that differ from the repository",
+        metrics.drifted_files as i64,
+    );
+    gauge(
+        &mut out,
+        "kitty_missing_files",
+        "Number of tracked files whose original no longer exists",
+        metrics.missing_files as i64,
+    );
+    gauge(
+        &mut out,
+        "kitty_check_passed",
+        "Whether the last check stayed within the drift threshold",
+        metrics.passed as i64,
+    );
+    gauge(
+        &mut out,
+        "kitty_last_check_timestamp_seconds",
+        "Unix time of the last completed check",
+        now,
+    );
+    if let Some(last_success) = last_success {
+        gauge(
+            &mut out,
+            LAST_SUCCESS_METRIC,
+            "Unix time of the last check that passed",
+            last_success,
+        );
+    }
+    gauge(
+        &mut out,
+        "kitty_repository_size_bytes",
+        "Size of the repository directory on disk",
+        repository_size(repo_path) as i64,
+    );
+
+    write_atomic(metrics_path, out)?;
+
+    Ok(())
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, value: i64) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} gauge", name);
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+/// Read the last successful timestamp from a previously written metrics file
+fn previous_last_success(metrics_path: &Path) -> Option<i64> {
+    let contents = fs::read_to_string(metrics_path).ok()?;
+    contents.lines().find_map(|line| {
+        let value = line.strip_prefix(LAST_SUCCESS_METRIC)?.trim();
+        value.parse().ok()
+    })
+}
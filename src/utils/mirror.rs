@@ -0,0 +1,169 @@
+use crate::{
+    commands::init::KittyError,
+    repository::{config::RepoConfig, keys::SIGNATURE_FILE},
+    utils::{
+        events::{self, Event, Severity},
+        file::{sync_parent, temp_path},
+        link::LINKED_DIR,
+        lock::LOCK_FILE,
+        shred::secure_delete_dir,
+        theme,
+    },
+};
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+use walkdir::WalkDir;
+
+/// Files that belong to this machine rather than the repository: the prompt cache, the daemon's API token and the lock
+const LOCAL_FILES: [&str; 3] = ["index.json", "daemon.token", LOCK_FILE];
+
+/// Files that say which objects exist, and the manifest's signature, copied after everything they refer to
+const MANIFESTS: [&str; 4] = ["config.enc", "kitty.db", "kitty.redb", SIGNATURE_FILE];
+
+/// What one pass over a mirror changed
+#[derive(Default)]
+pub struct MirrorStats {
+    pub copied: usize,
+    pub removed: usize,
+}
+
+/// Whether a file in the repository directory is left out of mirrors
+///
+/// The plaintext copies that restored links point to never leave the machine.
+fn is_local(relative: &Path) -> bool {
+    let name = relative.to_string_lossy();
+    LOCAL_FILES.contains(&name.as_ref())
+        || relative.starts_with(LINKED_DIR)
+        || name.starts_with("lock.stale-")
+        || name.ends_with(".tmp")
+        || name.ends_with("-journal")
+}
+
+/// Whether `dir` can become a mirror: it is empty, missing, or already holds a repository copy
+pub fn check_target(dir: &Path) -> Result<(), KittyError> {
+    let Ok(mut entries) = fs::read_dir(dir) else {
+        return Ok(());
+    };
+    if entries.next().is_none() || dir.join("salt.key").exists() {
+        return Ok(());
+    }
+    Err(KittyError::Mirror(format!(
+        "{} is not empty and holds no kitty repository; pick an empty directory",
+        dir.display()
+    )))
+}
+
+/// Bring a mirror in line with the repository, copying only files whose size or mtime differ
+///
+/// Stored content goes first and the manifest last, so a mirror read halfway
+/// through never lists content it does not have yet. Files no longer in the
+/// repository are removed afterwards.
+pub fn sync(repo_path: &Path, dir: &Path) -> Result<MirrorStats, KittyError> {
+    let mut stats = MirrorStats::default();
+    fs::create_dir_all(dir)?;
+
+    let mut sources: Vec<PathBuf> = WalkDir::new(repo_path)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| {
+            entry
+                .path()
+                .strip_prefix(repo_path)
+                .ok()
+                .map(Path::to_path_buf)
+        })
+        .filter(|relative| !is_local(relative))
+        .collect();
+    sources.sort_by_key(|relative| MANIFESTS.contains(&relative.to_string_lossy().as_ref()));
+
+    for relative in &sources {
+        if copy_if_changed(&repo_path.join(relative), &dir.join(relative))? {
+            stats.copied += 1;
+        }
+    }
+
+    // Plaintext copies that older kittys mirrored are shredded rather than just removed
+    let linked_copies = dir.join(LINKED_DIR);
+    if linked_copies.exists() {
+        stats.removed += WalkDir::new(&linked_copies)
+            .into_iter()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().is_file())
+            .count();
+        secure_delete_dir(&linked_copies)?;
+    }
+
+    let kept: HashSet<&PathBuf> = sources.iter().collect();
+    let stale: Vec<PathBuf> = WalkDir::new(dir)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| entry.path().strip_prefix(dir).ok().map(Path::to_path_buf))
+        .filter(|relative| !kept.contains(relative))
+        .collect();
+    for relative in stale {
+        fs::remove_file(dir.join(&relative))?;
+        stats.removed += 1;
+    }
+
+    events::emit(
+        repo_path,
+        &[Event::new(
+            "sync",
+            Severity::Info,
+            format!("Mirror {} synced", dir.display()),
+        )
+        .field("mirror", dir.display())
+        .field("copied", stats.copied)
+        .field("removed", stats.removed)],
+    );
+    Ok(stats)
+}
+
+/// Copy a file unless the mirror's copy has the same size and mtime, replacing it atomically
+fn copy_if_changed(source: &Path, target: &Path) -> Result<bool, KittyError> {
+    let metadata = fs::metadata(source)?;
+    let modified = metadata.modified()?;
+    if let Ok(existing) = fs::metadata(target) {
+        if existing.len() == metadata.len() && existing.modified().ok() == Some(modified) {
+            return Ok(false);
+        }
+    }
+
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let temp = temp_path(target);
+    fs::copy(source, &temp)?;
+    let copy = fs::File::options().write(true).open(&temp)?;
+    copy.set_modified(modified)?;
+    copy.sync_all()?;
+    fs::rename(&temp, target)?;
+    sync_parent(target);
+    Ok(true)
+}
+
+/// Update every mirror after a change to the repository
+///
+/// The change is already saved, so a failure is reported instead of returned.
+pub fn update(repo_path: &Path) {
+    let mirrors = RepoConfig::load(repo_path)
+        .map(|config| config.mirrors)
+        .unwrap_or_default();
+
+    for mirror in mirrors {
+        if let Err(e) = sync(repo_path, Path::new(&mirror)) {
+            eprintln!(
+                "{} Failed to update the mirror in {}: {}",
+                theme::warning("WARNING:"),
+                mirror,
+                e
+            );
+        }
+    }
+}
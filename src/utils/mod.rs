@@ -1,2 +1,36 @@
+pub mod age;
+pub mod alias;
+pub mod audit;
+pub mod aws;
+pub mod errors;
+pub mod events;
 pub mod file;
+pub mod filter;
+pub mod hash;
+pub mod hooks;
+pub mod i18n;
+pub mod index;
+pub mod inventory;
+pub mod link;
+pub mod lock;
+pub mod mail;
+pub mod metrics;
+pub mod mirror;
+pub mod moves;
+pub mod order;
+pub mod package;
+pub mod password;
+pub mod platform;
+pub mod policy;
 pub mod privileges;
+pub mod select;
+pub mod shred;
+pub mod stats;
+pub mod table;
+pub mod theme;
+pub mod timings;
+pub mod tree;
+pub mod validate;
+pub mod vars;
+pub mod vault;
+pub mod watch;
@@ -0,0 +1,120 @@
+use crate::{
+    collectors::Collector,
+    commands::init::{KittyError, TrackedFile, ENCRYPTION_OVERHEAD},
+    repository::config::RepoConfig,
+    storage::{is_database, open_database},
+    utils::{
+        file::{expand_home, get_storage_type},
+        hash::HashAlgorithm,
+    },
+};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+use walkdir::WalkDir;
+
+/// Look for missing files elsewhere, by size and then content hash
+///
+/// Only files whose size matches a stored copy are hashed, so searching a large
+/// directory stays cheap. Files with content filters are stored rewritten and
+/// cannot be matched. Returns the new location of each file found, by original path.
+pub fn detect_moves(
+    repo_path: &Path,
+    files: &[TrackedFile],
+    missing: &[&TrackedFile],
+) -> Result<BTreeMap<String, PathBuf>, KittyError> {
+    let search = RepoConfig::load(repo_path)?.moves;
+    let mut moves = BTreeMap::new();
+    if search.max_depth == 0 {
+        return Ok(moves);
+    }
+
+    let storage = if is_database(&get_storage_type(repo_path)?) {
+        Some(open_database(repo_path)?)
+    } else {
+        None
+    };
+    let stored_size = |path: &str| match &storage {
+        Some(storage) => storage.content_size(path).ok(),
+        None => fs::metadata(repo_path.join(path)).map(|m| m.len()).ok(),
+    };
+
+    // Tracked paths are canonical, so candidates are found through canonical roots too
+    let repo_dir = repo_path.canonicalize()?;
+    let tracked: HashSet<&Path> = files.iter().map(|f| Path::new(&f.original_path)).collect();
+    let configured: Vec<PathBuf> = search
+        .roots
+        .iter()
+        .filter_map(|root| Path::new(&expand_home(root)).canonicalize().ok())
+        .collect();
+    let mut listings: HashMap<PathBuf, Vec<(PathBuf, u64)>> = HashMap::new();
+    let mut hashes: HashMap<(PathBuf, HashAlgorithm), Option<String>> = HashMap::new();
+    let mut claimed: HashSet<PathBuf> = HashSet::new();
+
+    for file in missing {
+        if Collector::parse(&file.original_path).is_some() || !file.filters.is_empty() {
+            continue;
+        }
+        let Some(size) = stored_size(&file.repo_path)
+            .and_then(|size| size.checked_sub(ENCRYPTION_OVERHEAD as u64))
+        else {
+            continue;
+        };
+
+        let roots = if configured.is_empty() {
+            nearest_directory(Path::new(&file.original_path))
+                .into_iter()
+                .collect()
+        } else {
+            configured.clone()
+        };
+        let algorithm = HashAlgorithm::of(&file.hash);
+
+        'roots: for root in roots {
+            let listing = listings
+                .entry(root.clone())
+                .or_insert_with(|| list_files(&root, search.max_depth, &repo_dir));
+            for (candidate, _) in listing.iter().filter(|(_, len)| *len == size) {
+                if tracked.contains(candidate.as_path()) || claimed.contains(candidate) {
+                    continue;
+                }
+                let hash = hashes
+                    .entry((candidate.clone(), algorithm))
+                    .or_insert_with(|| algorithm.digest_file(candidate).ok());
+                if hash.as_deref() == Some(file.hash.as_str()) {
+                    claimed.insert(candidate.clone());
+                    moves.insert(file.original_path.clone(), candidate.clone());
+                    break 'roots;
+                }
+            }
+        }
+    }
+
+    Ok(moves)
+}
+
+/// The directory a file was in, or the closest parent of it that still exists
+fn nearest_directory(path: &Path) -> Option<PathBuf> {
+    path.ancestors()
+        .skip(1)
+        .find(|dir| dir.is_dir())
+        .map(Path::to_path_buf)
+}
+
+/// Regular files up to `max_depth` levels below `root` with their sizes, leaving out the repository
+fn list_files(root: &Path, max_depth: usize, repo_dir: &Path) -> Vec<(PathBuf, u64)> {
+    WalkDir::new(root)
+        .max_depth(max_depth)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|entry| entry.path() != repo_dir)
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| {
+            let len = entry.metadata().ok()?.len();
+            Some((entry.into_path(), len))
+        })
+        .collect()
+}
@@ -0,0 +1,68 @@
+use crate::{
+    commands::init::{KittyError, TrackedFile},
+    utils::file::expand_home,
+};
+use std::collections::BTreeSet;
+
+/// Whether an entry such as `after` refers to a file: by path, by bundle, or by a directory above it
+pub fn refers_to(entry: &str, file: &TrackedFile) -> bool {
+    let entry = expand_home(entry);
+    let entry = entry.trim_end_matches('/');
+
+    file.original_path == entry
+        || file.bundle.as_deref() == Some(entry)
+        || file
+            .original_path
+            .strip_prefix(entry)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
+/// Sort files so every file comes after the paths and bundles it declares in `after`
+///
+/// Constraints naming files outside the selection are ignored, and files without
+/// constraints between them keep their manifest order.
+pub fn order_for_restore<'a>(
+    files: &[&'a TrackedFile],
+) -> Result<Vec<&'a TrackedFile>, KittyError> {
+    // dependents[i] lists the files that must wait for file i
+    let mut dependents = vec![Vec::new(); files.len()];
+    let mut waiting_on = vec![0usize; files.len()];
+
+    for (index, file) in files.iter().enumerate() {
+        for dependency in &file.after {
+            for (other, candidate) in files.iter().enumerate() {
+                if other != index && refers_to(dependency, candidate) {
+                    dependents[other].push(index);
+                    waiting_on[index] += 1;
+                }
+            }
+        }
+    }
+
+    let mut ready: BTreeSet<usize> = (0..files.len()).filter(|&i| waiting_on[i] == 0).collect();
+    let mut ordered = Vec::with_capacity(files.len());
+
+    // Always take the earliest ready file so unconstrained files stay in manifest order
+    while let Some(index) = ready.pop_first() {
+        ordered.push(files[index]);
+        for &dependent in &dependents[index] {
+            waiting_on[dependent] -= 1;
+            if waiting_on[dependent] == 0 {
+                ready.insert(dependent);
+            }
+        }
+    }
+
+    if ordered.len() < files.len() {
+        let stuck: Vec<&str> = (0..files.len())
+            .filter(|&i| waiting_on[i] > 0)
+            .map(|i| files[i].original_path.as_str())
+            .collect();
+        return Err(KittyError::Ordering(format!(
+            "circular dependency; blocked files: {}",
+            stuck.join(", ")
+        )));
+    }
+
+    Ok(ordered)
+}
@@ -0,0 +1,212 @@
+use crate::commands::init::KittyError;
+use crate::utils::shred::{private_temp_dir, secure_delete_dir};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The package that owns a file on the system
+pub struct PackageOwner {
+    /// Package manager that reported ownership (dpkg, rpm or pacman)
+    pub manager: String,
+    /// Name of the owning package
+    pub name: String,
+}
+
+/// Returns true if the path is a system file whose ownership is worth recording
+pub fn is_system_path(path: &Path) -> bool {
+    path.starts_with("/etc")
+}
+
+/// Ask the available package managers which package owns `path`
+pub fn find_owning_package(path: &Path) -> Option<PackageOwner> {
+    let path_str = path.to_str()?;
+
+    // dpkg prints "package[, package...]: /path"
+    if let Some(output) = run_query("dpkg", &["-S", path_str]) {
+        for line in output.lines() {
+            if line.starts_with("diversion ") {
+                continue;
+            }
+            if let Some((packages, _)) = line.split_once(": ") {
+                if let Some(name) = packages.split(", ").next() {
+                    // Multi-arch packages are reported as name:arch
+                    let name = name.split(':').next().unwrap_or(name);
+                    return Some(PackageOwner {
+                        manager: "dpkg".to_string(),
+                        name: name.to_string(),
+                    });
+                }
+            }
+        }
+    }
+
+    if let Some(output) = run_query("rpm", &["-qf", "--queryformat", "%{NAME}", path_str]) {
+        return Some(PackageOwner {
+            manager: "rpm".to_string(),
+            name: output.trim().to_string(),
+        });
+    }
+
+    if let Some(output) = run_query("pacman", &["-Qqo", path_str]) {
+        return Some(PackageOwner {
+            manager: "pacman".to_string(),
+            name: output.trim().to_string(),
+        });
+    }
+
+    None
+}
+
+/// Run a package manager query, returning its stdout only if it succeeded
+fn run_query(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    if stdout.trim().is_empty() {
+        None
+    } else {
+        Some(stdout)
+    }
+}
+
+/// Fetch the pristine content of `path` as shipped by the owning package
+pub fn fetch_pristine_content(
+    manager: &str,
+    package: &str,
+    path: &Path,
+) -> Result<Vec<u8>, KittyError> {
+    let work_dir = private_temp_dir("kitty-pkg")?;
+    let extract_dir = work_dir.join("root");
+    fs::create_dir_all(&extract_dir)?;
+
+    let result = match manager {
+        "dpkg" => extract_dpkg(package, &work_dir, &extract_dir),
+        "rpm" => extract_rpm(package, &work_dir, &extract_dir),
+        "pacman" => extract_pacman(package, path, &extract_dir),
+        _ => Err(KittyError::Package(format!(
+            "Unsupported package manager: {}",
+            manager
+        ))),
+    }
+    .and_then(|_| {
+        let relative = path.strip_prefix("/").unwrap_or(path);
+        let pristine_path = extract_dir.join(relative);
+        fs::read(&pristine_path).map_err(|_| {
+            KittyError::Package(format!(
+                "Package {} does not ship {}",
+                package,
+                path.display()
+            ))
+        })
+    });
+
+    // Always clean up the extracted package, even on failure
+    let _ = secure_delete_dir(&work_dir);
+
+    result
+}
+
+/// The installed version of a package, from a query that prints one version per line
+///
+/// The archive has to be of this version: the latest one in the repositories
+/// may ship a different default config.
+fn installed_version(package: &str, program: &str, args: &[&str]) -> Result<String, KittyError> {
+    run_query(program, args)
+        .and_then(|output| output.lines().next().map(|v| v.trim().to_string()))
+        .filter(|version| !version.is_empty())
+        .ok_or_else(|| KittyError::Package(format!("Package {} is not installed", package)))
+}
+
+fn extract_dpkg(package: &str, work_dir: &Path, extract_dir: &Path) -> Result<(), KittyError> {
+    let version = installed_version(package, "dpkg-query", &["-W", "-f=${Version}\\n", package])?;
+    run_in(
+        work_dir,
+        "apt-get",
+        &["download", &format!("{}={}", package, version)],
+    )?;
+    let archive = find_archive(work_dir, ".deb")?;
+    run_in(
+        work_dir,
+        "dpkg-deb",
+        &[
+            "-x",
+            archive.to_str().unwrap_or_default(),
+            extract_dir.to_str().unwrap_or_default(),
+        ],
+    )
+}
+
+fn extract_rpm(package: &str, work_dir: &Path, extract_dir: &Path) -> Result<(), KittyError> {
+    let version = installed_version(
+        package,
+        "rpm",
+        &["-q", "--queryformat", "%{VERSION}-%{RELEASE}\\n", package],
+    )?;
+    let dest = format!("--destdir={}", work_dir.display());
+    run_in(
+        work_dir,
+        "dnf",
+        &["download", &dest, &format!("{}-{}", package, version)],
+    )?;
+    let archive = find_archive(work_dir, ".rpm")?;
+    let script = format!("rpm2cpio '{}' | cpio -idm --quiet", archive.display());
+    run_in(extract_dir, "sh", &["-c", &script])
+}
+
+fn extract_pacman(package: &str, path: &Path, extract_dir: &Path) -> Result<(), KittyError> {
+    // pacman keeps downloaded packages in its cache, so look for the installed version there
+    let version = run_query("pacman", &["-Q", package])
+        .and_then(|output| output.split_whitespace().nth(1).map(|v| v.to_string()))
+        .ok_or_else(|| KittyError::Package(format!("Package {} is not installed", package)))?;
+
+    let prefix = format!("{}-{}-", package, version);
+    let archive = fs::read_dir("/var/cache/pacman/pkg")?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .find(|p| {
+            let name = p.file_name().and_then(|n| n.to_str()).unwrap_or("");
+            name.starts_with(&prefix) && !name.ends_with(".sig")
+        })
+        .ok_or_else(|| KittyError::Package(format!("No cached package found for {}", package)))?;
+
+    let relative = path.strip_prefix("/").unwrap_or(path);
+    run_in(
+        extract_dir,
+        "tar",
+        &[
+            "-xf",
+            archive.to_str().unwrap_or_default(),
+            relative.to_str().unwrap_or_default(),
+        ],
+    )
+}
+
+/// Find the first downloaded package archive with the given extension
+fn find_archive(dir: &Path, extension: &str) -> Result<PathBuf, KittyError> {
+    fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .find(|p| p.to_string_lossy().ends_with(extension))
+        .ok_or_else(|| KittyError::Package(format!("No {} archive was downloaded", extension)))
+}
+
+fn run_in(dir: &Path, program: &str, args: &[&str]) -> Result<(), KittyError> {
+    let output = Command::new(program)
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .map_err(|e| KittyError::Package(format!("Failed to run {}: {}", program, e)))?;
+
+    if !output.status.success() {
+        return Err(KittyError::Package(format!(
+            "{} failed: {}",
+            program,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    Ok(())
+}
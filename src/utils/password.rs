@@ -0,0 +1,77 @@
+use crate::{
+    commands::{init::KittyError, session::session_password},
+    utils::file::get_repository_path,
+};
+use std::{
+    fs,
+    io::{self, Write},
+};
+
+/// Environment variable holding the repository password for unattended runs
+pub const PASSWORD_ENV: &str = "KITTY_PASSWORD";
+
+/// Get the repository password without ever prompting
+///
+/// A password file takes precedence over the `KITTY_PASSWORD` environment
+/// variable, which takes precedence over an unlocked `kitty session`.
+pub fn read_noninteractive_password(password_file: Option<&str>) -> Result<String, KittyError> {
+    if let Some(path) = password_file {
+        let contents = fs::read_to_string(path)?;
+        // Only strip the line ending so passwords with surrounding spaces still work
+        return Ok(contents.trim_end_matches(['\n', '\r']).to_string());
+    }
+
+    match std::env::var(PASSWORD_ENV) {
+        Ok(password) if !password.is_empty() => Ok(password),
+        _ => unlocked_password().ok_or_else(|| {
+            KittyError::PasswordUnavailable(format!(
+                "set {}, pass --password-file or start a `kitty session`",
+                PASSWORD_ENV
+            ))
+        }),
+    }
+}
+
+/// Get the password for commands other programs read the output of
+///
+/// Uses a password file or `KITTY_PASSWORD` when there is one, as editor
+/// plugins do. Otherwise the password is asked for on stderr, so stdout only
+/// carries the command's output.
+pub fn read_script_password(password_file: Option<&str>) -> Result<String, KittyError> {
+    let has_env = std::env::var(PASSWORD_ENV).is_ok_and(|password| !password.is_empty());
+    if password_file.is_some() || has_env {
+        return read_noninteractive_password(password_file);
+    }
+    read_repository_password(true)
+}
+
+/// Get the repository password from the shell's `kitty session`, or ask for it
+///
+/// The prompt goes to stderr with `on_stderr`, for commands whose stdout is
+/// read by other programs.
+pub fn read_repository_password(on_stderr: bool) -> Result<String, KittyError> {
+    if let Some(password) = unlocked_password() {
+        return Ok(password);
+    }
+
+    if on_stderr {
+        eprint!("{} ", crate::tr!("password-prompt"));
+        io::stderr().flush()?;
+    } else {
+        print!("{} ", crate::tr!("password-prompt"));
+        io::stdout().flush()?;
+    }
+    let password = rpassword::read_password()?;
+    if on_stderr {
+        eprintln!();
+    } else {
+        println!(); // Add a newline after password input
+    }
+    Ok(password)
+}
+
+/// The password held by an unlocked `kitty session`, if there is one for this repository
+fn unlocked_password() -> Option<String> {
+    let repo_path = get_repository_path().ok()?;
+    session_password(&repo_path)
+}
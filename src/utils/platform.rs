@@ -0,0 +1,182 @@
+use crate::commands::init::KittyError;
+use serde::{Deserialize, Serialize};
+use std::{env::consts, fmt, fs, sync::OnceLock};
+
+/// The machine kitty runs on, as far as conditions on tracked files are concerned
+pub struct Platform {
+    /// `linux`, `macos`, `freebsd`, ...
+    pub os: &'static str,
+
+    /// `x86_64`, `aarch64`, ...
+    pub arch: &'static str,
+
+    /// `ID` from os-release followed by its `ID_LIKE` entries, e.g. `ubuntu`, `debian`
+    pub distros: Vec<String>,
+}
+
+impl Platform {
+    /// The platform of this machine, detected once
+    pub fn current() -> &'static Platform {
+        static CURRENT: OnceLock<Platform> = OnceLock::new();
+        CURRENT.get_or_init(|| Platform {
+            os: consts::OS,
+            arch: consts::ARCH,
+            distros: os_release_ids(),
+        })
+    }
+}
+
+/// Distribution IDs from os-release; none on systems without one, such as macOS
+fn os_release_ids() -> Vec<String> {
+    let Ok(contents) = fs::read_to_string("/etc/os-release")
+        .or_else(|_| fs::read_to_string("/usr/lib/os-release"))
+    else {
+        return Vec::new();
+    };
+
+    let mut id = Vec::new();
+    let mut like = Vec::new();
+    for line in contents.lines() {
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let value = value.trim().trim_matches('"').trim_matches('\'');
+        match key.trim() {
+            "ID" => id.push(value.to_lowercase()),
+            "ID_LIKE" => like.extend(value.split_whitespace().map(str::to_lowercase)),
+            _ => {}
+        }
+    }
+    id.extend(like);
+    id
+}
+
+/// Spell a value the way Rust reports it, so `darwin` and `arm64` match too
+fn normalize(key: &str, value: &str) -> String {
+    let value = value.trim().to_lowercase();
+    match (key, value.as_str()) {
+        ("os", "darwin" | "osx" | "mac") => "macos".to_string(),
+        ("arch", "arm64") => "aarch64".to_string(),
+        ("arch", "amd64" | "x64") => "x86_64".to_string(),
+        _ => value,
+    }
+}
+
+/// Platforms a tracked file applies to
+///
+/// A file applies when every non-empty list contains the platform's value, so
+/// `os=linux distro=debian,fedora` means Linux, and Debian or Fedora.
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct Conditions {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub os: Vec<String>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub distro: Vec<String>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub arch: Vec<String>,
+}
+
+impl Conditions {
+    pub fn is_empty(&self) -> bool {
+        self.os.is_empty() && self.distro.is_empty() && self.arch.is_empty()
+    }
+
+    /// Add a `key=value` condition such as `os=macos` or `distro=debian,fedora`
+    pub fn add(&mut self, spec: &str) -> Result<(), KittyError> {
+        let (key, values) = spec.split_once('=').ok_or_else(|| {
+            KittyError::Condition(format!("'{}' is not of the form key=value", spec))
+        })?;
+        let key = key.trim();
+        let list = match key {
+            "os" => &mut self.os,
+            "distro" => &mut self.distro,
+            "arch" => &mut self.arch,
+            _ => {
+                return Err(KittyError::Condition(format!(
+                    "unknown key '{}' (use os, distro or arch)",
+                    key
+                )))
+            }
+        };
+
+        for value in values.split(',').map(|v| normalize(key, v)) {
+            if value.is_empty() {
+                return Err(KittyError::Condition(format!(
+                    "'{}' has an empty value",
+                    spec
+                )));
+            }
+            if !list.contains(&value) {
+                list.push(value);
+            }
+        }
+        Ok(())
+    }
+
+    /// Add every condition of `other` to these
+    pub fn merge(&mut self, other: &Conditions) {
+        for (list, values) in [
+            (&mut self.os, &other.os),
+            (&mut self.distro, &other.distro),
+            (&mut self.arch, &other.arch),
+        ] {
+            for value in values {
+                if !list.contains(value) {
+                    list.push(value.clone());
+                }
+            }
+        }
+    }
+
+    /// Whether a file with these conditions belongs on `platform`
+    ///
+    /// Values are normalized again here, since manifests may set them without `add`.
+    pub fn matches(&self, platform: &Platform) -> bool {
+        let any = |key: &str, values: &[String], wanted: &[&str]| {
+            values.is_empty()
+                || values
+                    .iter()
+                    .any(|value| wanted.contains(&normalize(key, value).as_str()))
+        };
+        let distros: Vec<&str> = platform.distros.iter().map(String::as_str).collect();
+
+        any("os", &self.os, &[platform.os])
+            && any("arch", &self.arch, &[platform.arch])
+            && any("distro", &self.distro, &distros)
+    }
+}
+
+impl fmt::Display for Conditions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts: Vec<String> = [
+            ("os", &self.os),
+            ("distro", &self.distro),
+            ("arch", &self.arch),
+        ]
+        .into_iter()
+        .filter(|(_, values)| !values.is_empty())
+        .map(|(key, values)| format!("{}={}", key, values.join(",")))
+        .collect();
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+/// Name of this machine, used as the default profile for template variables
+pub fn hostname() -> String {
+    ["/proc/sys/kernel/hostname", "/etc/hostname"]
+        .iter()
+        .find_map(|path| fs::read_to_string(path).ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .or_else(|| {
+            std::process::Command::new("hostname")
+                .output()
+                .ok()
+                .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        })
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "localhost".to_string())
+}
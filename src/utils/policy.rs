@@ -0,0 +1,56 @@
+use crate::{
+    commands::init::{Repository, PBKDF2_ITERATIONS},
+    repository::config::PasswordPolicy,
+};
+use chrono::Utc;
+
+/// Days before the password's maximum age that `status` starts reminding about it
+const REMINDER_DAYS: i64 = 14;
+
+/// How the repository measures up to its password policy
+#[derive(Default)]
+pub struct PolicyReport {
+    /// Ways the policy is violated
+    pub violations: Vec<String>,
+
+    /// Rotations coming due, which do not violate the policy yet
+    pub reminders: Vec<String>,
+}
+
+/// Compare the repository with its policy
+///
+/// The password is set when the repository is created, and a repository moves
+/// to a new password by being dumped and initialized again from the dump.
+/// Repositories from before this was recorded count from their creation.
+pub fn evaluate(policy: &PasswordPolicy, repository: &Repository) -> PolicyReport {
+    let password_set_at = repository.password_set_at.unwrap_or(repository.created_at);
+    let mut report = PolicyReport::default();
+
+    if let Some(max_age) = policy.max_password_age_days {
+        let age = (Utc::now() - password_set_at).num_days();
+        let left = max_age as i64 - age;
+        if left < 0 {
+            report.violations.push(format!(
+                "The repository password is {} days old; the policy allows {}. \
+                 Move the files to a new password with `kitty dump` and `kitty init --import`",
+                age, max_age
+            ));
+        } else if left <= REMINDER_DAYS {
+            report.reminders.push(format!(
+                "The repository password is due for rotation in {} day(s)",
+                left
+            ));
+        }
+    }
+
+    if let Some(min_iterations) = policy.min_kdf_iterations {
+        if PBKDF2_ITERATIONS < min_iterations {
+            report.violations.push(format!(
+                "Keys are derived with {} PBKDF2 iterations; the policy requires at least {}",
+                PBKDF2_ITERATIONS, min_iterations
+            ));
+        }
+    }
+
+    report
+}
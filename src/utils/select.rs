@@ -0,0 +1,298 @@
+use crate::{
+    commands::init::{Crypto, KittyError, TrackedFile},
+    storage::ManifestFilter,
+    utils::{
+        file::{expand_home, load_files, load_repository},
+        i18n::assume_yes,
+    },
+};
+use globset::{GlobBuilder, GlobMatcher};
+use regex::Regex;
+use std::{
+    io::{self, IsTerminal, Write},
+    path::Path,
+};
+
+/// Shortest ID prefix shown to users, as with abbreviated git commits
+const MIN_ID_LENGTH: usize = 4;
+
+/// The stable identifier of a tracked file: the UUID its content is stored under
+pub fn file_id(file: &TrackedFile) -> &str {
+    file.repo_path.rsplit('/').next().unwrap_or(&file.repo_path)
+}
+
+/// Short IDs for every tracked file, in the same order, written as `@a3f2`
+///
+/// All IDs get the same length, the shortest that keeps them unique across the
+/// repository, so an ID seen in a filtered listing still names one file.
+pub fn short_ids(files: &[TrackedFile]) -> Vec<String> {
+    let ids: Vec<&str> = files.iter().map(file_id).collect();
+    let longest = ids.iter().map(|id| id.len()).max().unwrap_or(0);
+
+    let mut length = MIN_ID_LENGTH.min(longest);
+    while length < longest {
+        let mut prefixes: Vec<&str> = ids.iter().map(|id| &id[..length.min(id.len())]).collect();
+        prefixes.sort_unstable();
+        prefixes.dedup();
+        if prefixes.len() == ids.len() {
+            break;
+        }
+        length += 1;
+    }
+
+    ids.iter()
+        .map(|id| format!("@{}", &id[..length.min(id.len())]))
+        .collect()
+}
+
+/// Resolve an `@id` argument to the tracked file whose ID starts with it
+///
+/// Returns None when the argument is not an ID, so callers can go on to treat
+/// it as a path.
+pub fn find_by_id(files: &[TrackedFile], path: &str) -> Result<Option<usize>, KittyError> {
+    let Some(prefix) = path.strip_prefix('@') else {
+        return Ok(None);
+    };
+    if prefix.is_empty() {
+        return Err(KittyError::FileNotTracked(path.to_string()));
+    }
+
+    let matches: Vec<usize> = files
+        .iter()
+        .enumerate()
+        .filter(|(_, f)| file_id(f).starts_with(prefix))
+        .map(|(index, _)| index)
+        .collect();
+
+    match matches.len() {
+        0 => Err(KittyError::FileNotTracked(path.to_string())),
+        1 => Ok(Some(matches[0])),
+        _ => Err(KittyError::AmbiguousPath(format!(
+            "'{}' is the start of {} file IDs; use a longer ID",
+            path,
+            matches.len()
+        ))),
+    }
+}
+
+/// A path argument that names several tracked files at once
+pub enum PathPattern {
+    /// Shell-style wildcards, where `*` stays within a directory and `**` crosses them
+    Glob(GlobMatcher),
+
+    /// A regular expression searched for in the tracked path
+    Regex(Regex),
+}
+
+impl PathPattern {
+    /// Read a path argument as a regex when asked to, or as a glob when it has wildcards
+    ///
+    /// Returns None for plain paths. Globs that are not absolute are taken
+    /// relative to the current directory, like the paths they expand to.
+    pub fn parse(path: &str, regex: bool) -> Result<Option<Self>, KittyError> {
+        if regex {
+            return Regex::new(path)
+                .map(|regex| Some(PathPattern::Regex(regex)))
+                .map_err(|e| KittyError::Pattern(format!("{}: {}", path, e)));
+        }
+        if !is_glob(path) {
+            return Ok(None);
+        }
+
+        GlobBuilder::new(&absolute_glob(path)?)
+            .literal_separator(true)
+            .build()
+            .map(|glob| Some(PathPattern::Glob(glob.compile_matcher())))
+            .map_err(|e| KittyError::Pattern(format!("{}: {}", path, e)))
+    }
+
+    pub fn is_match(&self, original_path: &str) -> bool {
+        match self {
+            PathPattern::Glob(glob) => glob.is_match(original_path),
+            PathPattern::Regex(regex) => regex.is_match(original_path),
+        }
+    }
+}
+
+/// Whether a path argument has wildcards
+fn is_glob(path: &str) -> bool {
+    path.contains(['*', '?', '['])
+}
+
+/// A glob with `~` expanded and relative globs taken from the current directory
+fn absolute_glob(path: &str) -> Result<String, KittyError> {
+    let glob = expand_home(path);
+    if glob.starts_with('/') {
+        return Ok(glob);
+    }
+    let cwd = std::env::current_dir()?;
+    Ok(format!(
+        "{}/{}",
+        cwd.to_string_lossy().trim_end_matches('/'),
+        glob
+    ))
+}
+
+/// The tracked files a path argument can name, read from as little of the manifest as possible
+///
+/// Exact paths and globs only load the files at that path or below the start
+/// of the glob. IDs, regexes and partial names can match anywhere, so they
+/// load every file, and so does an argument the narrow load finds nothing
+/// for, as it may still be a partial name. [`select_tracked`] then picks from
+/// the result exactly as it would from the whole repository.
+pub fn load_candidates(
+    repo_path: &Path,
+    crypto: &Crypto,
+    path: &str,
+    regex: bool,
+) -> Result<Vec<TrackedFile>, KittyError> {
+    let filter = if regex || path.starts_with('@') {
+        None
+    } else if is_glob(path) {
+        let glob = absolute_glob(path)?;
+        let literal = glob.find(['*', '?', '[', '{', '\\']).unwrap_or(glob.len());
+        Some(ManifestFilter::Prefix(glob[..literal].to_string()))
+    } else {
+        let canonical = Path::new(path)
+            .canonicalize()
+            .map(|p| p.to_string_lossy().into_owned())
+            .unwrap_or_else(|_| path.to_string());
+        Some(ManifestFilter::Paths(vec![path.to_string(), canonical]))
+    };
+
+    if let Some(filter) = filter {
+        let files = load_files(repo_path, crypto, &filter)?;
+        if !files.is_empty() {
+            return Ok(files);
+        }
+    }
+    Ok(load_repository(repo_path, crypto)?.files)
+}
+
+/// Resolve a path argument to the indices of the tracked files it names
+///
+/// An `@id` names exactly one file. Globs and regexes (with `regex`) select
+/// every file they match. An exact match on the canonical path, or on the
+/// entry name for collectors, always wins, and the root of a tracked directory
+/// names the whole tree. Otherwise the argument is matched as a substring of tracked
+/// paths. When several files match, `all` takes every one of them, a terminal
+/// user is asked to pick, and anything else is refused with the candidates
+/// listed, so a short name never silently picks the wrong file. Messages go
+/// to stderr so they never mix with JSON output.
+pub fn select_tracked(
+    files: &[TrackedFile],
+    path: &str,
+    all: bool,
+    regex: bool,
+) -> Result<Vec<usize>, KittyError> {
+    if !regex {
+        if let Some(index) = find_by_id(files, path)? {
+            return Ok(vec![index]);
+        }
+    }
+
+    if let Some(pattern) = PathPattern::parse(path, regex)? {
+        let matches: Vec<usize> = files
+            .iter()
+            .enumerate()
+            .filter(|(_, f)| pattern.is_match(&f.original_path))
+            .map(|(index, _)| index)
+            .collect();
+        if matches.is_empty() {
+            return Err(KittyError::FileNotTracked(path.to_string()));
+        }
+        return Ok(matches);
+    }
+
+    let file_path = Path::new(path)
+        .canonicalize()
+        .unwrap_or_else(|_| Path::new(path).to_path_buf());
+
+    if let Some(index) = files
+        .iter()
+        .position(|f| f.original_path == path || Path::new(&f.original_path) == file_path)
+    {
+        return Ok(vec![index]);
+    }
+
+    // The root of a tracked directory names every file in the tree
+    let members: Vec<usize> = files
+        .iter()
+        .enumerate()
+        .filter(|(_, f)| {
+            f.tree
+                .as_deref()
+                .is_some_and(|root| root == path || Path::new(root) == file_path)
+        })
+        .map(|(index, _)| index)
+        .collect();
+    if !members.is_empty() {
+        return Ok(members);
+    }
+
+    let matches: Vec<usize> = files
+        .iter()
+        .enumerate()
+        .filter(|(_, f)| f.original_path.contains(path))
+        .map(|(index, _)| index)
+        .collect();
+
+    match matches.len() {
+        0 => Err(KittyError::FileNotTracked(path.to_string())),
+        1 => {
+            eprintln!("Matched {}", files[matches[0]].original_path);
+            Ok(matches)
+        }
+        _ if all => Ok(matches),
+        _ if io::stdin().is_terminal() && !assume_yes() => choose(files, path, &matches),
+        _ => Err(ambiguous(files, path, &matches)),
+    }
+}
+
+fn ambiguous(files: &[TrackedFile], path: &str, matches: &[usize]) -> KittyError {
+    let candidates: Vec<&str> = matches
+        .iter()
+        .map(|&index| files[index].original_path.as_str())
+        .collect();
+    KittyError::AmbiguousPath(format!(
+        "'{}' matches {} tracked files ({}); give the full path, an @id, or pass --all",
+        path,
+        matches.len(),
+        candidates.join(", ")
+    ))
+}
+
+/// Ask which of several matching files were meant
+fn choose(files: &[TrackedFile], path: &str, matches: &[usize]) -> Result<Vec<usize>, KittyError> {
+    eprintln!("'{}' matches {} tracked files:", path, matches.len());
+    for (number, &index) in matches.iter().enumerate() {
+        eprintln!("  {}) {}", number + 1, files[index].original_path);
+    }
+    eprint!("Select files by number (e.g. 1,3), 'a' for all, or Enter to cancel: ");
+    io::stderr().flush()?;
+
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+    let input = input.trim();
+
+    if input.eq_ignore_ascii_case("a") {
+        return Ok(matches.to_vec());
+    }
+
+    let mut selected = Vec::new();
+    for choice in input.split(',').map(str::trim).filter(|c| !c.is_empty()) {
+        match choice.parse::<usize>() {
+            Ok(number) if (1..=matches.len()).contains(&number) => {
+                if !selected.contains(&matches[number - 1]) {
+                    selected.push(matches[number - 1]);
+                }
+            }
+            _ => return Err(ambiguous(files, path, matches)),
+        }
+    }
+
+    if selected.is_empty() {
+        return Err(ambiguous(files, path, matches));
+    }
+    Ok(selected)
+}
@@ -0,0 +1,67 @@
+use std::{
+    fs,
+    io::{self, Seek, SeekFrom, Write},
+    os::unix::fs::{DirBuilderExt, MetadataExt},
+    path::{Path, PathBuf},
+};
+use uuid::Uuid;
+use walkdir::WalkDir;
+
+const ZEROS: [u8; 8192] = [0u8; 8192];
+
+/// Overwrite a file with zeros, then delete it
+///
+/// This is best effort: copy-on-write filesystems, SSD wear levelling and
+/// journals can keep old blocks around. It still keeps secrets out of reach
+/// of a plain undelete. A file with other hard links is only unlinked, as
+/// the other names still need its content.
+pub fn secure_delete(path: &Path) -> io::Result<()> {
+    let metadata = fs::metadata(path)?;
+    if metadata.nlink() > 1 {
+        return fs::remove_file(path);
+    }
+    let length = metadata.len();
+    let mut file = fs::OpenOptions::new().write(true).open(path)?;
+    file.seek(SeekFrom::Start(0))?;
+
+    let mut remaining = length;
+    while remaining > 0 {
+        let chunk = remaining.min(ZEROS.len() as u64) as usize;
+        file.write_all(&ZEROS[..chunk])?;
+        remaining -= chunk as u64;
+    }
+    file.sync_all()?;
+    drop(file);
+
+    fs::remove_file(path)
+}
+
+/// Securely delete every file below a directory, then remove the directory
+pub fn secure_delete_dir(dir: &Path) -> io::Result<()> {
+    for entry in WalkDir::new(dir).into_iter().filter_map(|e| e.ok()) {
+        if entry.file_type().is_file() {
+            let _ = secure_delete(entry.path());
+        }
+    }
+    fs::remove_dir_all(dir)
+}
+
+/// Create a private directory for plaintext that only lives for one command
+///
+/// Memory-backed locations are preferred so nothing reaches a disk:
+/// `$XDG_RUNTIME_DIR`, then `/dev/shm`, then the system temp directory.
+pub fn private_temp_dir(prefix: &str) -> io::Result<PathBuf> {
+    let base = std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_dir())
+        .or_else(|| Some(PathBuf::from("/dev/shm")).filter(|dir| dir.is_dir()))
+        .unwrap_or_else(std::env::temp_dir);
+
+    let dir = base.join(format!("{}-{}", prefix, Uuid::new_v4()));
+    fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(&dir)?;
+
+    Ok(dir)
+}
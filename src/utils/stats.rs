@@ -0,0 +1,104 @@
+use crate::{
+    commands::init::{Crypto, KittyError, Repository, ENCRYPTION_OVERHEAD},
+    storage::{is_database, open_database},
+    utils::{file::get_storage_type, theme},
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::Path,
+};
+
+/// Append-only log of repository statistics, one encrypted sample per line
+const STATS_LOG: &str = "stats.log";
+
+/// Size of the repository at one point in time
+#[derive(Serialize, Deserialize, Clone)]
+pub struct StatsSample {
+    pub taken_at: DateTime<Utc>,
+    pub files: usize,
+    pub versions: usize,
+
+    /// Current content of every tracked file, before encryption
+    pub plaintext_bytes: u64,
+
+    /// Everything stored, including previous versions and encryption overhead
+    pub stored_bytes: u64,
+}
+
+/// Measure a repository as it is now
+pub fn measure(repo_path: &Path, repository: &Repository) -> Result<StatsSample, KittyError> {
+    let storage = if is_database(&get_storage_type(repo_path)?) {
+        Some(open_database(repo_path)?)
+    } else {
+        None
+    };
+    let stored_size = |path: &str| match &storage {
+        Some(storage) => storage.content_size(path).unwrap_or(0),
+        None => fs::metadata(repo_path.join(path))
+            .map(|m| m.len())
+            .unwrap_or(0),
+    };
+
+    let mut sample = StatsSample {
+        taken_at: Utc::now(),
+        files: repository.files.len(),
+        versions: 0,
+        plaintext_bytes: 0,
+        stored_bytes: 0,
+    };
+    for file in &repository.files {
+        let size = stored_size(&file.repo_path);
+        sample.plaintext_bytes += size.saturating_sub(ENCRYPTION_OVERHEAD as u64);
+        sample.stored_bytes += size;
+        sample.versions += file.versions.len();
+        for version in &file.versions {
+            sample.stored_bytes += stored_size(&version.repo_path);
+        }
+    }
+    Ok(sample)
+}
+
+/// Append a sample of the repository after a change
+///
+/// The change is already saved, so a failure is reported instead of returned.
+pub fn record(repo_path: &Path, crypto: &Crypto, repository: &Repository) {
+    let appended = measure(repo_path, repository).and_then(|sample| {
+        let encrypted = crypto.encrypt(&serde_json::to_vec(&sample)?)?;
+        let mut log = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(repo_path.join(STATS_LOG))?;
+        writeln!(log, "{}", hex::encode(encrypted))?;
+        Ok(())
+    });
+
+    if let Err(e) = appended {
+        eprintln!(
+            "{} Failed to record repository statistics: {}",
+            theme::warning("WARNING:"),
+            e
+        );
+    }
+}
+
+/// Read every recorded sample, oldest first
+pub fn read_samples(repo_path: &Path, crypto: &Crypto) -> Result<Vec<StatsSample>, KittyError> {
+    let log_path = repo_path.join(STATS_LOG);
+    if !log_path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut samples = Vec::new();
+    for line in fs::read_to_string(log_path)?.lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let decrypted = crypto.decrypt(&hex::decode(line.trim())?)?;
+        samples.push(serde_json::from_slice(&decrypted)?);
+    }
+
+    Ok(samples)
+}
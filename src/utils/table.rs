@@ -0,0 +1,51 @@
+use std::io;
+use terminal_size::{terminal_size_of, Width};
+
+/// Narrowest a path column gets, however small the terminal
+const MIN_PATH_WIDTH: usize = 20;
+
+/// Width of the terminal stdout is attached to
+///
+/// `COLUMNS` wins when set. Returns None when output goes to a pipe or file,
+/// where nothing should be cut short.
+pub fn terminal_width() -> Option<usize> {
+    if let Some(columns) = std::env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.parse().ok())
+        .filter(|&columns| columns > 0)
+    {
+        return Some(columns);
+    }
+    terminal_size_of(io::stdout()).map(|(Width(width), _)| width as usize)
+}
+
+/// How wide to make a column of paths that shares the line with `other_columns` characters
+///
+/// The column is as wide as its longest value, but shrinks to fit the terminal.
+pub fn path_column_width<'a>(values: impl Iterator<Item = &'a str>, other_columns: usize) -> usize {
+    let longest = values.map(|value| value.chars().count()).max().unwrap_or(0);
+    match terminal_width() {
+        Some(width) => longest.min(width.saturating_sub(other_columns).max(MIN_PATH_WIDTH)),
+        None => longest,
+    }
+}
+
+/// Shorten a path to `width` characters by eliding its middle
+///
+/// The start and the file name are what identify a path, so both are kept.
+pub fn fit_path(path: &str, width: usize) -> String {
+    let length = path.chars().count();
+    if length <= width {
+        return path.to_string();
+    }
+    if width <= 3 {
+        return ".".repeat(width);
+    }
+
+    // Give two thirds of the room to the end, where the file name is
+    let keep = width - 3;
+    let tail = (keep * 2).div_ceil(3);
+    let start: String = path.chars().take(keep - tail).collect();
+    let end: String = path.chars().skip(length - tail).collect();
+    format!("{}...{}", start, end)
+}
@@ -0,0 +1,92 @@
+use crate::repository::config::{RepoConfig, ThemeConfig};
+use colored::{Color, ColoredString, Colorize};
+use std::sync::OnceLock;
+
+/// Output styles, resolved once from the repository's `theme` settings
+struct Theme {
+    addition: Option<Color>,
+    deletion: Option<Color>,
+    hunk: Option<Color>,
+    success: Option<Color>,
+    error: Option<Color>,
+    warning: Option<Color>,
+    bold: bool,
+}
+
+static THEME: OnceLock<Theme> = OnceLock::new();
+
+impl Theme {
+    fn load() -> Self {
+        let config = RepoConfig::current().map(|c| c.theme).unwrap_or_default();
+        let defaults = ThemeConfig::default();
+
+        Theme {
+            addition: color(&config.addition, &defaults.addition),
+            deletion: color(&config.deletion, &defaults.deletion),
+            hunk: color(&config.hunk, &defaults.hunk),
+            success: color(&config.success, &defaults.success),
+            error: color(&config.error, &defaults.error),
+            warning: color(&config.warning, &defaults.warning),
+            bold: config.bold,
+        }
+    }
+}
+
+/// Parse a color name, using the default for names colored does not know
+fn color(name: &str, default: &str) -> Option<Color> {
+    if name.eq_ignore_ascii_case("none") {
+        return None;
+    }
+    name.parse().or_else(|_| default.parse()).ok()
+}
+
+fn theme() -> &'static Theme {
+    THEME.get_or_init(Theme::load)
+}
+
+fn paint(text: &str, color: Option<Color>, bold: bool) -> ColoredString {
+    let styled = match color {
+        Some(color) => text.color(color),
+        None => text.normal(),
+    };
+    if bold && theme().bold {
+        styled.bold()
+    } else {
+        styled
+    }
+}
+
+/// Markers for added lines in diffs
+pub fn addition(text: &str) -> ColoredString {
+    paint(text, theme().addition, false)
+}
+
+/// Markers for removed lines in diffs
+pub fn deletion(text: &str) -> ColoredString {
+    paint(text, theme().deletion, false)
+}
+
+/// Hunk headers
+pub fn hunk(text: &str) -> ColoredString {
+    paint(text, theme().hunk, false)
+}
+
+/// SUCCESS labels
+pub fn success(text: &str) -> ColoredString {
+    paint(text, theme().success, true)
+}
+
+/// ERROR labels and missing files
+pub fn error(text: &str) -> ColoredString {
+    paint(text, theme().error, true)
+}
+
+/// WARNING labels and modified files
+pub fn warning(text: &str) -> ColoredString {
+    paint(text, theme().warning, true)
+}
+
+/// Paths and headings that should stand out
+pub fn emphasis(text: &str) -> ColoredString {
+    paint(text, None, true)
+}
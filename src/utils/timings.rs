@@ -0,0 +1,125 @@
+use std::{
+    cell::RefCell,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, OnceLock,
+    },
+    time::{Duration, Instant},
+};
+
+/// Set by the global `--timings` flag
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// When timings were enabled, the start of the reported total
+static STARTED: OnceLock<Instant> = OnceLock::new();
+
+/// Time spent and calls made per phase, indexed by `Phase as usize`
+static TOTALS: Mutex<[(Duration, u32); Phase::ALL.len()]> =
+    Mutex::new([(Duration::ZERO, 0); Phase::ALL.len()]);
+
+thread_local! {
+    /// Time spent in phases nested inside the ones running on this thread
+    static NESTED: RefCell<Vec<Duration>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Part of a command whose duration `--timings` reports
+#[derive(Clone, Copy)]
+pub enum Phase {
+    KeyDerivation,
+    Encryption,
+    Decryption,
+    Hashing,
+    StorageIo,
+    Network,
+}
+
+impl Phase {
+    const ALL: [Phase; 6] = [
+        Phase::KeyDerivation,
+        Phase::Encryption,
+        Phase::Decryption,
+        Phase::Hashing,
+        Phase::StorageIo,
+        Phase::Network,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            Phase::KeyDerivation => "Key derivation",
+            Phase::Encryption => "Encryption",
+            Phase::Decryption => "Decryption",
+            Phase::Hashing => "Hashing",
+            Phase::StorageIo => "Storage IO",
+            Phase::Network => "Network",
+        }
+    }
+}
+
+/// Start collecting timings for the report printed when the command finishes
+pub fn enable() {
+    STARTED.get_or_init(Instant::now);
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Run `f`, counting its duration towards `phase`
+///
+/// A phase running inside another, such as decryption while the manifest is
+/// loaded, is only counted once: towards the inner phase.
+pub fn time<T>(phase: Phase, f: impl FnOnce() -> T) -> T {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return f();
+    }
+
+    NESTED.with(|nested| nested.borrow_mut().push(Duration::ZERO));
+    let started = Instant::now();
+    let result = f();
+    let elapsed = started.elapsed();
+    let nested = NESTED.with(|nested| {
+        let mut nested = nested.borrow_mut();
+        let inner = nested.pop().unwrap_or_default();
+        if let Some(outer) = nested.last_mut() {
+            *outer += elapsed;
+        }
+        inner
+    });
+
+    if let Ok(mut totals) = TOTALS.lock() {
+        let (total, calls) = &mut totals[phase as usize];
+        *total += elapsed.saturating_sub(nested);
+        *calls += 1;
+    }
+    result
+}
+
+/// Print how long each phase took, if `--timings` was given
+///
+/// Goes to stderr, so the output of commands like `list --paths-only` stays usable.
+pub fn report() {
+    let (Some(started), Ok(totals)) = (STARTED.get(), TOTALS.lock()) else {
+        return;
+    };
+
+    let wall = started.elapsed();
+    let mut measured = Duration::ZERO;
+    eprintln!("\n{:<16} {:>10} {:>8}", "Phase", "Time", "Calls");
+    for phase in Phase::ALL {
+        let (total, calls) = totals[phase as usize];
+        measured += total;
+        eprintln!(
+            "{:<16} {:>10} {:>8}",
+            phase.label(),
+            format_duration(total),
+            calls
+        );
+    }
+    eprintln!(
+        "{:<16} {:>10}",
+        "Other",
+        format_duration(wall.saturating_sub(measured))
+    );
+    eprintln!("{:<16} {:>10}", "Total", format_duration(wall));
+}
+
+fn format_duration(duration: Duration) -> String {
+    format!("{:.1} ms", duration.as_secs_f64() * 1000.0)
+}
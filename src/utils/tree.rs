@@ -0,0 +1,81 @@
+use crate::commands::init::TrackedFile;
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::{Path, PathBuf},
+};
+use walkdir::WalkDir;
+
+/// A directory tracked as one unit: its root and the files tracked below it
+///
+/// Each member records the root in its `tree` field, so the members' paths and
+/// hashes are the tree's manifest and a tree needs no entry of its own.
+pub struct Tree<'a> {
+    pub root: &'a str,
+    pub members: Vec<&'a TrackedFile>,
+}
+
+impl<'a> Tree<'a> {
+    /// Short name for messages, such as `nvim` for `~/.config/nvim`
+    pub fn name(&self) -> &'a str {
+        self.root
+            .rsplit('/')
+            .find(|part| !part.is_empty())
+            .unwrap_or(self.root)
+    }
+
+    /// Files on disk below the root that are not part of the tree
+    pub fn strays(&self) -> Vec<PathBuf> {
+        let tracked: HashSet<&Path> = self
+            .members
+            .iter()
+            .map(|f| Path::new(f.original_path.as_str()))
+            .collect();
+        walk(Path::new(self.root))
+            .into_iter()
+            .filter(|path| !tracked.contains(path.as_path()))
+            .collect()
+    }
+}
+
+/// The trees among `files`, keyed by root
+pub fn trees<'a, I>(files: I) -> BTreeMap<&'a str, Tree<'a>>
+where
+    I: IntoIterator<Item = &'a TrackedFile>,
+{
+    let mut trees: BTreeMap<&str, Tree> = BTreeMap::new();
+    for file in files {
+        if let Some(root) = file.tree.as_deref() {
+            trees
+                .entry(root)
+                .or_insert_with(|| Tree {
+                    root,
+                    members: Vec::new(),
+                })
+                .members
+                .push(file);
+        }
+    }
+    trees
+}
+
+/// Every regular file below a directory, sorted
+///
+/// Symlinks are not followed, so a link out of the tree never pulls in files
+/// elsewhere. The `<file>.bak` copies restore leaves next to files are left out.
+pub fn walk(root: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = WalkDir::new(root)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .map(|e| e.into_path())
+        .filter(|path| !is_backup(path))
+        .collect();
+    files.sort();
+    files
+}
+
+fn is_backup(path: &Path) -> bool {
+    path.to_str()
+        .and_then(|path| path.strip_suffix(".bak"))
+        .is_some_and(|original| Path::new(original).exists())
+}
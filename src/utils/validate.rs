@@ -0,0 +1,79 @@
+use crate::{
+    commands::init::{KittyError, TrackedFile},
+    utils::{file::write_private, order::refers_to},
+};
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Commands from the `validate` setting that apply to a file, in the order they are configured
+pub fn validators<'a>(validate: &'a BTreeMap<String, String>, file: &TrackedFile) -> Vec<&'a str> {
+    validate
+        .iter()
+        .filter(|(entry, _)| refers_to(entry, file))
+        .map(|(_, command)| command.as_str())
+        .collect()
+}
+
+/// Where the candidate content is written for checking: next to the file, so relative
+/// includes resolve as they will once it is in place
+fn candidate_path(original_path: &str) -> PathBuf {
+    let path = Path::new(original_path);
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let dir = path
+        .parent()
+        .filter(|dir| dir.is_dir())
+        .map(Path::to_path_buf)
+        .unwrap_or_else(env::temp_dir);
+    dir.join(format!(".{}.kitty-validate-{}", name, std::process::id()))
+}
+
+/// Quote a path for `sh`
+fn shell_quote(path: &Path) -> String {
+    format!("'{}'", path.to_string_lossy().replace('\'', r"'\''"))
+}
+
+/// Run each command against `content` before it replaces the file, failing on the first that does
+///
+/// `{file}` in a command is replaced with a temporary copy of the content,
+/// which is also in `KITTY_FILE`. The copy is removed again afterwards.
+pub fn check(commands: &[&str], file: &TrackedFile, content: &[u8]) -> Result<(), KittyError> {
+    if commands.is_empty() {
+        return Ok(());
+    }
+
+    let candidate = candidate_path(&file.original_path);
+    write_private(&candidate, content)?;
+    let result = commands.iter().try_for_each(|command| {
+        let output = Command::new("sh")
+            .arg("-c")
+            .arg(command.replace("{file}", &shell_quote(&candidate)))
+            .env("KITTY_FILE", &candidate)
+            .env("KITTY_PATH", &file.original_path)
+            .output()?;
+        if output.status.success() {
+            return Ok(());
+        }
+
+        let mut detail = format!("`{}` ({})", command, output.status);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let message = if stderr.trim().is_empty() {
+            stdout
+        } else {
+            stderr
+        };
+        if !message.trim().is_empty() {
+            detail.push_str(&format!(": {}", message.trim()));
+        }
+        Err(KittyError::Validation(detail))
+    });
+    let _ = fs::remove_file(&candidate);
+    result
+}
@@ -0,0 +1,181 @@
+use crate::{
+    commands::init::{Crypto, KittyError},
+    utils::{
+        aws::{self, Aws},
+        file::write_atomic,
+        platform::hostname,
+        vault::{self, Vault},
+    },
+};
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, path::Path, sync::OnceLock};
+
+/// Template variables, encrypted like the rest of the repository
+const VARS_FILE: &str = "vars.enc";
+
+/// Environment variable naming the profile to use instead of the hostname
+pub const PROFILE_ENV: &str = "KITTY_PROFILE";
+
+/// Variables shared by every machine, and per profile
+#[derive(Serialize, Deserialize, Default)]
+pub struct VarCatalog {
+    /// Values every profile sees
+    #[serde(default)]
+    pub global: BTreeMap<String, String>,
+
+    /// Values for one host or profile, taking precedence over global ones
+    #[serde(default)]
+    pub profiles: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+/// Where a resolved variable came from
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum VarSource {
+    Global,
+    Profile,
+}
+
+/// The profile to use: the one given, `KITTY_PROFILE`, or this machine's hostname
+pub fn profile_name(profile: Option<&str>) -> String {
+    profile
+        .map(str::to_string)
+        .or_else(|| std::env::var(PROFILE_ENV).ok().filter(|p| !p.is_empty()))
+        .unwrap_or_else(hostname)
+}
+
+/// Variable names may hold letters, digits, `_`, `-` and `.`, starting with a letter or `_`
+pub fn check_name(name: &str) -> Result<(), KittyError> {
+    let valid = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+    if valid {
+        Ok(())
+    } else {
+        Err(KittyError::Template(format!(
+            "'{}' is not a valid variable name",
+            name
+        )))
+    }
+}
+
+impl VarCatalog {
+    /// Read the variables, or an empty catalog when none were set yet
+    pub fn load(repo_path: &Path, crypto: &Crypto) -> Result<Self, KittyError> {
+        let vars_path = repo_path.join(VARS_FILE);
+        if !vars_path.exists() {
+            return Ok(Self::default());
+        }
+        let decrypted = crypto.decrypt(&fs::read(vars_path)?)?;
+        Ok(serde_json::from_slice(&decrypted)?)
+    }
+
+    pub fn save(&self, repo_path: &Path, crypto: &Crypto) -> Result<(), KittyError> {
+        let json = serde_json::to_vec(self)?;
+        write_atomic(&repo_path.join(VARS_FILE), crypto.encrypt(&json)?)?;
+        Ok(())
+    }
+
+    /// The global variables, or those of one profile
+    pub fn scope_mut(&mut self, profile: Option<&str>) -> &mut BTreeMap<String, String> {
+        match profile {
+            Some(profile) => self.profiles.entry(profile.to_string()).or_default(),
+            None => &mut self.global,
+        }
+    }
+
+    /// Every variable a profile sees, with where its value comes from
+    pub fn resolve(&self, profile: &str) -> BTreeMap<String, (String, VarSource)> {
+        let mut resolved: BTreeMap<String, (String, VarSource)> = self
+            .global
+            .iter()
+            .map(|(name, value)| (name.clone(), (value.clone(), VarSource::Global)))
+            .collect();
+        for (name, value) in self.profiles.get(profile).into_iter().flatten() {
+            resolved.insert(name.clone(), (value.clone(), VarSource::Profile));
+        }
+        resolved
+    }
+}
+
+fn placeholder() -> &'static Regex {
+    static PLACEHOLDER: OnceLock<Regex> = OnceLock::new();
+    PLACEHOLDER.get_or_init(|| {
+        Regex::new(
+            r"\{\{\s*((?:vault|ssm|secretsmanager):[^\s{}]+|[A-Za-z_][A-Za-z0-9_.-]*)\s*\}\}",
+        )
+        .expect("placeholder pattern is valid")
+    })
+}
+
+/// Fills `{{ name }}` placeholders with a profile's variables, and
+/// `{{ vault:mount/path#key }}`, `{{ ssm:name }}` and `{{ secretsmanager:id#key }}`
+/// ones with secrets read from Vault or AWS
+pub struct Renderer {
+    vars: BTreeMap<String, (String, VarSource)>,
+
+    /// Connected on the first `vault:` placeholder, so templates without one need no Vault
+    vault: Option<Vault>,
+
+    aws: Aws,
+}
+
+impl Renderer {
+    pub fn new(vars: BTreeMap<String, (String, VarSource)>) -> Self {
+        Self {
+            vars,
+            vault: None,
+            aws: Aws::default(),
+        }
+    }
+
+    /// Replace each placeholder in a template with its value
+    ///
+    /// Every variable must have a value; the missing names are reported together.
+    pub fn render(&mut self, template: &str) -> Result<String, KittyError> {
+        let mut values: BTreeMap<String, String> = BTreeMap::new();
+        let mut missing: Vec<String> = Vec::new();
+
+        for captures in placeholder().captures_iter(template) {
+            let name = &captures[1];
+            if values.contains_key(name) || missing.iter().any(|m| m == name) {
+                continue;
+            }
+            if let Some(reference) = name.strip_prefix(vault::PREFIX) {
+                let vault = match &mut self.vault {
+                    Some(vault) => vault,
+                    None => self.vault.insert(Vault::from_env()?),
+                };
+                values.insert(name.to_string(), vault.read(reference)?);
+            } else if let Some(parameter) = name.strip_prefix(aws::SSM_PREFIX) {
+                values.insert(name.to_string(), self.aws.parameter(parameter)?);
+            } else if let Some(secret) = name.strip_prefix(aws::SECRETS_MANAGER_PREFIX) {
+                values.insert(name.to_string(), self.aws.secret(secret)?);
+            } else if let Some((value, _)) = self.vars.get(name) {
+                values.insert(name.to_string(), value.clone());
+            } else {
+                missing.push(name.to_string());
+            }
+        }
+
+        if !missing.is_empty() {
+            return Err(KittyError::Template(format!(
+                "no value for {}",
+                missing.join(", ")
+            )));
+        }
+        let rendered =
+            placeholder().replace_all(template, |captures: &Captures| values[&captures[1]].clone());
+        Ok(rendered.into_owned())
+    }
+
+    /// Render content that is text, leaving anything else as it is
+    pub fn render_bytes(&mut self, content: Vec<u8>) -> Result<Vec<u8>, KittyError> {
+        match String::from_utf8(content) {
+            Ok(template) => Ok(self.render(&template)?.into_bytes()),
+            Err(e) => Ok(e.into_bytes()),
+        }
+    }
+}
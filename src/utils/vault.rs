@@ -0,0 +1,176 @@
+use crate::{
+    commands::init::KittyError,
+    utils::timings::{self, Phase},
+};
+use serde_json::{json, Map, Value};
+use std::{collections::HashMap, env, fs, path::PathBuf, time::Duration};
+
+/// Prefix of template placeholders read from Vault, e.g. `{{ vault:secret/app#password }}`
+pub const PREFIX: &str = "vault:";
+
+/// A client for Vault's KV version 2 secrets engine, configured from the environment
+///
+/// `VAULT_ADDR` is required. The token is `VAULT_TOKEN`, one obtained by AppRole
+/// login with `VAULT_ROLE_ID` and `VAULT_SECRET_ID`, or the one `vault login`
+/// left in `~/.vault-token`, in that order.
+pub struct Vault {
+    address: String,
+    token: String,
+    namespace: Option<String>,
+    agent: ureq::Agent,
+
+    /// Secrets already read, by mount and path, so each is fetched once per run
+    cache: HashMap<String, Map<String, Value>>,
+}
+
+impl Vault {
+    pub fn from_env() -> Result<Self, KittyError> {
+        let address = env::var("VAULT_ADDR")
+            .ok()
+            .filter(|a| !a.is_empty())
+            .ok_or_else(|| {
+                KittyError::Vault("set VAULT_ADDR to use vault: placeholders".to_string())
+            })?;
+        let address = address.trim_end_matches('/').to_string();
+        let namespace = env::var("VAULT_NAMESPACE").ok().filter(|n| !n.is_empty());
+        let agent = ureq::AgentBuilder::new()
+            .timeout(Duration::from_secs(30))
+            .build();
+
+        let mut vault = Vault {
+            address,
+            token: String::new(),
+            namespace,
+            agent,
+            cache: HashMap::new(),
+        };
+        vault.token = match env::var("VAULT_TOKEN").ok().filter(|t| !t.is_empty()) {
+            Some(token) => token,
+            None => match env::var("VAULT_ROLE_ID").ok().filter(|r| !r.is_empty()) {
+                Some(role_id) => vault.approle_login(&role_id)?,
+                None => token_file().ok_or_else(|| {
+                    KittyError::Vault(
+                        "no token; set VAULT_TOKEN, VAULT_ROLE_ID and VAULT_SECRET_ID, or run `vault login`"
+                            .to_string(),
+                    )
+                })?,
+            },
+        };
+        Ok(vault)
+    }
+
+    /// Exchange an AppRole's role and secret ID for a token
+    fn approle_login(&self, role_id: &str) -> Result<String, KittyError> {
+        let secret_id = env::var("VAULT_SECRET_ID").unwrap_or_default();
+        let mount = env::var("VAULT_APPROLE_MOUNT").unwrap_or_else(|_| "approle".to_string());
+        let url = format!("{}/v1/auth/{}/login", self.address, mount);
+
+        let mut request = self.agent.post(&url);
+        if let Some(namespace) = &self.namespace {
+            request = request.set("X-Vault-Namespace", namespace);
+        }
+        let body = json!({ "role_id": role_id, "secret_id": secret_id }).to_string();
+        let response = timings::time(Phase::Network, || {
+            send(request.send_string(&body), "AppRole login")
+        })?;
+
+        response["auth"]["client_token"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| KittyError::Vault("AppRole login returned no token".to_string()))
+    }
+
+    /// Read one key of a secret, given as `mount/path#key`
+    pub fn read(&mut self, reference: &str) -> Result<String, KittyError> {
+        let (secret, key) = reference.split_once('#').ok_or_else(|| {
+            KittyError::Vault(format!(
+                "'{}' names no key; use e.g. vault:secret/app#password",
+                reference
+            ))
+        })?;
+        let (mount, path) = secret
+            .split_once('/')
+            .filter(|(mount, path)| !mount.is_empty() && !path.is_empty())
+            .ok_or_else(|| {
+                KittyError::Vault(format!(
+                    "'{}' needs a mount and a path, e.g. secret/app",
+                    secret
+                ))
+            })?;
+
+        if !self.cache.contains_key(secret) {
+            let data = self.fetch(mount, path)?;
+            self.cache.insert(secret.to_string(), data);
+        }
+
+        match self.cache[secret].get(key) {
+            Some(Value::String(value)) => Ok(value.clone()),
+            Some(value) => Ok(value.to_string()),
+            None => Err(KittyError::Vault(format!(
+                "{} has no key '{}'",
+                secret, key
+            ))),
+        }
+    }
+
+    /// Fetch the latest version of a KV v2 secret
+    fn fetch(&self, mount: &str, path: &str) -> Result<Map<String, Value>, KittyError> {
+        let url = format!("{}/v1/{}/data/{}", self.address, mount, path);
+        let mut request = self.agent.get(&url).set("X-Vault-Token", &self.token);
+        if let Some(namespace) = &self.namespace {
+            request = request.set("X-Vault-Namespace", namespace);
+        }
+        let secret = format!("{}/{}", mount, path);
+        let response = timings::time(Phase::Network, || send(request.call(), &secret))?;
+
+        match &response["data"]["data"] {
+            Value::Object(data) => Ok(data.clone()),
+            _ => Err(KittyError::Vault(format!(
+                "{}/{} is not a KV version 2 secret",
+                mount, path
+            ))),
+        }
+    }
+}
+
+/// The token `vault login` stores for the CLI
+fn token_file() -> Option<String> {
+    let home = env::var_os("HOME")?;
+    fs::read_to_string(PathBuf::from(home).join(".vault-token"))
+        .ok()
+        .map(|token| token.trim().to_string())
+        .filter(|token| !token.is_empty())
+}
+
+/// Turn a response into JSON, and Vault's error list into a readable message
+fn send(result: Result<ureq::Response, ureq::Error>, what: &str) -> Result<Value, KittyError> {
+    match result {
+        Ok(response) => {
+            let body = response
+                .into_string()
+                .map_err(|e| KittyError::Vault(format!("{}: {}", what, e)))?;
+            Ok(serde_json::from_str(&body)?)
+        }
+        Err(ureq::Error::Status(status, response)) => {
+            let errors = response
+                .into_string()
+                .ok()
+                .and_then(|body| serde_json::from_str::<Value>(&body).ok())
+                .and_then(|body| body["errors"].as_array().cloned())
+                .map(|errors| {
+                    errors
+                        .iter()
+                        .filter_map(Value::as_str)
+                        .collect::<Vec<_>>()
+                        .join("; ")
+                })
+                .filter(|errors| !errors.is_empty());
+            Err(KittyError::Vault(match errors {
+                Some(errors) => format!("{}: {} ({})", what, errors, status),
+                None if status == 404 => format!("{}: not found", what),
+                None => format!("{}: HTTP {}", what, status),
+            }))
+        }
+        Err(e) => Err(KittyError::Vault(format!("{}: {}", what, e))),
+    }
+}
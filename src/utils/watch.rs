@@ -0,0 +1,188 @@
+use crate::{
+    collectors::Collector,
+    commands::init::{KittyError, TrackedFile},
+    repository::config::WatchSettings,
+};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, Receiver},
+    time::{Duration, Instant},
+};
+
+/// Files editors write next to the ones they save: vim swap and backup files, its
+/// `4913` write test, Emacs lock and autosave files, and generic temporaries
+const EDITOR_TEMPORARIES: [&str; 8] = [
+    "*.swp",
+    "*.swx",
+    "*~",
+    "4913",
+    ".#*",
+    "#*#",
+    "*.tmp",
+    "*.kate-swp",
+];
+
+/// Events for one file that have not been acted on yet
+struct Pending {
+    first: Instant,
+    last: Instant,
+}
+
+/// Watches the directories of tracked files and reports each logical save once
+///
+/// Directories are watched rather than the files themselves, because atomic saves
+/// replace a file with a renamed temporary and a watch on the old file would be lost.
+pub struct FileWatcher {
+    watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<Event>>,
+    directories: BTreeSet<PathBuf>,
+    tracked: BTreeSet<PathBuf>,
+    ignore: GlobSet,
+    quiet: Duration,
+    max_delay: Duration,
+    pending: HashMap<PathBuf, Pending>,
+}
+
+impl FileWatcher {
+    pub fn new(settings: &WatchSettings) -> Result<Self, KittyError> {
+        let (sender, events) = channel();
+        let watcher =
+            notify::recommended_watcher(sender).map_err(|e| KittyError::Daemon(e.to_string()))?;
+
+        let mut ignore = GlobSetBuilder::new();
+        for pattern in EDITOR_TEMPORARIES
+            .iter()
+            .copied()
+            .chain(settings.ignore.iter().map(String::as_str))
+        {
+            let glob = Glob::new(pattern).map_err(|e| {
+                KittyError::Daemon(format!("Invalid ignore pattern '{}': {}", pattern, e))
+            })?;
+            ignore.add(glob);
+        }
+        let ignore = ignore
+            .build()
+            .map_err(|e| KittyError::Daemon(e.to_string()))?;
+
+        Ok(Self {
+            watcher,
+            events,
+            directories: BTreeSet::new(),
+            tracked: BTreeSet::new(),
+            ignore,
+            quiet: Duration::from_millis(settings.quiet_ms),
+            max_delay: Duration::from_millis(settings.max_delay_ms.max(settings.quiet_ms)),
+            pending: HashMap::new(),
+        })
+    }
+
+    /// Watch the directories holding these files, dropping directories no longer needed
+    pub fn track(&mut self, files: &[TrackedFile]) {
+        self.tracked = files
+            .iter()
+            .filter(|f| Collector::parse(&f.original_path).is_none())
+            .map(|f| PathBuf::from(&f.original_path))
+            .collect();
+        let wanted: BTreeSet<PathBuf> = self
+            .tracked
+            .iter()
+            .filter_map(|path| path.parent())
+            .map(Path::to_path_buf)
+            .collect();
+
+        for directory in self.directories.difference(&wanted) {
+            let _ = self.watcher.unwatch(directory);
+        }
+
+        let mut watched = BTreeSet::new();
+        for directory in wanted {
+            if self.directories.contains(&directory) {
+                watched.insert(directory);
+                continue;
+            }
+            // Missing directories are picked up by a later call once they exist
+            match self.watcher.watch(&directory, RecursiveMode::NonRecursive) {
+                Ok(()) => {
+                    watched.insert(directory);
+                }
+                Err(e) => println!("Cannot watch {}: {}", directory.display(), e),
+            }
+        }
+        self.directories = watched;
+    }
+
+    fn is_ignored(&self, path: &Path) -> bool {
+        self.ignore.is_match(path)
+            || path
+                .file_name()
+                .is_some_and(|name| self.ignore.is_match(name))
+    }
+
+    /// Take in the events received so far
+    fn drain(&mut self) {
+        let now = Instant::now();
+        while let Ok(event) = self.events.try_recv() {
+            let Ok(event) = event else {
+                continue;
+            };
+            if matches!(event.kind, EventKind::Access(_)) {
+                continue;
+            }
+
+            // A rename lists both ends, so a temporary renamed over a tracked file counts for that file
+            for path in event.paths {
+                if !self.tracked.contains(&path) || self.is_ignored(&path) {
+                    continue;
+                }
+                self.pending
+                    .entry(path)
+                    .and_modify(|pending| pending.last = now)
+                    .or_insert(Pending {
+                        first: now,
+                        last: now,
+                    });
+            }
+        }
+    }
+
+    /// When a file is due: once it has been quiet for a while, but never later than
+    /// the maximum delay, so a file that keeps changing is still checked regularly
+    fn due_at(&self, pending: &Pending) -> Instant {
+        (pending.last + self.quiet).min(pending.first + self.max_delay)
+    }
+
+    /// Time until the next changed file is due, or None when nothing changed
+    pub fn next_due(&mut self) -> Option<Duration> {
+        self.drain();
+        self.pending
+            .values()
+            .map(|pending| self.due_at(pending))
+            .min()
+            .map(|due| due.saturating_duration_since(Instant::now()))
+    }
+
+    /// Every changed file, due or not, for an immediate check
+    pub fn take_all(&mut self) -> Vec<PathBuf> {
+        self.drain();
+        self.pending.drain().map(|(path, _)| path).collect()
+    }
+
+    /// Changed files that are due, each reported once per burst of events
+    pub fn settled(&mut self) -> Vec<PathBuf> {
+        self.drain();
+        let now = Instant::now();
+        let due: Vec<PathBuf> = self
+            .pending
+            .iter()
+            .filter(|(_, pending)| self.due_at(pending) <= now)
+            .map(|(path, _)| path.clone())
+            .collect();
+        for path in &due {
+            self.pending.remove(path);
+        }
+        due
+    }
+}
@@ -0,0 +1,88 @@
+#!/bin/bash
+#
+# Kitty Adopt Test Script
+# This script checks that `kitty init --adopt <dir>` tracks the files below
+# the directory but never the repository's own files, on every backend
+#
+
+set -e
+
+# Text formatting
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+BLUE='\033[0;34m'
+BOLD='\033[1m'
+RESET='\033[0m'
+
+# Configuration
+TEST_DIR="$(pwd)/kitty_adopt_test"
+KITTY_CMD="$(pwd)/target/debug/kitty"  # Use your kitty command here (e.g., "kitty" if installed)
+PASSWORD="testpassword"  # Password for the test repository
+
+echo -e "${BOLD}Kitty Adopt Test${RESET}"
+echo "================"
+echo
+
+for tool in jq script; do
+    if ! command -v "$tool" &> /dev/null; then
+        echo -e "${RED}The $tool command is required for this test.${RESET}"
+        exit 1
+    fi
+done
+
+cleanup() {
+    rm -rf "$TEST_DIR"
+}
+
+fail() {
+    echo -e "${RED}FAILED: $1${RESET}"
+    cleanup
+    exit 1
+}
+
+trap cleanup EXIT
+
+# Run kitty on a terminal, answering its password prompts
+with_password() {
+    (sleep 0.5; echo "$PASSWORD"; sleep 0.5; echo "$PASSWORD"; sleep 0.5) \
+        | script -qec "$KITTY_CMD $*" /dev/null > /dev/null
+}
+
+test_backend() {
+    local backend="$1"
+    local dir="$TEST_DIR/$backend"
+
+    echo -e "\n${BOLD}Testing backend: $backend${RESET}"
+    mkdir -p "$dir/conf.d"
+    printf 'listen 80;\n' > "$dir/site.conf"
+    printf 'gzip on;\n' > "$dir/conf.d/gzip.conf"
+
+    # The repository is created inside the directory being adopted
+    export KITTY_REPO="$dir/.kitty"
+    (cd "$dir" && with_password init --backend "$backend" --adopt .)
+
+    export KITTY_PASSWORD="$PASSWORD"
+    local paths
+    paths=$($KITTY_CMD ls-files --json | jq -r '.files[].path') || fail "$backend: ls-files --json failed"
+    unset KITTY_PASSWORD
+
+    grep -q '/site.conf$' <<< "$paths" || fail "$backend: site.conf was not adopted"
+    grep -q '/conf.d/gzip.conf$' <<< "$paths" || fail "$backend: conf.d/gzip.conf was not adopted"
+    if grep -q '/\.kitty/' <<< "$paths"; then
+        fail "$backend: files of the repository itself were adopted: $(grep '/\.kitty/' <<< "$paths" | tr '\n' ' ')"
+    fi
+    [ "$(wc -l <<< "$paths")" = "2" ] || fail "$backend: expected 2 files, got $(wc -l <<< "$paths")"
+
+    echo -e "${GREEN}$backend: only the directory's own files were adopted${RESET}"
+}
+
+echo -e "${BLUE}Building kitty...${RESET}"
+cargo build --quiet
+
+cleanup
+for backend in file sqlite redb; do
+    test_backend "$backend"
+done
+cleanup
+
+echo -e "\n${GREEN}All adopt tests passed!${RESET}"
@@ -0,0 +1,129 @@
+#!/bin/bash
+#
+# Kitty Machine Interface Compatibility Test Script
+# This script checks that the output of `kitty ls-files` and `kitty blob`,
+# which editor plugins parse, and errors reported with --json keep their
+# documented shape on every backend
+#
+
+set -e
+
+# Text formatting
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+BLUE='\033[0;34m'
+BOLD='\033[1m'
+RESET='\033[0m'
+
+# Configuration
+TEST_DIR="$(pwd)/kitty_machine_interface_test"
+KITTY_CMD="$(pwd)/target/debug/kitty"  # Use your kitty command here (e.g., "kitty" if installed)
+PASSWORD="testpassword"  # Password for the test repository
+
+# Fields of each file in `ls-files --json`, format 1
+FILE_FIELDS='["added_at","bundle","expires_at","frozen","hash","id","last_updated","path","pinned","state","tags","versions"]'
+
+echo -e "${BOLD}Kitty Machine Interface Compatibility Test${RESET}"
+echo "=========================================="
+echo
+
+for tool in jq script; do
+    if ! command -v "$tool" &> /dev/null; then
+        echo -e "${RED}The $tool command is required for this test.${RESET}"
+        exit 1
+    fi
+done
+
+cleanup() {
+    rm -rf "$TEST_DIR"
+}
+
+fail() {
+    echo -e "${RED}FAILED: $1${RESET}"
+    cleanup
+    exit 1
+}
+
+# Run kitty on a terminal, answering its password prompts
+with_password() {
+    (sleep 0.5; echo "$PASSWORD"; sleep 0.5; echo "$PASSWORD"; sleep 0.5) \
+        | script -qec "$KITTY_CMD $*" /dev/null > /dev/null
+}
+
+test_backend() {
+    local backend="$1"
+    local dir="$TEST_DIR/$backend"
+
+    echo -e "\n${BOLD}Testing backend: $backend${RESET}"
+    mkdir -p "$dir"
+    export KITTY_REPO="$dir/.kitty"
+
+    (cd "$dir" && with_password init --backend "$backend")
+    printf 'listen 80;\n' > "$dir/clean.conf"
+    printf 'no newline at the end' > "$dir/modified.conf"
+    printf '\x00\x01\xff binary' > "$dir/missing.bin"
+    for file in clean.conf modified.conf missing.bin; do
+        with_password add "$dir/$file"
+    done
+    echo "changed" >> "$dir/modified.conf"
+    cp "$dir/missing.bin" "$dir/missing.bin.orig"
+    rm "$dir/missing.bin"
+
+    # The plugin-facing commands take the password from the environment
+    export KITTY_PASSWORD="$PASSWORD"
+    local listing
+    listing=$($KITTY_CMD ls-files --json) || fail "$backend: ls-files --json failed"
+
+    [ "$(jq '.format' <<< "$listing")" = "1" ] || fail "$backend: format is not 1"
+    jq -e '.repository | type == "string"' <<< "$listing" > /dev/null || fail "$backend: repository is missing"
+    [ "$(jq '.files | length' <<< "$listing")" = "3" ] || fail "$backend: expected 3 files"
+    jq -e --argjson fields "$FILE_FIELDS" 'all(.files[]; (keys | sort) == $fields)' <<< "$listing" > /dev/null \
+        || fail "$backend: file fields changed"
+    jq -e '.files | map(.path) == (map(.path) | sort)' <<< "$listing" > /dev/null || fail "$backend: files are not sorted"
+
+    local state
+    for expected in "clean.conf clean" "modified.conf modified" "missing.bin missing"; do
+        set -- $expected
+        state=$(jq -r --arg path "$dir/$1" '.files[] | select(.path == $path) | .state' <<< "$listing")
+        [ "$state" = "$2" ] || fail "$backend: $1 is '$state', expected '$2'"
+    done
+
+    # Text output: state, a tab and the path
+    $KITTY_CMD ls-files | grep -qP "^modified\t$dir/modified.conf$" || fail "$backend: text output changed"
+    [ "$($KITTY_CMD ls-files "$dir/clean.conf" | wc -l)" = "1" ] || fail "$backend: path filter failed"
+
+    # blob prints the stored bytes unchanged, by path or by id
+    cmp -s <($KITTY_CMD blob "$dir/missing.bin") "$dir/missing.bin.orig" || fail "$backend: binary blob differs"
+    [ "$($KITTY_CMD blob "$dir/modified.conf")" = "no newline at the end" ] || fail "$backend: blob is not the stored copy"
+    local id
+    id=$(jq -r --arg path "$dir/clean.conf" '.files[] | select(.path == $path) | .id' <<< "$listing")
+    cmp -s <($KITTY_CMD blob "@$id") "$dir/clean.conf" || fail "$backend: blob by id failed"
+
+    # Partial or unknown paths are errors, never a guess
+    if $KITTY_CMD blob "modified" > /dev/null 2>&1; then
+        fail "$backend: blob accepted a partial path"
+    fi
+    if $KITTY_CMD blob "$dir/untracked.conf" > /dev/null 2>&1; then
+        fail "$backend: blob accepted an untracked path"
+    fi
+
+    # With --json, errors are objects with a stable code
+    local error
+    error=$(KITTY_REPO="$dir/nowhere" $KITTY_CMD ls-files --json 2>&1 > /dev/null; true)
+    jq -e '(keys == ["code","hint","message","path"]) and .code == "repository-not-found"' <<< "$error" > /dev/null \
+        || fail "$backend: JSON error changed: $error"
+    unset KITTY_PASSWORD
+
+    echo -e "${GREEN}Machine interface unchanged on $backend.${RESET}"
+}
+
+echo -e "${BLUE}Building kitty...${RESET}"
+cargo build --quiet
+
+cleanup
+for backend in file sqlite redb; do
+    test_backend "$backend"
+done
+cleanup
+
+echo -e "\n${GREEN}All machine interface tests passed!${RESET}"
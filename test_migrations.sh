@@ -0,0 +1,178 @@
+#!/bin/bash
+#
+# Kitty SQLite Migration Test Script
+# This script checks that databases created by every earlier schema are
+# upgraded to the current schema without losing data
+#
+
+set -e
+
+# Text formatting
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+BLUE='\033[0;34m'
+BOLD='\033[1m'
+RESET='\033[0m'
+
+# Configuration
+TEST_REPO_DIR="kitty_migration_test"
+KITTY_CMD="$(pwd)/target/debug/kitty"  # Use your kitty command here (e.g., "kitty" if installed)
+LATEST_VERSION=16
+
+echo -e "${BOLD}Kitty SQLite Migration Test${RESET}"
+echo "==========================="
+echo
+
+if ! command -v sqlite3 &> /dev/null; then
+    echo -e "${RED}The sqlite3 command-line tool is required for this test.${RESET}"
+    exit 1
+fi
+
+cleanup() {
+    rm -rf "$TEST_REPO_DIR"
+}
+
+fail() {
+    echo -e "${RED}FAILED:${RESET} $1"
+    cleanup
+    exit 1
+}
+
+trap cleanup EXIT
+
+# Schema of the first SQLite release
+SCHEMA_ORIGINAL="
+CREATE TABLE repository (id INTEGER PRIMARY KEY, created_at TEXT NOT NULL, salt TEXT NOT NULL);
+CREATE TABLE files (id INTEGER PRIMARY KEY, original_path TEXT NOT NULL, repo_path TEXT NOT NULL,
+    added_at TEXT NOT NULL, last_updated TEXT NOT NULL, hash TEXT NOT NULL, content BLOB);"
+
+# Package provenance columns added
+SCHEMA_PACKAGE="$SCHEMA_ORIGINAL
+ALTER TABLE files ADD COLUMN package TEXT;
+ALTER TABLE files ADD COLUMN package_manager TEXT;"
+
+# Previous versions table added
+SCHEMA_VERSIONS="$SCHEMA_PACKAGE
+CREATE TABLE versions (id INTEGER PRIMARY KEY, file_repo_path TEXT NOT NULL, repo_path TEXT NOT NULL,
+    hash TEXT NOT NULL, saved_at TEXT NOT NULL, content BLOB);"
+
+# Bundle and tag columns added
+SCHEMA_LABELS="$SCHEMA_VERSIONS
+ALTER TABLE files ADD COLUMN bundle TEXT;
+ALTER TABLE files ADD COLUMN tags TEXT;"
+
+# Restore ordering column added
+SCHEMA_ORDERING="$SCHEMA_LABELS
+ALTER TABLE files ADD COLUMN after TEXT;"
+
+# File mode and ownership column added
+SCHEMA_METADATA="$SCHEMA_ORDERING
+ALTER TABLE files ADD COLUMN metadata TEXT;"
+
+# Pinned files column added
+SCHEMA_PINNED="$SCHEMA_METADATA
+ALTER TABLE files ADD COLUMN ignore_drift INTEGER NOT NULL DEFAULT 0;"
+
+# Content filters column added
+SCHEMA_FILTERS="$SCHEMA_PINNED
+ALTER TABLE files ADD COLUMN filters TEXT;"
+
+# Platform conditions column added
+SCHEMA_CONDITIONS="$SCHEMA_FILTERS
+ALTER TABLE files ADD COLUMN conditions TEXT;"
+
+# Generation and revision counters added
+SCHEMA_GENERATION="$SCHEMA_CONDITIONS
+ALTER TABLE repository ADD COLUMN generation INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE files ADD COLUMN revision INTEGER NOT NULL DEFAULT 0;"
+
+# Path lookup indexes added
+SCHEMA_INDEXES="$SCHEMA_GENERATION
+CREATE INDEX idx_files_original_path ON files (original_path);
+CREATE INDEX idx_versions_file_repo_path ON versions (file_repo_path);"
+
+# Expiry column added
+SCHEMA_EXPIRY="$SCHEMA_INDEXES
+ALTER TABLE files ADD COLUMN expires_at TEXT;"
+
+SCHEMA_FROZEN="$SCHEMA_EXPIRY
+ALTER TABLE files ADD COLUMN frozen INTEGER NOT NULL DEFAULT 0;"
+
+SCHEMA_SCOPE="$SCHEMA_FROZEN
+ALTER TABLE files ADD COLUMN scope TEXT;"
+
+SCHEMA_TREES="$SCHEMA_SCOPE
+ALTER TABLE files ADD COLUMN tree TEXT;"
+
+SEED_DATA="
+INSERT INTO repository (id, created_at, salt) VALUES (1, '2024-01-01T00:00:00+00:00', '00112233445566778899aabbccddeeff');
+INSERT INTO files (original_path, repo_path, added_at, last_updated, hash, content)
+    VALUES ('/etc/example.conf', 'files/example', '2024-01-01T00:00:00+00:00', '2024-01-01T00:00:00+00:00', 'abc123', x'deadbeef');"
+
+# Create a repository whose database uses the given schema, then upgrade it
+test_upgrade() {
+    local name="$1"
+    local schema="$2"
+
+    echo -e "\n${BOLD}Upgrading from schema: $name${RESET}"
+    cleanup
+    mkdir -p "$TEST_REPO_DIR/.kitty"
+    echo "sqlite" > "$TEST_REPO_DIR/.kitty/storage.type"
+    echo "00112233445566778899aabbccddeeff" > "$TEST_REPO_DIR/.kitty/salt.key"
+
+    if [ -n "$schema" ]; then
+        sqlite3 "$TEST_REPO_DIR/.kitty/kitty.db" "$schema $SEED_DATA"
+    fi
+
+    # Opening the database runs the migrations; run twice to check they are not reapplied
+    (cd "$TEST_REPO_DIR" && $KITTY_CMD db check > /dev/null) || fail "$name: first open failed"
+    (cd "$TEST_REPO_DIR" && $KITTY_CMD db check > /dev/null) || fail "$name: second open failed"
+
+    local db="$TEST_REPO_DIR/.kitty/kitty.db"
+    local version
+    version=$(sqlite3 "$db" "SELECT MAX(version) FROM schema_migrations")
+    [ "$version" = "$LATEST_VERSION" ] || fail "$name: schema version is $version, expected $LATEST_VERSION"
+
+    local applied
+    applied=$(sqlite3 "$db" "SELECT COUNT(*) FROM schema_migrations")
+    [ "$applied" = "$LATEST_VERSION" ] || fail "$name: $applied migrations recorded, expected $LATEST_VERSION"
+
+    for column in package package_manager bundle tags after metadata ignore_drift filters conditions revision expires_at frozen scope tree; do
+        sqlite3 "$db" "PRAGMA table_info(files)" | grep -q "|$column|" || fail "$name: files.$column is missing"
+    done
+    sqlite3 "$db" "PRAGMA table_info(repository)" | grep -q "|generation|" || fail "$name: repository.generation is missing"
+    sqlite3 "$db" ".tables" | grep -q "versions" || fail "$name: versions table is missing"
+    for index in idx_files_original_path idx_versions_file_repo_path; do
+        sqlite3 "$db" ".indexes" | grep -q "$index" || fail "$name: index $index is missing"
+    done
+
+    if [ -n "$schema" ]; then
+        local content
+        content=$(sqlite3 "$db" "SELECT hex(content) FROM files WHERE repo_path = 'files/example'")
+        [ "$content" = "DEADBEEF" ] || fail "$name: file content was not preserved"
+    fi
+
+    echo -e "${GREEN}Upgraded to schema version $version.${RESET}"
+}
+
+echo -e "${BLUE}Building kitty...${RESET}"
+cargo build --quiet
+
+test_upgrade "empty database" ""
+test_upgrade "original" "$SCHEMA_ORIGINAL"
+test_upgrade "package columns" "$SCHEMA_PACKAGE"
+test_upgrade "versions table" "$SCHEMA_VERSIONS"
+test_upgrade "bundles and tags" "$SCHEMA_LABELS"
+test_upgrade "restore ordering" "$SCHEMA_ORDERING"
+test_upgrade "file metadata" "$SCHEMA_METADATA"
+test_upgrade "pinned files" "$SCHEMA_PINNED"
+test_upgrade "content filters" "$SCHEMA_FILTERS"
+test_upgrade "platform conditions" "$SCHEMA_CONDITIONS"
+test_upgrade "generation counters" "$SCHEMA_GENERATION"
+test_upgrade "path indexes" "$SCHEMA_INDEXES"
+test_upgrade "expiring files" "$SCHEMA_EXPIRY"
+test_upgrade "frozen files" "$SCHEMA_FROZEN"
+test_upgrade "system and user files" "$SCHEMA_SCOPE"
+test_upgrade "directory trees" "$SCHEMA_TREES"
+
+echo -e "\n${GREEN}All migration tests passed!${RESET}"